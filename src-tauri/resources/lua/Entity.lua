@@ -6,6 +6,31 @@ local Entity = {
     scripts = {},
 }
 
+-- Tilemaps keep their tiles as a list, row after row, but save them run-length encoded
+-- (e.g. "0*38,1,1"), since levels are mostly long runs of the same tile
+local function encode_tiles(tiles)
+    local runs = {}
+    local i = 1
+    while i <= #tiles do
+        local count = 1
+        while tiles[i + count] == tiles[i] do count = count + 1 end
+        runs[#runs + 1] = count > 1 and string.format("%d*%d", tiles[i], count) or tostring(tiles[i])
+        i = i + count
+    end
+    return table.concat(runs, ",")
+end
+
+local function decode_tiles(encoded)
+    local tiles = {}
+    for run in encoded:gmatch("[^,%s]+") do
+        local tile, count = run:match("^(%d+)%*(%d+)$")
+        tile, count = tonumber(tile or run), tonumber(count or 1)
+        assert(tile and count, string.format("Couldn't read tiles, \"%s\" isn't a tile.", run))
+        for _ = 1, count do tiles[#tiles + 1] = tile end
+    end
+    return tiles
+end
+
 function Entity:new(o)
     local entity = {}
     setmetatable(entity, self)
@@ -20,35 +45,62 @@ function Entity:update(data)
         print("Provided layer out of bounds; set to default (0)")
     end
 
+    if (data.type or self.type) == "tilemap" and type(data.tiles) == "string" then
+        data.tiles = decode_tiles(data.tiles)
+    end
+
     for k, v in pairs(data) do self[k] = v end
 end
 
-function Entity:load_script(funcname, script_string)
+-- Tile numbers are 0 for empty, or the frame of the tileset to show. Columns and rows count from 1.
+function Entity:get_tile(column, row)
+    if column < 1 or column > self.columns or row < 1 or row > self.rows then return nil end
+    return (self.tiles or {})[(row - 1) * self.columns + column] or 0
+end
+
+function Entity:set_tile(column, row, tile)
+    assert(column >= 1 and column <= self.columns and row >= 1 and row <= self.rows,
+        string.format("Tile %d, %d is outside the tilemap.", column, row))
+    tile = assert(math.tointeger(tile), "Tiles must be whole numbers.")
+    self.tiles = self.tiles or {}
+    -- fill in any gap before it, so the tiles stay a list
+    for i = #self.tiles + 1, (row - 1) * self.columns + column - 1 do self.tiles[i] = 0 end
+    self.tiles[(row - 1) * self.columns + column] = tile
+end
+
+function Entity:load_script(id, funcname, script_string)
     if not script_string then
-        if self.scripts[funcname].string then
+        if self.scripts[funcname] and self.scripts[funcname].string then
             script_string = self.scripts[funcname].string
         else
             error "Can't load an empty function."
         end
     end
 
-    local success, loaded = serializer.load(
-        "local func = function(self, data) " .. (script_string) .. " ; end ; return func",
-        { safe = false })
-    assert(success, "Serializer couldn't load script as function.")
+    local func, err = compile_script(id, funcname, script_string)
+    if not func then error(err, 0) end
 
     if not self.scripts[funcname] then self.scripts[funcname] = {} end
     self.scripts[funcname].string = script_string
-    self.scripts[funcname].func = (loaded --[[@as function]])
+    self.scripts[funcname].func = func
+    -- a fresh load gets a fresh chance
+    self.scripts[funcname].failures = nil
+    self.scripts[funcname].disabled = nil
 end
 
-function Entity:run_script(funcname, params)
+-- Drop compiled scripts, e.g. when their chunk names no longer match the entity's ID
+function Entity:unload_scripts()
+    for _, script in pairs(self.scripts) do script.func = nil end
+end
+
+function Entity:run_script(id, funcname, params)
     assert(self.scripts[funcname],
         string.format("Warning: %s is not a valid function on this entity.", funcname))
-    assert(type(self.scripts[funcname].func) == "function" or pcall(self.load_script, self, funcname),
-        "Couldn't load script.")
+    if self.scripts[funcname].disabled then return end
+    if type(self.scripts[funcname].func) ~= "function" then self:load_script(id, funcname) end
 
-    local success, data
+    -- data arrives serialized from other scripts, or as a table from the runtime
+    local success, data = true, params
     if type(params) == "string" then
         success, data = serializer.load(params, { safe = false })
         assert(success, "Deserializing data failed: " .. serializer.line(data))
@@ -60,7 +112,16 @@ end
 function Entity:serializable()
     local copy = deep_copy(self)
     copy.scene = nil
-    if copy.scripts then for _, script in pairs(copy.scripts) do script.func = nil end end
+    if copy.type == "tilemap" and type(copy.tiles) == "table" then copy.tiles = encode_tiles(copy.tiles) end
+    if copy.scripts then
+        for _, script in pairs(copy.scripts) do
+            script.func = nil
+            script.failures = nil
+            script.disabled = nil
+        end
+        -- the same as having none, which is how entities without scripts are loaded
+        if next(copy.scripts) == nil then copy.scripts = nil end
+    end
     return copy
 end
 
@@ -13,16 +13,31 @@ function Scene:new(o)
     return o
 end
 
-function Scene:emit_update(dt)
-    for _, entity in pairs(self.entities) do if entity.scripts.on_tick then entity:run_script("on_tick") end end
+-- Entity IDs in order, so scripts run in the same order every time (which replays rely on)
+function Scene:sorted_ids()
+    local ids = {}
+    for id, _ in pairs(self.entities) do ids[#ids + 1] = id end
+    table.sort(ids)
+    return ids
+end
+
+function Scene:tick()
+    for _, id in ipairs(self:sorted_ids()) do
+        local entity = self.entities[id]
+        if entity and entity.scripts.on_tick then self:run_protected(id, "on_tick") end
+    end
+end
 
+-- Sends the entities to be drawn, along with the `view` (camera & canvas) to draw them through
+function Scene:emit_update(view)
     local entities_copy = deep_copy(self.entities)
     for _, entity in pairs(entities_copy) do
         for script, _ in pairs(entity.scripts) do
             entity.scripts[script] = entity.scripts[script].string
         end
     end
-    emit("scene_update", entities_copy)
+    view.entities = entities_copy
+    emit("scene_update", view)
 end
 
 function Scene:update_entity_id(original_id, new_id, data)
@@ -33,6 +48,7 @@ function Scene:update_entity_id(original_id, new_id, data)
     if (new_id ~= original_id) then
         self.entities[new_id] = self.entities[original_id]
         self.entities[original_id] = nil
+        self.entities[new_id]:unload_scripts()
     end
     if (data) then self.entities[new_id]:update(data) end
 end
@@ -44,6 +60,7 @@ function Scene:duplicate_entity(id)
     while self.entities[new_key] do new_key = new_key .. "_clone" end
 
     self.entities[new_key] = deep_copy(self.entities[id])
+    self.entities[new_key]:unload_scripts()
     self.entities[new_key].pos.x = self.entities[new_key].pos.x + 15
     self.entities[new_key].pos.y = self.entities[new_key].pos.y + 15
 end
@@ -58,69 +75,95 @@ function Scene:add_entity(id, data)
     self.entities[id] = Entity:new(data)
 end
 
-function Scene:save_scene(path)
-    local to_save = {}
-    for id, entity in pairs(self.entities) do to_save[id] = entity:serializable() end
+-- The scene's entities, along with anything in `extra` (e.g. pending timers), as a scene file's contents
+function Scene:dump(extra)
+    local to_save = { version = 2, entities = {} }
+    for id, entity in pairs(self.entities) do to_save.entities[id] = entity:serializable() end
+    for k, v in pairs(extra or {}) do to_save[k] = v end
+    -- sorted, so saving the same scene twice gives the same file
+    return serializer.dump(to_save, { sortkeys = true })
+end
 
+-- Saves the scene's entities, along with anything in `extra`
+function Scene:save_scene(path, extra)
     local file = assert(io.open(path, "w"), "Couldn't open file")
-    file:write(serializer.dump(to_save))
+    file:write(self:dump(extra))
     file:close()
 end
 
+-- Loads a scene's entities, returning the whole saved table
 function Scene:load_scene(path)
     local file = assert(io.open(path, "r"), string.format("Couldn't open file at \"%s\".", path))
     local content = file:read("*all")
     file:close()
+    return self:load_dump(content)
+end
 
-    local success, loaded_entities = serializer.load(content)
-    if not success then error "Couldn't deserialize scene." end
+-- Loads a scene's entities from a scene file's contents, returning the whole saved table
+function Scene:load_dump(content)
+    local success, loaded = serializer.load(content)
+    if not success or type(loaded) ~= "table" then error "Couldn't deserialize scene." end
+    -- scenes saved before versioning are just the entities
+    if type(loaded.version) ~= "number" then loaded = { entities = loaded } end
 
     local new_entities = {}
-    for k, v in pairs(loaded_entities --[[@as table]]) do new_entities[k] = Entity:new(v) end
+    for k, v in pairs(loaded.entities or {}) do new_entities[k] = Entity:new(v) end
     self.entities = new_entities
+    return loaded
 end
 
+local keywords = {}
+for word in ("and break do else elseif end false for function goto if in local nil not or repeat return then true until while"):gmatch("%a+") do
+    keywords[word] = true
+end
+
+-- A table key as it's written in Lua, e.g. `pos` or `["has spaces"]`
+local function key_name(key)
+    if type(key) == "string" and key:match("^[%a_][%w_]*$") and not keywords[key] then return key end
+    return "[" .. serializer.line(key, { comment = false }) .. "]"
+end
+
+-- The entity's properties for the inspector, like the inside of a table without its braces.
+-- Each one's written on its own, so nested tables are indented from the left without changing multiline strings.
 function Scene:entity_as_block_string(id)
     local copy = self.entities[id]:serializable()
     copy.id = id
     copy.scripts = nil
-    local block = serializer.block(copy, { comment = false })
-
-    -- strip brackets & unindent
-    block = block:sub(5, #block - 2)
-    local i = 1
-    while i < #block do
-        if block:sub(i, i) == "\n" and block:sub(i + 1, i + 2) == "  " then
-            block = block:sub(1, i) .. block:sub(i + 3, #block)
-        end
-        i = i + 1
+
+    local keys = {}
+    for key, _ in pairs(copy) do keys[#keys + 1] = key end
+    table.sort(keys, function(a, b) return tostring(a) < tostring(b) end)
+
+    local properties = {}
+    for _, key in ipairs(keys) do
+        properties[#properties + 1] = key_name(key) .. " = " .. serializer.block(copy[key], { comment = false })
+    end
+    return table.concat(properties, ",\n")
+end
+
+-- Run a script as a coroutine, reporting any failure to the script error channel instead of raising it
+function Scene:run_protected(id, script, data)
+    return run_entity_script(id, script, data)
+end
+
+-- Count a script's failure, disabling it if the error channel says so
+function Scene:report_failure(id, script, err)
+    local entity = self.entities[id]
+    local failing = entity and entity.scripts[script]
+    local failures = 1
+    if failing then
+        failing.failures = (failing.failures or 0) + 1
+        failures = failing.failures
     end
-    return block
+    if report_script_error(id, script, err, failures) and failing then failing.disabled = true end
 end
 
 -- Invoke script on any listening entity
 function Scene:handle_broadcast(event, data)
     assert(type(event) == "string", "Broadcast event must be a string.")
-    local failed = {}
-    for id, entity in pairs(self.entities) do
-        for script, _ in pairs(entity.scripts) do
-            if script == event then
-                local success, result = pcall(entity.run_script, entity, script, data)
-                if not success then table.insert(failed, { entity = id, error = result }) end
-            end
-        end
-    end
-
-    local fails = #failed
-    if fails == 0 then return end
-    if fails == 1 then
-        error(string.format("Script on \"%s\" failed: %s", failed[1].entity, tostring(failed[1].error)))
-    else
-        local err_total = string.format("%i scripts failed.", fails)
-        for _, fail in ipairs(failed) do
-            err_total = err_total .. string.format("\n%s: %s", fail.entity, fail.error)
-        end
-        error(err_total)
+    for _, id in ipairs(self:sorted_ids()) do
+        local entity = self.entities[id]
+        if entity and entity.scripts[event] then self:run_protected(id, event, data) end
     end
 end
 
@@ -134,8 +177,7 @@ function Scene:handle_message(target, event, data)
         string.format("Couldn't invoke message \"%s\" because no matching script on \"%s\" was found.",
             event, target))
 
-    local success, result = pcall(self.entities[target].run_script, self.entities[target], event, data)
-    assert(success, string.format("Couldn't invoke message \"%s\" on \"%s\": %s", event, target, tostring(result)))
+    self:run_protected(target, event, data)
 end
 
 return Scene
@@ -42,14 +42,16 @@ local function s(t, opts)
         elseif type(s) ~= "string" then
             return tostring(s)
         else
-            -- Check if string contains newlines or quotes
-            if s:find("\n") or s:find('"') or s:find("'") then
+            -- Check if string contains newlines or quotes, but not carriage returns, which long strings turn into newlines
+            if (s:find("\n") or s:find('"') or s:find("'")) and not s:find("\r") then
                 -- Find the appropriate number of equal signs needed
                 local eq = ""
                 while s:find("%[" .. eq .. "%[") or s:find("%]" .. eq .. "%]") do
                     eq = eq .. "="
                 end
-                return "[" .. eq .. "[" .. s .. "]" .. eq .. "]"
+                -- a newline straight after the opening bracket is skipped when loading, so it needs another
+                local lead = s:sub(1, 1) == "\n" and "\n" or ""
+                return "[" .. eq .. "[" .. lead .. s .. "]" .. eq .. "]"
             else
                 -- Use regular quoted string for simple strings
                 return ("%q"):format(s):gsub("\010", "n"):gsub("\026", "\\026")
@@ -9,11 +9,10 @@ return require('Scene'):new({
 
 next line]],
             scripts = {
-                on_click = { string = "self.pos = { x = 200, y = 200 }" },
-                on_tick = {
-                    string = [[if self.pos.y > 100
-    then self.pos.y = self.pos.y - 1
-end]]
+                on_click = {
+                    string = [[tween(self.pos, { x = 200, y = 200 }, 0.5, "ease_out_quad", function()
+    tween(self.pos, { y = 100 }, 2, "ease_in_out_sine")
+end)]]
                 }
             }
         }),
@@ -0,0 +1,125 @@
+-- Functions for scene tests, built on `harness` (from testing.rs) for the parts that go through the runtime.
+-- Returns the test file's environment, where anything else falls through to the usual globals.
+local serializer = require('serpent')
+
+local function show(value)
+    if type(value) == "string" then return string.format("%q", value) end
+    if type(value) ~= "table" then return tostring(value) end
+    return serializer.line(value, { comment = false, nocode = true })
+end
+
+local function equal(a, b)
+    if a == b then return true end
+    if type(a) ~= "table" or type(b) ~= "table" then return false end
+    for k, v in pairs(a) do
+        if not equal(v, b[k]) then return false end
+    end
+    for k, _ in pairs(b) do
+        if a[k] == nil then return false end
+    end
+    return true
+end
+
+-- Errors from the runtime, without where in the runtime they came from
+local function without_position(err)
+    return (tostring(err):gsub("^runtime error: ", ""):gsub('%[string "[^"]*"%]:%d+: ', ""))
+end
+
+return function(harness)
+    local t = setmetatable({}, { __index = _G })
+
+    -- Fails the test at the line in it that set off a script that failed, two calls up from here
+    local function check_scripts()
+        local errors = harness.take_script_errors()
+        if #errors == 0 then return end
+        local failed = errors[1]
+        local line = failed.line and string.format(" on line %d", failed.line) or ""
+        error(string.format("%s's %s script failed%s: %s", failed.entity, failed.script, line, failed.message), 3)
+    end
+
+    -- Call `f`, failing the test at the caller's caller's line if it errors
+    local function protected(f, ...)
+        local success, err = pcall(f, ...)
+        if not success then error(without_position(err), 3) end
+    end
+
+    function t.scene(path)
+        local loaded, message = harness.load_scene(path)
+        if not loaded then error(without_position(message), 2) end
+        check_scripts()
+    end
+
+    -- Add an entity, with scripts given as just their code
+    function t.add(id, data)
+        local scripts = {}
+        for name, script in pairs(data.scripts or {}) do
+            scripts[name] = type(script) == "string" and { string = script } or script
+        end
+        data.scripts = scripts
+        protected(currentScene.add_entity, currentScene, id, data)
+    end
+
+    function t.entity(id)
+        local entity = currentScene.entities[id]
+        if entity == nil then error(string.format("There's no entity called \"%s\"", id), 2) end
+        return entity
+    end
+
+    function t.tick(n, dt)
+        for _ = 1, n or 1 do
+            harness.tick(dt or 1 / 60)
+            check_scripts()
+        end
+    end
+
+    -- What double-clicking the entity does in the scene
+    function t.click(id)
+        local entity = t.entity(id)
+        if not entity.scripts.on_click then error(string.format("%s has no on_click script", id), 2) end
+        currentScene:run_protected(id, "on_click", {})
+        check_scripts()
+    end
+
+    function t.message(target, event, data)
+        protected(message, target, event, data)
+        check_scripts()
+    end
+
+    function t.broadcast(event, data)
+        protected(broadcast, event, data)
+        check_scripts()
+    end
+
+    -- Queue an input event for the next tick, e.g. `input({ type = "key_down", key = "ArrowLeft" })`
+    function t.input(event)
+        protected(harness.input, event)
+    end
+
+    -- The data of every `event` emitted so far, oldest first
+    function t.events(event)
+        return harness.events(event)
+    end
+
+    function t.clear_events()
+        harness.clear_events()
+    end
+
+    -- Compares tables by what's in them, so `assert_eq(entity("door").pos, { x = 10, y = 0 })` works
+    function t.assert_eq(actual, expected, message)
+        if equal(actual, expected) then return end
+        local failure = string.format("expected %s, got %s", show(expected), show(actual))
+        if message then failure = message .. ": " .. failure end
+        error(failure, 2)
+    end
+
+    -- For numbers that have been through some arithmetic, like positions after a few ticks of physics
+    function t.assert_near(actual, expected, tolerance, message)
+        tolerance = tolerance or 1e-6
+        if type(actual) == "number" and math.abs(actual - expected) <= tolerance then return end
+        local failure = string.format("expected %s (give or take %s), got %s", show(expected), tolerance, show(actual))
+        if message then failure = message .. ": " .. failure end
+        error(failure, 2)
+    end
+
+    return t
+end
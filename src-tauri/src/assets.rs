@@ -0,0 +1,38 @@
+use crate::lua_types::LuaError;
+use mlua::prelude::*;
+use mlua::AppDataRefMut;
+use std::path::{Path, PathBuf};
+
+/// Where a scene's files (like sounds) are found: relative to the folder the scene was saved to or loaded from.
+pub struct Assets {
+    root: PathBuf,
+}
+
+impl Assets {
+    pub fn new() -> Self {
+        Assets {
+            root: std::env::current_dir().unwrap_or_default(),
+        }
+    }
+
+    /// Look for assets next to the scene file at `path` from now on.
+    pub fn set_scene_path(&mut self, path: &str) {
+        if let Some(folder) = Path::new(path).parent() {
+            self.root = folder.to_path_buf();
+        }
+    }
+
+    pub fn resolve(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+}
+
+pub fn assets_mut(lua: &Lua) -> Result<AppDataRefMut<'_, Assets>, LuaError> {
+    lua.app_data_mut::<Assets>()
+        .ok_or_else(|| LuaError::InitializationError("Assets missing".to_string()))
+}
+
+pub fn set_globals(lua: &Lua) -> Result<(), LuaError> {
+    lua.set_app_data(Assets::new());
+    Ok(())
+}
@@ -0,0 +1,579 @@
+use crate::assets::assets_mut;
+use crate::lua_types::LuaError;
+use mlua::prelude::*;
+use mlua::AppDataRefMut;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Sample rate sounds are mixed at, unless a sound card wants something else.
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// A decoded sound, as interleaved stereo samples.
+pub struct Sound {
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+impl Sound {
+    /// Decode a `.wav` or `.ogg` file.
+    pub fn load(path: &Path) -> Result<Sound, String> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        match extension.as_deref() {
+            Some("wav") => Sound::load_wav(path),
+            Some("ogg") => Sound::load_ogg(path),
+            _ => Err("only .wav and .ogg sounds are supported".to_string()),
+        }
+    }
+
+    fn load_wav(path: &Path) -> Result<Sound, String> {
+        let mut reader = hound::WavReader::open(path).map_err(|e| e.to_string())?;
+        let spec = reader.spec();
+        let samples = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<_>, _>>(),
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1_i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|s| s as f32 * scale))
+                    .collect()
+            }
+        }
+        .map_err(|e| e.to_string())?;
+        Ok(Sound::from_interleaved(
+            &samples,
+            spec.channels as usize,
+            spec.sample_rate,
+        ))
+    }
+
+    fn load_ogg(path: &Path) -> Result<Sound, String> {
+        let file = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+        let mut reader =
+            lewton::inside_ogg::OggStreamReader::new(file).map_err(|e| e.to_string())?;
+        let mut samples = Vec::new();
+        while let Some(packet) = reader.read_dec_packet_itl().map_err(|e| e.to_string())? {
+            samples.extend(packet.into_iter().map(|s| s as f32 / 32768.0));
+        }
+        Ok(Sound::from_interleaved(
+            &samples,
+            reader.ident_hdr.audio_channels as usize,
+            reader.ident_hdr.audio_sample_rate,
+        ))
+    }
+
+    /// Mono sounds play out of both speakers, and anything past the first two channels is dropped.
+    pub fn from_interleaved(samples: &[f32], channels: usize, sample_rate: u32) -> Sound {
+        let samples = match channels {
+            0 => Vec::new(),
+            1 => samples.iter().flat_map(|&s| [s, s]).collect(),
+            2 => samples.to_vec(),
+            _ => samples
+                .chunks_exact(channels)
+                .flat_map(|frame| [frame[0], frame[1]])
+                .collect(),
+        };
+        Sound {
+            samples,
+            sample_rate,
+        }
+    }
+
+    fn frames(&self) -> usize {
+        self.samples.len() / 2
+    }
+
+    fn frame(&self, index: usize) -> (f32, f32) {
+        (self.samples[index * 2], self.samples[index * 2 + 1])
+    }
+}
+
+/// A sound that's playing.
+struct Channel {
+    handle: u64,
+    sound: Arc<Sound>,
+    /// In frames of the sound, which may be part way between two of them
+    position: f64,
+    volume: f32,
+    looping: bool,
+}
+
+/// Adds together every sound that's playing, for a backend to send to the speakers.
+pub struct Mixer {
+    sample_rate: u32,
+    channels: Vec<Channel>,
+    music: Option<u64>,
+    next_handle: u64,
+}
+
+impl Mixer {
+    pub fn new(sample_rate: u32) -> Self {
+        Mixer {
+            sample_rate,
+            channels: Vec::new(),
+            music: None,
+            next_handle: 1,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn play(&mut self, sound: Arc<Sound>, volume: f32, looping: bool) -> u64 {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.channels.push(Channel {
+            handle,
+            sound,
+            position: 0.0,
+            volume,
+            looping,
+        });
+        handle
+    }
+
+    /// Play a music track in place of whichever was playing before.
+    pub fn play_music(&mut self, sound: Arc<Sound>, volume: f32, looping: bool) -> u64 {
+        self.stop_music();
+        let handle = self.play(sound, volume, looping);
+        self.music = Some(handle);
+        handle
+    }
+
+    pub fn stop(&mut self, handle: u64) {
+        self.channels.retain(|channel| channel.handle != handle);
+    }
+
+    pub fn stop_music(&mut self) {
+        if let Some(handle) = self.music.take() {
+            self.stop(handle);
+        }
+    }
+
+    pub fn stop_all(&mut self) {
+        self.channels.clear();
+        self.music = None;
+    }
+
+    pub fn is_playing(&self, handle: u64) -> bool {
+        self.channels.iter().any(|channel| channel.handle == handle)
+    }
+
+    /// Fill `out` with the next interleaved stereo frames, and move every sound along.
+    pub fn mix(&mut self, out: &mut [f32]) {
+        out.fill(0.0);
+        let output_rate = self.sample_rate as f64;
+        for channel in &mut self.channels {
+            let frames = channel.sound.frames();
+            // sounds recorded at a different rate are stretched to fit, blending between neighbouring frames
+            let step = channel.sound.sample_rate as f64 / output_rate;
+            for pair in out.chunks_exact_mut(2) {
+                if channel.position >= frames as f64 {
+                    if !channel.looping || frames == 0 {
+                        break;
+                    }
+                    channel.position %= frames as f64;
+                }
+                let index = channel.position as usize;
+                let next = if index + 1 < frames {
+                    index + 1
+                } else if channel.looping {
+                    0
+                } else {
+                    index
+                };
+                let blend = (channel.position - index as f64) as f32;
+                let ((l0, r0), (l1, r1)) = (channel.sound.frame(index), channel.sound.frame(next));
+                pair[0] += (l0 + (l1 - l0) * blend) * channel.volume;
+                pair[1] += (r0 + (r1 - r0) * blend) * channel.volume;
+                channel.position += step;
+            }
+        }
+        out.iter_mut().for_each(|s| *s = s.clamp(-1.0, 1.0));
+
+        self.channels.retain(|channel| {
+            let frames = channel.sound.frames();
+            (channel.looping && frames > 0) || channel.position < frames as f64
+        });
+        if self.music.is_some_and(|music| !self.is_playing(music)) {
+            self.music = None;
+        }
+    }
+}
+
+pub type SharedMixer = Arc<Mutex<Mixer>>;
+
+/// Somewhere mixed sound goes.
+pub trait AudioBackend {
+    /// Samples per second the backend wants sound mixed at.
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    /// Called once per tick. Backends that aren't driven by a sound card mix `dt` seconds of sound here.
+    fn tick(&mut self, mixer: &SharedMixer, dt: f64);
+}
+
+/// Mix `dt` more seconds of sound, carrying over the part of a frame that doesn't fit.
+fn mix_for(mixer: &SharedMixer, dt: f64, leftover: &mut f64) -> Vec<f32> {
+    let Ok(mut mixer) = mixer.lock() else {
+        return Vec::new();
+    };
+    *leftover += dt * mixer.sample_rate() as f64;
+    let frames = leftover.floor();
+    *leftover -= frames;
+    let mut out = vec![0.0; frames as usize * 2];
+    mixer.mix(&mut out);
+    out
+}
+
+/// Plays sounds to nowhere, so they still start & finish on time without a sound card.
+#[derive(Default)]
+pub struct NullAudio {
+    leftover: f64,
+}
+
+impl NullAudio {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl AudioBackend for NullAudio {
+    fn tick(&mut self, mixer: &SharedMixer, dt: f64) {
+        mix_for(mixer, dt, &mut self.leftover);
+    }
+}
+
+/// Records everything that's played into a `.wav` file, e.g. to check what a scene sounds like without listening.
+// only tests record to files so far
+#[cfg(test)]
+pub struct FileAudio {
+    writer: hound::WavWriter<std::io::BufWriter<File>>,
+    leftover: f64,
+}
+
+#[cfg(test)]
+impl FileAudio {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        Ok(FileAudio {
+            writer: hound::WavWriter::create(path, spec).map_err(|e| e.to_string())?,
+            leftover: 0.0,
+        })
+    }
+}
+
+#[cfg(test)]
+impl AudioBackend for FileAudio {
+    fn tick(&mut self, mixer: &SharedMixer, dt: f64) {
+        for sample in mix_for(mixer, dt, &mut self.leftover) {
+            if let Err(e) = self.writer.write_sample(sample) {
+                eprintln!("[audio] Failed to write sound to file: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+pub struct CpalAudio {
+    _stream: cpal::Stream,
+    sample_rate: u32,
+}
+
+#[cfg(feature = "audio")]
+impl CpalAudio {
+    pub fn new(mixer: &SharedMixer) -> Result<Self, String> {
+        use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("no output device")?;
+        let supported = device.default_output_config().map_err(|e| e.to_string())?;
+        let format = supported.sample_format();
+        let config: cpal::StreamConfig = supported.into();
+        let stream = match format {
+            cpal::SampleFormat::F32 => output_stream::<f32>(&device, &config, mixer),
+            cpal::SampleFormat::I16 => output_stream::<i16>(&device, &config, mixer),
+            cpal::SampleFormat::U16 => output_stream::<u16>(&device, &config, mixer),
+            cpal::SampleFormat::I32 => output_stream::<i32>(&device, &config, mixer),
+            format => Err(format!("unsupported sample format {}", format)),
+        }?;
+        stream.play().map_err(|e| e.to_string())?;
+        Ok(CpalAudio {
+            _stream: stream,
+            sample_rate: config.sample_rate.0,
+        })
+    }
+}
+
+/// A stream playing the mixer's sound, in whichever sample format the sound card takes.
+#[cfg(feature = "audio")]
+fn output_stream<T: cpal::SizedSample + cpal::FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mixer: &SharedMixer,
+) -> Result<cpal::Stream, String> {
+    use cpal::traits::DeviceTrait;
+
+    let speakers = config.channels as usize;
+    let mixer = mixer.clone();
+    let mut stereo = Vec::new();
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                stereo.resize(data.len() / speakers * 2, 0.0);
+                match mixer.lock() {
+                    Ok(mut mixer) => mixer.mix(&mut stereo),
+                    Err(_) => stereo.fill(0.0),
+                }
+                for (frame, pair) in data.chunks_exact_mut(speakers).zip(stereo.chunks_exact(2)) {
+                    match frame {
+                        [mono] => *mono = T::from_sample((pair[0] + pair[1]) / 2.0),
+                        [left, right, rest @ ..] => {
+                            (*left, *right) = (T::from_sample(pair[0]), T::from_sample(pair[1]));
+                            rest.fill(T::EQUILIBRIUM);
+                        }
+                        [] => (),
+                    }
+                }
+            },
+            |e| eprintln!("[audio] Output stream error: {}", e),
+            None,
+        )
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "audio")]
+impl AudioBackend for CpalAudio {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // the sound card asks for sound as it needs it
+    fn tick(&mut self, _mixer: &SharedMixer, _dt: f64) {}
+}
+
+/// The sound card when built with the `audio` feature and there is one, otherwise nowhere.
+#[cfg_attr(not(feature = "audio"), allow(unused_variables))]
+pub fn default_backend(mixer: &SharedMixer) -> Box<dyn AudioBackend> {
+    #[cfg(feature = "audio")]
+    match CpalAudio::new(mixer) {
+        Ok(audio) => return Box::new(audio),
+        Err(e) => eprintln!(
+            "[audio] Couldn't open a sound card, so nothing will be heard: {}",
+            e
+        ),
+    }
+    Box::new(NullAudio::new())
+}
+
+pub struct Audio {
+    mixer: SharedMixer,
+    backend: Box<dyn AudioBackend>,
+    /// Decoded sounds, by where they were loaded from
+    sounds: HashMap<PathBuf, Arc<Sound>>,
+}
+
+impl Audio {
+    /// Mix sound for the backend `open` opens, e.g. `default_backend` or `NullAudio`.
+    pub fn new(open: fn(&SharedMixer) -> Box<dyn AudioBackend>) -> Self {
+        let mixer = Arc::new(Mutex::new(Mixer::new(SAMPLE_RATE)));
+        let backend = open(&mixer);
+        if let Ok(mut mixer) = mixer.lock() {
+            mixer.set_sample_rate(backend.sample_rate());
+        }
+        Audio {
+            mixer,
+            backend,
+            sounds: HashMap::new(),
+        }
+    }
+
+    fn sound(&mut self, path: PathBuf) -> Result<Arc<Sound>, String> {
+        if let Some(sound) = self.sounds.get(&path) {
+            return Ok(sound.clone());
+        }
+        let sound = Arc::new(Sound::load(&path)?);
+        self.sounds.insert(path, sound.clone());
+        Ok(sound)
+    }
+
+    fn with_mixer<T>(&self, f: impl FnOnce(&mut Mixer) -> T) -> Result<T, LuaError> {
+        let mut mixer = self
+            .mixer
+            .lock()
+            .map_err(|_| LuaError::InitializationError("Audio mixer poisoned".to_string()))?;
+        Ok(f(&mut mixer))
+    }
+
+    /// Stop everything playing, e.g. because another scene has been loaded.
+    pub fn stop_all(&self) -> Result<(), LuaError> {
+        self.with_mixer(|mixer| mixer.stop_all())
+    }
+}
+
+pub fn audio_mut(lua: &Lua) -> Result<AppDataRefMut<'_, Audio>, LuaError> {
+    lua.app_data_mut::<Audio>()
+        .ok_or_else(|| LuaError::InitializationError("Audio missing".to_string()))
+}
+
+/// Let the backend play the last tick's worth of sound.
+pub fn advance_audio(lua: &Lua, dt: f64) -> Result<(), LuaError> {
+    let mut audio = audio_mut(lua)?;
+    let Audio { mixer, backend, .. } = &mut *audio;
+    backend.tick(mixer, dt);
+    Ok(())
+}
+
+/// Start a sound from script arguments, as music or not.
+fn play(lua: &Lua, name: String, options: Option<LuaTable>, music: bool) -> LuaResult<u64> {
+    let (volume, looping) = match options {
+        Some(options) => (
+            options.get::<_, Option<f32>>("volume")?.unwrap_or(1.0),
+            options.get::<_, Option<bool>>("loop")?.unwrap_or(music),
+        ),
+        None => (1.0, music),
+    };
+    let path = assets_mut(lua)?.resolve(&name);
+    let mut audio = audio_mut(lua)?;
+    let sound = audio
+        .sound(path)
+        .map_err(|e| LuaError::FormatError(format!("Couldn't load sound \"{}\": {}", name, e)))?;
+    Ok(audio.with_mixer(|mixer| match music {
+        true => mixer.play_music(sound, volume, looping),
+        false => mixer.play(sound, volume, looping),
+    })?)
+}
+
+pub fn set_globals(
+    lua: &Lua,
+    open: fn(&SharedMixer) -> Box<dyn AudioBackend>,
+) -> Result<(), LuaError> {
+    lua.set_app_data(Audio::new(open));
+
+    let sound = lua.create_table()?;
+    sound.set(
+        "play",
+        lua.create_function(|l: &Lua, (name, options): (String, Option<LuaTable>)| {
+            play(l, name, options, false)
+        })?,
+    )?;
+    sound.set(
+        "music",
+        lua.create_function(|l: &Lua, (name, options): (String, Option<LuaTable>)| {
+            play(l, name, options, true)
+        })?,
+    )?;
+    sound.set(
+        "stop",
+        lua.create_function(|l: &Lua, handle: u64| {
+            Ok(audio_mut(l)?.with_mixer(|mixer| mixer.stop(handle))?)
+        })?,
+    )?;
+    sound.set(
+        "stop_music",
+        lua.create_function(|l: &Lua, ()| {
+            Ok(audio_mut(l)?.with_mixer(|mixer| mixer.stop_music())?)
+        })?,
+    )?;
+    sound.set(
+        "stop_all",
+        lua.create_function(|l: &Lua, ()| Ok(audio_mut(l)?.stop_all()?))?,
+    )?;
+    sound.set(
+        "is_playing",
+        lua.create_function(|l: &Lua, handle: u64| {
+            Ok(audio_mut(l)?.with_mixer(|mixer| mixer.is_playing(handle))?)
+        })?,
+    )?;
+
+    lua.globals()
+        .set("sound", sound)
+        .map_err(|e| LuaError::InitializationError(format!("Failed to set sound: {}", e)))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn beep(frames: usize, sample_rate: u32) -> Arc<Sound> {
+        Arc::new(Sound::from_interleaved(&vec![0.5; frames], 1, sample_rate))
+    }
+
+    #[test]
+    fn sounds_finish_unless_looping() {
+        let mut mixer = Mixer::new(100);
+        let once = mixer.play(beep(10, 100), 1.0, false);
+        let looping = mixer.play(beep(10, 100), 0.5, true);
+
+        let mut out = vec![0.0; 30];
+        mixer.mix(&mut out);
+        assert_eq!(out[0..2], [0.75, 0.75]);
+        assert_eq!(out[20..22], [0.25, 0.25]);
+        assert!(!mixer.is_playing(once));
+        assert!(mixer.is_playing(looping));
+
+        mixer.stop(looping);
+        mixer.mix(&mut out);
+        assert!(out.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn music_replaces_music() {
+        let mut mixer = Mixer::new(100);
+        let sound = mixer.play(beep(10, 100), 1.0, true);
+        let first = mixer.play_music(beep(10, 100), 1.0, true);
+        let second = mixer.play_music(beep(10, 100), 1.0, true);
+        assert!(mixer.is_playing(sound));
+        assert!(!mixer.is_playing(first));
+        assert!(mixer.is_playing(second));
+
+        mixer.stop_music();
+        assert!(mixer.is_playing(sound));
+        assert!(!mixer.is_playing(second));
+    }
+
+    #[test]
+    fn file_records_what_plays() {
+        let path = std::env::temp_dir().join(format!("stacks-audio-{}.wav", std::process::id()));
+        let mixer: SharedMixer = Arc::new(Mutex::new(Mixer::new(SAMPLE_RATE)));
+        // recorded at half the rate, so it lasts twice as many output frames
+        let handle = mixer
+            .lock()
+            .unwrap()
+            .play(beep(441, SAMPLE_RATE / 2), 1.0, false);
+
+        let mut file = FileAudio::create(&path).unwrap();
+        file.tick(&mixer, 0.01);
+        assert!(mixer.lock().unwrap().is_playing(handle));
+        file.tick(&mixer, 0.01);
+        assert!(!mixer.lock().unwrap().is_playing(handle));
+        drop(file);
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.duration(), 882);
+        let samples: Vec<f32> = reader.samples::<f32>().map(Result::unwrap).collect();
+        assert!(samples.iter().all(|&s| s == 0.5));
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,7 @@
+//! A language server for stacks scripts, for editors to start. See `stacks-lsp --help`.
+
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    stacks_lib::run_language_server()
+}
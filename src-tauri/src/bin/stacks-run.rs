@@ -0,0 +1,7 @@
+//! Runs a scene without a window, for tests & scripts. See `stacks-run --help`.
+
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    stacks_lib::run_headless()
+}
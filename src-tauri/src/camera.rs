@@ -0,0 +1,502 @@
+use crate::geometry::Vec2;
+use crate::lua_setup::get_scene;
+use crate::lua_types::LuaError;
+use mlua::prelude::*;
+use mlua::AppDataRefMut;
+use serde::{Deserialize, Serialize};
+
+/// Size of the scene's canvas, in pixels, unless the scene says otherwise.
+const DEFAULT_CANVAS: Canvas = Canvas {
+    width: 1280.0,
+    height: 720.0,
+};
+
+/// The area of the world the scene window shows, which is the same shape as the window.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Canvas {
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        DEFAULT_CANVAS
+    }
+}
+
+impl Canvas {
+    /// Reject a size that scripts couldn't have set, for canvases read from a scene file.
+    pub fn checked(self) -> Result<Self, LuaError> {
+        above_zero("Canvas width", self.width)?;
+        above_zero("Canvas height", self.height)?;
+        Ok(self)
+    }
+}
+
+/// A rectangle of the world the camera keeps its view inside.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Bounds {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// What part of the world is shown on the canvas. Saved with the scene.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Camera {
+    /// The point shown in the middle of the canvas, which is the middle of the world until set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    x: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    y: Option<f64>,
+    zoom: f64,
+    /// In degrees, like an entity's `rotation`
+    rotation: f64,
+    /// Entity kept in the middle of the canvas
+    #[serde(skip_serializing_if = "Option::is_none")]
+    follow: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bounds: Option<Bounds>,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            x: None,
+            y: None,
+            zoom: 1.0,
+            rotation: 0.0,
+            follow: None,
+            bounds: None,
+        }
+    }
+}
+
+/// What the frontend needs to draw the scene through the camera.
+#[derive(Serialize)]
+pub struct View {
+    pub camera: ViewCamera,
+    pub canvas: Canvas,
+}
+
+#[derive(Serialize)]
+pub struct ViewCamera {
+    pub x: f64,
+    pub y: f64,
+    pub zoom: f64,
+    pub rotation: f64,
+}
+
+impl Camera {
+    /// Reject a zoom that scripts couldn't have set, for cameras read from a scene file.
+    pub fn checked(self) -> Result<Self, LuaError> {
+        above_zero("Camera zoom", self.zoom)?;
+        Ok(self)
+    }
+
+    fn centre(&self, canvas: Canvas) -> Vec2 {
+        Vec2::new(
+            self.x.unwrap_or(canvas.width / 2.0),
+            self.y.unwrap_or(canvas.height / 2.0),
+        )
+    }
+
+    /// Where a point on the canvas is in the world.
+    pub fn to_world(&self, canvas: Canvas, point: Vec2) -> Vec2 {
+        self.centre(canvas)
+            + (point - Vec2::new(canvas.width, canvas.height) * 0.5)
+                .rotated(self.rotation.to_radians())
+                * (1.0 / self.zoom)
+    }
+
+    /// Move to `target` if following something, then back inside the bounds if there are any.
+    fn update(&mut self, canvas: Canvas, target: Option<Vec2>) {
+        let mut centre = target.unwrap_or_else(|| self.centre(canvas));
+        if let Some(bounds) = self.bounds {
+            // rotation is ignored, so a rotated camera can peek a little past its bounds
+            let half = Vec2::new(canvas.width, canvas.height) * (0.5 / self.zoom);
+            centre.x = clamp_axis(centre.x, half.x, bounds.x, bounds.width);
+            centre.y = clamp_axis(centre.y, half.y, bounds.y, bounds.height);
+        }
+        if target.is_some() || self.bounds.is_some() {
+            self.x = Some(centre.x);
+            self.y = Some(centre.y);
+        }
+    }
+
+    pub fn rename_entity(&mut self, from: &str, to: &str) {
+        if self.follow.as_deref() == Some(from) {
+            self.follow = Some(to.to_string());
+        }
+    }
+
+    pub fn view(&self, canvas: Canvas) -> View {
+        let centre = self.centre(canvas);
+        View {
+            camera: ViewCamera {
+                x: centre.x,
+                y: centre.y,
+                zoom: self.zoom,
+                rotation: self.rotation,
+            },
+            canvas,
+        }
+    }
+}
+
+/// Keep a view `half` wide either side of `centre` between `start` & `start + length`,
+/// or in the middle if it's too big to fit.
+fn clamp_axis(centre: f64, half: f64, start: f64, length: f64) -> f64 {
+    if length <= half * 2.0 {
+        start + length / 2.0
+    } else {
+        centre.clamp(start + half, start + length - half)
+    }
+}
+
+pub fn camera_mut(lua: &Lua) -> Result<AppDataRefMut<'_, Camera>, LuaError> {
+    lua.app_data_mut::<Camera>()
+        .ok_or_else(|| LuaError::InitializationError("Camera missing".to_string()))
+}
+
+pub fn canvas_mut(lua: &Lua) -> Result<AppDataRefMut<'_, Canvas>, LuaError> {
+    lua.app_data_mut::<Canvas>()
+        .ok_or_else(|| LuaError::InitializationError("Canvas missing".to_string()))
+}
+
+/// Middle of an entity, or just its position if it has no size.
+fn entity_centre(entity: &LuaTable) -> LuaResult<Vec2> {
+    let pos: LuaTable = entity.get("pos")?;
+    let mut centre = Vec2::new(pos.get("x")?, pos.get("y")?);
+    if let Some(size) = entity.get::<_, Option<LuaTable>>("size")? {
+        centre = centre
+            + Vec2::new(
+                size.get::<_, Option<f64>>("width")?.unwrap_or(0.0),
+                size.get::<_, Option<f64>>("height")?.unwrap_or(0.0),
+            ) * 0.5;
+    }
+    Ok(centre)
+}
+
+/// Move the camera after scripts have moved everything else, returning what the frontend should show.
+pub fn update_camera(lua: &Lua) -> Result<View, LuaError> {
+    let follow = camera_mut(lua)?.follow.clone();
+    // like a broken collider, following an entity that's gone or has no position just doesn't move the camera
+    let target = match follow {
+        Some(id) => get_scene(lua)?
+            .get::<_, LuaTable>("entities")?
+            .get::<_, Option<LuaTable>>(id)?
+            .and_then(|entity| entity_centre(&entity).ok()),
+        None => None,
+    };
+    let canvas = *canvas_mut(lua)?;
+    let mut camera = camera_mut(lua)?;
+    camera.update(canvas, target);
+    Ok(camera.view(canvas))
+}
+
+/// A table scripts read & set properties on, which are kept in app data so they survive
+/// the scene being reloaded, and can still be tweened like an entity's.
+fn proxy_table<'lua>(
+    lua: &'lua Lua,
+    get: impl Fn(&'lua Lua, String) -> LuaResult<LuaValue<'lua>> + 'static,
+    set: impl Fn(&'lua Lua, String, LuaValue<'lua>) -> LuaResult<()> + 'static,
+) -> LuaResult<LuaTable<'lua>> {
+    let metatable = lua.create_table()?;
+    metatable.set(
+        "__index",
+        lua.create_function(move |l, (_, key): (LuaTable, String)| get(l, key))?,
+    )?;
+    metatable.set(
+        "__newindex",
+        lua.create_function(move |l, (_, key, value): (LuaTable, String, LuaValue)| {
+            set(l, key, value)
+        })?,
+    )?;
+    let table = lua.create_table()?;
+    table.set_metatable(Some(metatable));
+    Ok(table)
+}
+
+fn positive(name: &str, value: LuaValue) -> LuaResult<f64> {
+    let number = match value {
+        LuaValue::Integer(n) => n as f64,
+        LuaValue::Number(n) => n,
+        _ => {
+            return Err(LuaError::FormatError(format!(
+                "{} must be a number, not {}.",
+                name,
+                value.type_name()
+            ))
+            .into())
+        }
+    };
+    Ok(above_zero(name, number)?)
+}
+
+/// Infinity is rejected too, since an infinite zoom or canvas shows nothing.
+fn above_zero(name: &str, number: f64) -> Result<f64, LuaError> {
+    if !(number.is_finite() && number > 0.0) {
+        return Err(LuaError::FormatError(format!(
+            "{} must be above 0, not {}.",
+            name, number
+        )));
+    }
+    Ok(number)
+}
+
+fn camera_get<'lua>(lua: &'lua Lua, key: String) -> LuaResult<LuaValue<'lua>> {
+    let canvas = *canvas_mut(lua)?;
+    let camera = camera_mut(lua)?;
+    Ok(match key.as_str() {
+        "x" => camera.centre(canvas).x.into_lua(lua)?,
+        "y" => camera.centre(canvas).y.into_lua(lua)?,
+        "zoom" => camera.zoom.into_lua(lua)?,
+        "rotation" => camera.rotation.into_lua(lua)?,
+        "follow" => camera.follow.clone().into_lua(lua)?,
+        "bounds" => match camera.bounds {
+            Some(bounds) => lua.to_value(&bounds)?,
+            None => LuaNil,
+        },
+        _ => LuaNil,
+    })
+}
+
+fn camera_set<'lua>(lua: &'lua Lua, key: String, value: LuaValue<'lua>) -> LuaResult<()> {
+    match key.as_str() {
+        "x" => camera_mut(lua)?.x = lua.unpack(value)?,
+        "y" => camera_mut(lua)?.y = lua.unpack(value)?,
+        "zoom" => camera_mut(lua)?.zoom = positive("Camera zoom", value)?,
+        "rotation" => camera_mut(lua)?.rotation = lua.unpack(value)?,
+        "follow" => camera_mut(lua)?.follow = lua.unpack(value)?,
+        "bounds" => {
+            camera_mut(lua)?.bounds = match value {
+                LuaNil => None,
+                value => Some(lua.from_value(value).map_err(|_| {
+                    LuaError::FormatError(
+                        "Camera bounds must be a table like { x = 0, y = 0, width = 2560, height = 720 }."
+                            .to_string(),
+                    )
+                })?),
+            }
+        }
+        _ => {
+            return Err(LuaError::FormatError(format!(
+                "The camera has no \"{}\". Try x, y, zoom, rotation, follow or bounds.",
+                key
+            ))
+            .into())
+        }
+    }
+    Ok(())
+}
+
+fn canvas_get<'lua>(lua: &'lua Lua, key: String) -> LuaResult<LuaValue<'lua>> {
+    let canvas = *canvas_mut(lua)?;
+    Ok(match key.as_str() {
+        "width" => canvas.width.into_lua(lua)?,
+        "height" => canvas.height.into_lua(lua)?,
+        _ => LuaNil,
+    })
+}
+
+fn canvas_set<'lua>(lua: &'lua Lua, key: String, value: LuaValue<'lua>) -> LuaResult<()> {
+    match key.as_str() {
+        "width" => canvas_mut(lua)?.width = positive("Canvas width", value)?,
+        "height" => canvas_mut(lua)?.height = positive("Canvas height", value)?,
+        _ => {
+            return Err(LuaError::FormatError(format!(
+                "The canvas has no \"{}\". Try width or height.",
+                key
+            ))
+            .into())
+        }
+    }
+    Ok(())
+}
+
+pub fn set_globals(lua: &Lua) -> Result<(), LuaError> {
+    lua.set_app_data(Camera::default());
+    lua.set_app_data(Canvas::default());
+
+    lua.globals()
+        .set("camera", proxy_table(lua, camera_get, camera_set)?)
+        .map_err(|e| LuaError::InitializationError(format!("Failed to set camera: {}", e)))?;
+
+    lua.globals()
+        .set("canvas", proxy_table(lua, canvas_get, canvas_set)?)
+        .map_err(|e| LuaError::InitializationError(format!("Failed to set canvas: {}", e)))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lua_setup::match_message;
+    use crate::lua_types::LuaMessage;
+    use crate::runner::{headless_lua, LUA_DIR};
+    use crate::testing::Recorder;
+    use serde_json::Value;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::{mpsc, Arc};
+
+    const CANVAS: Canvas = Canvas {
+        width: 200.0,
+        height: 100.0,
+    };
+
+    fn assert_near(actual: Vec2, expected: Vec2) {
+        assert!(
+            (actual - expected).length() < 1e-9,
+            "expected {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    fn lua() -> Lua {
+        headless_lua(Arc::new(Recorder::new(Path::new(LUA_DIR)))).unwrap()
+    }
+
+    fn centre(lua: &Lua) -> (f64, f64) {
+        lua.load("return camera.x, camera.y").eval().unwrap()
+    }
+
+    #[test]
+    fn canvas_points_to_the_world() {
+        let mut camera = Camera::default();
+        // the middle of the world until it's moved
+        assert_near(
+            camera.to_world(CANVAS, Vec2::new(20.0, 10.0)),
+            Vec2::new(20.0, 10.0),
+        );
+
+        camera.x = Some(500.0);
+        camera.y = Some(300.0);
+        camera.zoom = 2.0;
+        assert_near(
+            camera.to_world(CANVAS, Vec2::new(100.0, 50.0)),
+            Vec2::new(500.0, 300.0),
+        );
+        assert_near(
+            camera.to_world(CANVAS, Vec2::new(200.0, 100.0)),
+            Vec2::new(550.0, 325.0),
+        );
+
+        camera.rotation = 90.0;
+        // the right edge of the canvas is below the middle when the camera's turned a quarter
+        assert_near(
+            camera.to_world(CANVAS, Vec2::new(200.0, 50.0)),
+            Vec2::new(500.0, 350.0),
+        );
+        assert_near(
+            camera.to_world(CANVAS, Vec2::new(100.0, 0.0)),
+            Vec2::new(525.0, 300.0),
+        );
+    }
+
+    #[test]
+    fn bounds_keep_the_view_inside() {
+        assert_eq!(clamp_axis(50.0, 100.0, 0.0, 1000.0), 100.0);
+        assert_eq!(clamp_axis(500.0, 100.0, 0.0, 1000.0), 500.0);
+        assert_eq!(clamp_axis(990.0, 100.0, 0.0, 1000.0), 900.0);
+        // too small to fit the view, so it stays in the middle of them
+        assert_eq!(clamp_axis(990.0, 100.0, 400.0, 150.0), 475.0);
+        assert_eq!(clamp_axis(0.0, 100.0, 400.0, 200.0), 500.0);
+
+        let mut camera = Camera {
+            bounds: Some(Bounds {
+                x: 0.0,
+                y: 0.0,
+                width: 1000.0,
+                height: 80.0,
+            }),
+            ..Camera::default()
+        };
+        camera.update(CANVAS, Some(Vec2::new(-50.0, 500.0)));
+        assert_eq!((camera.x, camera.y), (Some(100.0), Some(40.0)));
+        // zoomed in, the view is half the size, so it can get closer to the edges
+        camera.zoom = 2.0;
+        camera.update(CANVAS, Some(Vec2::new(-50.0, 500.0)));
+        assert_eq!((camera.x, camera.y), (Some(50.0), Some(55.0)));
+    }
+
+    #[test]
+    fn following_entities_that_are_deleted_or_renamed() {
+        let lua = lua();
+        lua.load(
+            r#"
+            currentScene:add_entity("player", {
+                type = "rect", pos = { x = 90, y = 40 }, size = { width = 20, height = 20 },
+            })
+            camera.follow = "player"
+            "#,
+        )
+        .exec()
+        .unwrap();
+        let tick = || match_message(&lua, LuaMessage::Tick(1.0 / 60.0)).unwrap();
+        tick();
+        assert_eq!(centre(&lua), (100.0, 50.0));
+
+        match_message(
+            &lua,
+            LuaMessage::UpdateEntityId("player".to_string(), "hero".to_string(), Value::Null),
+        )
+        .unwrap();
+        lua.load("currentScene.entities.hero.pos.x = 190")
+            .exec()
+            .unwrap();
+        tick();
+        assert_eq!(centre(&lua), (200.0, 50.0));
+
+        // once it's gone, the camera stays where it was
+        match_message(&lua, LuaMessage::DeleteEntity("hero".to_string())).unwrap();
+        tick();
+        assert_eq!(centre(&lua), (200.0, 50.0));
+    }
+
+    #[test]
+    fn saved_cameras_need_a_zoom_above_0() {
+        let lua = lua();
+        for (camera, loaded) in [
+            ("{zoom=2,rotation=0}", true),
+            ("{zoom=0,rotation=0}", false),
+            ("{zoom=-1,rotation=0}", false),
+        ] {
+            let path =
+                std::env::temp_dir().join(format!("stacks-camera-{}.lua", std::process::id()));
+            fs::write(
+                &path,
+                format!(
+                    "do local _={{version=2,entities={{}},camera={}}};return _;end",
+                    camera
+                ),
+            )
+            .unwrap();
+            let (tx, rx) = mpsc::channel();
+            match_message(
+                &lua,
+                LuaMessage::LoadScene(path.to_string_lossy().to_string(), tx),
+            )
+            .unwrap();
+            let _ = fs::remove_file(&path);
+            let (success, message) = rx.recv().unwrap();
+            assert_eq!(success, loaded, "{}: {}", camera, message);
+            if !loaded {
+                assert!(
+                    message.contains("Camera zoom must be above 0"),
+                    "{}",
+                    message
+                );
+            }
+        }
+        // the last scene that loaded is still being looked at the same way
+        assert_eq!(camera_mut(&lua).unwrap().zoom, 2.0);
+
+        assert!(lua.load("camera.zoom = 0/0").exec().is_err());
+    }
+}
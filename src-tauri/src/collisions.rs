@@ -0,0 +1,301 @@
+use crate::geometry::{Shape, Vec2};
+use crate::lua_setup::get_scene;
+use crate::lua_types::LuaError;
+use crate::script_runner::run_listener;
+use crate::tilemaps::tile_shapes;
+use mlua::prelude::*;
+use mlua::AppDataRefMut;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Size of the broad-phase grid's cells, in scene pixels.
+/// Around the size of a typical entity, so most only land in a few cells.
+const CELL_SIZE: f64 = 128.0;
+
+/// Most cells a shape is put in. Bigger shapes, like a floor as wide as the level,
+/// are checked against every other shape instead.
+const MAX_CELLS: f64 = 1024.0;
+
+/// Buckets shapes by the grid cells their bounds cover,
+/// so only shapes sharing a cell need checking against each other.
+pub struct SpatialGrid {
+    cells: HashMap<(i64, i64), Vec<usize>>,
+    /// Shapes too big for the grid
+    oversized: Vec<usize>,
+}
+
+impl SpatialGrid {
+    /// Shapes with bounds that aren't finite (e.g. `size = math.huge`) can't touch anything, and are left out.
+    pub fn new(shapes: &[Shape]) -> Self {
+        let mut cells: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        let mut oversized = Vec::new();
+        for (index, shape) in shapes.iter().enumerate() {
+            let (min, max) = shape.bounds();
+            if ![min.x, min.y, max.x, max.y].iter().all(|v| v.is_finite()) {
+                continue;
+            }
+            let cell = |v: f64| (v / CELL_SIZE).floor();
+            let columns = cell(max.x) - cell(min.x) + 1.0;
+            let rows = cell(max.y) - cell(min.y) + 1.0;
+            if columns * rows > MAX_CELLS {
+                oversized.push(index);
+                continue;
+            }
+            for x in cell(min.x) as i64..=cell(max.x) as i64 {
+                for y in cell(min.y) as i64..=cell(max.y) as i64 {
+                    cells.entry((x, y)).or_default().push(index);
+                }
+            }
+        }
+        SpatialGrid { cells, oversized }
+    }
+
+    /// Pairs of shapes that might overlap, each once with the lower index first.
+    pub fn candidate_pairs(&self) -> HashSet<(usize, usize)> {
+        let mut pairs = HashSet::new();
+        for indices in self.cells.values() {
+            for (i, &a) in indices.iter().enumerate() {
+                for &b in &indices[i + 1..] {
+                    pairs.insert((a.min(b), a.max(b)));
+                }
+            }
+        }
+        let in_grid: HashSet<usize> = self.cells.values().flatten().copied().collect();
+        for &a in &self.oversized {
+            for &b in in_grid.iter().chain(&self.oversized) {
+                if a != b {
+                    pairs.insert((a.min(b), a.max(b)));
+                }
+            }
+        }
+        pairs
+    }
+}
+
+/// Entity pairs touching as of the last tick, each with the lower ID first.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Collisions {
+    contacts: BTreeSet<(String, String)>,
+}
+
+impl Collisions {
+    pub fn new() -> Self {
+        Collisions {
+            contacts: BTreeSet::new(),
+        }
+    }
+
+    /// IDs of the entities touching the given one.
+    pub fn touching(&self, id: &str) -> Vec<String> {
+        self.contacts
+            .iter()
+            .filter_map(|(a, b)| match (a == id, b == id) {
+                (true, _) => Some(b.clone()),
+                (_, true) => Some(a.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn rename_entity(&mut self, from: &str, to: &str) {
+        self.contacts = std::mem::take(&mut self.contacts)
+            .into_iter()
+            .map(|(a, b)| {
+                let rename = |id: String| if id == from { to.to_string() } else { id };
+                let (a, b) = (rename(a), rename(b));
+                if a < b {
+                    (a, b)
+                } else {
+                    (b, a)
+                }
+            })
+            .collect();
+    }
+
+    pub fn clear(&mut self) {
+        self.contacts.clear();
+    }
+}
+
+pub fn collisions_mut(lua: &Lua) -> Result<AppDataRefMut<'_, Collisions>, LuaError> {
+    lua.app_data_mut::<Collisions>()
+        .ok_or_else(|| LuaError::InitializationError("Collisions missing".to_string()))
+}
+
+/// The shape an entity collides as, if it has opted in with a `collider`:
+/// `"rect"` (or `true`) for its box, or `"circle"` for the biggest circle that fits in it.
+/// A table like `{ shape = "circle", radius = 10 }` sets the circle's radius.
+pub fn collider_shape(entity: &LuaTable) -> LuaResult<Option<Shape>> {
+    let (shape, radius) = match entity.get::<_, LuaValue>("collider")? {
+        LuaValue::Boolean(true) => ("rect".to_string(), None),
+        LuaValue::String(shape) => (shape.to_str()?.to_string(), None),
+        LuaValue::Table(collider) => (
+            collider
+                .get::<_, Option<String>>("shape")?
+                .unwrap_or_else(|| "rect".to_string()),
+            collider.get::<_, Option<f64>>("radius")?,
+        ),
+        _ => return Ok(None),
+    };
+
+    let pos: LuaTable = entity.get("pos")?;
+    let size = match entity.get::<_, Option<LuaTable>>("size")? {
+        Some(size) => Vec2::new(size.get("width")?, size.get("height")?),
+        None => Vec2::default(),
+    };
+    let center = Vec2::new(pos.get("x")?, pos.get("y")?) + size * 0.5;
+
+    Ok(match shape.as_str() {
+        // rotated around the center, like the scene window draws it
+        "rect" => Some(Shape::Rect {
+            center,
+            half_size: size * 0.5,
+            angle: entity
+                .get::<_, Option<f64>>("rotation")?
+                .unwrap_or(0.0)
+                .to_radians(),
+        }),
+        "circle" => Some(Shape::Circle {
+            center,
+            radius: radius.unwrap_or(size.x.min(size.y) / 2.0),
+        }),
+        _ => None,
+    })
+}
+
+/// The shapes an entity collides as: one for most entities, but one for each run of solid tiles in a tilemap.
+pub fn collider_shapes(entity: &LuaTable) -> LuaResult<Vec<Shape>> {
+    match entity.get::<_, Option<String>>("type")?.as_deref() {
+        Some("tilemap") => tile_shapes(entity),
+        _ => Ok(collider_shape(entity)?.into_iter().collect()),
+    }
+}
+
+/// Find which colliders overlap, and run the collision scripts for what changed since the last tick.
+pub fn detect_collisions(lua: &Lua) -> Result<(), LuaError> {
+    let mut ids = Vec::new();
+    let mut shapes = Vec::new();
+    for pair in get_scene(lua)?
+        .get::<_, LuaTable>("entities")?
+        .pairs::<String, LuaTable>()
+    {
+        let (id, entity) = pair?;
+        // an entity with a broken collider just doesn't collide
+        if let Ok(entity_shapes) = collider_shapes(&entity) {
+            ids.extend(std::iter::repeat_n(id, entity_shapes.len()));
+            shapes.extend(entity_shapes);
+        }
+    }
+
+    let contacts: BTreeSet<(String, String)> = SpatialGrid::new(&shapes)
+        .candidate_pairs()
+        .into_iter()
+        // a tilemap's tiles don't collide with each other
+        .filter(|&(a, b)| ids[a] != ids[b] && shapes[a].overlaps(&shapes[b]))
+        .map(|(a, b)| {
+            let (a, b) = (ids[a].clone(), ids[b].clone());
+            if a < b {
+                (a, b)
+            } else {
+                (b, a)
+            }
+        })
+        .collect();
+
+    let previous = std::mem::replace(&mut collisions_mut(lua)?.contacts, contacts.clone());
+    let events = [
+        (
+            "on_collision_enter",
+            contacts.difference(&previous).collect(),
+        ),
+        (
+            "on_collision_stay",
+            contacts.intersection(&previous).collect(),
+        ),
+        (
+            "on_collision_exit",
+            previous.difference(&contacts).collect::<Vec<_>>(),
+        ),
+    ];
+    for (event, pairs) in events {
+        for (a, b) in pairs {
+            for (id, other) in [(a, b), (b, a)] {
+                let data = lua.create_table()?;
+                data.set("other", other.as_str())?;
+                run_listener(lua, id, event, LuaValue::Table(data))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn set_globals(lua: &Lua) -> Result<(), LuaError> {
+    lua.set_app_data(Collisions::new());
+
+    lua.globals()
+        .set(
+            "touching",
+            lua.create_function(|l: &Lua, id: String| Ok(collisions_mut(l)?.touching(&id)))?,
+        )
+        .map_err(|e| LuaError::InitializationError(format!("Failed to set touching: {}", e)))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: f64, y: f64, size: f64) -> Shape {
+        Shape::Rect {
+            center: Vec2::new(x, y),
+            half_size: Vec2::new(size / 2.0, size / 2.0),
+            angle: 0.0,
+        }
+    }
+
+    fn pairs(shapes: &[Shape]) -> BTreeSet<(usize, usize)> {
+        SpatialGrid::new(shapes)
+            .candidate_pairs()
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn only_pairs_shapes_in_the_same_cells() {
+        let shapes = [
+            square(0.0, 0.0, 10.0),
+            square(20.0, 0.0, 10.0),
+            square(1000.0, 0.0, 10.0),
+        ];
+        assert_eq!(pairs(&shapes), BTreeSet::from([(0, 1)]));
+    }
+
+    #[test]
+    fn pairs_huge_shapes_with_everything() {
+        let shapes = [
+            square(0.0, 0.0, 10.0),
+            square(1e12, 0.0, 10.0),
+            square(0.0, 0.0, 1e15),
+            square(0.0, 0.0, 1e15),
+        ];
+        assert_eq!(
+            pairs(&shapes),
+            BTreeSet::from([(0, 2), (0, 3), (1, 2), (1, 3), (2, 3)])
+        );
+    }
+
+    #[test]
+    fn leaves_out_shapes_that_arent_finite() {
+        let shapes = [
+            square(0.0, 0.0, 10.0),
+            square(0.0, 0.0, f64::INFINITY),
+            square(f64::NAN, 0.0, 10.0),
+            Shape::Circle {
+                center: Vec2::new(0.0, 0.0),
+                radius: f64::INFINITY,
+            },
+        ];
+        assert_eq!(pairs(&shapes), BTreeSet::new());
+    }
+}
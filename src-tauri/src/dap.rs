@@ -0,0 +1,422 @@
+use crate::debugger::{DebugCommand, DebugEvent, Debugger, PauseReason};
+use crate::scripts::{parse_chunk_name, script_file_name};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// The only thread debug adapters see, since every script runs on the Lua thread.
+const THREAD_ID: i64 = 1;
+
+/// Listen for editors speaking the Debug Adapter Protocol, one at a time, on localhost only.
+/// Only if `STACKS_DAP_PORT` asks for it, since the debugger can run any code in the scene.
+/// Editors have to give the session's token when they attach, so web pages can't.
+pub fn serve(debugger: Debugger) {
+    let Some(port) = env::var("STACKS_DAP_PORT")
+        .ok()
+        .and_then(|port| port.parse::<u16>().ok())
+    else {
+        return;
+    };
+    let token = match env::var("STACKS_DAP_TOKEN") {
+        Ok(token) => token,
+        Err(_) => match new_token() {
+            Ok(token) => token,
+            Err(e) => {
+                eprintln!("[debugger] Couldn't make a token for editors: {}", e);
+                return;
+            }
+        },
+    };
+    let _ = thread::Builder::new()
+        .name("Debug Adapter".to_string())
+        .spawn(move || {
+            let listener = match TcpListener::bind(("127.0.0.1", port)) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    eprintln!(
+                        "[debugger] Couldn't listen for editors on port {}: {}",
+                        port, e
+                    );
+                    return;
+                }
+            };
+            eprintln!(
+                "[debugger] Listening for editors on port {}, attach with the token {}",
+                port, token
+            );
+            for stream in listener.incoming().flatten() {
+                let client = Client::new(debugger.clone(), token.clone(), stream);
+                if let Err(e) = client.and_then(Client::run) {
+                    eprintln!("[debugger] {}", e);
+                }
+            }
+        });
+}
+
+/// Sends numbered messages to an editor, from the client & the thread forwarding pauses.
+struct Writer {
+    stream: Mutex<(TcpStream, i64)>,
+}
+
+impl Writer {
+    fn send(&self, mut message: Value) -> io::Result<()> {
+        let mut stream = self.stream.lock().unwrap_or_else(|e| e.into_inner());
+        stream.1 += 1;
+        message["seq"] = json!(stream.1);
+        let body = message.to_string();
+        write!(stream.0, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        stream.0.flush()
+    }
+
+    fn event(&self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn respond(&self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+}
+
+/// A token for a debugging session, from the operating system's secure random numbers.
+fn new_token() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(|e| io::Error::other(e.to_string()))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// What the debugger supports, which is all an editor is told before it gives the token.
+fn capabilities() -> Value {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsEvaluateForHovers": true,
+    })
+}
+
+struct Client {
+    debugger: Debugger,
+    token: String,
+    /// Whether the editor has given the token yet
+    attached: bool,
+    reader: BufReader<TcpStream>,
+    writer: Arc<Writer>,
+    /// Scripts this editor set breakpoints in, which are cleared when it goes
+    scripts: HashSet<(String, String)>,
+}
+
+impl Client {
+    fn new(debugger: Debugger, token: String, stream: TcpStream) -> io::Result<Self> {
+        Ok(Client {
+            debugger,
+            token,
+            attached: false,
+            reader: BufReader::new(stream.try_clone()?),
+            writer: Arc::new(Writer {
+                stream: Mutex::new((stream, 0)),
+            }),
+            scripts: HashSet::new(),
+        })
+    }
+
+    fn run(mut self) -> io::Result<()> {
+        let events = self.debugger.subscribe();
+        let writer = self.writer.clone();
+        thread::spawn(move || {
+            for event in events {
+                let sent = match event {
+                    DebugEvent::Paused(paused) => writer.event(
+                        "stopped",
+                        json!({
+                            "reason": match paused.reason {
+                                PauseReason::Breakpoint => "breakpoint",
+                                PauseReason::Step => "step",
+                                PauseReason::Pause => "pause",
+                            },
+                            "threadId": THREAD_ID,
+                            "allThreadsStopped": true,
+                        }),
+                    ),
+                    DebugEvent::Resumed => writer.event(
+                        "continued",
+                        json!({ "threadId": THREAD_ID, "allThreadsContinued": true }),
+                    ),
+                };
+                // the editor's gone
+                if sent.is_err() {
+                    break;
+                }
+            }
+        });
+
+        while let Some(request) = read_message(&mut self.reader)? {
+            let command = request["command"].as_str().unwrap_or_default().to_string();
+            let attaching = matches!(command.as_str(), "initialize" | "launch" | "attach");
+            if attaching && request["arguments"]["token"].as_str() == Some(self.token.as_str()) {
+                self.attached = true;
+            }
+            let result = match (self.attached, command.as_str()) {
+                (true, _) => self.handle(&command, &request["arguments"]),
+                (false, "initialize") => Ok(capabilities()),
+                (false, _) => Err("Attach with the token Stacks gave when it started.".to_string()),
+            };
+            self.writer.respond(&request, result)?;
+            if !self.attached && command != "initialize" {
+                break;
+            }
+            match command.as_str() {
+                "initialize" => self.writer.event("initialized", json!({}))?,
+                "disconnect" => break,
+                _ => (),
+            }
+        }
+        self.disconnect();
+        Ok(())
+    }
+
+    fn handle(&mut self, command: &str, args: &Value) -> Result<Value, String> {
+        let frame = || args["frameId"].as_u64().unwrap_or(0) as usize;
+        match command {
+            "initialize" => Ok(capabilities()),
+            "launch" | "attach" | "configurationDone" => Ok(json!({})),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "Scripts" }] })),
+            "stackTrace" => {
+                let paused = self
+                    .debugger
+                    .paused()
+                    .ok_or_else(|| "The scene isn't paused.".to_string())?;
+                let frames: Vec<Value> = paused
+                    .frames
+                    .iter()
+                    .enumerate()
+                    .map(|(id, frame)| {
+                        json!({
+                            "id": id,
+                            "name": frame.name,
+                            "line": frame.line,
+                            "column": 1,
+                            "source": { "name": frame.source },
+                        })
+                    })
+                    .collect();
+                Ok(json!({ "totalFrames": frames.len(), "stackFrames": frames }))
+            }
+            // variable references are frame numbers plus one, since 0 means nothing to expand
+            "scopes" => Ok(json!({ "scopes": [{
+                "name": "Locals",
+                "variablesReference": frame() + 1,
+                "expensive": false,
+            }]})),
+            "variables" => {
+                let reference = args["variablesReference"].as_u64().unwrap_or(0) as usize;
+                let variables = self.debugger.locals(reference.saturating_sub(1))?;
+                Ok(json!({ "variables": variables.iter().map(|variable| json!({
+                    "name": variable.name,
+                    "value": variable.value,
+                    "variablesReference": 0,
+                })).collect::<Vec<_>>() }))
+            }
+            "continue" => {
+                self.debugger.send(DebugCommand::Continue)?;
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" => self.step(DebugCommand::StepOver),
+            "stepIn" => self.step(DebugCommand::StepIn),
+            "stepOut" => self.step(DebugCommand::StepOut),
+            "pause" => {
+                self.debugger.request_pause();
+                Ok(json!({}))
+            }
+            "evaluate" => {
+                let expression = args["expression"].as_str().unwrap_or_default().to_string();
+                let result = self.debugger.evaluate(expression, frame())?;
+                Ok(json!({ "result": result, "variablesReference": 0 }))
+            }
+            "disconnect" => Ok(json!({})),
+            _ => Err(format!("Stacks can't handle \"{}\" requests.", command)),
+        }
+    }
+
+    fn step(&self, command: DebugCommand) -> Result<Value, String> {
+        self.debugger.send(command)?;
+        Ok(json!({}))
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let lines: Vec<u32> = args["breakpoints"]
+            .as_array()
+            .map(|breakpoints| {
+                breakpoints
+                    .iter()
+                    .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                    .map(|line| line as u32)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let Some((entity, script)) = source_script(&args["source"]) else {
+            return Ok(json!({ "breakpoints": lines.iter().map(|line| json!({
+                "verified": false,
+                "line": line,
+                "message": "Breakpoints go in sources named like entity:script, or files named like entity.script.lua",
+            })).collect::<Vec<_>>() }));
+        };
+        self.debugger
+            .set_breakpoints(&entity, &script, lines.clone());
+        self.scripts.insert((entity, script));
+        Ok(json!({ "breakpoints": lines.iter().map(|line| json!({
+            "verified": true,
+            "line": line,
+        })).collect::<Vec<_>>() }))
+    }
+
+    /// Leave the scene running the way it was before the editor attached.
+    fn disconnect(&mut self) {
+        for (entity, script) in self.scripts.drain() {
+            self.debugger.set_breakpoints(&entity, &script, Vec::new());
+        }
+        let _ = self.debugger.send(DebugCommand::Continue);
+    }
+}
+
+/// The entity & script a DAP source refers to, either by name (`player:on_tick`)
+/// or by a file named after it (`player.on_tick.lua`).
+fn source_script(source: &Value) -> Option<(String, String)> {
+    let name = source["name"].as_str().unwrap_or_default();
+    if let Some((entity, script)) = parse_chunk_name(&format!("={}", name)) {
+        return Some((entity.to_string(), script.to_string()));
+    }
+    script_file_name(Path::new(source["path"].as_str()?))
+}
+
+/// Read one `Content-Length` framed message, or `None` once the editor hangs up.
+/// Language servers are framed the same way. Anything else, like an HTTP request
+/// from a web page, is an error.
+pub(crate) fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(invalid(format!("Not a header: {}", line)));
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "content-length" => length = value.trim().parse().ok(),
+            "content-type" => (),
+            _ => return Err(invalid(format!("Unexpected header: {}", name))),
+        }
+    }
+    let length = length.ok_or_else(|| invalid("Missing Content-Length".to_string()))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(message: &str) -> io::Result<Option<Value>> {
+        read_message(&mut message.as_bytes())
+    }
+
+    #[test]
+    fn reads_framed_messages() {
+        let message =
+            read("Content-Length: 13\r\nContent-Type: application/json\r\n\r\n{\"seq\": 1}   ");
+        assert_eq!(message.unwrap(), Some(json!({ "seq": 1 })));
+        assert_eq!(read("").unwrap(), None);
+    }
+
+    #[test]
+    fn wont_read_http_requests() {
+        let request = "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2\r\n\r\n{}";
+        assert!(read(request).is_err());
+        let headers = "Content-Length: 2\r\nOrigin: https://example.com\r\n\r\n{}";
+        assert!(read(headers).is_err());
+    }
+
+    /// A client for a session with the token "secret", and the editor's end of its connection.
+    fn connect() -> (TcpStream, BufReader<TcpStream>) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let editor = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let (debugger, commands) = Debugger::new();
+        thread::spawn(move || {
+            let _commands = commands;
+            Client::new(debugger, "secret".to_string(), stream).and_then(Client::run)
+        });
+        let reader = BufReader::new(editor.try_clone().unwrap());
+        (editor, reader)
+    }
+
+    fn send(editor: &mut TcpStream, seq: i64, command: &str, arguments: Value) {
+        let body =
+            json!({ "seq": seq, "type": "request", "command": command, "arguments": arguments })
+                .to_string();
+        write!(editor, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+    }
+
+    #[test]
+    fn tokens_are_random() {
+        let (first, second) = (new_token().unwrap(), new_token().unwrap());
+        assert_eq!(first.len(), 32);
+        assert!(first.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn editors_without_the_token_only_get_the_capabilities() {
+        let (mut editor, mut reader) = connect();
+        send(&mut editor, 1, "initialize", json!({}));
+        let response = read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(response["success"], true);
+        assert_eq!(response["body"], capabilities());
+        assert_eq!(
+            read_message(&mut reader).unwrap().unwrap()["event"],
+            "initialized"
+        );
+
+        send(&mut editor, 2, "threads", json!({}));
+        let response = read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(response["success"], false);
+        // and then it's hung up on
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn editors_with_the_token_can_debug() {
+        let (mut editor, mut reader) = connect();
+        send(&mut editor, 1, "initialize", json!({ "token": "secret" }));
+        read_message(&mut reader).unwrap();
+        read_message(&mut reader).unwrap();
+
+        send(&mut editor, 2, "threads", json!({}));
+        let response = read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(response["success"], true);
+        assert_eq!(response["body"]["threads"][0]["id"], THREAD_ID);
+    }
+}
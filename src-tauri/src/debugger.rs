@@ -0,0 +1,616 @@
+use crate::lua_types::LuaError;
+use crate::scripts::{chunk_name, parse_chunk_name};
+use mlua::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Registry name of the `debug` functions the debugger uses, which are kept away from scripts.
+const DEBUG_LIBRARY: &str = "stacks.debug";
+
+/// Registry name of the function called on every line while debugging.
+const LINE_HOOK: &str = "stacks.line_hook";
+
+/// How deep into tables to show values, so a paused entity doesn't print the whole scene.
+const VALUE_DEPTH: u32 = 3;
+
+/// A function a script is paused in, innermost first.
+#[derive(Serialize, Clone, Debug)]
+pub struct Frame {
+    pub name: String,
+    /// Set for frames in an entity's script
+    pub entity: Option<String>,
+    pub script: Option<String>,
+    /// Chunk the function is in, like `player:on_tick`
+    pub source: String,
+    pub line: u32,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Variable {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Watch {
+    pub expression: String,
+    /// The value, or why it couldn't be worked out
+    pub value: String,
+    pub error: bool,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseReason {
+    Breakpoint,
+    Step,
+    Pause,
+}
+
+/// Where the scene is paused, sent to the frontend & debug adapters.
+#[derive(Serialize, Clone, Debug)]
+pub struct Paused {
+    pub reason: PauseReason,
+    pub entity: String,
+    pub script: String,
+    pub line: u32,
+    pub frames: Vec<Frame>,
+    /// Of the innermost frame, including `self` & `data`
+    pub locals: Vec<Variable>,
+    pub watches: Vec<Watch>,
+}
+
+#[derive(Clone, Debug)]
+pub enum DebugEvent {
+    Paused(Paused),
+    Resumed,
+}
+
+/// What to do while paused. Only read once the scene is paused, since the Lua thread
+/// can't answer anything else until it carries on.
+pub enum DebugCommand {
+    Continue,
+    StepIn,
+    StepOver,
+    StepOut,
+    Locals(usize, Sender<Result<Vec<Variable>, String>>),
+    Evaluate(String, usize, Sender<Result<String, String>>),
+}
+
+#[derive(Default)]
+struct Shared {
+    /// Lines by chunk name
+    breakpoints: HashMap<String, BTreeSet<u32>>,
+    watches: Vec<String>,
+    pause_requested: bool,
+    stepping: bool,
+    paused: Option<Paused>,
+    listeners: Vec<Sender<DebugEvent>>,
+}
+
+impl Shared {
+    fn is_active(&self) -> bool {
+        !self.breakpoints.is_empty() || self.pause_requested || self.stepping
+    }
+
+    fn notify(&mut self, event: DebugEvent) {
+        self.listeners
+            .retain(|listener| listener.send(event.clone()).is_ok());
+    }
+}
+
+/// Controls the debugger from outside the Lua thread, e.g. from Tauri commands or a debug adapter.
+#[derive(Clone)]
+pub struct Debugger {
+    shared: Arc<Mutex<Shared>>,
+    commands: Sender<DebugCommand>,
+}
+
+impl Debugger {
+    /// The debugger, and what the Lua thread reads its commands from.
+    pub fn new() -> (Self, Receiver<DebugCommand>) {
+        let (commands, receiver) = mpsc::channel();
+        (
+            Debugger {
+                shared: Arc::new(Mutex::new(Shared::default())),
+                commands,
+            },
+            receiver,
+        )
+    }
+
+    fn shared(&self) -> MutexGuard<'_, Shared> {
+        // nothing holding the lock can panic part way through changing it
+        self.shared.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set_breakpoints(&self, entity: &str, script: &str, lines: Vec<u32>) {
+        let mut shared = self.shared();
+        let chunk = chunk_name(entity, script);
+        match lines.is_empty() {
+            true => shared.breakpoints.remove(&chunk),
+            false => shared
+                .breakpoints
+                .insert(chunk, lines.into_iter().collect()),
+        };
+    }
+
+    /// Lines with breakpoints, by entity & then script.
+    pub fn breakpoints(&self) -> HashMap<String, HashMap<String, Vec<u32>>> {
+        let mut breakpoints: HashMap<String, HashMap<String, Vec<u32>>> = HashMap::new();
+        for (chunk, lines) in &self.shared().breakpoints {
+            if let Some((entity, script)) = parse_chunk_name(&format!("={}", chunk)) {
+                breakpoints
+                    .entry(entity.to_string())
+                    .or_default()
+                    .insert(script.to_string(), lines.iter().copied().collect());
+            }
+        }
+        breakpoints
+    }
+
+    pub fn set_watches(&self, watches: Vec<String>) {
+        self.shared().watches = watches;
+    }
+
+    pub fn watches(&self) -> Vec<String> {
+        self.shared().watches.clone()
+    }
+
+    /// Pause as soon as any script runs another line.
+    pub fn request_pause(&self) {
+        self.shared().pause_requested = true;
+    }
+
+    pub fn paused(&self) -> Option<Paused> {
+        self.shared().paused.clone()
+    }
+
+    /// Events for every pause & resume from now on.
+    pub fn subscribe(&self) -> Receiver<DebugEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.shared().listeners.push(tx);
+        rx
+    }
+
+    pub fn send(&self, command: DebugCommand) -> Result<(), String> {
+        if self.shared().paused.is_none() {
+            return Err("The scene isn't paused.".to_string());
+        }
+        self.commands.send(command).map_err(|e| e.to_string())
+    }
+
+    pub fn locals(&self, frame: usize) -> Result<Vec<Variable>, String> {
+        let (tx, rx) = mpsc::channel();
+        self.send(DebugCommand::Locals(frame, tx))?;
+        rx.recv().map_err(|e| e.to_string())?
+    }
+
+    pub fn evaluate(&self, expression: String, frame: usize) -> Result<String, String> {
+        let (tx, rx) = mpsc::channel();
+        self.send(DebugCommand::Evaluate(expression, frame, tx))?;
+        rx.recv().map_err(|e| e.to_string())?
+    }
+}
+
+enum StepKind {
+    In,
+    Over,
+    Out,
+}
+
+struct Step {
+    kind: StepKind,
+    /// Coroutine the step started in, since every script runs in its own
+    thread: LuaRegistryKey,
+    depth: usize,
+}
+
+/// The debugger's side of the Lua thread, kept in app data.
+struct Session {
+    debugger: Debugger,
+    commands: Receiver<DebugCommand>,
+    step: Option<Step>,
+    /// Stack level of each frame in the pause, since C functions aren't shown
+    levels: Vec<usize>,
+}
+
+/// Hand the Lua thread its end of the debugger. Scripts can't use the `debug` library
+/// themselves, since changing hooks or locals would get in the debugger's way, and it
+/// can reach anything in the Lua state. The debugger keeps just the functions it needs.
+pub fn attach(
+    lua: &Lua,
+    debugger: Debugger,
+    commands: Receiver<DebugCommand>,
+) -> Result<(), LuaError> {
+    let debug: LuaTable = lua.globals().get("debug").map_err(|e| {
+        LuaError::InitializationError(format!("Failed to find debug library: {}", e))
+    })?;
+    let kept = lua.create_table()?;
+    for name in ["sethook", "getlocal"] {
+        kept.set(name, debug.get::<_, LuaFunction>(name)?)?;
+    }
+    lua.set_named_registry_value(DEBUG_LIBRARY, kept)?;
+    lua.globals().set("debug", LuaNil)?;
+    // or `require("debug")` would still hand it out
+    lua.globals()
+        .get::<_, LuaTable>("package")?
+        .get::<_, LuaTable>("loaded")?
+        .set("debug", LuaNil)?;
+
+    let hook = lua.create_function(|l: &Lua, (event, line): (String, Option<u32>)| {
+        match (event.as_str(), line) {
+            ("line", Some(line)) => Ok(on_line(l, line)?),
+            _ => Ok(()),
+        }
+    })?;
+    lua.set_named_registry_value(LINE_HOOK, hook)?;
+
+    lua.set_app_data(Session {
+        debugger,
+        commands,
+        step: None,
+        levels: Vec::new(),
+    });
+    Ok(())
+}
+
+fn debug_library(lua: &Lua) -> LuaResult<LuaTable<'_>> {
+    lua.named_registry_value(DEBUG_LIBRARY)
+}
+
+/// Watch a script's coroutine for breakpoints before it runs, if anything is being debugged.
+/// Hooks left on coroutines after debugging stops take themselves off the next time they run.
+pub fn hook_thread(lua: &Lua, thread: &LuaThread) -> Result<(), LuaError> {
+    let active = match lua.app_data_ref::<Session>() {
+        Some(session) => session.debugger.shared().is_active(),
+        None => return Ok(()),
+    };
+    if active {
+        debug_library(lua)?
+            .get::<_, LuaFunction>("sethook")?
+            .call::<_, ()>((
+                thread,
+                lua.named_registry_value::<LuaFunction>(LINE_HOOK)?,
+                "l",
+            ))?;
+    }
+    Ok(())
+}
+
+/// How many functions deep the running coroutine is. Level 0 is the line hook.
+fn stack_depth(lua: &Lua) -> usize {
+    let mut depth = 1;
+    while lua.inspect_stack(depth).is_some() {
+        depth += 1;
+    }
+    depth - 1
+}
+
+fn on_line(lua: &Lua, line: u32) -> Result<(), LuaError> {
+    let Some(debug) = lua.inspect_stack(1) else {
+        return Ok(());
+    };
+    let source = debug.source().source.map(|s| s.into_owned());
+    // only pause in entity scripts, not in the Lua that runs them
+    let Some((entity, script)) = source.as_deref().and_then(parse_chunk_name) else {
+        return Ok(());
+    };
+    let (entity, script) = (entity.to_string(), script.to_string());
+
+    let reason = {
+        let Some(session) = lua.app_data_ref::<Session>() else {
+            return Ok(());
+        };
+        let shared = session.debugger.shared();
+        if !shared.is_active() {
+            drop(shared);
+            drop(session);
+            debug_library(lua)?
+                .get::<_, LuaFunction>("sethook")?
+                .call::<_, ()>(())?;
+            return Ok(());
+        }
+        let on_breakpoint = shared
+            .breakpoints
+            .get(&chunk_name(&entity, &script))
+            .is_some_and(|lines| lines.contains(&line));
+        if shared.pause_requested {
+            Some(PauseReason::Pause)
+        } else if on_breakpoint {
+            Some(PauseReason::Breakpoint)
+        } else {
+            match &session.step {
+                Some(step) => {
+                    let same_thread = lua
+                        .registry_value::<LuaThread>(&step.thread)
+                        .is_ok_and(|thread| thread == lua.current_thread());
+                    let finished = match step.kind {
+                        StepKind::In => true,
+                        StepKind::Over => same_thread && stack_depth(lua) <= step.depth,
+                        StepKind::Out => same_thread && stack_depth(lua) < step.depth,
+                    };
+                    finished.then_some(PauseReason::Step)
+                }
+                None => None,
+            }
+        }
+    };
+
+    match reason {
+        Some(reason) => pause(lua, reason, entity, script, line),
+        None => Ok(()),
+    }
+}
+
+/// Block the Lua thread, answering debugger commands until told to carry on.
+fn pause(
+    lua: &Lua,
+    reason: PauseReason,
+    entity: String,
+    script: String,
+    line: u32,
+) -> Result<(), LuaError> {
+    let (frames, levels) = stack_frames(lua);
+    if let Some(mut session) = lua.app_data_mut::<Session>() {
+        session.step = None;
+        session.levels = levels;
+    }
+    let paused = Paused {
+        reason,
+        entity,
+        script,
+        line,
+        frames,
+        locals: locals(lua, 0)?,
+        watches: evaluate_watches(lua)?,
+    };
+    let debugger = match lua.app_data_ref::<Session>() {
+        Some(session) => session.debugger.clone(),
+        None => return Ok(()),
+    };
+    {
+        let mut shared = debugger.shared();
+        shared.pause_requested = false;
+        shared.stepping = false;
+        shared.paused = Some(paused.clone());
+        shared.notify(DebugEvent::Paused(paused.clone()));
+    }
+    emit(lua, "debugger_paused", lua.to_value(&paused)?)?;
+
+    loop {
+        let command = match lua.app_data_ref::<Session>() {
+            Some(session) => session.commands.recv(),
+            None => break,
+        };
+        let step = |kind| -> Result<Option<Step>, LuaError> {
+            Ok(Some(Step {
+                kind,
+                thread: lua.create_registry_value(lua.current_thread())?,
+                depth: stack_depth(lua),
+            }))
+        };
+        let step = match command {
+            Ok(DebugCommand::Continue) | Err(_) => None,
+            Ok(DebugCommand::StepIn) => step(StepKind::In)?,
+            Ok(DebugCommand::StepOver) => step(StepKind::Over)?,
+            Ok(DebugCommand::StepOut) => step(StepKind::Out)?,
+            Ok(DebugCommand::Locals(frame, response_tx)) => {
+                let _ = response_tx.send(locals(lua, frame).map_err(|e| e.to_string()));
+                continue;
+            }
+            Ok(DebugCommand::Evaluate(expression, frame, response_tx)) => {
+                let _ = response_tx.send(evaluate(lua, &expression, frame));
+                continue;
+            }
+        };
+        debugger.shared().stepping = step.is_some();
+        if let Some(mut session) = lua.app_data_mut::<Session>() {
+            session.step = step;
+        }
+        break;
+    }
+
+    {
+        let mut shared = debugger.shared();
+        shared.paused = None;
+        shared.notify(DebugEvent::Resumed);
+    }
+    emit(lua, "debugger_resumed", LuaNil)?;
+    Ok(())
+}
+
+fn emit<'lua>(lua: &'lua Lua, event: &str, data: LuaValue<'lua>) -> Result<(), LuaError> {
+    Ok(lua
+        .globals()
+        .get::<_, LuaFunction>("emit")?
+        .call::<_, ()>((event, data))?)
+}
+
+/// The Lua functions on the running coroutine's stack, and the stack level of each.
+fn stack_frames(lua: &Lua) -> (Vec<Frame>, Vec<usize>) {
+    let mut frames = Vec::new();
+    let mut levels = Vec::new();
+    // level 0 is the line hook
+    let mut level = 1;
+    while let Some(debug) = lua.inspect_stack(level) {
+        level += 1;
+        let source = debug.source();
+        if source.what == "C" {
+            continue;
+        }
+        let chunk = source.source.as_deref().unwrap_or("?").to_string();
+        let located = parse_chunk_name(&chunk);
+        frames.push(Frame {
+            name: match (located, debug.names().name) {
+                // named after the script, rather than the field Entity:run_script calls it through
+                (Some((_, script)), _) if source.what == "main" => script.to_string(),
+                (_, Some(name)) => name.to_string(),
+                _ => "?".to_string(),
+            },
+            entity: located.map(|(entity, _)| entity.to_string()),
+            script: located.map(|(_, script)| script.to_string()),
+            source: chunk.trim_start_matches(['=', '@']).to_string(),
+            line: debug.curr_line().max(0) as u32,
+        });
+        levels.push(level - 1);
+    }
+    (frames, levels)
+}
+
+/// `debug.getlocal` level of a frame in the pause. One more than `inspect_stack`'s,
+/// since `getlocal` counts itself.
+fn frame_level(lua: &Lua, frame: usize) -> Result<i64, LuaError> {
+    lua.app_data_ref::<Session>()
+        .and_then(|session| session.levels.get(frame).copied())
+        .map(|level| level as i64 + 1)
+        .ok_or_else(|| LuaError::FormatError(format!("There's no frame {}.", frame)))
+}
+
+/// A value as it's shown in the debugger.
+fn describe(lua: &Lua, value: LuaValue) -> Result<String, LuaError> {
+    Ok(match value {
+        LuaValue::String(s) => format!("{:?}", s.to_string_lossy()),
+        LuaValue::Table(_) => {
+            let serpent: LuaTable = lua
+                .globals()
+                .get::<_, LuaFunction>("require")?
+                .call("serpent")?;
+            let options = lua.create_table()?;
+            options.set("comment", false)?;
+            options.set("nocode", true)?;
+            options.set("maxlevel", VALUE_DEPTH)?;
+            serpent
+                .get::<_, LuaFunction>("line")?
+                .call::<_, String>((value, options))?
+        }
+        value => value.to_string()?,
+    })
+}
+
+/// Local variables of a frame, by name & value, skipping Lua's own temporaries.
+fn frame_locals<'lua>(
+    lua: &'lua Lua,
+    frame: usize,
+) -> Result<Vec<(String, LuaValue<'lua>)>, LuaError> {
+    let level = frame_level(lua, frame)?;
+    let getlocal: LuaFunction = debug_library(lua)?.get("getlocal")?;
+    let mut found = Vec::new();
+    for i in 1.. {
+        let (name, value): (Option<String>, LuaValue) = getlocal.call((level, i))?;
+        let Some(name) = name else { break };
+        if !name.starts_with('(') {
+            found.push((name, value));
+        }
+    }
+    Ok(found)
+}
+
+fn locals(lua: &Lua, frame: usize) -> Result<Vec<Variable>, LuaError> {
+    frame_locals(lua, frame)?
+        .into_iter()
+        .map(|(name, value)| {
+            Ok(Variable {
+                name,
+                value: describe(lua, value)?,
+            })
+        })
+        .collect()
+}
+
+/// Run `expression` with a frame's locals in scope, returning what it gives back.
+/// Anything else is looked up in the globals, so `currentScene` & `random` work as usual.
+fn evaluate(lua: &Lua, expression: &str, frame: usize) -> Result<String, String> {
+    let run = || -> Result<String, LuaError> {
+        let env = lua.create_table()?;
+        let metatable = lua.create_table()?;
+        metatable.set("__index", lua.globals())?;
+        env.set_metatable(Some(metatable));
+        for (name, value) in frame_locals(lua, frame)? {
+            env.set(name, value)?;
+        }
+        // expressions first, then statements like `self.speed = 2`
+        let function = match lua
+            .load(format!("return {}", expression))
+            .set_name("=watch")
+            .set_environment(env.clone())
+            .into_function()
+        {
+            Ok(function) => function,
+            Err(_) => lua
+                .load(expression)
+                .set_name("=watch")
+                .set_environment(env)
+                .into_function()?,
+        };
+        let values: LuaMultiValue = function.call(())?;
+        let shown = values
+            .into_iter()
+            .map(|value| describe(lua, value))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(match shown.is_empty() {
+            true => "nil".to_string(),
+            false => shown.join(", "),
+        })
+    };
+    run().map_err(|e| match e {
+        // the traceback would only show the debugger's own calls
+        LuaError::LuaError(mlua::Error::RuntimeError(msg)) => msg
+            .split("\nstack traceback:")
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        LuaError::LuaError(mlua::Error::SyntaxError { message, .. }) => message,
+        e => e.to_string(),
+    })
+}
+
+fn evaluate_watches(lua: &Lua) -> Result<Vec<Watch>, LuaError> {
+    let watches = match lua.app_data_ref::<Session>() {
+        Some(session) => session.debugger.watches(),
+        None => return Ok(Vec::new()),
+    };
+    Ok(watches
+        .into_iter()
+        .map(|expression| {
+            let (value, error) = match evaluate(lua, &expression, 0) {
+                Ok(value) => (value, false),
+                Err(e) => (e, true),
+            };
+            Watch {
+                expression,
+                value,
+                error,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runner::{headless_lua, LUA_DIR};
+    use crate::testing::Recorder;
+    use serde_json::json;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    #[test]
+    fn scripts_cant_get_the_debug_library() {
+        let recorder = Arc::new(Recorder::new(Path::new(LUA_DIR)));
+        let lua = headless_lua(recorder.clone()).unwrap();
+        lua.load(
+            r#"currentScene:add_entity("spy", { type = "rect", scripts = { on_click = { string = [[
+                emit("found", { global = debug ~= nil, required = pcall(require, "debug") })
+            ]] } } })
+            currentScene:run_protected("spy", "on_click", {})"#,
+        )
+        .exec()
+        .unwrap();
+        assert_eq!(
+            recorder.events("found").unwrap(),
+            vec![json!({ "global": false, "required": false })]
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use crate::input::{input_mut, InputEvent};
+use crate::lua_types::LuaError;
+use crate::recording::{record, Recorded};
+use mlua::prelude::*;
+use std::sync::{Arc, Mutex};
+
+/// Somewhere gamepad input comes from, polled once per tick.
+pub trait GamepadBackend {
+    fn poll(&mut self) -> Vec<InputEvent>;
+}
+
+/// Gamepads driven from code rather than hardware, e.g. by tests.
+/// Clones share their events, so one can be kept to drive the one the runtime polls.
+#[derive(Clone, Default)]
+pub struct VirtualGamepads {
+    events: Arc<Mutex<Vec<InputEvent>>>,
+}
+
+impl VirtualGamepads {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+// only tests drive virtual gamepads so far
+#[cfg(test)]
+impl VirtualGamepads {
+    pub fn connect(&self, pad: u32) {
+        self.push(InputEvent::GamepadConnected { pad });
+    }
+
+    pub fn disconnect(&self, pad: u32) {
+        self.push(InputEvent::GamepadDisconnected { pad });
+    }
+
+    pub fn press(&self, pad: u32, button: &str) {
+        self.push(InputEvent::GamepadButton {
+            pad,
+            button: button.to_string(),
+            down: true,
+        });
+    }
+
+    pub fn release(&self, pad: u32, button: &str) {
+        self.push(InputEvent::GamepadButton {
+            pad,
+            button: button.to_string(),
+            down: false,
+        });
+    }
+
+    pub fn set_axis(&self, pad: u32, axis: &str, value: f64) {
+        self.push(InputEvent::GamepadAxis {
+            pad,
+            axis: axis.to_string(),
+            value,
+        });
+    }
+
+    fn push(&self, event: InputEvent) {
+        if let Ok(mut events) = self.events.lock() {
+            events.push(event);
+        }
+    }
+}
+
+impl GamepadBackend for VirtualGamepads {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        self.events
+            .lock()
+            .map(|mut events| std::mem::take(&mut *events))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "gamepad")]
+pub struct GilrsGamepads(gilrs::Gilrs);
+
+#[cfg(feature = "gamepad")]
+impl GilrsGamepads {
+    pub fn new() -> Result<Self, String> {
+        gilrs::Gilrs::new()
+            .map(GilrsGamepads)
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(feature = "gamepad")]
+impl GamepadBackend for GilrsGamepads {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        use gilrs::{Axis, Button, EventType};
+
+        fn button_name(button: Button) -> Option<&'static str> {
+            Some(match button {
+                Button::South => "a",
+                Button::East => "b",
+                Button::West => "x",
+                Button::North => "y",
+                Button::C => "c",
+                Button::Z => "z",
+                Button::LeftTrigger => "left_bumper",
+                Button::RightTrigger => "right_bumper",
+                Button::LeftTrigger2 => "left_trigger",
+                Button::RightTrigger2 => "right_trigger",
+                Button::Select => "select",
+                Button::Start => "start",
+                Button::Mode => "mode",
+                Button::LeftThumb => "left_stick",
+                Button::RightThumb => "right_stick",
+                Button::DPadUp => "dpad_up",
+                Button::DPadDown => "dpad_down",
+                Button::DPadLeft => "dpad_left",
+                Button::DPadRight => "dpad_right",
+                Button::Unknown => return None,
+            })
+        }
+
+        // gilrs has up as positive, but scene coordinates have down as positive
+        fn axis(axis: Axis, value: f32) -> Option<(&'static str, f64)> {
+            let value = value as f64;
+            Some(match axis {
+                Axis::LeftStickX => ("left_x", value),
+                Axis::LeftStickY => ("left_y", -value),
+                Axis::RightStickX => ("right_x", value),
+                Axis::RightStickY => ("right_y", -value),
+                Axis::LeftZ => ("left_trigger", value),
+                Axis::RightZ => ("right_trigger", value),
+                Axis::DPadX => ("dpad_x", value),
+                Axis::DPadY => ("dpad_y", -value),
+                Axis::Unknown => return None,
+            })
+        }
+
+        let mut events = Vec::new();
+        while let Some(event) = self.0.next_event() {
+            // numbered from 1, like everything else scripts count
+            let pad = usize::from(event.id) as u32 + 1;
+            match event.event {
+                EventType::Connected => events.push(InputEvent::GamepadConnected { pad }),
+                EventType::Disconnected => events.push(InputEvent::GamepadDisconnected { pad }),
+                EventType::ButtonPressed(button, _) | EventType::ButtonReleased(button, _) => {
+                    if let Some(name) = button_name(button) {
+                        events.push(InputEvent::GamepadButton {
+                            pad,
+                            button: name.to_string(),
+                            down: matches!(event.event, EventType::ButtonPressed(..)),
+                        });
+                    }
+                }
+                // analog triggers report how far they're pulled as a button value
+                EventType::ButtonChanged(
+                    button @ (Button::LeftTrigger2 | Button::RightTrigger2),
+                    value,
+                    _,
+                ) => {
+                    if let Some(name) = button_name(button) {
+                        events.push(InputEvent::GamepadAxis {
+                            pad,
+                            axis: name.to_string(),
+                            value: value as f64,
+                        });
+                    }
+                }
+                EventType::AxisChanged(changed, value, _) => {
+                    if let Some((name, value)) = axis(changed, value) {
+                        events.push(InputEvent::GamepadAxis {
+                            pad,
+                            axis: name.to_string(),
+                            value,
+                        });
+                    }
+                }
+                _ => (),
+            }
+        }
+        events
+    }
+}
+
+/// Real gamepads when built with the `gamepad` feature and they're available, otherwise virtual ones.
+pub fn default_backend() -> Box<dyn GamepadBackend> {
+    #[cfg(feature = "gamepad")]
+    match GilrsGamepads::new() {
+        Ok(gamepads) => return Box::new(gamepads),
+        Err(e) => eprintln!(
+            "[gamepad] Couldn't access gamepads, so none will connect: {}",
+            e
+        ),
+    }
+    Box::new(VirtualGamepads::new())
+}
+
+struct Gamepads(Box<dyn GamepadBackend>);
+
+/// Queue up whatever the gamepads have done since the last tick.
+pub fn poll_gamepads(lua: &Lua) -> Result<(), LuaError> {
+    let events = match lua.app_data_mut::<Gamepads>() {
+        Some(mut gamepads) => gamepads.0.poll(),
+        None => return Ok(()),
+    };
+    for event in events {
+        record(
+            lua,
+            Recorded::Input {
+                event: event.clone(),
+            },
+        );
+        input_mut(lua)?.push(event);
+    }
+    Ok(())
+}
+
+/// Take gamepad input from `backend`, e.g. `default_backend` or `VirtualGamepads`.
+pub fn set_globals(lua: &Lua, backend: Box<dyn GamepadBackend>) -> Result<(), LuaError> {
+    lua.set_app_data(Gamepads(backend));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Input;
+
+    fn tick(gamepads: &mut VirtualGamepads, input: &mut Input) -> Vec<&'static str> {
+        input.end_tick();
+        gamepads
+            .poll()
+            .into_iter()
+            .for_each(|event| input.push(event));
+        input
+            .begin_tick(|point| point)
+            .iter()
+            .map(|dispatch| dispatch.event)
+            .collect()
+    }
+
+    #[test]
+    fn buttons_are_held_until_released() {
+        let (mut gamepads, mut input) = (VirtualGamepads::new(), Input::new());
+        let driver = gamepads.clone();
+
+        driver.connect(1);
+        driver.press(1, "a");
+        assert_eq!(
+            tick(&mut gamepads, &mut input),
+            ["on_gamepad_connected", "on_gamepad_button"]
+        );
+        assert!(input.is_gamepad_down("a", None));
+        assert!(input.was_gamepad_pressed("a", Some(1)));
+        assert!(!input.is_gamepad_down("a", Some(2)));
+
+        assert!(tick(&mut gamepads, &mut input).is_empty());
+        assert!(input.is_gamepad_down("a", Some(1)));
+        assert!(!input.was_gamepad_pressed("a", Some(1)));
+
+        driver.release(1, "a");
+        assert_eq!(tick(&mut gamepads, &mut input), ["on_gamepad_button"]);
+        assert!(!input.is_gamepad_down("a", None));
+        assert!(input.was_gamepad_released("a", None));
+    }
+
+    #[test]
+    fn disconnecting_releases_buttons() {
+        let (mut gamepads, mut input) = (VirtualGamepads::new(), Input::new());
+        let driver = gamepads.clone();
+
+        driver.press(2, "start");
+        tick(&mut gamepads, &mut input);
+        assert_eq!(input.gamepads(), [2]);
+
+        driver.disconnect(2);
+        assert_eq!(
+            tick(&mut gamepads, &mut input),
+            ["on_gamepad_button", "on_gamepad_disconnected"]
+        );
+        assert!(input.gamepads().is_empty());
+        assert!(!input.is_gamepad_down("start", None));
+    }
+
+    #[test]
+    fn axes_have_a_deadzone() {
+        let (mut gamepads, mut input) = (VirtualGamepads::new(), Input::new());
+        let driver = gamepads.clone();
+
+        driver.set_axis(1, "left_x", 0.05);
+        driver.set_axis(2, "left_x", -0.5);
+        driver.set_axis(1, "left_y", 3.0);
+        tick(&mut gamepads, &mut input);
+        assert_eq!(input.gamepad_axis("left_x", Some(1)), 0.0);
+        assert_eq!(input.gamepad_axis("left_x", None), -0.5);
+        assert_eq!(input.gamepad_axis("left_y", None), 1.0);
+        assert_eq!(input.gamepad_axis("right_x", None), 0.0);
+    }
+}
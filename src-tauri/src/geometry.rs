@@ -0,0 +1,365 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::FRAC_PI_2;
+use std::ops::{Add, Mul, Sub};
+
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct Vec2 {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Vec2 {
+    pub fn new(x: f64, y: f64) -> Self {
+        Vec2 { x, y }
+    }
+
+    pub fn dot(self, other: Vec2) -> f64 {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn length(self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    /// Same direction with a length of 1, or `None` for a zero vector.
+    pub fn normalized(self) -> Option<Vec2> {
+        let length = self.length();
+        (length > 0.0).then(|| self * (1.0 / length))
+    }
+
+    /// Rotate around the origin by `angle` radians, clockwise in scene coordinates (where y points down).
+    pub fn rotated(self, angle: f64) -> Vec2 {
+        let (sin, cos) = angle.sin_cos();
+        Vec2::new(self.x * cos - self.y * sin, self.x * sin + self.y * cos)
+    }
+}
+
+impl Add for Vec2 {
+    type Output = Vec2;
+    fn add(self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for Vec2 {
+    type Output = Vec2;
+    fn sub(self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Mul<f64> for Vec2 {
+    type Output = Vec2;
+    fn mul(self, scale: f64) -> Vec2 {
+        Vec2::new(self.x * scale, self.y * scale)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Shape {
+    /// A rectangle rotated `angle` radians around its center; axis-aligned when the angle is 0
+    Rect {
+        center: Vec2,
+        half_size: Vec2,
+        angle: f64,
+    },
+    Circle {
+        center: Vec2,
+        radius: f64,
+    },
+}
+
+impl Shape {
+    /// Corners of a rectangle, or `None` for a circle.
+    fn corners(&self) -> Option<[Vec2; 4]> {
+        match *self {
+            Shape::Rect {
+                center,
+                half_size,
+                angle,
+            } => Some(
+                [
+                    Vec2::new(-half_size.x, -half_size.y),
+                    Vec2::new(half_size.x, -half_size.y),
+                    Vec2::new(half_size.x, half_size.y),
+                    Vec2::new(-half_size.x, half_size.y),
+                ]
+                .map(|corner| center + corner.rotated(angle)),
+            ),
+            Shape::Circle { .. } => None,
+        }
+    }
+
+    /// Axis-aligned bounding box, as its top left & bottom right corners.
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        match *self {
+            Shape::Rect {
+                center,
+                half_size,
+                angle,
+            } => {
+                // extent of the rotated rectangle along each axis
+                let (sin, cos) = (angle.sin().abs(), angle.cos().abs());
+                let extent = Vec2::new(
+                    half_size.x * cos + half_size.y * sin,
+                    half_size.x * sin + half_size.y * cos,
+                );
+                (center - extent, center + extent)
+            }
+            Shape::Circle { center, radius } => {
+                let extent = Vec2::new(radius, radius);
+                (center - extent, center + extent)
+            }
+        }
+    }
+
+    /// Move by `offset`, and turn by `turn` radians around the center.
+    pub fn moved(self, offset: Vec2, turn: f64) -> Shape {
+        match self {
+            Shape::Rect {
+                center,
+                half_size,
+                angle,
+            } => Shape::Rect {
+                center: center + offset,
+                half_size,
+                angle: angle + turn,
+            },
+            Shape::Circle { center, radius } => Shape::Circle {
+                center: center + offset,
+                radius,
+            },
+        }
+    }
+
+    pub fn overlaps(&self, other: &Shape) -> bool {
+        self.contact(other).is_some()
+    }
+
+    /// How this shape & another overlap, if they do.
+    pub fn contact(&self, other: &Shape) -> Option<Contact> {
+        match (*self, *other) {
+            (
+                Shape::Circle {
+                    center: a,
+                    radius: ra,
+                },
+                Shape::Circle {
+                    center: b,
+                    radius: rb,
+                },
+            ) => {
+                let distance = (b - a).length();
+                (distance < ra + rb).then(|| Contact {
+                    // circles in exactly the same place get pushed apart sideways
+                    normal: (b - a).normalized().unwrap_or(Vec2::new(1.0, 0.0)),
+                    depth: ra + rb - distance,
+                })
+            }
+            (
+                Shape::Rect {
+                    center,
+                    half_size,
+                    angle,
+                },
+                Shape::Circle {
+                    center: circle,
+                    radius,
+                },
+            ) => rect_circle_contact(center, half_size, angle, circle, radius),
+            (
+                Shape::Circle {
+                    center: circle,
+                    radius,
+                },
+                Shape::Rect {
+                    center,
+                    half_size,
+                    angle,
+                },
+            ) => rect_circle_contact(center, half_size, angle, circle, radius).map(|contact| {
+                Contact {
+                    normal: contact.normal * -1.0,
+                    ..contact
+                }
+            }),
+            (
+                Shape::Rect {
+                    center: a_center,
+                    angle: a,
+                    ..
+                },
+                Shape::Rect {
+                    center: b_center,
+                    angle: b,
+                    ..
+                },
+            ) => {
+                // separating axis theorem: the rectangles overlap unless one of their edge normals separates them,
+                // and they can be pushed apart along whichever axis they overlap least on
+                let (a_corners, b_corners) = (self.corners()?, other.corners()?);
+                let mut best: Option<Contact> = None;
+                for angle in [a, a + FRAC_PI_2, b, b + FRAC_PI_2] {
+                    let axis = Vec2::new(angle.cos(), angle.sin());
+                    let project = |corners: &[Vec2; 4]| {
+                        corners
+                            .iter()
+                            .map(|c| c.dot(axis))
+                            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), p| {
+                                (min.min(p), max.max(p))
+                            })
+                    };
+                    let ((a_min, a_max), (b_min, b_max)) =
+                        (project(&a_corners), project(&b_corners));
+                    let depth = a_max.min(b_max) - a_min.max(b_min);
+                    if depth <= 0.0 {
+                        return None;
+                    }
+                    if best.is_none_or(|best| depth < best.depth) {
+                        let normal = if (b_center - a_center).dot(axis) < 0.0 {
+                            axis * -1.0
+                        } else {
+                            axis
+                        };
+                        best = Some(Contact { normal, depth });
+                    }
+                }
+                best
+            }
+        }
+    }
+}
+
+/// How two shapes overlap.
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    /// Direction from the first shape towards the second, to push them apart along
+    pub normal: Vec2,
+    /// How far they'd need pushing to stop overlapping
+    pub depth: f64,
+}
+
+/// Contact from a rectangle to a circle, working in the rectangle's own frame.
+fn rect_circle_contact(
+    center: Vec2,
+    half_size: Vec2,
+    angle: f64,
+    circle: Vec2,
+    radius: f64,
+) -> Option<Contact> {
+    let local = (circle - center).rotated(-angle);
+    let closest = Vec2::new(
+        local.x.clamp(-half_size.x, half_size.x),
+        local.y.clamp(-half_size.y, half_size.y),
+    );
+
+    let (normal, depth) = if closest == local {
+        // the circle's center is inside the rectangle, so push it out through the nearest side
+        let (x_depth, y_depth) = (half_size.x - local.x.abs(), half_size.y - local.y.abs());
+        if x_depth < y_depth {
+            (Vec2::new(local.x.signum(), 0.0), x_depth + radius)
+        } else {
+            (Vec2::new(0.0, local.y.signum()), y_depth + radius)
+        }
+    } else {
+        let distance = (local - closest).length();
+        if distance >= radius {
+            return None;
+        }
+        ((local - closest).normalized()?, radius - distance)
+    };
+
+    Some(Contact {
+        normal: normal.rotated(angle),
+        depth,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_PI_4;
+
+    fn rect(x: f64, y: f64, width: f64, height: f64, angle: f64) -> Shape {
+        Shape::Rect {
+            center: Vec2::new(x, y),
+            half_size: Vec2::new(width / 2.0, height / 2.0),
+            angle,
+        }
+    }
+
+    fn circle(x: f64, y: f64, radius: f64) -> Shape {
+        Shape::Circle {
+            center: Vec2::new(x, y),
+            radius,
+        }
+    }
+
+    fn assert_near(actual: Vec2, expected: Vec2) {
+        assert!(
+            (actual - expected).length() < 1e-9,
+            "expected {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn rects_overlap_along_the_shallowest_axis() {
+        let contact = rect(0.0, 0.0, 10.0, 10.0, 0.0)
+            .contact(&rect(8.0, 1.0, 10.0, 10.0, 0.0))
+            .unwrap();
+        assert_near(contact.normal, Vec2::new(1.0, 0.0));
+        assert!((contact.depth - 2.0).abs() < 1e-9);
+        assert!(!rect(0.0, 0.0, 10.0, 10.0, 0.0).overlaps(&rect(20.0, 0.0, 10.0, 10.0, 0.0)));
+    }
+
+    #[test]
+    fn touching_edges_dont_overlap() {
+        assert!(!rect(0.0, 0.0, 10.0, 10.0, 0.0).overlaps(&rect(10.0, 0.0, 10.0, 10.0, 0.0)));
+        assert!(!circle(0.0, 0.0, 5.0).overlaps(&circle(10.0, 0.0, 5.0)));
+        assert!(!rect(0.0, 0.0, 10.0, 10.0, 0.0).overlaps(&circle(10.0, 0.0, 5.0)));
+    }
+
+    #[test]
+    fn rotated_rects_use_their_own_edges() {
+        // a diamond's corner reaches 7.07 to the right, so it overlaps a box whose edge is at 6,
+        // but its bounding box would overlap one at 6.5 on the diagonal when the diamond doesn't
+        let diamond = rect(0.0, 0.0, 10.0, 10.0, FRAC_PI_4);
+        assert!(diamond.overlaps(&rect(11.0, 0.0, 10.0, 2.0, 0.0)));
+        assert!(!diamond.overlaps(&rect(6.5, 6.5, 2.0, 2.0, 0.0)));
+        let (min, max) = diamond.bounds();
+        assert_near(max - min, Vec2::new(2.0 * 50f64.sqrt(), 2.0 * 50f64.sqrt()));
+    }
+
+    #[test]
+    fn circles_push_out_of_rects() {
+        let contact = rect(0.0, 0.0, 10.0, 10.0, 0.0)
+            .contact(&circle(0.0, 7.0, 3.0))
+            .unwrap();
+        assert_near(contact.normal, Vec2::new(0.0, 1.0));
+        assert!((contact.depth - 1.0).abs() < 1e-9);
+        // the other way round, the normal still points from the first shape to the second
+        let contact = circle(0.0, 7.0, 3.0)
+            .contact(&rect(0.0, 0.0, 10.0, 10.0, 0.0))
+            .unwrap();
+        assert_near(contact.normal, Vec2::new(0.0, -1.0));
+    }
+
+    #[test]
+    fn circles_inside_rects_leave_through_the_nearest_side() {
+        let contact = rect(0.0, 0.0, 10.0, 10.0, 0.0)
+            .contact(&circle(-4.0, 1.0, 1.0))
+            .unwrap();
+        assert_near(contact.normal, Vec2::new(-1.0, 0.0));
+        assert!((contact.depth - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn circles_near_a_rotated_rects_corner() {
+        // the diamond's top corner is at (0, -7.07)
+        let diamond = rect(0.0, 0.0, 10.0, 10.0, FRAC_PI_4);
+        assert!(diamond.overlaps(&circle(0.0, -8.0, 1.0)));
+        assert!(!diamond.overlaps(&circle(4.0, -7.0, 1.0)));
+    }
+}
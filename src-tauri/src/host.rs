@@ -0,0 +1,37 @@
+use serde_json::Value;
+use std::path::PathBuf;
+use tauri::{Emitter, Manager, WebviewWindow};
+
+/// What the Lua thread runs inside: the app's window, or a terminal when a scene is run headless.
+pub trait Host: Send + Sync + 'static {
+    fn emit(&self, event: &str, payload: Value) -> Result<(), String>;
+
+    /// Send an event to one window, e.g. an inspector
+    fn emit_to(&self, target: &str, event: &str, payload: Value) -> Result<(), String>;
+
+    /// Where the runtime's own Lua modules, like `Scene`, are
+    fn lua_dir(&self) -> Result<PathBuf, String>;
+
+    /// Show a line of the runtime's own output, like what scripts print, to whoever started it
+    fn show(&self, line: &str) {
+        println!("{}", line);
+    }
+}
+
+impl Host for WebviewWindow {
+    fn emit(&self, event: &str, payload: Value) -> Result<(), String> {
+        Emitter::emit(self, event, payload).map_err(|e| e.to_string())
+    }
+
+    fn emit_to(&self, target: &str, event: &str, payload: Value) -> Result<(), String> {
+        Emitter::emit_to(self, target, event, payload).map_err(|e| e.to_string())
+    }
+
+    fn lua_dir(&self) -> Result<PathBuf, String> {
+        self.app_handle()
+            .path()
+            .resource_dir()
+            .map(|dir| dir.join("resources").join("lua"))
+            .map_err(|e| format!("Failed to get resource dir: {}", e))
+    }
+}
@@ -0,0 +1,641 @@
+use crate::camera::{camera_mut, canvas_mut};
+use crate::geometry::Vec2;
+use crate::lua_setup::get_scene;
+use crate::lua_types::LuaError;
+use crate::script_runner::run_listener;
+use mlua::prelude::*;
+use mlua::AppDataRefMut;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Stick positions closer to the middle than this count as the middle, since sticks rarely rest at exactly 0.
+const AXIS_DEADZONE: f64 = 0.1;
+
+/// Raw input from the scene window, with the mouse in canvas coordinates.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputEvent {
+    KeyDown {
+        key: String,
+    },
+    KeyUp {
+        key: String,
+    },
+    MouseMove {
+        x: f64,
+        y: f64,
+        entity: Option<String>,
+    },
+    MouseDown {
+        button: u8,
+        x: f64,
+        y: f64,
+        entity: Option<String>,
+    },
+    MouseUp {
+        button: u8,
+        x: f64,
+        y: f64,
+        entity: Option<String>,
+    },
+    Wheel {
+        dx: f64,
+        dy: f64,
+    },
+    /// The scene window lost focus, so nothing is held any more
+    Blur,
+    GamepadConnected {
+        pad: u32,
+    },
+    GamepadDisconnected {
+        pad: u32,
+    },
+    GamepadButton {
+        pad: u32,
+        button: String,
+        down: bool,
+    },
+    /// Axes go from -1 to 1, with down & right positive like scene coordinates
+    GamepadAxis {
+        pad: u32,
+        axis: String,
+        value: f64,
+    },
+}
+
+/// A script event to run because of some input.
+pub struct Dispatch {
+    /// Entity to run it on, or every entity with a matching script if `None`
+    pub target: Option<String>,
+    pub event: &'static str,
+    pub data: Value,
+}
+
+#[derive(Default)]
+struct Pad {
+    buttons: HashSet<String>,
+    pressed: HashSet<String>,
+    released: HashSet<String>,
+    axes: HashMap<String, f64>,
+}
+
+/// Input state as scripts see it, which only changes between ticks.
+pub struct Input {
+    queue: Vec<InputEvent>,
+    keys: HashSet<String>,
+    keys_pressed: HashSet<String>,
+    keys_released: HashSet<String>,
+    buttons: HashSet<String>,
+    buttons_pressed: HashSet<String>,
+    buttons_released: HashSet<String>,
+    /// Where the mouse is on the canvas, rather than in the world
+    mouse: (f64, f64),
+    wheel: (f64, f64),
+    hovered: Option<String>,
+    /// Connected gamepads, numbered from 1
+    pads: BTreeMap<u32, Pad>,
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Input {
+            queue: Vec::new(),
+            keys: HashSet::new(),
+            keys_pressed: HashSet::new(),
+            keys_released: HashSet::new(),
+            buttons: HashSet::new(),
+            buttons_pressed: HashSet::new(),
+            buttons_released: HashSet::new(),
+            mouse: (0.0, 0.0),
+            wheel: (0.0, 0.0),
+            hovered: None,
+            pads: BTreeMap::new(),
+        }
+    }
+
+    /// Queue an event, to be applied at the start of the next tick.
+    pub fn push(&mut self, event: InputEvent) {
+        self.queue.push(event);
+    }
+
+    /// Apply the queued events, returning the script events they cause.
+    /// Scripts see the mouse in the world, which `to_world` finds from where it is on the canvas.
+    pub fn begin_tick(&mut self, to_world: impl Fn(Vec2) -> Vec2) -> Vec<Dispatch> {
+        let mut dispatches = Vec::new();
+        for event in std::mem::take(&mut self.queue) {
+            match event {
+                InputEvent::KeyDown { key } => {
+                    let key = key_name(&key);
+                    // held keys repeat, but only the first press counts
+                    if self.keys.insert(key.clone()) {
+                        self.keys_pressed.insert(key.clone());
+                        dispatches.push(broadcast("on_key_down", json!({ "key": key })));
+                    }
+                }
+                InputEvent::KeyUp { key } => {
+                    let key = key_name(&key);
+                    if self.keys.remove(&key) {
+                        self.keys_released.insert(key.clone());
+                        dispatches.push(broadcast("on_key_up", json!({ "key": key })));
+                    }
+                }
+                InputEvent::MouseMove { x, y, entity } => {
+                    self.move_mouse(x, y, to_world(Vec2::new(x, y)), entity, &mut dispatches);
+                }
+                InputEvent::MouseDown {
+                    button,
+                    x,
+                    y,
+                    entity,
+                } => {
+                    let world = to_world(Vec2::new(x, y));
+                    self.move_mouse(x, y, world, entity, &mut dispatches);
+                    let button = button_name(button);
+                    if self.buttons.insert(button.to_string()) {
+                        self.buttons_pressed.insert(button.to_string());
+                    }
+                    if let Some(id) = &self.hovered {
+                        dispatches.push(Dispatch {
+                            target: Some(id.clone()),
+                            event: "on_mouse_down",
+                            data: json!({ "button": button, "x": world.x, "y": world.y }),
+                        });
+                    }
+                }
+                InputEvent::MouseUp {
+                    button,
+                    x,
+                    y,
+                    entity,
+                } => {
+                    let world = to_world(Vec2::new(x, y));
+                    self.move_mouse(x, y, world, entity, &mut dispatches);
+                    let button = button_name(button);
+                    if self.buttons.remove(button) {
+                        self.buttons_released.insert(button.to_string());
+                    }
+                    if let Some(id) = &self.hovered {
+                        dispatches.push(Dispatch {
+                            target: Some(id.clone()),
+                            event: "on_mouse_up",
+                            data: json!({ "button": button, "x": world.x, "y": world.y }),
+                        });
+                    }
+                }
+                InputEvent::Wheel { dx, dy } => {
+                    self.wheel.0 += dx;
+                    self.wheel.1 += dy;
+                }
+                InputEvent::Blur => {
+                    for key in std::mem::take(&mut self.keys) {
+                        dispatches.push(broadcast("on_key_up", json!({ "key": key })));
+                        self.keys_released.insert(key);
+                    }
+                    self.buttons_released
+                        .extend(std::mem::take(&mut self.buttons));
+                }
+                InputEvent::GamepadConnected { pad } => {
+                    if let Entry::Vacant(entry) = self.pads.entry(pad) {
+                        entry.insert(Pad::default());
+                        dispatches.push(broadcast("on_gamepad_connected", json!({ "pad": pad })));
+                    }
+                }
+                InputEvent::GamepadDisconnected { pad } => {
+                    if let Some(state) = self.pads.remove(&pad) {
+                        for button in state.buttons {
+                            dispatches.push(broadcast(
+                                "on_gamepad_button",
+                                json!({ "pad": pad, "button": button, "down": false }),
+                            ));
+                        }
+                        dispatches
+                            .push(broadcast("on_gamepad_disconnected", json!({ "pad": pad })));
+                    }
+                }
+                InputEvent::GamepadButton { pad, button, down } => {
+                    let state = self.pads.entry(pad).or_default();
+                    let changed = match down {
+                        true => state.buttons.insert(button.clone()),
+                        false => state.buttons.remove(&button),
+                    };
+                    if changed {
+                        match down {
+                            true => state.pressed.insert(button.clone()),
+                            false => state.released.insert(button.clone()),
+                        };
+                        dispatches.push(broadcast(
+                            "on_gamepad_button",
+                            json!({ "pad": pad, "button": button, "down": down }),
+                        ));
+                    }
+                }
+                InputEvent::GamepadAxis { pad, axis, value } => {
+                    let value = match value.abs() < AXIS_DEADZONE {
+                        true => 0.0,
+                        false => value.clamp(-1.0, 1.0),
+                    };
+                    self.pads.entry(pad).or_default().axes.insert(axis, value);
+                }
+            }
+        }
+        dispatches
+    }
+
+    /// Forget what happened this tick, once every script has had a chance to see it.
+    pub fn end_tick(&mut self) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.wheel = (0.0, 0.0);
+        for pad in self.pads.values_mut() {
+            pad.pressed.clear();
+            pad.released.clear();
+        }
+    }
+
+    pub fn gamepads(&self) -> Vec<u32> {
+        self.pads.keys().copied().collect()
+    }
+
+    /// Whether a gamepad button is held, on the given pad or any pad.
+    pub fn is_gamepad_down(&self, button: &str, pad: Option<u32>) -> bool {
+        self.pads_matching(pad)
+            .any(|state| state.buttons.contains(button))
+    }
+
+    pub fn was_gamepad_pressed(&self, button: &str, pad: Option<u32>) -> bool {
+        self.pads_matching(pad)
+            .any(|state| state.pressed.contains(button))
+    }
+
+    pub fn was_gamepad_released(&self, button: &str, pad: Option<u32>) -> bool {
+        self.pads_matching(pad)
+            .any(|state| state.released.contains(button))
+    }
+
+    /// An axis' position on the given pad, or the one pushed furthest across all pads.
+    pub fn gamepad_axis(&self, axis: &str, pad: Option<u32>) -> f64 {
+        self.pads_matching(pad)
+            .filter_map(|state| state.axes.get(axis).copied())
+            .fold(0.0, |furthest, value| match value.abs() > furthest.abs() {
+                true => value,
+                false => furthest,
+            })
+    }
+
+    fn pads_matching(&self, pad: Option<u32>) -> impl Iterator<Item = &Pad> {
+        self.pads
+            .iter()
+            .filter(move |(number, _)| pad.is_none_or(|pad| **number == pad))
+            .map(|(_, state)| state)
+    }
+
+    /// Stop tracking an entity, e.g. because it was deleted or renamed.
+    pub fn forget_entity(&mut self, id: &str) {
+        if self.hovered.as_deref() == Some(id) {
+            self.hovered = None;
+        }
+    }
+
+    fn move_mouse(
+        &mut self,
+        x: f64,
+        y: f64,
+        world: Vec2,
+        entity: Option<String>,
+        dispatches: &mut Vec<Dispatch>,
+    ) {
+        self.mouse = (x, y);
+        if entity == self.hovered {
+            return;
+        }
+        if let Some(left) = self.hovered.take() {
+            dispatches.push(Dispatch {
+                target: Some(left),
+                event: "on_mouse_leave",
+                data: json!({ "x": world.x, "y": world.y }),
+            });
+        }
+        if let Some(entered) = &entity {
+            dispatches.push(Dispatch {
+                target: Some(entered.clone()),
+                event: "on_mouse_enter",
+                data: json!({ "x": world.x, "y": world.y }),
+            });
+        }
+        self.hovered = entity;
+    }
+}
+
+fn broadcast(event: &'static str, data: Value) -> Dispatch {
+    Dispatch {
+        target: None,
+        event,
+        data,
+    }
+}
+
+/// Name scripts use for a key, from a browser `KeyboardEvent.key`:
+/// lowercase, `space` for the space bar, and arrow keys as `left`, `right`, `up` & `down`.
+pub fn key_name(key: &str) -> String {
+    match key {
+        " " => "space".to_string(),
+        _ => key.strip_prefix("Arrow").unwrap_or(key).to_lowercase(),
+    }
+}
+
+/// Name scripts use for a mouse button, from a browser `MouseEvent.button`.
+pub fn button_name(button: u8) -> &'static str {
+    match button {
+        0 => "left",
+        1 => "middle",
+        2 => "right",
+        3 => "back",
+        4 => "forward",
+        _ => "other",
+    }
+}
+
+pub fn input_mut(lua: &Lua) -> Result<AppDataRefMut<'_, Input>, LuaError> {
+    lua.app_data_mut::<Input>()
+        .ok_or_else(|| LuaError::InitializationError("Input missing".to_string()))
+}
+
+/// Apply queued input, running the scripts listening for it.
+pub fn dispatch_input(lua: &Lua) -> Result<(), LuaError> {
+    let canvas = *canvas_mut(lua)?;
+    let camera = camera_mut(lua)?.clone();
+    let dispatches = input_mut(lua)?.begin_tick(|point| camera.to_world(canvas, point));
+    for dispatch in dispatches {
+        let mut targets: Vec<String> = match dispatch.target {
+            Some(id) => vec![id],
+            None => get_scene(lua)?
+                .get::<_, LuaTable>("entities")?
+                .pairs::<String, LuaValue>()
+                .map(|pair| pair.map(|(id, _)| id))
+                .collect::<LuaResult<_>>()?,
+        };
+        // in the same order every time, so replays run scripts in the order they were recorded
+        targets.sort();
+
+        for id in targets {
+            run_listener(lua, &id, dispatch.event, lua.to_value(&dispatch.data)?)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn set_globals(lua: &Lua) -> Result<(), LuaError> {
+    lua.set_app_data(Input::new());
+
+    let input = lua.create_table()?;
+    input.set(
+        "is_down",
+        lua.create_function(|l: &Lua, key: String| {
+            Ok(input_mut(l)?.keys.contains(&key_name(&key)))
+        })?,
+    )?;
+    input.set(
+        "was_pressed",
+        lua.create_function(|l: &Lua, key: String| {
+            Ok(input_mut(l)?.keys_pressed.contains(&key_name(&key)))
+        })?,
+    )?;
+    input.set(
+        "was_released",
+        lua.create_function(|l: &Lua, key: String| {
+            Ok(input_mut(l)?.keys_released.contains(&key_name(&key)))
+        })?,
+    )?;
+    input.set(
+        "is_mouse_down",
+        lua.create_function(|l: &Lua, button: Option<String>| {
+            Ok(input_mut(l)?
+                .buttons
+                .contains(button.as_deref().unwrap_or("left")))
+        })?,
+    )?;
+    input.set(
+        "was_mouse_pressed",
+        lua.create_function(|l: &Lua, button: Option<String>| {
+            Ok(input_mut(l)?
+                .buttons_pressed
+                .contains(button.as_deref().unwrap_or("left")))
+        })?,
+    )?;
+    input.set(
+        "was_mouse_released",
+        lua.create_function(|l: &Lua, button: Option<String>| {
+            Ok(input_mut(l)?
+                .buttons_released
+                .contains(button.as_deref().unwrap_or("left")))
+        })?,
+    )?;
+    input.set(
+        "mouse_pos",
+        lua.create_function(|l: &Lua, ()| {
+            // the camera may have moved since the mouse did, so this is worked out fresh
+            let (x, y) = input_mut(l)?.mouse;
+            let pos = camera_mut(l)?.to_world(*canvas_mut(l)?, Vec2::new(x, y));
+            let table = l.create_table()?;
+            table.set("x", pos.x)?;
+            table.set("y", pos.y)?;
+            Ok(table)
+        })?,
+    )?;
+    input.set(
+        "mouse_wheel",
+        lua.create_function(|l: &Lua, ()| {
+            let (x, y) = input_mut(l)?.wheel;
+            let wheel = l.create_table()?;
+            wheel.set("x", x)?;
+            wheel.set("y", y)?;
+            Ok(wheel)
+        })?,
+    )?;
+    input.set(
+        "gamepads",
+        lua.create_function(|l: &Lua, ()| Ok(input_mut(l)?.gamepads()))?,
+    )?;
+    input.set(
+        "is_gamepad_down",
+        lua.create_function(|l: &Lua, (button, pad): (String, Option<u32>)| {
+            Ok(input_mut(l)?.is_gamepad_down(&button, pad))
+        })?,
+    )?;
+    input.set(
+        "was_gamepad_pressed",
+        lua.create_function(|l: &Lua, (button, pad): (String, Option<u32>)| {
+            Ok(input_mut(l)?.was_gamepad_pressed(&button, pad))
+        })?,
+    )?;
+    input.set(
+        "was_gamepad_released",
+        lua.create_function(|l: &Lua, (button, pad): (String, Option<u32>)| {
+            Ok(input_mut(l)?.was_gamepad_released(&button, pad))
+        })?,
+    )?;
+    input.set(
+        "gamepad_axis",
+        lua.create_function(|l: &Lua, (axis, pad): (String, Option<u32>)| {
+            Ok(input_mut(l)?.gamepad_axis(&axis, pad))
+        })?,
+    )?;
+    input.set(
+        "hovered",
+        lua.create_function(|l: &Lua, ()| Ok(input_mut(l)?.hovered.clone()))?,
+    )?;
+
+    lua.globals()
+        .set("input", input)
+        .map_err(|e| LuaError::InitializationError(format!("Failed to set input: {}", e)))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lua_setup::match_message;
+    use crate::lua_types::LuaMessage;
+    use crate::runner::{headless_lua, LUA_DIR};
+    use crate::testing::Recorder;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    /// Apply `events` as one tick, with the world twice the size of the canvas.
+    fn tick(
+        input: &mut Input,
+        events: Vec<InputEvent>,
+    ) -> Vec<(Option<String>, &'static str, Value)> {
+        events.into_iter().for_each(|event| input.push(event));
+        input
+            .begin_tick(|canvas| canvas * 2.0)
+            .into_iter()
+            .map(|dispatch| (dispatch.target, dispatch.event, dispatch.data))
+            .collect()
+    }
+
+    fn mouse_move(x: f64, entity: Option<&str>) -> InputEvent {
+        InputEvent::MouseMove {
+            x,
+            y: 0.0,
+            entity: entity.map(str::to_string),
+        }
+    }
+
+    fn on(id: &str, event: &'static str, data: Value) -> (Option<String>, &'static str, Value) {
+        (Some(id.to_string()), event, data)
+    }
+
+    #[test]
+    fn the_mouse_enters_and_leaves_entities() {
+        let mut input = Input::new();
+        assert_eq!(
+            tick(&mut input, vec![mouse_move(1.0, Some("a"))]),
+            [on("a", "on_mouse_enter", json!({ "x": 2.0, "y": 0.0 }))]
+        );
+        // staying over the same entity doesn't enter it again
+        assert!(tick(&mut input, vec![mouse_move(2.0, Some("a"))]).is_empty());
+        assert_eq!(
+            tick(
+                &mut input,
+                vec![
+                    mouse_move(3.0, Some("b")),
+                    InputEvent::MouseDown {
+                        button: 0,
+                        x: 3.0,
+                        y: 0.0,
+                        entity: Some("b".to_string()),
+                    },
+                ]
+            ),
+            [
+                on("a", "on_mouse_leave", json!({ "x": 6.0, "y": 0.0 })),
+                on("b", "on_mouse_enter", json!({ "x": 6.0, "y": 0.0 })),
+                on(
+                    "b",
+                    "on_mouse_down",
+                    json!({ "button": "left", "x": 6.0, "y": 0.0 })
+                ),
+            ]
+        );
+        assert!(input.buttons_pressed.contains("left"));
+        assert_eq!(
+            tick(&mut input, vec![mouse_move(4.0, None)]),
+            [on("b", "on_mouse_leave", json!({ "x": 8.0, "y": 0.0 }))]
+        );
+        assert_eq!(input.mouse, (4.0, 0.0));
+    }
+
+    #[test]
+    fn losing_focus_lets_go_of_everything() {
+        let mut input = Input::new();
+        tick(
+            &mut input,
+            vec![
+                InputEvent::KeyDown {
+                    key: "ArrowLeft".to_string(),
+                },
+                InputEvent::MouseDown {
+                    button: 2,
+                    x: 0.0,
+                    y: 0.0,
+                    entity: None,
+                },
+            ],
+        );
+        input.end_tick();
+
+        assert_eq!(
+            tick(&mut input, vec![InputEvent::Blur]),
+            [(None, "on_key_up", json!({ "key": "left" }))]
+        );
+        assert!(input.keys.is_empty() && input.buttons.is_empty());
+        assert!(input.keys_released.contains("left"));
+        assert!(input.buttons_released.contains("right"));
+    }
+
+    #[test]
+    fn sticks_rest_in_the_middle() {
+        let mut input = Input::new();
+        let axis = |value| InputEvent::GamepadAxis {
+            pad: 1,
+            axis: "left_x".to_string(),
+            value,
+        };
+        for (value, seen) in [(0.05, 0.0), (-0.09, 0.0), (0.5, 0.5), (-1.7, -1.0)] {
+            tick(&mut input, vec![axis(value)]);
+            assert_eq!(input.gamepad_axis("left_x", Some(1)), seen);
+        }
+    }
+
+    #[test]
+    fn presses_only_last_a_tick_even_if_it_fails() {
+        let recorder = Arc::new(Recorder::new(Path::new(LUA_DIR)));
+        let lua = headless_lua(recorder.clone()).unwrap();
+        lua.load(
+            r#"currentScene:add_entity("player", { type = "rect", scripts = { on_tick = { string = [[
+                emit("keys", { pressed = input.was_pressed("a"), down = input.is_down("a") })
+            ]] } } })
+            working_tick = currentScene.tick
+            currentScene.tick = function() error("broken") end"#,
+        )
+        .exec()
+        .unwrap();
+        input_mut(&lua).unwrap().push(InputEvent::KeyDown {
+            key: "a".to_string(),
+        });
+        assert!(match_message(&lua, LuaMessage::Tick(1.0 / 60.0)).is_err());
+
+        lua.load("currentScene.tick = working_tick").exec().unwrap();
+        match_message(&lua, LuaMessage::Tick(1.0 / 60.0)).unwrap();
+        assert_eq!(
+            recorder.events("keys").unwrap(),
+            vec![json!({ "pressed": false, "down": true })]
+        );
+    }
+}
@@ -1,13 +1,51 @@
+mod assets;
+mod audio;
+mod camera;
+mod collisions;
+mod dap;
+mod debugger;
 mod frontend_commands;
+mod gamepad;
+mod geometry;
+mod host;
+mod input;
+mod logs;
+mod lsp;
 mod lua_commands;
 mod lua_setup;
 mod lua_types;
+mod physics;
+mod pins;
+mod profiler;
+mod random;
+mod recording;
+mod repl;
+mod runner;
+mod scheduler;
+mod script_api;
+mod script_errors;
+mod script_runner;
+mod scripts;
+mod snapshots;
+mod sprites;
+mod testing;
+mod tilemaps;
+mod tweens;
 use frontend_commands::{resize_window, set_frontend_ready, window_scale, SetupState};
 use lua_commands::{
-    delete_entity, duplicate_entity, get_entity_string, handle_inspector_save, load_scene,
-    new_entity, run_script, save_scene, tick, update_entity,
+    check_scripts, clear_logs, clear_script_errors, debugger_step, delete_entity, duplicate_entity,
+    eval, evaluate_in_frame, export_logs, export_profile_trace, get_breakpoints, get_entity_string,
+    get_eval_history, get_locals, get_logs, get_paused, get_pins, get_script_errors,
+    get_snapshot_entity, get_timeline, get_watches, handle_inspector_save, input_event, load_scene,
+    new_entity, pause_scripts, pin_expression, profile_report, replay, reset_profile, resolve_asset,
+    resume_from_snapshot, run_script, save_scene, set_breakpoints, set_profiling,
+    set_script_error_options, set_watches, start_recording, stop_recording, tick, unpin_expression,
+    update_entity, view_snapshot,
 };
+pub use lsp::run_language_server;
 use lua_setup::init_lua_thread;
+pub use runner::run_headless;
+pub use testing::{report as report_scene_tests, run_scene_tests, TestResult};
 use std::sync::Mutex;
 use tauri::{
     menu::{Menu, MenuItem, SubmenuBuilder},
@@ -26,6 +64,7 @@ pub fn run() {
         .setup(|app| {
             let window = app.get_webview_window("main").unwrap();
             let state = init_lua_thread(window.clone()).expect("Error initializing lua thread");
+            dap::serve(state.debugger.clone());
             app.manage(state);
 
             let handle = app.handle();
@@ -59,6 +98,28 @@ pub fn run() {
                     Some("CmdOrCtrl+O"),
                 )?)
                 .separator()
+                .item(&MenuItem::with_id(
+                    handle,
+                    "start_recording",
+                    "Start Recording",
+                    true,
+                    None::<&str>,
+                )?)
+                .item(&MenuItem::with_id(
+                    handle,
+                    "stop_recording",
+                    "Stop Recording",
+                    true,
+                    None::<&str>,
+                )?)
+                .item(&MenuItem::with_id(
+                    handle,
+                    "replay",
+                    "Replay Recording",
+                    true,
+                    None::<&str>,
+                )?)
+                .separator()
                 .item(&MenuItem::with_id(
                     handle,
                     "save_entity",
@@ -76,11 +137,30 @@ pub fn run() {
                 .build()?;
             menu.append(&file_menu)?;
 
+            let view_menu = SubmenuBuilder::with_id(handle, "view", "View")
+                .item(&MenuItem::with_id(
+                    handle,
+                    "open_console",
+                    "Console",
+                    true,
+                    Some("CmdOrCtrl+Shift+C"),
+                )?)
+                .item(&MenuItem::with_id(
+                    handle,
+                    "toggle_timeline",
+                    "Time Travel",
+                    true,
+                    Some("CmdOrCtrl+Shift+T"),
+                )?)
+                .build()?;
+            menu.append(&view_menu)?;
+
             let window_clone = window.clone();
             app.set_menu(menu)?;
             app.on_menu_event(move |app_handle: &tauri::AppHandle, event| {
                 match event.id().0.as_str() {
-                    file_op @ ("save_scene" | "open_scene") => {
+                    file_op @ ("save_scene" | "open_scene" | "start_recording"
+                    | "stop_recording" | "replay") => {
                         if window_clone
                             .is_focused()
                             .expect("Couldn't find main window focus status")
@@ -95,6 +175,16 @@ pub fn run() {
                             .emit_to("inspector", "save_entity", ())
                             .expect("Failed to emit save_entity to inspector");
                     }
+                    "open_console" => {
+                        app_handle
+                            .emit_to("main", "open_console", ())
+                            .expect("Failed to emit open_console to main");
+                    }
+                    "toggle_timeline" => {
+                        app_handle
+                            .emit_to("main", "toggle_timeline", ())
+                            .expect("Failed to emit toggle_timeline to main");
+                    }
                     "revert_entity" => {
                         app_handle
                             .emit_to("inspector", "revert_entity", ())
@@ -163,6 +253,7 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
             tick,
+            input_event,
             new_entity,
             update_entity,
             delete_entity,
@@ -174,7 +265,40 @@ pub fn run() {
             window_scale,
             set_frontend_ready,
             get_entity_string,
-            handle_inspector_save
+            handle_inspector_save,
+            set_script_error_options,
+            get_script_errors,
+            clear_script_errors,
+            get_logs,
+            clear_logs,
+            export_logs,
+            eval,
+            get_eval_history,
+            pin_expression,
+            unpin_expression,
+            get_pins,
+            set_profiling,
+            reset_profile,
+            profile_report,
+            export_profile_trace,
+            check_scripts,
+            resolve_asset,
+            start_recording,
+            stop_recording,
+            replay,
+            get_timeline,
+            view_snapshot,
+            get_snapshot_entity,
+            resume_from_snapshot,
+            set_breakpoints,
+            get_breakpoints,
+            set_watches,
+            get_watches,
+            get_paused,
+            pause_scripts,
+            debugger_step,
+            get_locals,
+            evaluate_in_frame
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
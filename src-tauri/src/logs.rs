@@ -0,0 +1,301 @@
+use crate::host::Host;
+use crate::lua_types::LuaError;
+use crate::script_runner::current_script;
+use mlua::prelude::*;
+use mlua::AppDataRefMut;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::fs;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of log entries kept around for consoles that open later, and for exporting.
+const HISTORY_LENGTH: usize = 1000;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LogEntry {
+    /// Milliseconds since the Unix epoch
+    pub time: u64,
+    pub level: Level,
+    /// The entity & script that logged it, if it came from a script
+    pub entity: Option<String>,
+    pub script: Option<String>,
+    pub message: String,
+}
+
+impl LogEntry {
+    /// One line, without the time, e.g. `warn player:on_tick: low on health`.
+    fn describe(&self) -> String {
+        match (&self.entity, &self.script) {
+            (Some(entity), Some(script)) => {
+                format!(
+                    "{} {}:{}: {}",
+                    self.level.name(),
+                    entity,
+                    script,
+                    self.message
+                )
+            }
+            _ => format!("{} {}", self.level.name(), self.message),
+        }
+    }
+}
+
+pub struct Logs {
+    history: VecDeque<LogEntry>,
+    /// Where entries are shown as they're logged, besides the console
+    host: Arc<dyn Host>,
+}
+
+impl Logs {
+    pub fn new(host: Arc<dyn Host>) -> Self {
+        Logs {
+            history: VecDeque::new(),
+            host,
+        }
+    }
+
+    fn push(&mut self, entry: LogEntry) {
+        if self.history.len() >= HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(entry);
+    }
+
+    pub fn history(&self) -> Vec<LogEntry> {
+        self.history.iter().cloned().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    /// Write everything still in the history to `path`, one entry per line.
+    pub fn export(&self, path: &str) -> Result<(), LuaError> {
+        let mut text = String::new();
+        for entry in &self.history {
+            let _ = writeln!(text, "{} {}", timestamp(entry.time), entry.describe());
+        }
+        fs::write(path, text)
+            .map_err(|e| LuaError::FormatError(format!("Couldn't save log to \"{}\": {}", path, e)))
+    }
+}
+
+pub fn logs_mut(lua: &Lua) -> Result<AppDataRefMut<'_, Logs>, LuaError> {
+    lua.app_data_mut::<Logs>()
+        .ok_or_else(|| LuaError::InitializationError("Logs missing".to_string()))
+}
+
+/// Add a message to the log, tagged with whichever entity's script is running, and show it in the console.
+pub fn log(lua: &Lua, level: Level, message: String) -> Result<(), LuaError> {
+    let (entity, script) = current_script(lua).unzip();
+    let entry = LogEntry {
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_millis() as u64)
+            .unwrap_or_default(),
+        level,
+        entity,
+        script,
+        message,
+    };
+    let mut logs = logs_mut(lua)?;
+    logs.host.show(&format!("[lua] {}", entry.describe()));
+    logs.push(entry.clone());
+    drop(logs);
+    lua.globals()
+        .get::<_, LuaFunction>("emit")?
+        .call::<_, ()>(("log_entry", lua.to_value(&entry)?))?;
+    Ok(())
+}
+
+/// Report the runtime itself going wrong, rather than a script, without stopping the scene.
+/// `part` is which part of it, like `scheduler`.
+pub fn log_fault(lua: &Lua, part: &str, fault: &LuaError) {
+    // there's nowhere left to report it if logging fails too
+    let _ = log(lua, Level::Error, format!("[{}] {}", part, fault));
+}
+
+/// Values joined by tabs, the way Lua's own `print` shows them.
+fn join<'lua>(lua: &'lua Lua, values: LuaMultiValue<'lua>) -> LuaResult<String> {
+    let tostring: LuaFunction = lua.globals().get("tostring")?;
+    let mut parts = Vec::with_capacity(values.len());
+    for value in values {
+        parts.push(tostring.call::<_, String>(value)?);
+    }
+    Ok(parts.join("\t"))
+}
+
+/// An ISO 8601 time in UTC, like `2025-01-31T09:05:02.250Z`.
+fn timestamp(millis: u64) -> String {
+    let seconds = millis / 1000;
+    let (days, time) = (seconds / 86400, seconds % 86400);
+    // days since the epoch to a calendar date, from Howard Hinnant's `civil_from_days`
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        millis % 1000
+    )
+}
+
+pub fn set_globals(lua: &Lua, host: Arc<dyn Host>) -> Result<(), LuaError> {
+    lua.set_app_data(Logs::new(host));
+
+    // anything scripts print goes to the console too, tagged with where it came from
+    lua.globals()
+        .set(
+            "print",
+            lua.create_function(|l: &Lua, values: LuaMultiValue| {
+                let message = join(l, values)?;
+                Ok(log(l, Level::Info, message)?)
+            })?,
+        )
+        .map_err(|e| {
+            LuaError::InitializationError(format!("Failed to set Lua print function: {}", e))
+        })?;
+
+    let log_table = lua.create_table()?;
+    for level in [Level::Info, Level::Warn, Level::Error] {
+        log_table.set(
+            level.name(),
+            lua.create_function(move |l: &Lua, values: LuaMultiValue| {
+                let message = join(l, values)?;
+                Ok(log(l, level, message)?)
+            })?,
+        )?;
+    }
+    lua.globals()
+        .set("log", log_table)
+        .map_err(|e| LuaError::InitializationError(format!("Failed to set log: {}", e)))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{headless_lua, LUA_DIR};
+    use crate::testing::Recorder;
+    use std::path::Path;
+
+    fn entry(message: &str) -> LogEntry {
+        LogEntry {
+            time: 1709210096789,
+            level: Level::Info,
+            entity: None,
+            script: None,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn print_joins_values_with_tabs_and_tags_the_script() {
+        let lua = headless_lua(Arc::new(Recorder::new(Path::new(LUA_DIR)))).unwrap();
+        lua.load(
+            r#"
+            print(1, nil, "two")
+            currentScene:add_entity("player", {
+                type = "rect",
+                scripts = { on_click = { string = [[print(1, nil, {}); log.warn("low on health")]] } },
+            })
+            currentScene:run_protected("player", "on_click", {})
+            "#,
+        )
+        .exec()
+        .unwrap();
+
+        let history = logs_mut(&lua).unwrap().history();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].message, "1\tnil\ttwo");
+        assert_eq!((&history[0].entity, &history[0].script), (&None, &None));
+        assert!(history[1].message.starts_with("1\tnil\ttable: "));
+        assert_eq!(history[1].entity.as_deref(), Some("player"));
+        assert_eq!(history[1].script.as_deref(), Some("on_click"));
+        assert_eq!(history[2].level, Level::Warn);
+        assert_eq!(history[2].describe(), "warn player:on_click: low on health");
+    }
+
+    #[test]
+    fn the_oldest_entries_are_dropped() {
+        let mut logs = Logs::new(Arc::new(Recorder::new(Path::new(LUA_DIR))));
+        for i in 0..HISTORY_LENGTH + 5 {
+            logs.push(entry(&i.to_string()));
+        }
+        let history = logs.history();
+        assert_eq!(history.len(), HISTORY_LENGTH);
+        assert_eq!(history[0].message, "5");
+        assert_eq!(
+            history.last().unwrap().message,
+            (HISTORY_LENGTH + 4).to_string()
+        );
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(timestamp(1709210096789), "2024-02-29T12:34:56.789Z");
+        assert_eq!(timestamp(951782400000), "2000-02-29T00:00:00.000Z");
+        // 2100 isn't a leap year, so February goes straight to March
+        assert_eq!(timestamp(4107542400000 - 1), "2100-02-28T23:59:59.999Z");
+        assert_eq!(timestamp(1704067199000), "2023-12-31T23:59:59.000Z");
+    }
+
+    #[test]
+    fn exports_one_line_per_entry() {
+        let mut logs = Logs::new(Arc::new(Recorder::new(Path::new(LUA_DIR))));
+        logs.push(entry("started"));
+        logs.push(LogEntry {
+            level: Level::Error,
+            entity: Some("player".to_string()),
+            script: Some("on_tick".to_string()),
+            ..entry("fell off")
+        });
+        let path = std::env::temp_dir().join(format!("stacks-log-{}.txt", std::process::id()));
+        logs.export(path.to_str().unwrap()).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(
+            text,
+            "2024-02-29T12:34:56.789Z info started\n\
+             2024-02-29T12:34:56.789Z error player:on_tick: fell off\n"
+        );
+    }
+}
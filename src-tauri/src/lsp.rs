@@ -0,0 +1,1226 @@
+use crate::dap::read_message;
+use crate::runner::value;
+use crate::script_api::{self, Item, Kind};
+use crate::scripts::{check_script, script_file_name};
+use mlua::prelude::*;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "A language server for Stacks scripts, speaking LSP over stdin & stdout.
+
+Usage: stacks-lsp [--scene <file>]
+
+Options:
+  --scene <file>  the scene the scripts are from, to check what they message against
+  -h, --help      show this
+
+Scripts are files named after their entity & script, like player.on_tick.lua. Editors can give the
+scene as the `scene` initialization option instead.";
+
+/// JSON-RPC's code for a request the server doesn't know.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// LSP's numbers for kinds of completion & how bad a diagnostic is
+const VARIABLE: u8 = 6;
+const VALUE: u8 = 12;
+const ERROR: u8 = 1;
+const WARNING: u8 = 2;
+const INFORMATION: u8 = 3;
+
+fn completion_kind(kind: Kind) -> u8 {
+    match kind {
+        Kind::Method => 2,
+        Kind::Function => 3,
+        Kind::Field => 5,
+        Kind::Table => 9,
+        Kind::Event => 23,
+    }
+}
+
+/// What's in the scene scripts are from, for completing & checking the names they use.
+#[derive(Default)]
+struct Scene {
+    entities: BTreeMap<String, SceneEntity>,
+}
+
+struct SceneEntity {
+    entity_type: Option<String>,
+    /// Everything set on it, including properties scripts made up, like a door's `open`
+    properties: BTreeSet<String>,
+    scripts: BTreeSet<String>,
+}
+
+impl Scene {
+    /// Read a scene file's entities. Scene files are Lua, so they're run with nothing in reach.
+    fn load(content: &str) -> LuaResult<Scene> {
+        let lua = Lua::new();
+        let loaded: LuaTable = lua
+            .load(content)
+            .set_environment(lua.create_table()?)
+            .eval()?;
+        // scenes saved before versioning are just the entities
+        let entities: LuaTable = match loaded.get::<_, LuaValue>("version")? {
+            LuaValue::Integer(_) | LuaValue::Number(_) => loaded.get("entities")?,
+            _ => loaded,
+        };
+
+        let mut scene = Scene::default();
+        for pair in entities.pairs::<String, LuaTable>() {
+            let (id, entity) = pair?;
+            let keys = |table: LuaTable| {
+                table
+                    .pairs::<String, LuaValue>()
+                    .map(|pair| pair.map(|(key, _)| key))
+                    .collect::<LuaResult<BTreeSet<String>>>()
+            };
+            let scripts = match entity.get::<_, Option<LuaTable>>("scripts")? {
+                Some(scripts) => keys(scripts)?,
+                None => BTreeSet::new(),
+            };
+            scene.entities.insert(
+                id,
+                SceneEntity {
+                    entity_type: entity.get("type")?,
+                    properties: keys(entity)?,
+                    scripts,
+                },
+            );
+        }
+        Ok(scene)
+    }
+
+    fn has_script(&self, name: &str) -> bool {
+        self.entities
+            .values()
+            .any(|entity| entity.scripts.contains(name))
+    }
+}
+
+/// Which entity & script a document is, going by its file's name, like `door.on_click.lua`.
+#[derive(Default)]
+struct Script {
+    entity: Option<String>,
+    name: Option<String>,
+}
+
+impl Script {
+    fn new(uri: &str) -> Self {
+        match uri_path(uri).and_then(|path| script_file_name(&path)) {
+            Some((entity, name)) => Script {
+                entity: Some(entity),
+                name: Some(name),
+            },
+            None => Script::default(),
+        }
+    }
+}
+
+/// The file a `file://` URI points at.
+fn uri_path(uri: &str) -> Option<PathBuf> {
+    let mut encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::new();
+    while let Some((&byte, rest)) = encoded.split_first() {
+        let escaped = rest
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (byte, escaped) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                encoded = &rest[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                encoded = rest;
+            }
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // Windows paths come as `/C:/...`
+    match path.as_bytes() {
+        [b'/', _, b':', ..] => Some(PathBuf::from(&path[1..])),
+        _ => Some(PathBuf::from(path)),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Name(&'a str),
+    Number,
+    /// Whether it's still open at the end of the code, like one being typed
+    Str {
+        value: String,
+        open: bool,
+    },
+    Symbol(&'a str),
+    Comment {
+        open: bool,
+    },
+}
+
+/// A token, and the bytes of the code it came from.
+#[derive(Debug)]
+struct Lexeme<'a> {
+    token: Token<'a>,
+    start: usize,
+    end: usize,
+}
+
+const SYMBOLS: [&str; 10] = ["...", "..", "==", "~=", "<=", ">=", "//", "::", "<<", ">>"];
+
+/// The level of a long bracket like `[==[` at the start of the code, i.e. how many `=` it has.
+fn long_bracket(code: &str) -> Option<usize> {
+    let rest = code.strip_prefix('[')?;
+    let level = rest.bytes().take_while(|&b| b == b'=').count();
+    rest[level..].starts_with('[').then_some(level)
+}
+
+/// Split Lua code into tokens, well enough to find names & strings in it.
+/// Unfinished code is fine, since it's usually being typed.
+fn lex(code: &str) -> Vec<Lexeme<'_>> {
+    let mut lexemes = Vec::new();
+    let mut i = 0;
+    while let Some(c) = code[i..].chars().next() {
+        let start = i;
+        let rest = &code[i..];
+        let token = if c.is_whitespace() {
+            i += c.len_utf8();
+            continue;
+        } else if let Some(comment) = rest.strip_prefix("--") {
+            match long_bracket(comment) {
+                Some(level) => {
+                    let close = format!("]{}]", "=".repeat(level));
+                    let from = i + 2 + level + 2;
+                    let end = code[from..].find(&close).map(|at| from + at + close.len());
+                    i = end.unwrap_or(code.len());
+                    Token::Comment {
+                        open: end.is_none(),
+                    }
+                }
+                None => {
+                    i = rest.find('\n').map_or(code.len(), |at| i + at);
+                    Token::Comment {
+                        open: i == code.len(),
+                    }
+                }
+            }
+        } else if let Some(level) = long_bracket(rest) {
+            let close = format!("]{}]", "=".repeat(level));
+            let from = i + level + 2;
+            let (value, open) = match code[from..].find(&close) {
+                Some(at) => {
+                    i = from + at + close.len();
+                    (&code[from..from + at], false)
+                }
+                None => {
+                    i = code.len();
+                    (&code[from..], true)
+                }
+            };
+            // like Lua, a newline straight after the opening bracket isn't part of the string
+            let value = value.strip_prefix('\n').unwrap_or(value).to_string();
+            Token::Str { value, open }
+        } else if c == '"' || c == '\'' {
+            i += 1;
+            let mut value = String::new();
+            let mut open = true;
+            while let Some(next) = code[i..].chars().next() {
+                if next == '\n' {
+                    break;
+                }
+                i += next.len_utf8();
+                match next {
+                    _ if next == c => {
+                        open = false;
+                        break;
+                    }
+                    '\\' => {
+                        if let Some(escaped) = code[i..].chars().next() {
+                            i += escaped.len_utf8();
+                            value.push(match escaped {
+                                'n' => '\n',
+                                't' => '\t',
+                                other => other,
+                            });
+                        }
+                    }
+                    _ => value.push(next),
+                }
+            }
+            Token::Str { value, open }
+        } else if c.is_ascii_digit()
+            || (c == '.' && rest[1..].starts_with(|d: char| d.is_ascii_digit()))
+        {
+            let mut previous = c;
+            i += rest
+                .chars()
+                .take_while(|&d| {
+                    let part = d.is_ascii_alphanumeric()
+                        || d == '.'
+                        || ((d == '+' || d == '-') && "eEpP".contains(previous));
+                    previous = d;
+                    part
+                })
+                .count();
+            Token::Number
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            i += rest
+                .chars()
+                .take_while(|&d| d.is_ascii_alphanumeric() || d == '_')
+                .count();
+            Token::Name(&code[start..i])
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(*symbol))
+                .map_or(c.len_utf8(), |symbol| symbol.len());
+            i += symbol;
+            Token::Symbol(&code[start..i])
+        };
+        lexemes.push(Lexeme {
+            token,
+            start,
+            end: i,
+        });
+    }
+    lexemes
+}
+
+/// The tokens that are code, leaving out comments.
+fn code_tokens(lexemes: Vec<Lexeme>) -> Vec<Lexeme> {
+    lexemes
+        .into_iter()
+        .filter(|lexeme| !matches!(lexeme.token, Token::Comment { .. }))
+        .collect()
+}
+
+/// Whether the token at `i` comes after `.` or `:`, like the `message` in `helpers.message`.
+fn is_member(tokens: &[Lexeme], i: usize) -> bool {
+    i > 0 && matches!(tokens[i - 1].token, Token::Symbol("." | ":"))
+}
+
+/// A call's arguments, split at the commas between them.
+fn arguments<'t, 'a>(tokens: &'t [Lexeme<'a>]) -> Vec<&'t [Lexeme<'a>]> {
+    let mut arguments = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, lexeme) in tokens.iter().enumerate() {
+        match lexeme.token {
+            Token::Symbol("(" | "[" | "{") => depth += 1,
+            Token::Symbol(")" | "]" | "}") => depth -= 1,
+            Token::Symbol(",") if depth == 0 => {
+                arguments.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    arguments.push(&tokens[start..]);
+    arguments
+}
+
+/// The arguments of every call to the global function `name`.
+fn calls<'t, 'a>(tokens: &'t [Lexeme<'a>], name: &str) -> Vec<Vec<&'t [Lexeme<'a>]>> {
+    let mut calls = Vec::new();
+    for (i, lexeme) in tokens.iter().enumerate() {
+        if !matches!(lexeme.token, Token::Name(called) if called == name) || is_member(tokens, i) {
+            continue;
+        }
+        match tokens.get(i + 1).map(|next| &next.token) {
+            Some(Token::Symbol("(")) => {
+                let mut depth = 0;
+                let close = tokens[i + 1..]
+                    .iter()
+                    .position(|lexeme| {
+                        match lexeme.token {
+                            Token::Symbol("(" | "[" | "{") => depth += 1,
+                            Token::Symbol(")" | "]" | "}") => depth -= 1,
+                            _ => (),
+                        }
+                        depth == 0
+                    })
+                    .map_or(tokens.len(), |at| i + 1 + at);
+                calls.push(arguments(&tokens[i + 2..close]));
+            }
+            // `broadcast "knock"` is a call too
+            Some(Token::Str { .. }) => calls.push(vec![&tokens[i + 1..i + 2]]),
+            _ => (),
+        }
+    }
+    calls
+}
+
+/// An argument that's just a string, like `"door"`, and its token.
+fn string_argument<'t>(
+    arguments: &[&'t [Lexeme]],
+    index: usize,
+) -> Option<(&'t str, &'t Lexeme<'t>)> {
+    match arguments.get(index)? {
+        [lexeme @ Lexeme {
+            token: Token::Str { value, open: false },
+            ..
+        }] => Some((value, lexeme)),
+        _ => None,
+    }
+}
+
+/// The names in something like `self.body.velocity` at the end of the tokens, or none if it doesn't end in a name.
+fn path_ending<'a>(tokens: &[Lexeme<'a>]) -> Vec<&'a str> {
+    let mut path = Vec::new();
+    let mut i = tokens.len();
+    loop {
+        match tokens[..i].last().map(|lexeme| &lexeme.token) {
+            Some(Token::Name(name)) => path.push(*name),
+            _ => return Vec::new(),
+        }
+        i -= 1;
+        match tokens[..i].last().map(|lexeme| &lexeme.token) {
+            Some(Token::Symbol("." | ":")) => i -= 1,
+            _ => break,
+        }
+    }
+    path.reverse();
+    path
+}
+
+/// The global function call the end of the tokens is in the arguments of:
+/// its name, which argument the end is in, and the arguments so far.
+fn call_around<'t, 'a>(tokens: &'t [Lexeme<'a>]) -> Option<(&'a str, Vec<&'t [Lexeme<'a>]>)> {
+    let mut depth = 0;
+    for i in (0..tokens.len()).rev() {
+        match tokens[i].token {
+            Token::Symbol(")" | "]" | "}") => depth += 1,
+            Token::Symbol("(" | "[" | "{") if depth > 0 => depth -= 1,
+            Token::Symbol("(") => {
+                return match tokens[..i].last()?.token {
+                    Token::Name(name) if !is_member(tokens, i - 1) => {
+                        Some((name, arguments(&tokens[i + 1..])))
+                    }
+                    _ => None,
+                };
+            }
+            // in a table rather than a call
+            Token::Symbol("[" | "{") => return None,
+            _ => (),
+        }
+    }
+    None
+}
+
+/// What's being typed at the end of some code.
+enum Context<'t, 'a> {
+    /// A name on its own
+    Global,
+    /// A name after others, like `self.pos.` is `["self", "pos"]`, and whether it's after `:`
+    Member(Vec<&'a str>, bool),
+    /// A string in a call, with the function's name & the arguments so far, including the one being typed
+    Argument(&'a str, Vec<&'t [Lexeme<'a>]>),
+    Nothing,
+}
+
+fn context<'t, 'a>(tokens: &'t [Lexeme<'a>], end: usize) -> Context<'t, 'a> {
+    let last = tokens.last().filter(|lexeme| lexeme.end == end);
+    let before = match last.map(|lexeme| &lexeme.token) {
+        Some(Token::Str { open: true, .. }) => {
+            return match call_around(&tokens[..tokens.len() - 1]) {
+                Some((function, arguments)) => Context::Argument(function, arguments),
+                None => Context::Nothing,
+            };
+        }
+        Some(Token::Str { .. } | Token::Number) => return Context::Nothing,
+        Some(Token::Name(_)) => &tokens[..tokens.len() - 1],
+        _ => tokens,
+    };
+    match before.last().map(|lexeme| &lexeme.token) {
+        Some(Token::Symbol(separator @ ("." | ":"))) => {
+            match path_ending(&before[..before.len() - 1]) {
+                path if path.is_empty() => Context::Nothing,
+                path => Context::Member(path, *separator == ":"),
+            }
+        }
+        // naming something new
+        Some(Token::Name("local" | "function")) => Context::Nothing,
+        _ => Context::Global,
+    }
+}
+
+/// Where a byte is, as an LSP position: its line, and how far along it in UTF-16 code units.
+fn position(text: &str, byte: usize) -> Value {
+    let before = &text[..byte];
+    let line_start = before.rfind('\n').map_or(0, |at| at + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+/// The byte at an LSP position, or the end of its line if it's past it.
+fn offset(text: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or(0) as usize;
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+    let start: usize = text.split_inclusive('\n').take(line).map(str::len).sum();
+    let mut units = 0;
+    for (at, c) in text[start..].char_indices() {
+        if units >= character || c == '\n' {
+            return start + at;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+/// The byte at a 1-based line & column, counted in characters, like script diagnostics are.
+fn line_offset(text: &str, line: u32, column: u32) -> usize {
+    let start: usize = text
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1) as usize)
+        .map(str::len)
+        .sum();
+    text[start..]
+        .char_indices()
+        .nth(column.saturating_sub(1) as usize)
+        .map_or(text.len(), |(at, _)| start + at)
+}
+
+fn diagnostic(text: &str, start: usize, end: usize, severity: u8, message: String) -> Value {
+    json!({
+        "range": { "start": position(text, start), "end": position(text, end) },
+        "severity": severity,
+        "source": "stacks",
+        "message": message,
+    })
+}
+
+fn markdown(value: String) -> Value {
+    json!({ "kind": "markdown", "value": value })
+}
+
+/// What's in an event's `data`, as a markdown list.
+fn data_fields(event: &Item) -> String {
+    if event.fields.is_empty() {
+        return "\n\nIt doesn't come with anything in `data`.".to_string();
+    }
+    let mut text = "\n\n`data` has:".to_string();
+    for field in event.fields {
+        text += &format!("\n- `data.{}`: {}", field.name, field.doc);
+    }
+    text
+}
+
+fn documentation(item: &Item) -> String {
+    let mut text = item.doc.to_string();
+    if !item.types.is_empty() {
+        let types: Vec<String> = item.types.iter().map(|t| format!("`{}`", t)).collect();
+        text += &format!("\n\nOnly for {} entities.", types.join(", "));
+    }
+    if item.kind == Kind::Event {
+        text += &data_fields(item);
+    }
+    text
+}
+
+fn completion(item: &Item) -> Value {
+    json!({
+        "label": item.name,
+        "kind": completion_kind(item.kind),
+        "detail": item.signature,
+        "documentation": markdown(documentation(item)),
+    })
+}
+
+/// Script names from the scene, or just the ones on one entity, with the entities they're on.
+fn script_completions(scene: &Scene, only: Option<&str>) -> Vec<Value> {
+    let mut scripts: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (id, entity) in &scene.entities {
+        if only.is_none_or(|only| only == id) {
+            for script in &entity.scripts {
+                scripts.entry(script).or_default().push(id);
+            }
+        }
+    }
+    scripts
+        .into_iter()
+        .map(|(name, ids)| {
+            let mut completion = json!({
+                "label": name,
+                "kind": completion_kind(Kind::Event),
+                "detail": format!("on {}", ids.join(", ")),
+            });
+            if let Some(event) = script_api::event(name) {
+                completion["documentation"] = markdown(documentation(event));
+            }
+            completion
+        })
+        .collect()
+}
+
+fn send(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+struct Server {
+    /// The scene file scripts are checked against, given on the command line or by the editor
+    scene_path: Option<PathBuf>,
+    scene: Option<Scene>,
+    /// The text of each open document, by URI
+    documents: BTreeMap<String, String>,
+    /// For compiling scripts the same way the runtime does, to find syntax errors
+    lua: Lua,
+    shut_down: bool,
+}
+
+impl Server {
+    fn new(scene_path: Option<PathBuf>) -> Self {
+        let mut server = Server {
+            scene_path: None,
+            scene: None,
+            documents: BTreeMap::new(),
+            lua: Lua::new(),
+            shut_down: false,
+        };
+        server.set_scene(scene_path);
+        server
+    }
+
+    fn set_scene(&mut self, path: Option<PathBuf>) {
+        self.scene_path = path;
+        self.scene = None;
+        self.reload_scene();
+    }
+
+    /// Read the scene again, since the app may have saved it. If it can't be read, what was read last is kept.
+    fn reload_scene(&mut self) {
+        let Some(path) = &self.scene_path else {
+            return;
+        };
+        let loaded = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| Scene::load(&content).map_err(|e| e.to_string()));
+        match loaded {
+            Ok(scene) => self.scene = Some(scene),
+            Err(e) => eprintln!(
+                "[language server] Couldn't read scene \"{}\": {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    /// Answer messages until the editor says to exit, giving whether it shut the server down first.
+    fn run(&mut self, input: &mut impl BufRead, output: &mut impl Write) -> io::Result<bool> {
+        while let Some(message) = read_message(input)? {
+            let method = message["method"].as_str().unwrap_or_default();
+            let params = &message["params"];
+            match message.get("id") {
+                // a response, but the server never asks the editor anything
+                _ if method.is_empty() => (),
+                Some(id) => {
+                    let response = match self.request(method, params) {
+                        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                        Err((code, error)) => json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "error": { "code": code, "message": error },
+                        }),
+                    };
+                    send(output, &response)?;
+                }
+                None if method == "exit" => return Ok(self.shut_down),
+                None => {
+                    for notification in self.notify(method, params) {
+                        send(output, &notification)?;
+                    }
+                }
+            }
+        }
+        Ok(self.shut_down)
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        let document = || {
+            let uri = params["textDocument"]["uri"].as_str()?;
+            let text = self.documents.get(uri)?;
+            Some((uri, text, offset(text, &params["position"])))
+        };
+        match method {
+            "initialize" => {
+                // relative to the workspace, and only if it wasn't given on the command line
+                if let (None, Some(scene)) = (
+                    &self.scene_path,
+                    params["initializationOptions"]["scene"].as_str(),
+                ) {
+                    let root = params["rootUri"]
+                        .as_str()
+                        .and_then(uri_path)
+                        .unwrap_or_default();
+                    self.set_scene(Some(root.join(scene)));
+                }
+                Ok(json!({
+                    "capabilities": {
+                        "textDocumentSync": { "openClose": true, "change": 1, "save": true },
+                        "completionProvider": { "triggerCharacters": [".", ":", "\"", "'"] },
+                        "hoverProvider": true,
+                    },
+                    "serverInfo": { "name": "stacks-lsp" },
+                }))
+            }
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Value::Null)
+            }
+            "textDocument/completion" => Ok(match document() {
+                Some((uri, text, offset)) => json!(self.completions(uri, &text[..offset])),
+                None => json!([]),
+            }),
+            "textDocument/hover" => Ok(document()
+                .and_then(|(uri, text, offset)| self.hover(uri, text, offset))
+                .map_or(Value::Null, |hover| json!({ "contents": markdown(hover) }))),
+            _ => Err((
+                METHOD_NOT_FOUND,
+                format!("Stacks can't handle \"{}\" requests.", method),
+            )),
+        }
+    }
+
+    /// Keep up with the editor's documents, giving the notifications to send back.
+    fn notify(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri, text.to_string());
+            }
+            // the whole text comes every time
+            "textDocument/didChange" => {
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    self.documents.insert(uri, text.to_string());
+                }
+            }
+            "textDocument/didSave" => (),
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![publish(&uri, Vec::new())];
+            }
+            _ => return Vec::new(),
+        }
+        // the scene may have changed since too, changing what every script can message
+        self.reload_scene();
+        self.documents
+            .keys()
+            .map(|uri| publish(uri, self.diagnostics(uri)))
+            .collect()
+    }
+
+    fn entity(&self, script: &Script) -> Option<(&String, &SceneEntity)> {
+        self.scene
+            .as_ref()?
+            .entities
+            .get_key_value(script.entity.as_ref()?)
+    }
+
+    fn entity_type(&self, script: &Script) -> Option<&str> {
+        self.entity(script)?.1.entity_type.as_deref()
+    }
+
+    /// What's at a path like `["self", "body", "velocity"]`, where `data` is the script's event.
+    fn item(&self, script: &Script, path: &[&str]) -> Option<&'static Item> {
+        let (first, rest) = path.split_first()?;
+        let (mut item, rest) = match *first {
+            "self" => {
+                let (name, rest) = rest.split_first()?;
+                (script_api::property(name)?, rest)
+            }
+            "data" => (script_api::event(script.name.as_deref()?)?, rest),
+            name => (script_api::global(name)?, rest),
+        };
+        for name in rest {
+            item = item.field(name)?;
+        }
+        Some(item)
+    }
+
+    fn self_doc(&self, script: &Script) -> String {
+        let about = match self.entity(script) {
+            Some((
+                id,
+                SceneEntity {
+                    entity_type: Some(entity_type),
+                    ..
+                },
+            )) => format!(": `{}`, a `{}`", id, entity_type),
+            Some((id, _)) => format!(": `{}`", id),
+            None => String::new(),
+        };
+        format!("The entity this script belongs to{}.", about)
+    }
+
+    fn data_doc(&self, script: &Script) -> String {
+        match script.name.as_deref().and_then(script_api::event) {
+            Some(event) => format!("What `{}` scripts are given.{}", event.name, data_fields(event)),
+            None => "What was passed along with the message or broadcast that ran this script, if anything."
+                .to_string(),
+        }
+    }
+
+    /// Syntax errors, and names in messages, broadcasts & timers that aren't in the scene.
+    fn diagnostics(&self, uri: &str) -> Vec<Value> {
+        let Some(text) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+        let script = Script::new(uri);
+        let mut diagnostics = Vec::new();
+        if let Some(error) = check_script(
+            &self.lua,
+            script.entity.as_deref().unwrap_or("script"),
+            script.name.as_deref().unwrap_or("script"),
+            text,
+        ) {
+            let start = line_offset(text, error.line, error.column);
+            let end = text[start..].find('\n').map_or(text.len(), |at| start + at);
+            diagnostics.push(diagnostic(text, start, end, ERROR, error.message));
+        }
+        let Some(scene) = &self.scene else {
+            return diagnostics;
+        };
+
+        let tokens = code_tokens(lex(text));
+        let mut flag = |lexeme: &Lexeme, severity: u8, message: String| {
+            diagnostics.push(diagnostic(
+                text,
+                lexeme.start,
+                lexeme.end,
+                severity,
+                message,
+            ))
+        };
+        for arguments in calls(&tokens, "message") {
+            let Some((target, lexeme)) = string_argument(&arguments, 0) else {
+                continue;
+            };
+            let Some(entity) = scene.entities.get(target) else {
+                flag(
+                    lexeme,
+                    WARNING,
+                    format!("There's no entity called \"{}\" in the scene.", target),
+                );
+                continue;
+            };
+            if let Some((event, lexeme)) = string_argument(&arguments, 1) {
+                if !entity.scripts.contains(event) {
+                    flag(
+                        lexeme,
+                        WARNING,
+                        format!("\"{}\" has no script called \"{}\".", target, event),
+                    );
+                }
+            }
+        }
+        // broadcasting to no one isn't a mistake, if the script's added later
+        for arguments in calls(&tokens, "broadcast") {
+            if let Some((event, lexeme)) = string_argument(&arguments, 0) {
+                if !scene.has_script(event) {
+                    flag(
+                        lexeme,
+                        INFORMATION,
+                        format!("No entity in the scene has a script called \"{}\".", event),
+                    );
+                }
+            }
+        }
+        // timers given a script's name run it on the same entity
+        if let Some((id, entity)) = self.entity(&script) {
+            for arguments in calls(&tokens, "after")
+                .into_iter()
+                .chain(calls(&tokens, "every"))
+            {
+                if let Some((callback, lexeme)) = string_argument(&arguments, 1) {
+                    if !entity.scripts.contains(callback) {
+                        flag(
+                            lexeme,
+                            WARNING,
+                            format!(
+                                "\"{}\" has no script called \"{}\" for the timer to run.",
+                                id, callback
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+        diagnostics
+    }
+
+    /// Completions for the end of `code`, which is a document up to the cursor.
+    fn completions(&self, uri: &str, code: &str) -> Vec<Value> {
+        let lexemes = lex(code);
+        if matches!(
+            lexemes.last(),
+            Some(Lexeme {
+                token: Token::Comment { open: true },
+                ..
+            })
+        ) {
+            return Vec::new();
+        }
+        let tokens = code_tokens(lexemes);
+        let script = Script::new(uri);
+        match context(&tokens, code.len()) {
+            Context::Global => {
+                let mut completions: Vec<Value> =
+                    script_api::GLOBALS.iter().map(completion).collect();
+                completions.push(json!({
+                    "label": "self",
+                    "kind": VARIABLE,
+                    "documentation": markdown(self.self_doc(&script)),
+                }));
+                completions.push(json!({
+                    "label": "data",
+                    "kind": VARIABLE,
+                    "documentation": markdown(self.data_doc(&script)),
+                }));
+                completions
+            }
+            Context::Member(path, method) if path == ["self"] => {
+                let entity_type = self.entity_type(&script);
+                let mut completions: Vec<Value> = script_api::PROPERTIES
+                    .iter()
+                    .filter(|item| item.applies_to(entity_type))
+                    .filter(|item| (item.kind == Kind::Method) == method)
+                    .map(completion)
+                    .collect();
+                // ones scripts made up, like a door's `open`
+                if let (Some((id, entity)), false) = (self.entity(&script), method) {
+                    completions.extend(
+                        entity
+                            .properties
+                            .iter()
+                            .filter(|name| *name != "scripts" && script_api::property(name).is_none())
+                            .map(|name| {
+                                json!({
+                                    "label": name,
+                                    "kind": completion_kind(Kind::Field),
+                                    "documentation": markdown(format!("Set on `{}` in the scene.", id)),
+                                })
+                            }),
+                    );
+                }
+                completions
+            }
+            Context::Member(path, method) => self
+                .item(&script, &path)
+                .map(|item| {
+                    item.fields
+                        .iter()
+                        .filter(|field| (field.kind == Kind::Method) == method)
+                        .map(completion)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            Context::Argument(function, arguments) => {
+                let Some(scene) = &self.scene else {
+                    return Vec::new();
+                };
+                let ids = || {
+                    scene
+                        .entities
+                        .iter()
+                        .map(|(id, entity)| {
+                            json!({ "label": id, "kind": VALUE, "detail": entity.entity_type })
+                        })
+                        .collect()
+                };
+                match (function, arguments.len() - 1) {
+                    ("message" | "touching", 0) => ids(),
+                    ("message", 1) => {
+                        let target = string_argument(&arguments, 0).map(|(target, _)| target);
+                        script_completions(
+                            scene,
+                            target.filter(|t| scene.entities.contains_key(*t)),
+                        )
+                    }
+                    ("broadcast", 0) => script_completions(scene, None),
+                    ("after" | "every", 1) => match &script.entity {
+                        Some(id) => script_completions(scene, Some(id)),
+                        None => Vec::new(),
+                    },
+                    _ => Vec::new(),
+                }
+            }
+            Context::Nothing => Vec::new(),
+        }
+    }
+
+    /// Documentation for the name at a byte in a document, as markdown.
+    fn hover(&self, uri: &str, text: &str, offset: usize) -> Option<String> {
+        let tokens = code_tokens(lex(text));
+        let index = tokens.iter().position(|lexeme| {
+            matches!(lexeme.token, Token::Name(_)) && lexeme.start <= offset && offset <= lexeme.end
+        })?;
+        let path = path_ending(&tokens[..=index]);
+        let script = Script::new(uri);
+        let (signature, doc) = match path.as_slice() {
+            ["self"] => ("self", self.self_doc(&script)),
+            ["data"] => ("data", self.data_doc(&script)),
+            _ => {
+                let item = self.item(&script, &path)?;
+                (item.signature, documentation(item))
+            }
+        };
+        Some(format!("```lua\n{}\n```\n{}", signature, doc))
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Option<PathBuf>, String> {
+    let mut scene = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scene" => scene = Some(value(&arg, args.next())?),
+            // what editors pass to say to talk over stdio, which is the only way this server talks
+            "--stdio" => (),
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+    Ok(scene)
+}
+
+/// Serve an editor over stdin & stdout, until it says to exit.
+pub fn run_language_server() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let scene = match parse_options(args.into_iter()) {
+        Ok(scene) => scene,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match Server::new(scene).run(&mut io::stdin().lock(), &mut io::stdout().lock()) {
+        Ok(true) => ExitCode::SUCCESS,
+        // exiting without being shut down first is an error, by LSP's rules
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("[language server] {}", e);
+            ExitCode::from(1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::path::Path;
+
+    const DOOR: &str = "file:///game/door.on_click.lua";
+
+    fn door_scene() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("scenes")
+            .join("door.lua")
+    }
+
+    fn labels(completions: Vec<Value>) -> Vec<String> {
+        completions
+            .iter()
+            .map(|completion| completion["label"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn completes_properties_for_the_entity_type() {
+        let server = Server::new(Some(door_scene()));
+        let door = labels(server.completions(DOOR, "self."));
+        for label in ["pos", "color", "size", "open"] {
+            assert!(
+                door.contains(&label.to_string()),
+                "no {} in {:?}",
+                label,
+                door
+            );
+        }
+        // the bell's text, so it has no colour or size
+        let bell = labels(server.completions("file:///game/bell.on_click.lua", "self."));
+        assert!(bell.contains(&"content".to_string()));
+        assert!(!bell
+            .iter()
+            .any(|label| ["color", "size", "image", "open"].contains(&label.as_str())));
+        assert_eq!(
+            labels(server.completions(DOOR, "if self.body.velocity.")),
+            ["x", "y"]
+        );
+        assert!(labels(server.completions(DOOR, "local pos = self:")).is_empty());
+    }
+
+    #[test]
+    fn completes_what_events_give_in_data() {
+        let server = Server::new(Some(door_scene()));
+        assert_eq!(
+            labels(server.completions("file:///game/name.on_change.lua", "print(data.")),
+            ["text"]
+        );
+        assert_eq!(
+            labels(server.completions("file:///game/door.on_mouse_down.lua", "data.")),
+            ["button", "x", "y"]
+        );
+        let globals = labels(server.completions(DOOR, "local x = in"));
+        for label in ["broadcast", "message", "emit", "input", "self", "data"] {
+            assert!(
+                globals.contains(&label.to_string()),
+                "no {} in {:?}",
+                label,
+                globals
+            );
+        }
+        assert!(labels(server.completions(DOOR, "input.")).contains(&"is_down".to_string()));
+        assert!(server.completions(DOOR, "-- input.").is_empty());
+    }
+
+    #[test]
+    fn completes_entities_and_scripts_in_the_scene() {
+        let server = Server::new(Some(door_scene()));
+        assert_eq!(
+            labels(server.completions(DOOR, "message(\"")),
+            ["bell", "door"]
+        );
+        assert_eq!(
+            labels(server.completions(DOOR, "message(\"door\", 'k")),
+            ["knock", "lock", "on_click"]
+        );
+        assert_eq!(
+            labels(server.completions(DOOR, "broadcast(\"")),
+            ["knock", "lock", "on_click"]
+        );
+        assert_eq!(
+            labels(server.completions(DOOR, "after(2 * 3, \"")),
+            ["knock", "lock", "on_click"]
+        );
+        assert!(server.completions(DOOR, "print(\"").is_empty());
+    }
+
+    #[test]
+    fn flags_names_that_arent_in_the_scene() {
+        let mut server = Server::new(Some(door_scene()));
+        server.documents.insert(
+            DOOR.to_string(),
+            "message(\"doer\", \"knock\")\n\
+             message(\"door\", \"knok\", { loud = true })\n\
+             message(\"door\", \"knock\")\n\
+             broadcast \"ring\"\n\
+             after(1, \"lok\")\n\
+             -- message(\"nobody\")\n\
+             if self.open then\n"
+                .to_string(),
+        );
+        let diagnostics = server.diagnostics(DOOR);
+        let found: Vec<(u64, &str)> = diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    d["range"]["start"]["line"].as_u64().unwrap(),
+                    d["message"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (7, "'end' expected (to close 'if' at line 7) near <eof>"),
+                (0, "There's no entity called \"doer\" in the scene."),
+                (1, "\"door\" has no script called \"knok\"."),
+                (3, "No entity in the scene has a script called \"ring\"."),
+                (
+                    4,
+                    "\"door\" has no script called \"lok\" for the timer to run."
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn hovers_show_the_docs() {
+        let server = Server::new(Some(door_scene()));
+        let hover = |uri: &str, text: &str, at: &str| {
+            server
+                .hover(uri, text, text.find(at).unwrap())
+                .unwrap_or_default()
+        };
+        assert!(hover(DOOR, "broadcast(\"knock\")", "broadcast").contains("broadcast(event, data)"));
+        assert!(hover(DOOR, "self.open = true", "self").contains("`door`, a `rect`"));
+        assert!(hover(DOOR, "self.body.bounce = 1", "bounce").contains("speed it keeps"));
+        assert!(hover(
+            "file:///game/name.on_change.lua",
+            "print(data.text)",
+            "data"
+        )
+        .contains("`data.text`"));
+        assert!(server.hover(DOOR, "local x = 1", 7).is_none());
+    }
+
+    #[test]
+    fn talks_lsp_over_stdio() {
+        let mut input = Vec::new();
+        for message in [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {
+                "rootUri": "file:///nowhere",
+                "initializationOptions": { "scene": door_scene() },
+            }}),
+            json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": { "textDocument": {
+                "uri": DOOR, "languageId": "lua", "version": 1, "text": "message(\"\")",
+            }}}),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "textDocument/completion", "params": {
+                "textDocument": { "uri": DOOR }, "position": { "line": 0, "character": 9 },
+            }}),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "shutdown" }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+        ] {
+            send(&mut input, &message).unwrap();
+        }
+        let mut output = Vec::new();
+        let shut_down = Server::new(None)
+            .run(&mut Cursor::new(input), &mut output)
+            .unwrap();
+        assert!(shut_down);
+
+        let mut output = Cursor::new(output);
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut output).unwrap() {
+            messages.push(message);
+        }
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0]["result"]["capabilities"]["hoverProvider"], true);
+        assert_eq!(messages[1]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(
+            messages[1]["params"]["diagnostics"][0]["message"],
+            "There's no entity called \"\" in the scene."
+        );
+        assert_eq!(
+            labels(messages[2]["result"].as_array().unwrap().clone()),
+            ["bell", "door"]
+        );
+        assert_eq!(messages[3]["result"], Value::Null);
+    }
+}
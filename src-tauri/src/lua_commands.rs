@@ -1,5 +1,14 @@
+use crate::debugger::{DebugCommand, Paused, Variable};
+use crate::input::InputEvent;
+use crate::logs::LogEntry;
 use crate::lua_types::{LuaMessage, LuaState};
+use crate::pins::PinInfo;
+use crate::profiler::ProfileReport;
+use crate::script_errors::ScriptError;
+use crate::scripts::Diagnostic;
+use crate::snapshots::Timeline;
 use serde_json::Value;
+use std::collections::HashMap;
 use std::sync::mpsc;
 use tauri::State;
 
@@ -11,6 +20,14 @@ pub async fn tick(state: State<'_, LuaState>, dt: f64) -> Result<(), String> {
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn input_event(state: State<'_, LuaState>, event: InputEvent) -> Result<(), String> {
+    state
+        .tx
+        .send(LuaMessage::Input(event))
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn new_entity(state: State<'_, LuaState>, data: Value) -> Result<(bool, String), String> {
     if !data.as_object().unwrap().contains_key("id") {
@@ -157,3 +174,349 @@ pub async fn handle_inspector_save(
         .map_err(|e| e.to_string())?;
     response_rx.recv().map_err(|e| e.to_string())
 }
+
+#[tauri::command]
+pub async fn set_script_error_options(
+    state: State<'_, LuaState>,
+    auto_disable: bool,
+) -> Result<(), String> {
+    state
+        .tx
+        .send(LuaMessage::SetScriptErrorOptions(auto_disable))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_script_errors(state: State<'_, LuaState>) -> Result<Vec<ScriptError>, String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::GetScriptErrors(response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn clear_script_errors(state: State<'_, LuaState>) -> Result<(), String> {
+    state
+        .tx
+        .send(LuaMessage::ClearScriptErrors)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_logs(state: State<'_, LuaState>) -> Result<Vec<LogEntry>, String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::GetLogs(response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn clear_logs(state: State<'_, LuaState>) -> Result<(), String> {
+    state
+        .tx
+        .send(LuaMessage::ClearLogs)
+        .map_err(|e| e.to_string())
+}
+
+/// Run a snippet of Lua in the live scene, optionally as an entity's `self`,
+/// returning what it evaluates to.
+#[tauri::command]
+pub async fn eval(
+    state: State<'_, LuaState>,
+    code: String,
+    entity: Option<String>,
+) -> Result<(bool, String), String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::Eval(code, entity, response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_eval_history(state: State<'_, LuaState>) -> Result<Vec<String>, String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::GetEvalHistory(response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())
+}
+
+/// Sample a Lua expression at the end of every tick, sent as `pin_samples`.
+#[tauri::command]
+pub async fn pin_expression(
+    state: State<'_, LuaState>,
+    expression: String,
+) -> Result<PinInfo, String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::Pin(expression, response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn unpin_expression(state: State<'_, LuaState>, id: u32) -> Result<(), String> {
+    state
+        .tx
+        .send(LuaMessage::Unpin(id))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_pins(state: State<'_, LuaState>) -> Result<Vec<PinInfo>, String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::GetPins(response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())
+}
+
+/// Start or stop timing the runtime & scripts. Starting throws away the last measurements.
+#[tauri::command]
+pub async fn set_profiling(state: State<'_, LuaState>, enabled: bool) -> Result<(), String> {
+    state
+        .tx
+        .send(LuaMessage::SetProfiling(enabled))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn reset_profile(state: State<'_, LuaState>) -> Result<(), String> {
+    state
+        .tx
+        .send(LuaMessage::ResetProfile)
+        .map_err(|e| e.to_string())
+}
+
+/// How long each step of the tick & each entity's scripts have taken while profiling.
+#[tauri::command]
+pub async fn profile_report(state: State<'_, LuaState>) -> Result<ProfileReport, String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::GetProfileReport(response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())
+}
+
+/// Save what was profiled as Chrome trace events.
+#[tauri::command]
+pub async fn export_profile_trace(
+    state: State<'_, LuaState>,
+    path: String,
+) -> Result<(bool, String), String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::ExportProfileTrace(path, response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())
+}
+
+/// Save the log to a text file, one line per entry.
+#[tauri::command]
+pub async fn export_logs(
+    state: State<'_, LuaState>,
+    path: String,
+) -> Result<(bool, String), String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::ExportLogs(path, response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())
+}
+
+/// Where an asset, like a sprite's `image`, is found on disk, for the frontend to load.
+#[tauri::command]
+pub async fn resolve_asset(state: State<'_, LuaState>, name: String) -> Result<String, String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::ResolveAsset(name, response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn check_scripts(
+    state: State<'_, LuaState>,
+    id: Option<String>,
+    scripts: Option<Value>,
+) -> Result<Vec<Diagnostic>, String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::CheckScripts(id, scripts, response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn start_recording(state: State<'_, LuaState>) -> Result<(bool, String), String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::StartRecording(response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn stop_recording(
+    state: State<'_, LuaState>,
+    path: String,
+) -> Result<(bool, String), String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::StopRecording(path, response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())
+}
+
+/// Play back a recording, checking the scene ends up the way it did when it was recorded.
+#[tauri::command]
+pub async fn replay(state: State<'_, LuaState>, path: String) -> Result<(bool, String), String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::Replay(path, response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_timeline(state: State<'_, LuaState>) -> Result<Timeline, String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::GetTimeline(response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())
+}
+
+/// Pause the scene & show it as it was at an earlier tick.
+#[tauri::command]
+pub async fn view_snapshot(
+    state: State<'_, LuaState>,
+    tick: u64,
+) -> Result<(bool, String), String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::ViewSnapshot(tick, response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_snapshot_entity(
+    state: State<'_, LuaState>,
+    tick: u64,
+    id: String,
+) -> Result<(bool, String), String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::GetSnapshotEntity(tick, id, response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())
+}
+
+/// Unpause the scene, carrying on from `tick` if given (forgetting everything after it),
+/// or from where it was paused if not.
+#[tauri::command]
+pub async fn resume_from_snapshot(
+    state: State<'_, LuaState>,
+    tick: Option<u64>,
+) -> Result<(bool, String), String> {
+    let (response_tx, response_rx) = mpsc::channel();
+    state
+        .tx
+        .send(LuaMessage::ResumeFromSnapshot(tick, response_tx))
+        .map_err(|e| e.to_string())?;
+    response_rx.recv().map_err(|e| e.to_string())
+}
+
+/// Set the lines a script pauses on, replacing any it had.
+#[tauri::command]
+pub async fn set_breakpoints(
+    state: State<'_, LuaState>,
+    entity: String,
+    script: String,
+    lines: Vec<u32>,
+) -> Result<(), String> {
+    state.debugger.set_breakpoints(&entity, &script, lines);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_breakpoints(
+    state: State<'_, LuaState>,
+) -> Result<HashMap<String, HashMap<String, Vec<u32>>>, String> {
+    Ok(state.debugger.breakpoints())
+}
+
+/// Set the expressions worked out every time the scene pauses.
+#[tauri::command]
+pub async fn set_watches(state: State<'_, LuaState>, watches: Vec<String>) -> Result<(), String> {
+    state.debugger.set_watches(watches);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_watches(state: State<'_, LuaState>) -> Result<Vec<String>, String> {
+    Ok(state.debugger.watches())
+}
+
+/// Where the scene is paused, if it is.
+#[tauri::command]
+pub async fn get_paused(state: State<'_, LuaState>) -> Result<Option<Paused>, String> {
+    Ok(state.debugger.paused())
+}
+
+#[tauri::command]
+pub async fn pause_scripts(state: State<'_, LuaState>) -> Result<(), String> {
+    state.debugger.request_pause();
+    Ok(())
+}
+
+/// Carry on from a pause: `continue`, or step `in`, `over` or `out`.
+#[tauri::command]
+pub async fn debugger_step(state: State<'_, LuaState>, action: String) -> Result<(), String> {
+    state.debugger.send(match action.as_str() {
+        "continue" => DebugCommand::Continue,
+        "in" => DebugCommand::StepIn,
+        "over" => DebugCommand::StepOver,
+        "out" => DebugCommand::StepOut,
+        _ => return Err(format!("Unknown debugger action \"{}\"", action)),
+    })
+}
+
+#[tauri::command]
+pub async fn get_locals(state: State<'_, LuaState>, frame: usize) -> Result<Vec<Variable>, String> {
+    state.debugger.locals(frame)
+}
+
+/// Work out an expression (or run a statement) in a paused frame.
+#[tauri::command]
+pub async fn evaluate_in_frame(
+    state: State<'_, LuaState>,
+    expression: String,
+    frame: usize,
+) -> Result<(bool, String), String> {
+    Ok(match state.debugger.evaluate(expression, frame) {
+        Ok(value) => (true, value),
+        Err(e) => (false, e),
+    })
+}
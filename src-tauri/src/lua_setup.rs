@@ -1,34 +1,105 @@
+use crate::assets::assets_mut;
+use crate::audio::{self, advance_audio, audio_mut, AudioBackend, SharedMixer};
+use crate::camera::{camera_mut, canvas_mut, update_camera, Camera, Canvas};
+use crate::collisions::{collisions_mut, detect_collisions};
+use crate::debugger::{attach as attach_debugger, DebugCommand, Debugger};
+use crate::gamepad::{self, poll_gamepads, GamepadBackend};
+use crate::host::Host;
+use crate::input::{dispatch_input, input_mut};
+use crate::logs::{log_fault, logs_mut};
 use crate::lua_types::{LuaError, LuaMessage, LuaState};
+use crate::physics::{physics_mut, step_physics};
+use crate::pins::{pin, pins_mut, sample_pins, unpin, Pins};
+use crate::profiler::{profile_phase, profiler_mut, with_cause, Kind, Profiler};
+use crate::random::{random_mut, Random};
+use crate::recording::{
+    is_recording, record, record_scene, replay, start_recording, stop_recording, Recorded,
+};
+use crate::repl::{eval, repl_mut, Repl};
+use crate::scheduler::{run_due_tasks, scheduler_mut, warn_unsaved_tasks, SavedTimer};
+use crate::script_errors::{error_handler, ScriptError, ScriptErrors};
+use crate::scripts::{check_script, compile_error_message, compile_script, Diagnostic};
+use crate::snapshots::{resume, snapshot_entity, snapshots_mut, take_snapshot, view_snapshot};
+use crate::sprites::advance_sprites;
+use crate::tweens::{advance_tweens, tweens_mut};
 use mlua::prelude::*;
 use std::fs;
 use std::path::Path;
-use std::sync::mpsc;
-use tauri::{Emitter, Manager, WebviewWindow};
-use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
 
-pub fn init_lua_thread(window: WebviewWindow) -> Result<LuaState, LuaError> {
+pub fn init_lua_thread(host: impl Host) -> Result<LuaState, LuaError> {
     let (tx, rx) = mpsc::channel(); // create communication channel
+    let (debugger, debug_commands) = Debugger::new();
+    let thread_debugger = debugger.clone();
     let _ = std::thread::Builder::new()
         .name("Lua Environment".to_string())
         .spawn(move || -> Result<(), LuaError> {
-            let lua = Lua::new();
-            set_globals(&lua, window)?;
+            let devices = Devices {
+                audio: audio::default_backend,
+                gamepads: gamepad::default_backend,
+            };
+            let lua = create_lua(Arc::new(host), thread_debugger, debug_commands, devices)?;
             while let Ok(msg) = rx.recv() {
-                match_message(&lua, msg)?;
+                let recorded = match is_recording(&lua) {
+                    true => Recorded::from_message(&msg),
+                    false => None,
+                };
+                // script errors are reported through the error channel, so anything
+                // reaching here is a runtime fault that shouldn't take the thread down
+                if let Err(e) = match_message(&lua, msg) {
+                    log_fault(&lua, "lua thread", &e);
+                }
+                // recorded after it's handled, so anything it causes (like gamepad input) comes first
+                if let Some(recorded) = recorded {
+                    record(&lua, recorded);
+                }
             }
             Ok(())
         });
-    Ok(LuaState { tx })
+    Ok(LuaState { tx, debugger })
+}
+
+/// Hardware the runtime uses, opened as the Lua state is set up.
+/// Headless runs use stand-ins, so they never touch the real thing.
+pub struct Devices {
+    pub audio: fn(&SharedMixer) -> Box<dyn AudioBackend>,
+    pub gamepads: fn() -> Box<dyn GamepadBackend>,
 }
 
-fn set_globals(lua: &Lua, window: WebviewWindow) -> Result<(), LuaError> {
-    let w_emit = window.clone();
+/// A Lua state with the runtime set up in it, ready for `match_message`.
+pub fn create_lua(
+    host: Arc<dyn Host>,
+    debugger: Debugger,
+    debug_commands: Receiver<DebugCommand>,
+    devices: Devices,
+) -> Result<Lua, LuaError> {
+    // the debug library is only for the debugger, which takes it away from scripts.
+    // It can only be loaded unsafely, so C modules are turned off like a safe Lua does
+    let lua = unsafe {
+        Lua::unsafe_new_with(
+            LuaStdLib::ALL_SAFE | LuaStdLib::DEBUG,
+            LuaOptions::default(),
+        )
+    };
+    lua.load(
+        "package.loadlib = nil; package.cpath = ''
+        table.remove(package.searchers, 4); table.remove(package.searchers, 3)",
+    )
+    .exec()?;
+    set_globals(&lua, host, devices)?;
+    attach_debugger(&lua, debugger, debug_commands)?;
+    Ok(lua)
+}
+
+fn set_globals(lua: &Lua, host: Arc<dyn Host>, devices: Devices) -> Result<(), LuaError> {
+    let w_emit = host.clone();
     lua.globals().set(
         "emit",
-        lua.create_function(move |_: &Lua, (evt, data): (String, LuaValue)| {
-            let json = serde_json::to_value(&data)
+        lua.create_function(move |l: &Lua, (evt, data): (String, LuaValue)| {
+            let json = profile_phase(l, "serialize", || serde_json::to_value(&data))
                 .map_err(|e| LuaError::FormatError(format!("JSON conversion error: {}", e)))?;
-            w_emit.emit(&evt, json).map_err(|e| {
+            profile_phase(l, "emit", || w_emit.emit(&evt, json)).map_err(|e| {
                 LuaError::CommunicationError(format!("Couldn't emit event {}: {}", evt, e))
             })?;
             Ok(())
@@ -38,21 +109,19 @@ fn set_globals(lua: &Lua, window: WebviewWindow) -> Result<(), LuaError> {
         })?,
     )?;
 
-    let w_emit_to = window.clone();
+    let w_emit_to = host.clone();
     lua.globals().set(
         "emit_to",
         lua.create_function(
             move |_: &Lua, (evt, window_label, data): (String, String, LuaValue)| {
                 let json = serde_json::to_value(&data)
                     .map_err(|e| LuaError::FormatError(format!("JSON conversion error: {}", e)))?;
-                w_emit_to
-                    .emit_to(window_label.clone(), &evt, json)
-                    .map_err(|e| {
-                        LuaError::CommunicationError(format!(
-                            "Failed to emit event {} to {}: {}",
-                            evt, window_label, e
-                        ))
-                    })?;
+                w_emit_to.emit_to(&window_label, &evt, json).map_err(|e| {
+                    LuaError::CommunicationError(format!(
+                        "Failed to emit event {} to {}: {}",
+                        evt, window_label, e
+                    ))
+                })?;
                 Ok(())
             },
         )
@@ -61,48 +130,116 @@ fn set_globals(lua: &Lua, window: WebviewWindow) -> Result<(), LuaError> {
         })?,
     )?;
 
+    // compile scripts under their own chunk names, so errors point at the right script & line
+    lua.globals()
+        .set(
+            "compile_script",
+            lua.create_function(
+                |l: &Lua, (entity, script, source): (String, String, String)| match compile_script(
+                    l, &entity, &script, &source,
+                ) {
+                    Ok(func) => Ok((Some(func), None)),
+                    Err(e) => Ok((None, Some(compile_error_message(&e)))),
+                },
+            )?,
+        )
+        .map_err(|e| {
+            LuaError::InitializationError(format!("Failed to set Lua compile function: {}", e))
+        })?;
+
+    // script errors
+    lua.set_app_data(ScriptErrors::new());
+    lua.globals()
+        .set(
+            "script_error_handler",
+            lua.create_function(|l: &Lua, err: LuaValue| error_handler(l, err))?,
+        )
+        .map_err(|e| {
+            LuaError::InitializationError(format!("Failed to set script error handler: {}", e))
+        })?;
+
+    let w_error = host.clone();
+    lua.globals().set(
+        "report_script_error",
+        lua.create_function(
+            move |l: &Lua, (entity, script, details, failures): (String, String, LuaTable, u32)| {
+                let mut errors = l.app_data_mut::<ScriptErrors>().ok_or_else(|| {
+                    LuaError::InitializationError("Script error channel missing".to_string())
+                })?;
+                let disable = errors.should_disable(failures);
+                if let Some(error) = errors.report(ScriptError {
+                    entity,
+                    script,
+                    line: details.get("line")?,
+                    message: details.get("message")?,
+                    traceback: details.get("traceback")?,
+                    count: 1,
+                    disabled: disable,
+                }) {
+                    let error = serde_json::to_value(error).map_err(|e| {
+                        LuaError::FormatError(format!("JSON conversion error: {}", e))
+                    })?;
+                    w_error.emit("script_error", error).map_err(|e| {
+                        LuaError::CommunicationError(format!("Couldn't emit script error: {}", e))
+                    })?;
+                }
+                Ok(disable)
+            },
+        )
+        .map_err(|e| {
+            LuaError::InitializationError(format!("Failed to create Lua error reporter: {}", e))
+        })?,
+    )?;
+
+    // coroutines & timers
+    crate::script_runner::set_globals(lua)?;
+    crate::scheduler::set_globals(lua)?;
+    crate::tweens::set_globals(lua)?;
+    crate::input::set_globals(lua)?;
+    crate::gamepad::set_globals(lua, (devices.gamepads)())?;
+    crate::physics::set_globals(lua)?;
+    crate::collisions::set_globals(lua)?;
+    crate::assets::set_globals(lua)?;
+    crate::audio::set_globals(lua, devices.audio)?;
+    crate::camera::set_globals(lua)?;
+    crate::random::set_globals(lua)?;
+    crate::snapshots::set_globals(lua)?;
+    crate::logs::set_globals(lua, host.clone())?;
+
+    // snippets run from the console
+    lua.set_app_data(Repl::new());
+
+    // timings, once the profiler's turned on
+    lua.set_app_data(Profiler::new());
+
+    // expressions sampled every tick
+    lua.set_app_data(Pins::new());
+
+    // where the runtime is running, for anything that needs a Lua state of its own like replays
+    lua.set_app_data(host.clone());
+
     // broadcasting
-    let w_broadcast = window.clone();
     lua.globals().set(
         "broadcast",
-        lua.create_function(move |l: &Lua, (event, data): (LuaValue, LuaValue)| {
+        lua.create_function(|l: &Lua, (event, data): (LuaValue, LuaValue)| {
             if !event.is_string() {
-                let _ = w_broadcast
-                    .app_handle()
-                    .dialog()
-                    .message(format!(
-                        "Event parameter must be a string.\nProvided: {}",
-                        event.type_name()
-                    ))
-                    .kind(MessageDialogKind::Error)
-                    .title("Broadcast failed")
-                    .blocking_show();
-                return Ok(());
+                return Err(LuaError::FormatError(format!(
+                    "Broadcast event must be a string, not {}.",
+                    event.type_name()
+                ))
+                .into());
             }
             let scene = get_scene(l)?;
-            let (success, error): (bool, Option<String>) =
-                l.globals().get::<_, LuaFunction>("pcall")?.call((
-                    scene
-                        .get::<_, LuaFunction>("handle_broadcast")
-                        .map_err(|e| LuaError::LuaError(e))?,
-                    scene,
-                    event,
-                    if let Some(table) = data.as_table() {
-                        serialized_table(&l, table)?.into_lua(l)?
-                    } else {
-                        LuaNil
-                    },
-                ))?;
-            if !success {
-                let _ = w_broadcast
-                    .app_handle()
-                    .dialog()
-                    .message(error.unwrap_or_else(|| "Unknown error".to_string()))
-                    .kind(MessageDialogKind::Error)
-                    .title("Broadcast failed")
-                    .blocking_show();
-            }
-            Ok(())
+            let data = if let Some(table) = data.as_table() {
+                serialized_table(&l, table)?.into_lua(l)?
+            } else {
+                LuaNil
+            };
+            with_cause(l, Kind::Broadcast, || {
+                scene
+                    .get::<_, LuaFunction>("handle_broadcast")?
+                    .call::<_, ()>((scene, event, data))
+            })
         })
         .map_err(|e| {
             LuaError::InitializationError(format!("Failed to create Lua broadcast function: {}", e))
@@ -110,64 +247,36 @@ fn set_globals(lua: &Lua, window: WebviewWindow) -> Result<(), LuaError> {
     )?;
 
     // messaging
-    let w_message = window.clone();
     lua.globals().set(
         "message",
         lua.create_function(
-            move |l: &Lua, (target, event, data): (LuaValue, LuaValue, LuaValue)| {
-                if !target.is_string() || !event.is_string() {
-                    let _ = w_message
-                        .app_handle()
-                        .dialog()
-                        .message(if !target.is_string() && !event.is_string() {
-                            format!(
-                                "Target and event parameters must both be strings.
-                                \nProvided (target): {}\nProvided (event): {}",
-                                target.type_name(),
-                                event.type_name()
-                            )
-                        } else if !target.is_string() {
-                            format!(
-                                "Target parameter must be a string.\nProvided: {}",
-                                target.type_name()
-                            )
-                        } else {
-                            format!(
-                                "Event parameter must be a string.\nProvided: {}",
-                                event.type_name()
-                            )
-                        })
-                        .kind(MessageDialogKind::Error)
-                        .title("Broadcast failed")
-                        .blocking_show();
-                    return Ok(());
+            |l: &Lua, (target, event, data): (LuaValue, LuaValue, LuaValue)| {
+                if !target.is_string() {
+                    return Err(LuaError::FormatError(format!(
+                        "Message target must be a string, not {}.",
+                        target.type_name()
+                    ))
+                    .into());
+                }
+                if !event.is_string() {
+                    return Err(LuaError::FormatError(format!(
+                        "Message event must be a string, not {}.",
+                        event.type_name()
+                    ))
+                    .into());
                 }
 
                 let scene = get_scene(l)?;
-                let (success, error): (bool, Option<String>) =
-                    l.globals().get::<_, LuaFunction>("pcall")?.call((
-                        scene
-                            .get::<_, LuaFunction>("handle_message")
-                            .map_err(|e| LuaError::LuaError(e))?,
-                        scene,
-                        target,
-                        event,
-                        if let Some(table) = data.as_table() {
-                            serialized_table(&l, table)?.into_lua(l)?
-                        } else {
-                            LuaNil
-                        },
-                    ))?;
-                if !success {
-                    let _ = w_message
-                        .app_handle()
-                        .dialog()
-                        .message(error.unwrap_or_else(|| "Unknown error".to_string()))
-                        .kind(MessageDialogKind::Error)
-                        .title("Message failed")
-                        .blocking_show();
-                }
-                Ok(())
+                let data = if let Some(table) = data.as_table() {
+                    serialized_table(&l, table)?.into_lua(l)?
+                } else {
+                    LuaNil
+                };
+                with_cause(l, Kind::Message, || {
+                    scene
+                        .get::<_, LuaFunction>("handle_message")?
+                        .call::<_, ()>((scene, target, event, data))
+                })
             },
         )
         .map_err(|e| {
@@ -175,21 +284,8 @@ fn set_globals(lua: &Lua, window: WebviewWindow) -> Result<(), LuaError> {
         })?,
     )?;
 
-    // intercept & tag lua prints to stdout
-    lua.globals()
-        .set(
-            "print",
-            lua.create_function(|_, msg: String| {
-                println!("[lua] {}", msg);
-                Ok(())
-            })?,
-        )
-        .map_err(|e| {
-            LuaError::InitializationError(format!("Failed to set Lua print function: {}", e))
-        })?;
-
     // Preload modules
-    preload_lua_modules(window.clone(), &lua)
+    preload_lua_modules(host.as_ref(), &lua)
         .map_err(|e| LuaError::InitializationError(e.to_string()))?;
 
     // load main scene
@@ -204,13 +300,19 @@ fn set_globals(lua: &Lua, window: WebviewWindow) -> Result<(), LuaError> {
     Ok(())
 }
 
-fn get_scene<'lua>(lua: &'lua Lua) -> Result<LuaTable<'lua>, LuaError> {
+pub fn get_scene<'lua>(lua: &'lua Lua) -> Result<LuaTable<'lua>, LuaError> {
     lua.globals()
         .get("currentScene")
         .map_err(|e| LuaError::LuaError(e))
 }
 
-fn get_entity<'lua>(lua: &'lua Lua, id: &str) -> Result<LuaTable<'lua>, LuaError> {
+pub fn get_host(lua: &Lua) -> Result<Arc<dyn Host>, LuaError> {
+    lua.app_data_ref::<Arc<dyn Host>>()
+        .map(|host| host.clone())
+        .ok_or_else(|| LuaError::InitializationError("Host missing".to_string()))
+}
+
+pub fn get_entity<'lua>(lua: &'lua Lua, id: &str) -> Result<LuaTable<'lua>, LuaError> {
     let scene = get_scene(lua)?;
     let entities = scene
         .get::<_, LuaTable>("entities")
@@ -221,7 +323,7 @@ fn get_entity<'lua>(lua: &'lua Lua, id: &str) -> Result<LuaTable<'lua>, LuaError
     })
 }
 
-fn serialized_table<'lua>(
+pub fn serialized_table<'lua>(
     lua: &'lua Lua,
     table: &LuaTable<'lua>,
 ) -> Result<LuaString<'lua>, LuaError> {
@@ -255,14 +357,133 @@ fn serialized_table<'lua>(
     }
 }
 
-fn match_message(lua: &Lua, msg: LuaMessage) -> Result<(), LuaError> {
+/// A response for the frontend, without the error's kind since it's shown to the user.
+fn response(result: Result<String, LuaError>) -> (bool, String) {
+    match result {
+        Ok(msg) => (true, msg),
+        Err(LuaError::FormatError(msg)) => (false, msg),
+        Err(e) => (false, e.to_string()),
+    }
+}
+
+/// Everything saved with a scene besides its entities.
+fn scene_extra(lua: &Lua) -> Result<LuaTable<'_>, LuaError> {
+    let extra = lua.create_table()?;
+    extra.set("timers", lua.to_value(&scheduler_mut(lua)?.saved_timers())?)?;
+    extra.set("camera", lua.to_value(&*camera_mut(lua)?)?)?;
+    extra.set("canvas", lua.to_value(&*canvas_mut(lua)?)?)?;
+    extra.set("gravity", lua.to_value(&physics_mut(lua)?.gravity())?)?;
+    let random = random_mut(lua)?;
+    extra.set("seed", random.seed())?;
+    // left out until something draws a number, so untouched scenes save the same as ever
+    let streams = random.saved_streams();
+    if !streams.is_empty() {
+        extra.set("random_streams", lua.to_value(&streams)?)?;
+    }
+    Ok(extra)
+}
+
+/// Swap in the state saved with a scene that was just loaded, since everything
+/// running before (e.g. pending timers) belongs to the old scene.
+fn restore_scene_state(lua: &Lua, loaded: &LuaValue) -> Result<(), LuaError> {
+    let saved = |key: &str| match loaded.as_table().map(|t| t.get::<_, LuaValue>(key)) {
+        Some(Ok(value)) if !value.is_nil() => Some(value),
+        _ => None,
+    };
+
+    let timers = match saved("timers") {
+        Some(timers) => lua.from_value::<Vec<SavedTimer>>(timers)?,
+        None => Vec::new(),
+    };
+    let mut scheduler = scheduler_mut(lua)?;
+    scheduler.clear();
+    scheduler.restore_timers(timers);
+    tweens_mut(lua)?.clear();
+    physics_mut(lua)?.clear();
+    // scenes saved before gravity was saved fall the way they always did
+    if let Some(gravity) = saved("gravity") {
+        physics_mut(lua)?.set_gravity(lua.from_value(gravity)?)?;
+    }
+    collisions_mut(lua)?.clear();
+    audio_mut(lua)?.stop_all()?;
+    // scenes saved before cameras are looked at the way they always were
+    *camera_mut(lua)? = match saved("camera") {
+        Some(camera) => lua.from_value::<Camera>(camera)?.checked()?,
+        None => Camera::default(),
+    };
+    *canvas_mut(lua)? = match saved("canvas") {
+        Some(canvas) => lua.from_value::<Canvas>(canvas)?.checked()?,
+        None => Canvas::default(),
+    };
+    // random numbers carry on from where they were saved, so a scene plays the same way each time
+    let mut random = match saved("seed") {
+        Some(seed) => Random::with_seed(lua.unpack(seed)?),
+        None => Random::new(),
+    };
+    if let Some(streams) = saved("random_streams") {
+        random.restore_streams(lua.from_value(streams)?)?;
+    }
+    *random_mut(lua)? = random;
+    snapshots_mut(lua)?.clear();
+    Ok(())
+}
+
+/// The whole scene, in the same format as a scene file.
+pub fn dump_scene(lua: &Lua) -> Result<String, LuaError> {
+    let scene = get_scene(lua)?;
+    Ok(scene
+        .get::<_, LuaFunction>("dump")?
+        .call::<_, String>((scene, scene_extra(lua)?))?)
+}
+
+/// Replace the whole scene with one from `dump_scene`.
+pub fn load_scene_dump(lua: &Lua, content: &str) -> Result<(), LuaError> {
+    let scene = get_scene(lua)?;
+    let loaded: LuaValue = scene
+        .get::<_, LuaFunction>("load_dump")?
+        .call((scene, content))?;
+    restore_scene_state(lua, &loaded)
+}
+
+pub fn match_message(lua: &Lua, msg: LuaMessage) -> Result<(), LuaError> {
     match msg {
         LuaMessage::Tick(dt) => {
-            let scene = get_scene(lua)?;
-            scene
-                .get::<_, LuaFunction>("emit_update")?
-                .call::<_, ()>((scene, dt))
-                .map_err(|e| LuaError::LuaError(e))?
+            // looking back at an earlier tick pauses the scene until it's resumed
+            if snapshots_mut(lua)?.is_paused() {
+                return Ok(());
+            }
+            let ticked = profile_phase(lua, "tick", || -> Result<(), LuaError> {
+                profile_phase(lua, "gamepads", || poll_gamepads(lua))?;
+                profile_phase(lua, "input", || dispatch_input(lua))?;
+                profile_phase(lua, "timers", || run_due_tasks(lua, dt))?;
+                profile_phase(lua, "tweens", || advance_tweens(lua, dt))?;
+                profile_phase(lua, "sprites", || advance_sprites(lua, dt))?;
+                profile_phase(lua, "physics", || step_physics(lua, dt))?;
+                profile_phase(lua, "collisions", || detect_collisions(lua))?;
+                let scene = get_scene(lua)?;
+                profile_phase(lua, "on_tick", || {
+                    with_cause(lua, Kind::Tick, || {
+                        scene
+                            .get::<_, LuaFunction>("tick")?
+                            .call::<_, ()>(scene.clone())
+                    })
+                })
+                .map_err(|e| LuaError::LuaError(e))?;
+                // after on_tick, so the camera keeps up with whatever it follows
+                let view = profile_phase(lua, "camera", || update_camera(lua))?;
+                profile_phase(lua, "emit_update", || {
+                    scene
+                        .get::<_, LuaFunction>("emit_update")?
+                        .call::<_, ()>((scene, lua.to_value(&view)?))
+                })
+                .map_err(|e| LuaError::LuaError(e))?;
+                profile_phase(lua, "pins", || sample_pins(lua))?;
+                profile_phase(lua, "audio", || advance_audio(lua, dt))?;
+                profile_phase(lua, "snapshot", || take_snapshot(lua, dt))
+            });
+            // even if part of the tick failed, or this tick's presses would be seen again next tick
+            input_mut(lua)?.end_tick();
+            ticked?;
         }
         LuaMessage::AddEntity(id, data, response_tx) => {
             let scene = get_scene(lua)?;
@@ -286,16 +507,24 @@ fn match_message(lua: &Lua, msg: LuaMessage) -> Result<(), LuaError> {
             let id_func: LuaFunction = scene
                 .get("update_entity_id")
                 .map_err(|e| LuaError::LuaError(e))?;
-            id_func.call::<_, ()>((scene, original_id, new_id, json_value_to_lua(&lua, &data)?))?;
+            id_func.call::<_, ()>((
+                scene,
+                original_id.clone(),
+                new_id.clone(),
+                json_value_to_lua(&lua, &data)?,
+            ))?;
+            rename_entity(lua, &original_id, &new_id)?;
         }
         LuaMessage::UpdateEntity(id, data) => {
             let entity: LuaTable = get_entity(lua, id.as_str())?;
+            // updating replaces the scripts, so keep hold of the ones that compiled
+            let loaded_scripts: LuaTable = entity.get("scripts")?;
             entity
                 .get::<&str, LuaFunction>("update")?
                 .call::<_, ()>((entity.clone(), json_value_to_lua(&lua, &data)?))
                 .map_err(|e| {
                     LuaError::EntityProcessingError(
-                        id,
+                        id.clone(),
                         format!("Couldn't call update function: {}", e),
                     )
                 })?;
@@ -323,16 +552,32 @@ fn match_message(lua: &Lua, msg: LuaMessage) -> Result<(), LuaError> {
                     })?
                     .keys()
                     .try_for_each(|script| {
+                        let scripts: LuaTable = entity.get("scripts")?;
+                        let source: Option<String> =
+                            scripts.get::<_, LuaTable>(script.as_str())?.get("string")?;
+                        if let Some(diagnostic) =
+                            source.and_then(|source| check_script(lua, &id, script, &source))
+                        {
+                            // a script that doesn't compile leaves the last one that did running
+                            let loaded: Option<LuaTable> = loaded_scripts.get(script.as_str())?;
+                            if let Some(loaded) = loaded {
+                                scripts.set(script.as_str(), loaded)?;
+                            }
+                            return report_diagnostic(lua, &diagnostic);
+                        }
                         load_func
-                            .call::<_, ()>((entity.clone(), script.clone()))
+                            .call::<_, ()>((entity.clone(), id.clone(), script.clone()))
                             .map_err(|e| LuaError::LuaError(e))
                     })?;
             }
         }
-        LuaMessage::DeleteEntity(id) => get_scene(lua)?
-            .get::<&str, LuaTable>("entities")?
-            .set(id, LuaNil)
-            .map_err(|e| LuaError::LuaError(e))?,
+        LuaMessage::DeleteEntity(id) => {
+            get_scene(lua)?
+                .get::<&str, LuaTable>("entities")?
+                .set(id.clone(), LuaNil)
+                .map_err(|e| LuaError::LuaError(e))?;
+            forget_entity(lua, &id)?;
+        }
         LuaMessage::DuplicateEntity(id) => {
             let scene = get_scene(lua)?;
             scene
@@ -341,53 +586,53 @@ fn match_message(lua: &Lua, msg: LuaMessage) -> Result<(), LuaError> {
                 .map_err(|e| LuaError::LuaError(e))?
         }
         LuaMessage::SaveScene(path) => {
+            warn_unsaved_tasks(lua, "Saving the scene")?;
             let scene = get_scene(lua)?;
             scene
                 .get::<_, LuaFunction>("save_scene")?
-                .call::<_, ()>((scene, path))
+                .call::<_, ()>((scene, path.as_str(), scene_extra(lua)?))
                 .map_err(|e| LuaError::LuaError(e))?;
+            assets_mut(lua)?.set_scene_path(&path);
         }
         LuaMessage::LoadScene(path, response_tx) => {
             let scene = get_scene(lua)?;
-            let (success, error): (bool, Option<String>) = lua
-                .globals()
-                .get::<_, LuaFunction>("pcall")?
-                .call((scene.get::<_, LuaFunction>("load_scene")?, scene, path))?;
+            let (success, loaded): (bool, LuaValue) =
+                lua.globals().get::<_, LuaFunction>("pcall")?.call((
+                    scene.get::<_, LuaFunction>("load_scene")?,
+                    scene,
+                    path.as_str(),
+                ))?;
+
+            // a file that reads fine can still hold what scripts couldn't set, like a zoom of 0
+            let failure = match success {
+                true => restore_scene_state(lua, &loaded).err().map(|e| match e {
+                    LuaError::FormatError(message) => message,
+                    e => e.to_string(),
+                }),
+                false => Some(
+                    lua.unpack::<Option<String>>(loaded)?
+                        .unwrap_or_else(|| "Unknown error".to_string()),
+                ),
+            };
+            if failure.is_none() {
+                assets_mut(lua)?.set_scene_path(&path);
+                record_scene(lua)?;
+            }
 
             response_tx
-                .send(match success {
-                    true => (true, "Successfully loaded scene".to_string()),
-                    false => (
-                        false,
-                        format!(
-                            "Failed loading: {}",
-                            error.unwrap_or_else(|| "Unknown error".to_string())
-                        ),
-                    ),
+                .send(match failure {
+                    None => (true, "Successfully loaded scene".to_string()),
+                    Some(message) => (false, format!("Failed loading: {}", message)),
                 })
                 .map_err(|e| {
                     LuaError::CommunicationError(format!("Failed to send error response: {}", e))
                 })?
         }
         LuaMessage::RunScript(id, function, params, response_tx) => {
-            let entity: LuaTable = get_scene(lua)?
-                .get::<_, LuaTable>("entities")?
-                .get::<_, LuaTable>(id.clone())
-                .map_err(|e| {
-                    let _ = response_tx.send((false, "Couldn't find entity".to_string()));
-                    LuaError::EntityProcessingError(
-                        id.clone(),
-                        format!("Couldn't get entity: {}", e),
-                    )
-                })?;
-
-            let (success, error): (bool, Option<String>) =
-                lua.globals().get::<_, LuaFunction>("pcall")?.call((
-                    entity.get::<_, LuaFunction>("run_script")?,
-                    entity,
-                    function.clone(),
-                    json_value_to_lua(lua, &params)?,
-                ))?;
+            let scene = get_scene(lua)?;
+            let (success, error): (bool, Option<String>) = scene
+                .get::<_, LuaFunction>("run_protected")?
+                .call((scene, id, function, json_value_to_lua(lua, &params)?))?;
 
             response_tx
                 .send(match success {
@@ -398,6 +643,165 @@ fn match_message(lua: &Lua, msg: LuaMessage) -> Result<(), LuaError> {
                     LuaError::CommunicationError(format!("Failed to send error response: {}", e))
                 })?
         }
+        LuaMessage::SetScriptErrorOptions(auto_disable) => {
+            if let Some(mut errors) = lua.app_data_mut::<ScriptErrors>() {
+                errors.auto_disable = auto_disable;
+            }
+        }
+        LuaMessage::GetScriptErrors(response_tx) => response_tx
+            .send(
+                lua.app_data_ref::<ScriptErrors>()
+                    .map(|errors| errors.history())
+                    .unwrap_or_default(),
+            )
+            .map_err(|e| {
+                LuaError::CommunicationError(format!("Failed to send script errors: {}", e))
+            })?,
+        LuaMessage::ClearScriptErrors => {
+            if let Some(mut errors) = lua.app_data_mut::<ScriptErrors>() {
+                errors.clear();
+            }
+        }
+        LuaMessage::GetLogs(response_tx) => response_tx
+            .send(logs_mut(lua)?.history())
+            .map_err(|e| LuaError::CommunicationError(format!("Failed to send logs: {}", e)))?,
+        LuaMessage::ClearLogs => logs_mut(lua)?.clear(),
+        LuaMessage::ExportLogs(path, response_tx) => response_tx
+            .send(response(
+                logs_mut(lua)?
+                    .export(&path)
+                    .map(|_| format!("Saved log to {}", path)),
+            ))
+            .map_err(|e| {
+                LuaError::CommunicationError(format!("Failed to send log export response: {}", e))
+            })?,
+        LuaMessage::Eval(code, entity, response_tx) => response_tx
+            .send(response(eval(lua, &code, entity.as_deref())))
+            .map_err(|e| {
+                LuaError::CommunicationError(format!("Failed to send eval response: {}", e))
+            })?,
+        LuaMessage::Pin(expression, response_tx) => response_tx
+            .send(pin(lua, &expression).map_err(|e| match e {
+                LuaError::FormatError(message) => message,
+                e => e.to_string(),
+            }))
+            .map_err(|e| LuaError::CommunicationError(format!("Failed to send pin: {}", e)))?,
+        LuaMessage::Unpin(id) => unpin(lua, id)?,
+        LuaMessage::GetPins(response_tx) => response_tx
+            .send(pins_mut(lua)?.list())
+            .map_err(|e| LuaError::CommunicationError(format!("Failed to send pins: {}", e)))?,
+        LuaMessage::SetProfiling(enabled) => profiler_mut(lua)?.set_enabled(enabled),
+        LuaMessage::ResetProfile => profiler_mut(lua)?.reset(),
+        LuaMessage::GetProfileReport(response_tx) => {
+            response_tx.send(profiler_mut(lua)?.report()).map_err(|e| {
+                LuaError::CommunicationError(format!("Failed to send profile report: {}", e))
+            })?
+        }
+        LuaMessage::ExportProfileTrace(path, response_tx) => response_tx
+            .send(response(
+                profiler_mut(lua)?
+                    .export_trace(&path)
+                    .map(|_| format!("Saved trace to {}", path)),
+            ))
+            .map_err(|e| {
+                LuaError::CommunicationError(format!("Failed to send trace export response: {}", e))
+            })?,
+        LuaMessage::GetEvalHistory(response_tx) => {
+            response_tx.send(repl_mut(lua)?.history()).map_err(|e| {
+                LuaError::CommunicationError(format!("Failed to send eval history: {}", e))
+            })?
+        }
+        LuaMessage::CheckScripts(id, scripts, response_tx) => {
+            let diagnostics = match (id, scripts) {
+                (Some(id), Some(scripts)) => match scripts.as_object() {
+                    Some(scripts) => check_script_sources(lua, &id, scripts),
+                    None => Err(LuaError::FormatError(
+                        "Scripts cannot be parsed as object".to_string(),
+                    )),
+                },
+                (Some(id), None) => {
+                    get_entity(lua, &id).and_then(|entity| check_entity_scripts(lua, &id, &entity))
+                }
+                (None, _) => get_scene(lua)?
+                    .get::<_, LuaTable>("entities")?
+                    .pairs::<String, LuaTable>()
+                    .try_fold(Vec::new(), |mut diagnostics, pair| {
+                        let (id, entity) = pair?;
+                        diagnostics.extend(check_entity_scripts(lua, &id, &entity)?);
+                        Ok(diagnostics)
+                    }),
+            };
+
+            response_tx
+                .send(
+                    diagnostics
+                        .map(sorted_diagnostics)
+                        .map_err(|e| e.to_string()),
+                )
+                .map_err(|e| {
+                    LuaError::CommunicationError(format!("Failed to send diagnostics: {}", e))
+                })?
+        }
+        LuaMessage::ResolveAsset(name, response_tx) => response_tx
+            .send(
+                assets_mut(lua)?
+                    .resolve(&name)
+                    .to_string_lossy()
+                    .to_string(),
+            )
+            .map_err(|e| {
+                LuaError::CommunicationError(format!("Failed to send asset path: {}", e))
+            })?,
+        LuaMessage::Input(event) => input_mut(lua)?.push(event),
+        LuaMessage::StartRecording(response_tx) => response_tx
+            .send(response(
+                start_recording(lua).map(|_| "Recording started".to_string()),
+            ))
+            .map_err(|e| {
+                LuaError::CommunicationError(format!("Failed to send recording response: {}", e))
+            })?,
+        LuaMessage::StopRecording(path, response_tx) => response_tx
+            .send(response(
+                stop_recording(lua, &path).map(|_| format!("Saved replay to {}", path)),
+            ))
+            .map_err(|e| {
+                LuaError::CommunicationError(format!("Failed to send recording response: {}", e))
+            })?,
+        LuaMessage::Replay(path, response_tx) => {
+            let result = replay(lua, &path).and_then(|differences| match differences.is_empty() {
+                true => Ok("The replay ended up just like the recording did.".to_string()),
+                false => Err(LuaError::FormatError(format!(
+                    "The replay ended up differently from the recording:\n{}",
+                    differences.join("\n")
+                ))),
+            });
+            response_tx.send(response(result)).map_err(|e| {
+                LuaError::CommunicationError(format!("Failed to send replay response: {}", e))
+            })?
+        }
+        LuaMessage::GetTimeline(response_tx) => response_tx
+            .send(snapshots_mut(lua)?.timeline())
+            .map_err(|e| LuaError::CommunicationError(format!("Failed to send timeline: {}", e)))?,
+        LuaMessage::ViewSnapshot(tick, response_tx) => response_tx
+            .send(response(
+                view_snapshot(lua, tick).map(|_| format!("Showing tick {}", tick)),
+            ))
+            .map_err(|e| {
+                LuaError::CommunicationError(format!("Failed to send snapshot response: {}", e))
+            })?,
+        LuaMessage::GetSnapshotEntity(tick, id, response_tx) => response_tx
+            .send(response(snapshot_entity(lua, tick, &id)))
+            .map_err(|e| {
+                LuaError::CommunicationError(format!("Failed to send snapshot entity: {}", e))
+            })?,
+        LuaMessage::ResumeFromSnapshot(tick, response_tx) => response_tx
+            .send(response(resume(lua, tick).map(|_| match tick {
+                Some(tick) => format!("Carrying on from tick {}", tick),
+                None => "Carrying on".to_string(),
+            })))
+            .map_err(|e| {
+                LuaError::CommunicationError(format!("Failed to send resume response: {}", e))
+            })?,
         LuaMessage::EmitEntityString(id, window) => {
             let scene = get_scene(lua)?;
             let data: LuaTable = lua.create_table()?;
@@ -464,18 +868,43 @@ fn match_message(lua: &Lua, msg: LuaMessage) -> Result<(), LuaError> {
             };
             entity.set("id", LuaNil)?;
 
-            for (key, value) in scripts
-                .as_object()
-                .ok_or_else(|| {
-                    LuaError::FormatError("Couldn't parse script as object".to_string())
-                })?
-                .iter()
-            {
+            let scripts = scripts.as_object().ok_or_else(|| {
+                LuaError::FormatError("Couldn't parse script as object".to_string())
+            })?;
+            let id_str = id.to_str()?.to_string();
+
+            // report every script that doesn't compile, rather than just the first
+            let diagnostics = check_script_sources(lua, &id_str, scripts)?;
+            if !diagnostics.is_empty() {
+                let _ = response_tx.send((
+                    false,
+                    match diagnostics.as_slice() {
+                        [diagnostic] => format!(
+                            "Invalid syntax in {} script, line {}: {}",
+                            diagnostic.script, diagnostic.line, diagnostic.message
+                        ),
+                        _ => diagnostics.iter().fold(
+                            format!("Invalid syntax in {} scripts:", diagnostics.len()),
+                            |acc, diagnostic| {
+                                format!(
+                                    "{}\n{}, line {}: {}",
+                                    acc, diagnostic.script, diagnostic.line, diagnostic.message
+                                )
+                            },
+                        ),
+                    },
+                    "".to_string(),
+                ));
+                return Ok(());
+            }
+
+            for (key, value) in scripts.iter() {
                 let ent_clone = entity.clone();
                 let (success, error): (bool, Option<String>) =
                     lua.globals().get::<_, LuaFunction>("pcall")?.call((
                         ent_clone.get::<_, LuaFunction>("load_script")?,
                         ent_clone,
+                        id.clone(),
                         key.to_string(),
                         value
                             .as_str()
@@ -489,7 +918,7 @@ fn match_message(lua: &Lua, msg: LuaMessage) -> Result<(), LuaError> {
                     let error_msg = error.unwrap_or_else(|| "Unknown error".to_string());
                     let _ = response_tx.send((
                         false,
-                        format!("Invalid syntax in {} script: {}", key, error_msg),
+                        format!("Couldn't load {} script: {}", key, error_msg),
                         "".to_string(),
                     ));
                     return Ok(());
@@ -512,12 +941,17 @@ fn match_message(lua: &Lua, msg: LuaMessage) -> Result<(), LuaError> {
                         })?;
                     return Ok(());
                 }
-                entities.set(original_id, LuaNil)?;
+                entities.set(original_id.as_str(), LuaNil)?;
             }
 
             entities.set(&id, entity)?;
+            // the saved entity replaces the old one, so anything the old one was waiting on goes too
+            forget_entity(lua, &original_id)?;
+            if id_str != original_id {
+                rename_entity(lua, &original_id, &id_str)?;
+            }
             response_tx
-                .send((true, "Success".to_string(), id.to_str()?.to_string()))
+                .send((true, "Success".to_string(), id_str))
                 .map_err(|e| {
                     LuaError::CommunicationError(format!("Failed to send success response: {}", e))
                 })?
@@ -526,23 +960,103 @@ fn match_message(lua: &Lua, msg: LuaMessage) -> Result<(), LuaError> {
     Ok(())
 }
 
+/// Carry everything the runtime keeps about an entity, like its timers & what the camera follows,
+/// over to its new ID.
+fn rename_entity(lua: &Lua, from: &str, to: &str) -> Result<(), LuaError> {
+    scheduler_mut(lua)?.rename_entity(from, to);
+    tweens_mut(lua)?.rename_entity(from, to);
+    collisions_mut(lua)?.rename_entity(from, to);
+    camera_mut(lua)?.rename_entity(from, to);
+    random_mut(lua)?.rename_entity(from, to);
+    input_mut(lua)?.forget_entity(from);
+    Ok(())
+}
+
+/// Stop everything running for an entity that's been deleted or replaced, and start its random numbers over.
+fn forget_entity(lua: &Lua, id: &str) -> Result<(), LuaError> {
+    scheduler_mut(lua)?.cancel_entity(id);
+    tweens_mut(lua)?.cancel_entity(id);
+    input_mut(lua)?.forget_entity(id);
+    random_mut(lua)?.forget_entity(id);
+    Ok(())
+}
+
+/// Compile errors in the scripts stored on an entity.
+fn check_entity_scripts(
+    lua: &Lua,
+    id: &str,
+    entity: &LuaTable,
+) -> Result<Vec<Diagnostic>, LuaError> {
+    let mut diagnostics = Vec::new();
+    for pair in entity
+        .get::<_, LuaTable>("scripts")?
+        .pairs::<String, LuaTable>()
+    {
+        let (script, data) = pair?;
+        if let Some(source) = data.get::<_, Option<String>>("string")? {
+            diagnostics.extend(check_script(lua, id, &script, &source));
+        }
+    }
+    Ok(diagnostics)
+}
+
+/// Compile errors in script sources given by name, e.g. unsaved scripts from the inspector.
+fn check_script_sources(
+    lua: &Lua,
+    id: &str,
+    scripts: &serde_json::Map<String, serde_json::Value>,
+) -> Result<Vec<Diagnostic>, LuaError> {
+    let mut diagnostics = Vec::new();
+    for (script, source) in scripts {
+        let source = source.as_str().ok_or_else(|| {
+            LuaError::FormatError(format!("Script {} cannot be parsed as string", script))
+        })?;
+        diagnostics.extend(check_script(lua, id, script, source));
+    }
+    Ok(sorted_diagnostics(diagnostics))
+}
+
+fn sorted_diagnostics(mut diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    diagnostics.sort_by(|a, b| {
+        (&a.entity, &a.script, a.line, a.column).cmp(&(&b.entity, &b.script, b.line, b.column))
+    });
+    diagnostics
+}
+
+/// Report a compile error through the script error channel.
+fn report_diagnostic(lua: &Lua, diagnostic: &Diagnostic) -> Result<(), LuaError> {
+    let details = lua.create_table()?;
+    details.set("message", diagnostic.message.clone())?;
+    details.set("line", diagnostic.line)?;
+    details.set(
+        "traceback",
+        format!(
+            "Compile error at line {}, column {}",
+            diagnostic.line, diagnostic.column
+        ),
+    )?;
+    lua.globals()
+        .get::<_, LuaFunction>("report_script_error")?
+        .call::<_, bool>((
+            diagnostic.entity.clone(),
+            diagnostic.script.clone(),
+            details,
+            1,
+        ))?;
+    Ok(())
+}
+
 /// Preload Lua modules (at runtime) as part of Lua initialization.
-fn preload_lua_modules(window: WebviewWindow, lua: &Lua) -> LuaResult<()> {
-    let resource_path = window
-        .app_handle()
-        .path()
-        .resource_dir()
-        .map_err(|e| LuaError::InitializationError(format!("Failed to get resource dir: {}", e)))?
-        .join("resources")
-        .join("lua");
-    println!(
+fn preload_lua_modules(host: &dyn Host, lua: &Lua) -> LuaResult<()> {
+    let resource_path = host.lua_dir().map_err(LuaError::InitializationError)?;
+    host.show(&format!(
         "Looking for Lua files in: {}",
         resource_path.to_str().ok_or_else(|| {
             LuaError::InitializationError(
                 "Couldn't resolve resource dir path to string".to_string(),
             )
         })?
-    );
+    ));
 
     let preload = lua
         .globals()
@@ -555,7 +1069,7 @@ fn preload_lua_modules(window: WebviewWindow, lua: &Lua) -> LuaResult<()> {
     scan_directory(&resource_path, &preload, lua, &mut loaded)
         .map_err(|e| LuaError::ModuleLoadError(format!("Failed scanning directory: {}", e)))?;
 
-    println!("Preloaded Lua modules: {:?}", loaded);
+    host.show(&format!("Preloaded Lua modules: {:?}", loaded));
     Ok(())
 }
 
@@ -663,3 +1177,297 @@ fn json_value_to_lua<'lua>(
         }
     }
 }
+
+/// Golden-file tests for saving & loading scenes, and showing entities in the inspector.
+/// Run with `UPDATE_GOLDEN=1` to rewrite the golden files after changing how scenes are saved.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{headless_lua, LUA_DIR};
+    use crate::testing::Recorder;
+    use serde_json::Value;
+    use std::path::PathBuf;
+
+    /// Scenes in `tests/golden`, each saved as `<name>.saved.lua` & shown in the inspector as `<name>.inspector.txt`.
+    const FIXTURES: [&str; 3] = ["entity_types", "tricky_values", "unversioned"];
+
+    fn golden(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("golden")
+            .join(name)
+    }
+
+    fn assert_golden(name: &str, actual: &str) {
+        let path = golden(name);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&path, actual).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path).unwrap_or_else(|e| {
+            panic!(
+                "Couldn't read {} ({}), run with UPDATE_GOLDEN=1 to make it",
+                name, e
+            )
+        });
+        assert!(
+            actual == expected,
+            "{} has changed, it's now:\n{}",
+            name,
+            actual
+        );
+    }
+
+    fn scene(path: &Path) -> (Lua, Arc<Recorder>) {
+        let recorder = Arc::new(Recorder::new(Path::new(LUA_DIR)));
+        let lua = headless_lua(recorder.clone()).unwrap();
+        let (tx, rx) = mpsc::channel();
+        let path = path.to_string_lossy().to_string();
+        match_message(&lua, LuaMessage::LoadScene(path.clone(), tx)).unwrap();
+        let (loaded, message) = rx.recv().unwrap();
+        assert!(loaded, "{}: {}", path, message);
+        (lua, recorder)
+    }
+
+    fn save(lua: &Lua, name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("stacks-{}-{}", std::process::id(), name));
+        let path = path.to_string_lossy().to_string();
+        match_message(lua, LuaMessage::SaveScene(path.clone())).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        saved
+    }
+
+    /// Compare entities one at a time, so it's clear which one changed.
+    fn assert_same_entities(actual: &Value, expected: &Value, changed: &str) {
+        let ids = expected.as_object().unwrap().keys();
+        assert_eq!(
+            actual.as_object().unwrap().keys().collect::<Vec<_>>(),
+            ids.clone().collect::<Vec<_>>(),
+            "{}",
+            changed
+        );
+        for id in ids {
+            assert_eq!(actual[id], expected[id], "{}: {}", changed, id);
+        }
+    }
+
+    /// The entities as they'd be saved, for comparing scenes by what's in them.
+    fn entities(lua: &Lua) -> Value {
+        let entities: LuaValue = lua
+            .load(
+                "local entities = {}
+                for id, entity in pairs(currentScene.entities) do entities[id] = entity:serializable() end
+                return entities",
+            )
+            .eval()
+            .unwrap();
+        serde_json::to_value(&entities).unwrap()
+    }
+
+    fn ids(lua: &Lua) -> Vec<String> {
+        get_scene(lua)
+            .unwrap()
+            .call_method("sorted_ids", ())
+            .unwrap()
+    }
+
+    fn block_string(lua: &Lua, recorder: &Recorder, id: &str) -> String {
+        match_message(
+            lua,
+            LuaMessage::EmitEntityString(id.to_string(), "inspector".to_string()),
+        )
+        .unwrap();
+        let emitted = recorder.events("entity_string").unwrap();
+        emitted.last().unwrap()["table"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn saved_scenes_match_golden_files() {
+        for fixture in FIXTURES {
+            let path = golden(&format!("{}.lua", fixture));
+            let (lua, _) = scene(&path);
+            // scenes without a seed get a new one every time they're loaded
+            if !fs::read_to_string(&path).unwrap().contains("seed =") {
+                *random_mut(&lua).unwrap() = Random::with_seed(1);
+            }
+            assert_golden(&format!("{}.saved.lua", fixture), &save(&lua, fixture));
+        }
+    }
+
+    #[test]
+    fn saving_and_loading_loses_nothing() {
+        for fixture in FIXTURES {
+            let (original, _) = scene(&golden(&format!("{}.lua", fixture)));
+            let saved = save(&original, fixture);
+
+            let path =
+                std::env::temp_dir().join(format!("stacks-{}-{}.lua", std::process::id(), fixture));
+            fs::write(&path, &saved).unwrap();
+            let (reloaded, _) = scene(&path);
+            let _ = fs::remove_file(&path);
+
+            assert_same_entities(
+                &entities(&reloaded),
+                &entities(&original),
+                &format!("{} changed loading it back", fixture),
+            );
+            assert!(
+                save(&reloaded, fixture) == saved,
+                "{} saved differently the second time",
+                fixture
+            );
+        }
+    }
+
+    #[test]
+    fn inspector_strings_match_golden_files() {
+        for fixture in FIXTURES {
+            let (lua, recorder) = scene(&golden(&format!("{}.lua", fixture)));
+            let blocks: Vec<String> = ids(&lua)
+                .iter()
+                .map(|id| format!("-- {}\n{}\n", id, block_string(&lua, &recorder, id)))
+                .collect();
+            assert_golden(&format!("{}.inspector.txt", fixture), &blocks.join("\n"));
+        }
+    }
+
+    #[test]
+    fn saving_from_the_inspector_loses_nothing() {
+        for fixture in FIXTURES {
+            let (lua, recorder) = scene(&golden(&format!("{}.lua", fixture)));
+            let before = entities(&lua);
+            for id in ids(&lua) {
+                let block = block_string(&lua, &recorder, &id);
+                // the inspector sends scripts separately from the rest of the entity
+                let scripts = before[&id]
+                    .get("scripts")
+                    .and_then(Value::as_object)
+                    .map(|scripts| {
+                        scripts
+                            .iter()
+                            .map(|(name, script)| (name.clone(), script["string"].clone()))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                let (tx, rx) = mpsc::channel();
+                match_message(
+                    &lua,
+                    LuaMessage::HandleInspectorSave(
+                        id.clone(),
+                        block.clone(),
+                        Value::Object(scripts),
+                        tx,
+                    ),
+                )
+                .unwrap();
+                let (saved, message, _) = rx.recv().unwrap();
+                assert!(saved, "{} {}: {}", fixture, id, message);
+                assert_eq!(
+                    block_string(&lua, &recorder, &id),
+                    block,
+                    "{} {} shows differently after saving it from the inspector",
+                    fixture,
+                    id
+                );
+            }
+            assert_same_entities(
+                &entities(&lua),
+                &before,
+                &format!("{} changed saving it from the inspector", fixture),
+            );
+        }
+    }
+
+    #[test]
+    fn broken_scripts_are_reported_and_the_last_good_one_kept() {
+        let door = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("scenes")
+            .join("door.lua");
+        let (lua, recorder) = scene(&door);
+        let broken = "self.open = = true";
+        let click = || {
+            lua.load(r#"currentScene:run_protected("door", "on_click", {})"#)
+                .exec()
+                .unwrap();
+            recorder.events("door_toggled").unwrap().last().unwrap()["open"].clone()
+        };
+
+        match_message(
+            &lua,
+            LuaMessage::UpdateEntity(
+                "door".to_string(),
+                serde_json::json!({ "scripts": { "on_click": { "string": broken } } }),
+            ),
+        )
+        .unwrap();
+        let errors = recorder.events("script_error").unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["script"], "on_click");
+        assert_eq!(errors[0]["line"], 1);
+        assert_eq!(errors[0]["message"], "unexpected symbol near '='");
+        assert_eq!(errors[0]["traceback"], "Compile error at line 1, column 13");
+        assert_eq!(click(), true);
+
+        let (tx, rx) = mpsc::channel();
+        let block = block_string(&lua, &recorder, "door");
+        let scripts = serde_json::json!({ "on_click": broken });
+        match_message(
+            &lua,
+            LuaMessage::HandleInspectorSave("door".to_string(), block, scripts.clone(), tx),
+        )
+        .unwrap();
+        let (saved, message, _) = rx.recv().unwrap();
+        assert!(!saved);
+        assert_eq!(
+            message,
+            "Invalid syntax in on_click script, line 1: unexpected symbol near '='"
+        );
+        assert_eq!(click(), false);
+
+        let (tx, rx) = mpsc::channel();
+        match_message(
+            &lua,
+            LuaMessage::CheckScripts(Some("door".to_string()), Some(scripts), tx),
+        )
+        .unwrap();
+        let diagnostics = rx.recv().unwrap().unwrap();
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| (d.script.as_str(), d.line, d.column))
+                .collect::<Vec<_>>(),
+            [("on_click", 1, 13)]
+        );
+    }
+
+    #[test]
+    fn renaming_from_the_inspector_keeps_the_camera_following() {
+        let door = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("scenes")
+            .join("door.lua");
+        let (lua, recorder) = scene(&door);
+        lua.load(r#"camera.follow = "door""#).exec().unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let block =
+            block_string(&lua, &recorder, "door").replace(r#"id = "door""#, r#"id = "gate""#);
+        match_message(
+            &lua,
+            LuaMessage::HandleInspectorSave("door".to_string(), block, serde_json::json!({}), tx),
+        )
+        .unwrap();
+        assert_eq!(
+            rx.recv().unwrap(),
+            (true, "Success".to_string(), "gate".to_string())
+        );
+        let follow: String = lua.load("return camera.follow").eval().unwrap();
+        assert_eq!(follow, "gate");
+    }
+}
@@ -1,3 +1,11 @@
+use crate::debugger::Debugger;
+use crate::input::InputEvent;
+use crate::logs::LogEntry;
+use crate::pins::PinInfo;
+use crate::profiler::ProfileReport;
+use crate::script_errors::ScriptError;
+use crate::scripts::Diagnostic;
+use crate::snapshots::Timeline;
 use serde_json::Value;
 use std::sync::mpsc::{self, Sender};
 use thiserror::Error;
@@ -5,6 +13,8 @@ use thiserror::Error;
 #[derive(Clone)]
 pub struct LuaState {
     pub tx: mpsc::Sender<LuaMessage>,
+    /// Answers while the Lua thread is paused at a breakpoint, unlike `tx`
+    pub debugger: Debugger,
 }
 
 pub enum LuaMessage {
@@ -19,6 +29,36 @@ pub enum LuaMessage {
     RunScript(String, String, Value, Sender<(bool, String)>),
     EmitEntityString(String, String),
     HandleInspectorSave(String, String, Value, Sender<(bool, String, String)>),
+    SetScriptErrorOptions(bool),
+    GetScriptErrors(Sender<Vec<ScriptError>>),
+    ClearScriptErrors,
+    GetLogs(Sender<Vec<LogEntry>>),
+    ClearLogs,
+    ExportLogs(String, Sender<(bool, String)>),
+    /// Code to run, and the entity to run it as
+    Eval(String, Option<String>, Sender<(bool, String)>),
+    GetEvalHistory(Sender<Vec<String>>),
+    Pin(String, Sender<Result<PinInfo, String>>),
+    Unpin(u32),
+    GetPins(Sender<Vec<PinInfo>>),
+    SetProfiling(bool),
+    ResetProfile,
+    GetProfileReport(Sender<ProfileReport>),
+    ExportProfileTrace(String, Sender<(bool, String)>),
+    CheckScripts(
+        Option<String>,
+        Option<Value>,
+        Sender<Result<Vec<Diagnostic>, String>>,
+    ),
+    Input(InputEvent),
+    ResolveAsset(String, Sender<String>),
+    StartRecording(Sender<(bool, String)>),
+    StopRecording(String, Sender<(bool, String)>),
+    Replay(String, Sender<(bool, String)>),
+    GetTimeline(Sender<Timeline>),
+    ViewSnapshot(u64, Sender<(bool, String)>),
+    GetSnapshotEntity(u64, String, Sender<(bool, String)>),
+    ResumeFromSnapshot(Option<u64>, Sender<(bool, String)>),
 }
 
 #[derive(Error, Debug)]
@@ -0,0 +1,462 @@
+use crate::collisions::{collider_shapes, SpatialGrid};
+use crate::geometry::{Shape, Vec2};
+use crate::lua_setup::get_scene;
+use crate::lua_types::LuaError;
+use mlua::prelude::*;
+use mlua::AppDataRefMut;
+use serde::{Deserialize, Serialize};
+
+/// Bodies are stepped this often, in seconds, however fast the scene is ticking.
+const STEP: f64 = 1.0 / 60.0;
+
+/// Most steps to take in one tick, so a long stall doesn't snowball into an even longer one.
+const MAX_STEPS: u32 = 8;
+
+/// Pixels per second squared, so things fall about like they would if a pixel were a centimetre.
+const DEFAULT_GRAVITY: Vec2 = Vec2 { x: 0.0, y: 980.0 };
+
+/// How far bodies may sink into each other before being pushed apart, in pixels.
+/// Letting resting bodies overlap a little keeps them touching, rather than flickering in & out of contact.
+const SLOP: f64 = 0.5;
+
+/// How much of the overlap to push out each step; pushing out all of it at once makes stacks jittery.
+const CORRECTION: f64 = 0.8;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Physics {
+    gravity: Vec2,
+    /// Time passed that hasn't been stepped yet
+    unstepped: f64,
+}
+
+impl Physics {
+    pub fn new() -> Self {
+        Physics {
+            gravity: DEFAULT_GRAVITY,
+            unstepped: 0.0,
+        }
+    }
+
+    /// How many steps are due after `dt` more seconds.
+    fn steps(&mut self, dt: f64) -> u32 {
+        self.unstepped += dt;
+        let steps = (self.unstepped / STEP).floor() as u32;
+        if steps > MAX_STEPS {
+            self.unstepped = 0.0;
+            return MAX_STEPS;
+        }
+        self.unstepped -= steps as f64 * STEP;
+        steps
+    }
+
+    pub fn gravity(&self) -> Vec2 {
+        self.gravity
+    }
+
+    /// Change gravity, which has to be finite since one NaN would spread to every body it pulls on.
+    pub fn set_gravity(&mut self, gravity: Vec2) -> Result<(), LuaError> {
+        if !(gravity.x.is_finite() && gravity.y.is_finite()) {
+            return Err(LuaError::FormatError(format!(
+                "Gravity has to be a finite number each way, not {} & {}.",
+                gravity.x, gravity.y
+            )));
+        }
+        self.gravity = gravity;
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        *self = Physics::new();
+    }
+}
+
+pub fn physics_mut(lua: &Lua) -> Result<AppDataRefMut<'_, Physics>, LuaError> {
+    lua.app_data_mut::<Physics>()
+        .ok_or_else(|| LuaError::InitializationError("Physics missing".to_string()))
+}
+
+/// An entity's `body`, read out of the scene to be stepped.
+#[derive(Clone)]
+struct Body<'lua> {
+    entity: LuaTable<'lua>,
+    table: LuaTable<'lua>,
+    dynamic: bool,
+    pos: Vec2,
+    /// In degrees, like the entity's `rotation`
+    rotation: f64,
+    velocity: Vec2,
+    angular_velocity: f64,
+    /// 0 for static bodies, which nothing can push
+    inverse_mass: f64,
+    friction: f64,
+    bounce: f64,
+    gravity_scale: f64,
+    /// Bodies without a collider move, but pass through everything
+    shape: Option<Shape>,
+}
+
+impl<'lua> Body<'lua> {
+    /// Usually one body, but a tilemap is a static body for each run of its solid tiles.
+    fn read(entity: LuaTable<'lua>) -> LuaResult<Vec<Body<'lua>>> {
+        let Some(table) = entity.get::<_, Option<LuaTable>>("body")? else {
+            return Ok(Vec::new());
+        };
+        let dynamic = match table.get::<_, Option<String>>("type")?.as_deref() {
+            None | Some("dynamic") => true,
+            Some("static") => false,
+            Some(_) => return Ok(Vec::new()),
+        };
+
+        let pos: LuaTable = entity.get("pos")?;
+        let velocity = match table.get::<_, Option<LuaTable>>("velocity")? {
+            Some(velocity) => Vec2::new(
+                velocity.get::<_, Option<f64>>("x")?.unwrap_or(0.0),
+                velocity.get::<_, Option<f64>>("y")?.unwrap_or(0.0),
+            ),
+            None => Vec2::default(),
+        };
+        let mass = table.get::<_, Option<f64>>("mass")?.unwrap_or(1.0);
+
+        let shapes = collider_shapes(&entity)?;
+        let tilemap = entity.get::<_, Option<String>>("type")?.as_deref() == Some("tilemap");
+        let body = Body {
+            dynamic,
+            pos: Vec2::new(pos.get("x")?, pos.get("y")?),
+            rotation: entity.get::<_, Option<f64>>("rotation")?.unwrap_or(0.0),
+            velocity,
+            angular_velocity: table
+                .get::<_, Option<f64>>("angular_velocity")?
+                .unwrap_or(0.0),
+            inverse_mass: if dynamic && mass > 0.0 {
+                1.0 / mass
+            } else {
+                0.0
+            },
+            friction: table.get::<_, Option<f64>>("friction")?.unwrap_or(0.3),
+            bounce: table.get::<_, Option<f64>>("bounce")?.unwrap_or(0.0),
+            gravity_scale: table.get::<_, Option<f64>>("gravity_scale")?.unwrap_or(1.0),
+            shape: None,
+            entity,
+            table,
+        };
+
+        if tilemap {
+            return Ok(shapes
+                .into_iter()
+                .map(|shape| Body {
+                    dynamic: false,
+                    inverse_mass: 0.0,
+                    shape: Some(shape),
+                    ..body.clone()
+                })
+                .collect());
+        }
+        Ok(vec![Body {
+            shape: shapes.into_iter().next(),
+            ..body
+        }])
+    }
+
+    fn step(&mut self, gravity: Vec2) {
+        if !self.dynamic {
+            return;
+        }
+        self.velocity = self.velocity + gravity * (self.gravity_scale * STEP);
+        let offset = self.velocity * STEP;
+        let turn = self.angular_velocity * STEP;
+        self.pos = self.pos + offset;
+        self.rotation += turn;
+        self.shape = self
+            .shape
+            .map(|shape| shape.moved(offset, turn.to_radians()));
+    }
+
+    fn push(&mut self, offset: Vec2) {
+        self.pos = self.pos + offset;
+        self.shape = self.shape.map(|shape| shape.moved(offset, 0.0));
+    }
+
+    /// Put where the body ended up back into its entity.
+    fn write(&self, lua: &Lua) -> LuaResult<()> {
+        if !self.dynamic {
+            return Ok(());
+        }
+        let pos: LuaTable = self.entity.get("pos")?;
+        pos.set("x", self.pos.x)?;
+        pos.set("y", self.pos.y)?;
+        if self.angular_velocity != 0.0 {
+            self.entity.set("rotation", self.rotation)?;
+        }
+
+        let velocity = match self.table.get::<_, Option<LuaTable>>("velocity")? {
+            Some(velocity) => velocity,
+            None => {
+                let velocity = lua.create_table()?;
+                self.table.set("velocity", velocity.clone())?;
+                velocity
+            }
+        };
+        velocity.set("x", self.velocity.x)?;
+        velocity.set("y", self.velocity.y)?;
+        Ok(())
+    }
+}
+
+/// Push two touching bodies apart, and change their velocities like they bumped into each other.
+fn resolve(a: &mut Body, b: &mut Body) {
+    let total_inverse_mass = a.inverse_mass + b.inverse_mass;
+    if total_inverse_mass == 0.0 {
+        return;
+    }
+    let (Some(a_shape), Some(b_shape)) = (a.shape, b.shape) else {
+        return;
+    };
+    let Some(contact) = a_shape.contact(&b_shape) else {
+        return;
+    };
+    let normal = contact.normal;
+
+    let closing = (b.velocity - a.velocity).dot(normal);
+    if closing < 0.0 {
+        let bounce = a.bounce.max(b.bounce);
+        let impulse = -(1.0 + bounce) * closing / total_inverse_mass;
+        a.velocity = a.velocity - normal * (impulse * a.inverse_mass);
+        b.velocity = b.velocity + normal * (impulse * b.inverse_mass);
+
+        // friction slows the bodies sliding along each other, but can't push harder than they're pressed together
+        let relative = b.velocity - a.velocity;
+        if let Some(tangent) = (relative - normal * relative.dot(normal)).normalized() {
+            let limit = impulse * (a.friction * b.friction).sqrt();
+            let friction = (-relative.dot(tangent) / total_inverse_mass).clamp(-limit, limit);
+            a.velocity = a.velocity - tangent * (friction * a.inverse_mass);
+            b.velocity = b.velocity + tangent * (friction * b.inverse_mass);
+        }
+    }
+
+    let correction = normal * ((contact.depth - SLOP).max(0.0) * CORRECTION / total_inverse_mass);
+    a.push(correction * -a.inverse_mass);
+    b.push(correction * b.inverse_mass);
+}
+
+/// Move every entity with a `body`, as many fixed steps as are due after `dt` seconds.
+pub fn step_physics(lua: &Lua, dt: f64) -> Result<(), LuaError> {
+    let (steps, gravity) = {
+        let mut physics = physics_mut(lua)?;
+        (physics.steps(dt), physics.gravity)
+    };
+    if steps == 0 {
+        return Ok(());
+    }
+
+    let mut bodies = Vec::new();
+    for pair in get_scene(lua)?
+        .get::<_, LuaTable>("entities")?
+        .pairs::<String, LuaTable>()
+    {
+        let (id, entity) = pair?;
+        // like a broken collider, a broken body just doesn't move
+        if let Ok(read) = Body::read(entity) {
+            bodies.extend(read.into_iter().map(|body| (id.clone(), body)));
+        }
+    }
+    // the scene's entities come out in no particular order, but the same scene should always play out the same
+    bodies.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let mut bodies: Vec<Body> = bodies.into_iter().map(|(_, body)| body).collect();
+
+    for _ in 0..steps {
+        bodies.iter_mut().for_each(|body| body.step(gravity));
+
+        let (indices, shapes): (Vec<usize>, Vec<Shape>) = bodies
+            .iter()
+            .enumerate()
+            .filter_map(|(index, body)| Some((index, body.shape?)))
+            .unzip();
+        let mut pairs: Vec<(usize, usize)> = SpatialGrid::new(&shapes)
+            .candidate_pairs()
+            .into_iter()
+            .map(|(a, b)| (indices[a], indices[b]))
+            .collect();
+        pairs.sort();
+        for (a, b) in pairs {
+            let (left, right) = bodies.split_at_mut(b);
+            resolve(&mut left[a], &mut right[0]);
+        }
+    }
+
+    for body in &bodies {
+        body.write(lua)?;
+    }
+    Ok(())
+}
+
+pub fn set_globals(lua: &Lua) -> Result<(), LuaError> {
+    lua.set_app_data(Physics::new());
+
+    lua.globals()
+        .set(
+            "set_gravity",
+            lua.create_function(|l: &Lua, (x, y): (f64, f64)| {
+                Ok(physics_mut(l)?.set_gravity(Vec2::new(x, y))?)
+            })?,
+        )
+        .map_err(|e| LuaError::InitializationError(format!("Failed to set set_gravity: {}", e)))?;
+
+    lua.globals()
+        .set(
+            "gravity",
+            lua.create_function(|l: &Lua, ()| {
+                let gravity = physics_mut(l)?.gravity;
+                let table = l.create_table()?;
+                table.set("x", gravity.x)?;
+                table.set("y", gravity.y)?;
+                Ok(table)
+            })?,
+        )
+        .map_err(|e| LuaError::InitializationError(format!("Failed to set gravity: {}", e)))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lua_setup::{dump_scene, load_scene_dump};
+    use crate::runner::{headless_lua, LUA_DIR};
+    use crate::testing::Recorder;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    /// A scene with entities added by `code`, like `add("box", { ... })`.
+    fn scene(code: &str) -> Lua {
+        let lua = headless_lua(Arc::new(Recorder::new(Path::new(LUA_DIR)))).unwrap();
+        lua.load(format!(
+            "local function add(id, entity) currentScene:add_entity(id, entity) end\n{}",
+            code
+        ))
+        .exec()
+        .unwrap();
+        lua
+    }
+
+    /// Step `steps` fixed steps, one tick at a time.
+    fn run(lua: &Lua, steps: u32) {
+        for _ in 0..steps {
+            step_physics(lua, STEP).unwrap();
+        }
+    }
+
+    /// An entity's `pos` or `body.velocity`, like `get(&lua, "box.pos")`.
+    fn get(lua: &Lua, path: &str) -> Vec2 {
+        let table: LuaTable = lua
+            .load(format!("return currentScene.entities.{}", path))
+            .eval()
+            .unwrap();
+        Vec2::new(table.get("x").unwrap(), table.get("y").unwrap())
+    }
+
+    fn close(actual: f64, expected: f64) -> bool {
+        (actual - expected).abs() < 1e-6
+    }
+
+    #[test]
+    fn partial_steps_add_up() {
+        let mut physics = Physics::new();
+        assert_eq!(physics.steps(STEP * 0.4), 0);
+        assert_eq!(physics.steps(STEP * 0.4), 0);
+        assert_eq!(physics.steps(STEP * 0.4), 1);
+        // the leftover fifth of a step carries over
+        assert_eq!(physics.steps(STEP * 2.0), 2);
+        assert!((physics.unstepped - STEP * 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn long_ticks_drop_the_time_past_max_steps() {
+        let mut physics = Physics::new();
+        assert_eq!(physics.steps(1.0), MAX_STEPS);
+        assert_eq!(physics.unstepped, 0.0);
+        assert_eq!(physics.steps(STEP * 0.5), 0);
+        assert_eq!(physics.steps(STEP * MAX_STEPS as f64), MAX_STEPS);
+    }
+
+    #[test]
+    fn bodies_fall_and_keep_moving() {
+        let lua = scene(
+            r#"add("ball", { type = "rect", pos = { x = 0, y = 0 }, body = { velocity = { x = 60, y = 0 } } })
+            add("balloon", { type = "rect", pos = { x = 0, y = 0 }, body = { gravity_scale = 0 } })
+            add("shelf", { type = "rect", pos = { x = 5, y = 5 }, body = { type = "static" } })"#,
+        );
+        run(&lua, 10);
+
+        // velocity changes before position each step, so the ball has fallen 1 + 2 + ... + 10 steps' worth
+        let velocity = get(&lua, "ball.body.velocity");
+        let pos = get(&lua, "ball.pos");
+        assert!(close(velocity.x, 60.0) && close(velocity.y, 980.0 * 10.0 * STEP));
+        assert!(close(pos.x, 10.0) && close(pos.y, 980.0 * 55.0 * STEP * STEP));
+        assert_eq!(get(&lua, "balloon.pos"), Vec2::new(0.0, 0.0));
+        assert_eq!(get(&lua, "shelf.pos"), Vec2::new(5.0, 5.0));
+    }
+
+    #[test]
+    fn bodies_come_to_rest_on_static_ones() {
+        let lua = scene(
+            r#"add("crate", { type = "rect", pos = { x = 0, y = 50 }, size = { width = 20, height = 20 },
+                collider = true, body = { velocity = { x = 100, y = 0 } } })
+            add("floor", { type = "rect", pos = { x = -500, y = 100 }, size = { width = 1000, height = 20 },
+                collider = true, body = { type = "static" } })"#,
+        );
+        run(&lua, 120);
+
+        let pos = get(&lua, "crate.pos");
+        let velocity = get(&lua, "crate.body.velocity");
+        // resting a little way into the floor, which is allowed so it stays touching
+        assert!(pos.y + 20.0 > 100.0 - 1e-9 && pos.y + 20.0 <= 100.0 + SLOP + 0.5);
+        assert!(velocity.y.abs() < 1e-6);
+        // friction with the floor has slowed the crate down, without pushing it backwards
+        assert!(velocity.x >= 0.0 && velocity.x < 100.0);
+        assert_eq!(get(&lua, "floor.pos"), Vec2::new(-500.0, 100.0));
+    }
+
+    #[test]
+    fn colliding_bodies_trade_momentum() {
+        let lua = scene(
+            r#"set_gravity(0, 0)
+            local function block(x, body)
+                return { type = "rect", pos = { x = x, y = 0 }, size = { width = 10, height = 10 },
+                    collider = true, body = body }
+            end
+            add("a", block(0, { velocity = { x = 100, y = 0 }, bounce = 1 }))
+            add("b", block(10, { bounce = 1 }))
+            add("c", block(100, { velocity = { x = 100, y = 0 } }))
+            add("d", block(110, { mass = 3 }))"#,
+        );
+        run(&lua, 1);
+
+        // the same mass bouncing perfectly swaps velocities
+        assert!(close(get(&lua, "a.body.velocity").x, 0.0));
+        assert!(close(get(&lua, "b.body.velocity").x, 100.0));
+        // without bouncing, they move on together, with the same momentum as before
+        assert!(close(get(&lua, "c.body.velocity").x, 25.0));
+        assert!(close(get(&lua, "d.body.velocity").x, 25.0));
+        // and they're pushed apart, the lighter one further
+        let (c, d) = (get(&lua, "c.pos").x, get(&lua, "d.pos").x);
+        assert!(c < 100.0 + 100.0 * STEP && d > 110.0);
+        assert!((100.0 + 100.0 * STEP - c) > (d - 110.0));
+    }
+
+    #[test]
+    fn gravity_has_to_be_finite() {
+        let lua = scene("");
+        assert!(lua.load("set_gravity(0/0, 1)").exec().is_err());
+        assert!(lua.load("set_gravity(1, math.huge)").exec().is_err());
+        assert_eq!(physics_mut(&lua).unwrap().gravity(), DEFAULT_GRAVITY);
+    }
+
+    #[test]
+    fn gravity_is_saved_with_the_scene() {
+        let lua = scene("set_gravity(0, -50)");
+        let saved = dump_scene(&lua).unwrap();
+        physics_mut(&lua).unwrap().clear();
+        load_scene_dump(&lua, &saved).unwrap();
+        assert_eq!(physics_mut(&lua).unwrap().gravity(), Vec2::new(0.0, -50.0));
+    }
+}
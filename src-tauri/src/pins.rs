@@ -0,0 +1,271 @@
+use crate::lua_types::LuaError;
+use crate::script_errors::root_cause;
+use mlua::prelude::*;
+use mlua::AppDataRefMut;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Where pinned expressions look up names: entities by ID first, so `player.pos.x` works, then globals.
+const PIN_ENVIRONMENT: &str = r#"
+return setmetatable({}, { __index = function(_, key)
+    local entity = currentScene and currentScene.entities[key]
+    if entity ~= nil then return entity end
+    return _G[key]
+end })
+"#;
+
+struct Pin {
+    id: u32,
+    expression: String,
+    function: LuaRegistryKey,
+}
+
+/// A pinned expression, as the frontend sees it.
+#[derive(Serialize, Clone, Debug)]
+pub struct PinInfo {
+    pub id: u32,
+    pub expression: String,
+}
+
+/// What a pinned expression was at the end of a tick.
+#[derive(Serialize, Clone, Debug)]
+pub struct Sample {
+    pub id: u32,
+    /// Numbers, booleans & strings as they are, and anything else described
+    pub value: Value,
+    pub error: Option<String>,
+}
+
+/// Expressions sampled every tick, so they can be watched changing over time.
+pub struct Pins {
+    next_id: u32,
+    pins: Vec<Pin>,
+}
+
+impl Pins {
+    pub fn new() -> Self {
+        Pins {
+            next_id: 1,
+            pins: Vec::new(),
+        }
+    }
+
+    pub fn list(&self) -> Vec<PinInfo> {
+        self.pins
+            .iter()
+            .map(|pin| PinInfo {
+                id: pin.id,
+                expression: pin.expression.clone(),
+            })
+            .collect()
+    }
+}
+
+pub fn pins_mut(lua: &Lua) -> Result<AppDataRefMut<'_, Pins>, LuaError> {
+    lua.app_data_mut::<Pins>()
+        .ok_or_else(|| LuaError::InitializationError("Pins missing".to_string()))
+}
+
+/// Compile an expression to evaluate later, where names can be entity IDs as well as globals.
+pub fn compile_expression<'lua>(
+    lua: &'lua Lua,
+    expression: &str,
+    name: &str,
+) -> Result<LuaFunction<'lua>, LuaError> {
+    let env: LuaTable = lua.load(PIN_ENVIRONMENT).set_name("=pins").eval()?;
+    lua.load(format!("return {}", expression))
+        .set_name(format!("={}", name))
+        .set_environment(env)
+        .into_function()
+        .map_err(|e| match e {
+            mlua::Error::SyntaxError { message, .. } => LuaError::FormatError(message),
+            e => LuaError::LuaError(e),
+        })
+}
+
+/// Start sampling an expression every tick, as long as it compiles.
+pub fn pin(lua: &Lua, expression: &str) -> Result<PinInfo, LuaError> {
+    let function = compile_expression(lua, expression, "pin")?;
+
+    let mut pins = pins_mut(lua)?;
+    let info = PinInfo {
+        id: pins.next_id,
+        expression: expression.to_string(),
+    };
+    pins.next_id += 1;
+    pins.pins.push(Pin {
+        id: info.id,
+        expression: info.expression.clone(),
+        function: lua.create_registry_value(function)?,
+    });
+    Ok(info)
+}
+
+pub fn unpin(lua: &Lua, id: u32) -> Result<(), LuaError> {
+    let mut pins = pins_mut(lua)?;
+    if let Some(index) = pins.pins.iter().position(|pin| pin.id == id) {
+        let pin = pins.pins.remove(index);
+        drop(pins);
+        lua.remove_registry_value(pin.function)?;
+    }
+    Ok(())
+}
+
+/// Evaluate every pinned expression, sending the values to the frontend as `pin_samples`.
+pub fn sample_pins(lua: &Lua) -> Result<(), LuaError> {
+    let functions = {
+        let pins = pins_mut(lua)?;
+        if pins.pins.is_empty() {
+            return Ok(());
+        }
+        pins.pins
+            .iter()
+            .map(|pin| Ok((pin.id, lua.registry_value::<LuaFunction>(&pin.function)?)))
+            .collect::<Result<Vec<_>, LuaError>>()?
+    };
+
+    let samples = functions
+        .into_iter()
+        .map(|(id, function)| {
+            let (value, error) = match function.call::<_, LuaValue>(()) {
+                Ok(value) => (describe(lua, value)?, None),
+                Err(e) => (Value::Null, Some(root_cause(&e))),
+            };
+            Ok(Sample { id, value, error })
+        })
+        .collect::<Result<Vec<_>, LuaError>>()?;
+
+    Ok(lua
+        .globals()
+        .get::<_, LuaFunction>("emit")?
+        .call::<_, ()>(("pin_samples", lua.to_value(&samples)?))?)
+}
+
+fn describe(lua: &Lua, value: LuaValue) -> Result<Value, LuaError> {
+    Ok(match value {
+        LuaValue::Nil => Value::Null,
+        LuaValue::Boolean(b) => Value::Bool(b),
+        LuaValue::Integer(i) => Value::from(i),
+        LuaValue::Number(n) => Value::from(n),
+        LuaValue::String(s) => Value::String(s.to_string_lossy().to_string()),
+        LuaValue::Table(_) => {
+            let serpent: LuaTable = lua
+                .globals()
+                .get::<_, LuaFunction>("require")?
+                .call("serpent")?;
+            let options = lua.create_table()?;
+            options.set("comment", false)?;
+            options.set("nocode", true)?;
+            options.set("maxlevel", 2)?;
+            Value::String(
+                serpent
+                    .get::<_, LuaFunction>("line")?
+                    .call::<_, String>((value, options))?,
+            )
+        }
+        value => Value::String(value.to_string()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lua_setup::match_message;
+    use crate::lua_types::LuaMessage;
+    use crate::runner::{headless_lua, LUA_DIR};
+    use crate::testing::Recorder;
+    use serde_json::json;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    fn scene() -> (Lua, Arc<Recorder>) {
+        let recorder = Arc::new(Recorder::new(Path::new(LUA_DIR)));
+        let lua = headless_lua(recorder.clone()).unwrap();
+        lua.load(
+            r#"
+            currentScene:add_entity("player", { type = "rect", pos = { x = 3, y = 4 }, scripts = {
+                on_tick = { string = [[self.pos.x = self.pos.x + 1]] },
+            } })
+            "#,
+        )
+        .exec()
+        .unwrap();
+        (lua, recorder)
+    }
+
+    /// The samples sent at the end of the next tick.
+    fn tick(lua: &Lua, recorder: &Recorder) -> Value {
+        match_message(lua, LuaMessage::Tick(1.0 / 60.0)).unwrap();
+        recorder
+            .events("pin_samples")
+            .unwrap()
+            .pop()
+            .unwrap_or(Value::Null)
+    }
+
+    #[test]
+    fn only_expressions_that_compile_are_pinned() {
+        let (lua, _) = scene();
+        assert!(matches!(
+            pin(&lua, "player.pos.x +"),
+            Err(LuaError::FormatError(_))
+        ));
+        assert!(matches!(pin(&lua, "x = 1"), Err(LuaError::FormatError(_))));
+        assert!(pins_mut(&lua).unwrap().list().is_empty());
+
+        let info = pin(&lua, "player.pos.x").unwrap();
+        assert_eq!(info.expression, "player.pos.x");
+        assert_eq!(pin(&lua, "player.pos.y").unwrap().id, info.id + 1);
+    }
+
+    #[test]
+    fn entities_come_before_globals() {
+        let (lua, recorder) = scene();
+        lua.load(r#"player = "a global"; speed = 5"#)
+            .exec()
+            .unwrap();
+        pin(&lua, "player.pos.x").unwrap();
+        pin(&lua, "speed").unwrap();
+        pin(&lua, "player").unwrap();
+        let samples = tick(&lua, &recorder);
+        assert_eq!(samples[0]["value"], 4);
+        assert_eq!(samples[1]["value"], 5);
+        assert!(samples[2]["value"].as_str().unwrap().contains("pos"));
+    }
+
+    #[test]
+    fn errors_are_sampled_without_stopping_the_tick() {
+        let (lua, recorder) = scene();
+        let ghost = pin(&lua, "ghost.pos.x").unwrap();
+        let player = pin(&lua, "player.pos.x").unwrap();
+        let samples = tick(&lua, &recorder);
+        assert_eq!(samples[0]["id"], ghost.id);
+        assert_eq!(samples[0]["value"], Value::Null);
+        assert!(samples[0]["error"].as_str().unwrap().contains("ghost"));
+        assert_eq!(
+            samples[1],
+            json!({ "id": player.id, "value": 4, "error": null })
+        );
+        // and the next tick still happens
+        assert_eq!(tick(&lua, &recorder)[1]["value"], 5);
+    }
+
+    #[test]
+    fn unpinning_stops_sampling() {
+        let (lua, recorder) = scene();
+        let x = pin(&lua, "player.pos.x").unwrap();
+        let y = pin(&lua, "player.pos.y").unwrap();
+        unpin(&lua, x.id).unwrap();
+        let samples = tick(&lua, &recorder);
+        assert_eq!(samples.as_array().unwrap().len(), 1);
+        assert_eq!(samples[0]["id"], y.id);
+        assert_eq!(pins_mut(&lua).unwrap().list().len(), 1);
+
+        // unknown IDs are ignored, and nothing is sent once there are no pins left
+        unpin(&lua, 99).unwrap();
+        unpin(&lua, y.id).unwrap();
+        let sent = recorder.events("pin_samples").unwrap().len();
+        tick(&lua, &recorder);
+        assert_eq!(recorder.events("pin_samples").unwrap().len(), sent);
+    }
+}
@@ -0,0 +1,450 @@
+use crate::lua_types::LuaError;
+use mlua::prelude::*;
+use mlua::AppDataRefMut;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::time::{Duration, Instant};
+
+/// Most spans kept for the trace, which is a minute or so of a busy scene.
+const MAX_TRACE_EVENTS: usize = 200_000;
+
+/// What made a script run.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Tick,
+    Broadcast,
+    Message,
+    /// Input, collisions, timers, the inspector, and anything else
+    Other,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Tick => "tick",
+            Kind::Broadcast => "broadcast",
+            Kind::Message => "message",
+            Kind::Other => "other",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Span {
+    /// A part of the runtime's own work, like stepping physics or emitting an update
+    Phase(&'static str),
+    Script {
+        entity: String,
+        script: String,
+        kind: Kind,
+    },
+}
+
+struct Open {
+    span: Span,
+    start: Instant,
+    /// Time spent in spans started inside this one, which isn't counted as its own
+    children: Duration,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Stats {
+    calls: u64,
+    total: Duration,
+    own: Duration,
+    max: Duration,
+}
+
+/// A span that's finished, kept for the trace.
+struct TraceEvent {
+    span: Span,
+    /// Since the profiler started
+    start: Duration,
+    duration: Duration,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Timing {
+    pub calls: u64,
+    pub total_ms: f64,
+    /// Not counting time spent in spans inside it, e.g. scripts messaged from a script
+    pub self_ms: f64,
+    pub average_ms: f64,
+    pub max_ms: f64,
+}
+
+impl From<Stats> for Timing {
+    fn from(stats: Stats) -> Self {
+        Timing {
+            calls: stats.calls,
+            total_ms: millis(stats.total),
+            self_ms: millis(stats.own),
+            average_ms: millis(stats.total) / stats.calls.max(1) as f64,
+            max_ms: millis(stats.max),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct PhaseTiming {
+    pub name: String,
+    #[serde(flatten)]
+    pub timing: Timing,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ScriptTiming {
+    pub entity: String,
+    pub script: String,
+    pub kind: Kind,
+    #[serde(flatten)]
+    pub timing: Timing,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ProfileReport {
+    pub enabled: bool,
+    /// How long it's been profiling for
+    pub seconds: f64,
+    /// Where each tick's time went, slowest first
+    pub phases: Vec<PhaseTiming>,
+    /// Slowest first, by time spent in the script itself
+    pub scripts: Vec<ScriptTiming>,
+}
+
+/// Times how long the runtime & each entity's scripts take, while it's turned on.
+pub struct Profiler {
+    enabled: bool,
+    started: Instant,
+    open: Vec<Open>,
+    causes: Vec<Kind>,
+    stats: HashMap<Span, Stats>,
+    trace: VecDeque<TraceEvent>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            enabled: false,
+            started: Instant::now(),
+            open: Vec::new(),
+            causes: Vec::new(),
+            stats: HashMap::new(),
+            trace: VecDeque::new(),
+        }
+    }
+
+    /// Turning it on starts the measurements over.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled && !self.enabled {
+            self.reset();
+        }
+        self.enabled = enabled;
+    }
+
+    pub fn reset(&mut self) {
+        self.started = Instant::now();
+        self.stats.clear();
+        self.trace.clear();
+    }
+
+    fn begin(&mut self, span: Span) {
+        self.open.push(Open {
+            span,
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    fn end(&mut self) {
+        let Some(open) = self.open.pop() else {
+            return;
+        };
+        let duration = open.start.elapsed();
+        if let Some(parent) = self.open.last_mut() {
+            parent.children += duration;
+        }
+        let stats = self.stats.entry(open.span.clone()).or_default();
+        stats.calls += 1;
+        stats.total += duration;
+        stats.own += duration.saturating_sub(open.children);
+        stats.max = stats.max.max(duration);
+
+        if self.trace.len() >= MAX_TRACE_EVENTS {
+            self.trace.pop_front();
+        }
+        self.trace.push_back(TraceEvent {
+            span: open.span,
+            start: open.start.saturating_duration_since(self.started),
+            duration,
+        });
+    }
+
+    pub fn report(&self) -> ProfileReport {
+        let mut phases = Vec::new();
+        let mut scripts = Vec::new();
+        for (span, stats) in &self.stats {
+            match span {
+                Span::Phase(name) => phases.push(PhaseTiming {
+                    name: name.to_string(),
+                    timing: (*stats).into(),
+                }),
+                Span::Script {
+                    entity,
+                    script,
+                    kind,
+                } => scripts.push(ScriptTiming {
+                    entity: entity.clone(),
+                    script: script.clone(),
+                    kind: *kind,
+                    timing: (*stats).into(),
+                }),
+            }
+        }
+        phases.sort_by(|a, b| b.timing.total_ms.total_cmp(&a.timing.total_ms));
+        scripts.sort_by(|a, b| b.timing.self_ms.total_cmp(&a.timing.self_ms));
+        ProfileReport {
+            enabled: self.enabled,
+            seconds: self.started.elapsed().as_secs_f64(),
+            phases,
+            scripts,
+        }
+    }
+
+    /// Save the spans as Chrome trace events, for `chrome://tracing` or Perfetto.
+    pub fn export_trace(&self, path: &str) -> Result<(), LuaError> {
+        let events: Vec<_> = self
+            .trace
+            .iter()
+            .map(|event| {
+                let micros = |duration: Duration| duration.as_secs_f64() * 1_000_000.0;
+                let (name, category, args) = match &event.span {
+                    Span::Phase(name) => (name.to_string(), "runtime", json!({})),
+                    Span::Script {
+                        entity,
+                        script,
+                        kind,
+                    } => (
+                        format!("{}:{}", entity, script),
+                        kind.name(),
+                        json!({ "entity": entity, "script": script }),
+                    ),
+                };
+                json!({
+                    "name": name,
+                    "cat": category,
+                    "ph": "X",
+                    "ts": micros(event.start),
+                    "dur": micros(event.duration),
+                    "pid": 1,
+                    "tid": 1,
+                    "args": args,
+                })
+            })
+            .collect();
+        let trace = json!({ "traceEvents": events, "displayTimeUnit": "ms" });
+        fs::write(path, trace.to_string()).map_err(|e| {
+            LuaError::FormatError(format!("Couldn't save trace to \"{}\": {}", path, e))
+        })
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+pub fn profiler_mut(lua: &Lua) -> Result<AppDataRefMut<'_, Profiler>, LuaError> {
+    lua.app_data_mut::<Profiler>()
+        .ok_or_else(|| LuaError::InitializationError("Profiler missing".to_string()))
+}
+
+/// Time `f` as a span, if the profiler's on.
+/// The profiler isn't borrowed while `f` runs, since scripts in it can start spans of their own.
+fn measure<R>(lua: &Lua, span: impl FnOnce(&Profiler) -> Span, f: impl FnOnce() -> R) -> R {
+    let measuring = match lua.app_data_mut::<Profiler>() {
+        Some(mut profiler) if profiler.enabled => {
+            let span = span(&profiler);
+            profiler.begin(span);
+            true
+        }
+        _ => false,
+    };
+    let result = f();
+    if measuring {
+        if let Some(mut profiler) = lua.app_data_mut::<Profiler>() {
+            profiler.end();
+        }
+    }
+    result
+}
+
+/// Time a part of the runtime's work, like a step of the tick.
+pub fn profile_phase<R>(lua: &Lua, name: &'static str, f: impl FnOnce() -> R) -> R {
+    measure(lua, |_| Span::Phase(name), f)
+}
+
+/// Time an entity's script, as whatever kind of event made it run.
+pub fn profile_script<R>(lua: &Lua, entity: &str, script: &str, f: impl FnOnce() -> R) -> R {
+    measure(
+        lua,
+        |profiler| Span::Script {
+            entity: entity.to_string(),
+            script: script.to_string(),
+            kind: profiler.causes.last().copied().unwrap_or(Kind::Other),
+        },
+        f,
+    )
+}
+
+/// Count scripts run by `f` as being run for `kind`, e.g. because they were broadcast to.
+pub fn with_cause<R>(lua: &Lua, kind: Kind, f: impl FnOnce() -> R) -> R {
+    if let Some(mut profiler) = lua.app_data_mut::<Profiler>() {
+        profiler.causes.push(kind);
+    }
+    let result = f();
+    if let Some(mut profiler) = lua.app_data_mut::<Profiler>() {
+        profiler.causes.pop();
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lua_setup::match_message;
+    use crate::lua_types::LuaMessage;
+    use crate::runner::{headless_lua, LUA_DIR};
+    use crate::testing::Recorder;
+    use serde_json::Value;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::thread::sleep;
+
+    fn script(entity: &str, script: &str, kind: Kind) -> Span {
+        Span::Script {
+            entity: entity.to_string(),
+            script: script.to_string(),
+            kind,
+        }
+    }
+
+    #[test]
+    fn self_time_leaves_out_nested_spans() {
+        let mut profiler = Profiler::new();
+        profiler.set_enabled(true);
+        profiler.begin(Span::Phase("on_tick"));
+        sleep(Duration::from_millis(20));
+        profiler.begin(script("player", "on_tick", Kind::Tick));
+        sleep(Duration::from_millis(30));
+        profiler.end();
+        profiler.end();
+
+        let report = profiler.report();
+        let phase = &report.phases[0].timing;
+        let script = &report.scripts[0].timing;
+        assert!(phase.total_ms >= 50.0, "{:?}", phase);
+        assert!(phase.self_ms >= 20.0, "{:?}", phase);
+        assert!(phase.total_ms - phase.self_ms >= 30.0, "{:?}", phase);
+        assert_eq!(script.self_ms, script.total_ms);
+        assert!((phase.total_ms - phase.self_ms - script.total_ms).abs() < 1e-6);
+    }
+
+    #[test]
+    fn scripts_are_counted_as_what_ran_them() {
+        let lua = headless_lua(Arc::new(Recorder::new(Path::new(LUA_DIR)))).unwrap();
+        lua.load(
+            r#"
+            currentScene:add_entity("a", { type = "rect", scripts = {
+                on_tick = { string = [[broadcast("ping"); message("b", "poke")]] },
+                on_click = { string = [[]] },
+            } })
+            currentScene:add_entity("b", { type = "rect", scripts = {
+                ping = { string = [[]] },
+                poke = { string = [[]] },
+            } })
+            "#,
+        )
+        .exec()
+        .unwrap();
+        profiler_mut(&lua).unwrap().set_enabled(true);
+        match_message(&lua, LuaMessage::Tick(1.0 / 60.0)).unwrap();
+        lua.load(r#"currentScene:run_protected("a", "on_click", {})"#)
+            .exec()
+            .unwrap();
+
+        let report = profiler_mut(&lua).unwrap().report();
+        let mut kinds: Vec<_> = report
+            .scripts
+            .iter()
+            .map(|timing| (timing.entity.as_str(), timing.script.as_str(), timing.kind))
+            .collect();
+        kinds.sort_by_key(|(entity, script, _)| (*entity, *script));
+        assert_eq!(
+            kinds,
+            [
+                ("a", "on_click", Kind::Other),
+                ("a", "on_tick", Kind::Tick),
+                ("b", "ping", Kind::Broadcast),
+                ("b", "poke", Kind::Message),
+            ]
+        );
+        assert!(report.phases.iter().any(|phase| phase.name == "on_tick"));
+    }
+
+    #[test]
+    fn the_trace_keeps_the_latest_spans() {
+        let mut profiler = Profiler::new();
+        profiler.set_enabled(true);
+        for _ in 0..MAX_TRACE_EVENTS + 10 {
+            profiler.begin(Span::Phase("tick"));
+            profiler.end();
+        }
+        assert_eq!(profiler.trace.len(), MAX_TRACE_EVENTS);
+        assert_eq!(
+            profiler.report().phases[0].timing.calls,
+            MAX_TRACE_EVENTS as u64 + 10
+        );
+    }
+
+    #[test]
+    fn traces_are_chrome_trace_events() {
+        let mut profiler = Profiler::new();
+        profiler.set_enabled(true);
+        profiler.begin(Span::Phase("on_tick"));
+        profiler.begin(script("player", "on_tick", Kind::Tick));
+        sleep(Duration::from_millis(2));
+        profiler.end();
+        profiler.end();
+
+        let path = std::env::temp_dir().join(format!("stacks-trace-{}.json", std::process::id()));
+        profiler.export_trace(path.to_str().unwrap()).unwrap();
+        let trace: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let _ = fs::remove_file(&path);
+
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 2);
+        // finished first, so the script comes before the phase it ran in
+        let (script, phase) = (&events[0], &events[1]);
+        assert_eq!(script["name"], "player:on_tick");
+        assert_eq!(script["cat"], "tick");
+        assert_eq!(script["args"]["entity"], "player");
+        assert_eq!(phase["name"], "on_tick");
+        assert_eq!(phase["cat"], "runtime");
+        for event in events {
+            assert_eq!(event["ph"], "X");
+            assert!(event["ts"].as_f64().unwrap() >= 0.0);
+            assert!(event["pid"].is_number() && event["tid"].is_number());
+        }
+        let micros = |event: &Value, field: &str| event[field].as_f64().unwrap();
+        assert!(micros(script, "dur") >= 2000.0, "{}", script);
+        assert!(micros(script, "dur") < 2_000_000.0, "{}", script);
+        assert!(micros(phase, "ts") <= micros(script, "ts"));
+        assert!(
+            micros(script, "ts") + micros(script, "dur")
+                <= micros(phase, "ts") + micros(phase, "dur")
+        );
+    }
+}
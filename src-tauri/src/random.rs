@@ -0,0 +1,336 @@
+use crate::lua_types::LuaError;
+use crate::script_runner::current_script;
+use mlua::prelude::*;
+use mlua::AppDataRefMut;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A small, fast generator (SplitMix64) whose numbers only depend on where it started,
+/// so the same seed gives the same numbers on every computer.
+#[derive(Clone)]
+struct Stream(u64);
+
+impl Stream {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Between 0 (inclusive) & 1 (exclusive).
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Between `min` & `max`, both inclusive.
+    fn next_between(&mut self, min: i64, max: i64) -> i64 {
+        let span = max.wrapping_sub(min) as u64 as u128 + 1;
+        min.wrapping_add(((self.next_u64() as u128 * span) >> 64) as i64)
+    }
+}
+
+/// FNV-1a, which unlike std's hashers is guaranteed to stay the same between Rust versions.
+fn hash(text: &str) -> u64 {
+    text.bytes().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// Random numbers for scripts, from a seed saved with the scene.
+///
+/// Each entity draws from its own stream, so one entity using more random numbers
+/// doesn't change the numbers any other entity gets.
+#[derive(Clone)]
+pub struct Random {
+    seed: i64,
+    /// By entity ID, or "" for code that isn't running as an entity's script
+    streams: HashMap<String, Stream>,
+}
+
+impl Random {
+    pub fn new() -> Self {
+        Random::with_seed(fresh_seed())
+    }
+
+    pub fn with_seed(seed: i64) -> Self {
+        Random {
+            seed,
+            streams: HashMap::new(),
+        }
+    }
+
+    pub fn seed(&self) -> i64 {
+        self.seed
+    }
+
+    /// Where each entity's stream has got to, to save with the scene.
+    /// They're written in hex, since scene files can't hold every 64-bit number exactly.
+    pub fn saved_streams(&self) -> BTreeMap<String, String> {
+        self.streams
+            .iter()
+            .map(|(entity, stream)| (entity.clone(), format!("{:016x}", stream.0)))
+            .collect()
+    }
+
+    /// Carry on from streams saved by `saved_streams`.
+    pub fn restore_streams(&mut self, streams: HashMap<String, String>) -> Result<(), LuaError> {
+        for (entity, state) in streams {
+            let state = u64::from_str_radix(&state, 16).map_err(|_| {
+                LuaError::FormatError(format!(
+                    "Couldn't read {}'s random numbers from \"{}\".",
+                    entity, state
+                ))
+            })?;
+            self.streams.insert(entity, Stream(state));
+        }
+        Ok(())
+    }
+
+    fn stream(&mut self, entity: &str) -> &mut Stream {
+        let seed = self.seed as u64;
+        self.streams
+            .entry(entity.to_string())
+            .or_insert_with(|| Stream(seed ^ hash(entity)))
+    }
+
+    pub fn rename_entity(&mut self, from: &str, to: &str) {
+        if let Some(stream) = self.streams.remove(from) {
+            self.streams.insert(to.to_string(), stream);
+        }
+    }
+
+    /// Start an entity's numbers over, e.g. because it was deleted or replaced.
+    pub fn forget_entity(&mut self, entity: &str) {
+        self.streams.remove(entity);
+    }
+}
+
+/// A seed for scenes that don't have one yet, kept small so it's easy to read & type back in.
+fn fresh_seed() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.subsec_nanos() as i64 ^ time.as_secs() as i64)
+        .unwrap_or_default()
+        & 0x7FFF_FFFF
+}
+
+pub fn random_mut(lua: &Lua) -> Result<AppDataRefMut<'_, Random>, LuaError> {
+    lua.app_data_mut::<Random>()
+        .ok_or_else(|| LuaError::InitializationError("Random missing".to_string()))
+}
+
+/// Run `f` with the stream belonging to whichever entity's script is running.
+fn with_stream<T>(lua: &Lua, f: impl FnOnce(&mut Stream) -> T) -> Result<T, LuaError> {
+    let entity = current_script(lua)
+        .map(|(entity, _)| entity)
+        .unwrap_or_default();
+    Ok(f(random_mut(lua)?.stream(&entity)))
+}
+
+fn range<'lua>(
+    lua: &'lua Lua,
+    min: LuaValue<'lua>,
+    max: LuaValue<'lua>,
+) -> LuaResult<LuaValue<'lua>> {
+    match (&min, &max) {
+        (LuaValue::Integer(min), LuaValue::Integer(max)) => {
+            if min > max {
+                return Err(LuaError::FormatError(format!(
+                    "Can't pick a number from {} up to {}, since {} is bigger.",
+                    min, max, min
+                ))
+                .into());
+            }
+            Ok(LuaValue::Integer(with_stream(lua, |stream| {
+                stream.next_between(*min, *max)
+            })?))
+        }
+        _ => {
+            let min: f64 = lua.unpack(min)?;
+            let max: f64 = lua.unpack(max)?;
+            Ok(LuaValue::Number(
+                min + with_stream(lua, Stream::next_f64)? * (max - min),
+            ))
+        }
+    }
+}
+
+pub fn set_globals(lua: &Lua) -> Result<(), LuaError> {
+    lua.set_app_data(Random::new());
+
+    let random = lua.create_table()?;
+    random.set(
+        "range",
+        lua.create_function(|l: &Lua, (min, max): (LuaValue, LuaValue)| range(l, min, max))?,
+    )?;
+    random.set(
+        "choice",
+        lua.create_function(|l: &Lua, list: LuaTable| {
+            let length = list.raw_len() as i64;
+            if length == 0 {
+                return Err(
+                    LuaError::FormatError("Can't choose from an empty list.".to_string()).into(),
+                );
+            }
+            list.get::<_, LuaValue>(with_stream(l, |stream| stream.next_between(1, length))?)
+        })?,
+    )?;
+    random.set(
+        "chance",
+        lua.create_function(|l: &Lua, probability: f64| {
+            Ok(with_stream(l, Stream::next_f64)? < probability)
+        })?,
+    )?;
+    random.set(
+        "seed",
+        lua.create_function(|l: &Lua, ()| Ok(random_mut(l)?.seed()))?,
+    )?;
+    random.set(
+        "set_seed",
+        lua.create_function(|l: &Lua, seed: i64| {
+            *random_mut(l)? = Random::with_seed(seed);
+            Ok(())
+        })?,
+    )?;
+    lua.globals()
+        .set("random", random)
+        .map_err(|e| LuaError::InitializationError(format!("Failed to set random: {}", e)))?;
+
+    // scripts written for plain Lua still get numbers that replay the same way
+    let math: LuaTable = lua.globals().get("math")?;
+    math.set(
+        "random",
+        lua.create_function(|l: &Lua, (m, n): (Option<i64>, Option<i64>)| match (m, n) {
+            (None, _) => with_stream(l, Stream::next_f64)?.into_lua(l),
+            (Some(max), None) => range(l, LuaValue::Integer(1), LuaValue::Integer(max)),
+            (Some(min), Some(max)) => range(l, LuaValue::Integer(min), LuaValue::Integer(max)),
+        })?,
+    )?;
+    math.set(
+        "randomseed",
+        lua.create_function(|l: &Lua, seed: Option<i64>| {
+            *random_mut(l)? = Random::with_seed(seed.unwrap_or_else(fresh_seed));
+            Ok(())
+        })?,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lua_setup::{dump_scene, load_scene_dump};
+    use crate::runner::{headless_lua, LUA_DIR};
+    use crate::testing::Recorder;
+    use serde_json::Value;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    /// A scene whose entities each emit a few `math.random` numbers when clicked.
+    fn scene(seed: i64, entities: &[&str]) -> (Lua, Arc<Recorder>) {
+        let recorder = Arc::new(Recorder::new(Path::new(LUA_DIR)));
+        let lua = headless_lua(recorder.clone()).unwrap();
+        *random_mut(&lua).unwrap() = Random::with_seed(seed);
+        for id in entities {
+            lua.load(format!(
+                r#"currentScene:add_entity("{}", {{ type = "rect", scripts = {{ on_click = {{ string = [[
+                    emit("{}", {{ math.random(), math.random(1, 1000), math.random() }})
+                ]] }} }} }})"#,
+                id, id
+            ))
+            .exec()
+            .unwrap();
+        }
+        (lua, recorder)
+    }
+
+    fn click(lua: &Lua, id: &str) {
+        lua.load(format!(
+            r#"currentScene:run_protected("{}", "on_click", {{}})"#,
+            id
+        ))
+        .exec()
+        .unwrap();
+    }
+
+    fn numbers(recorder: &Recorder, id: &str) -> Vec<Value> {
+        recorder.events(id).unwrap()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_numbers() {
+        let (first, first_numbers) = scene(42, &["die"]);
+        let (second, second_numbers) = scene(42, &["die"]);
+        let (other, other_numbers) = scene(43, &["die"]);
+        for _ in 0..3 {
+            click(&first, "die");
+            click(&second, "die");
+            click(&other, "die");
+        }
+
+        assert_eq!(numbers(&first_numbers, "die").len(), 3);
+        assert_eq!(
+            numbers(&first_numbers, "die"),
+            numbers(&second_numbers, "die")
+        );
+        assert_ne!(
+            numbers(&first_numbers, "die"),
+            numbers(&other_numbers, "die")
+        );
+    }
+
+    #[test]
+    fn entities_draw_from_their_own_streams() {
+        let (alone, alone_numbers) = scene(7, &["a"]);
+        let (busy, busy_numbers) = scene(7, &["a", "b"]);
+        click(&alone, "a");
+        click(&alone, "a");
+        click(&busy, "b");
+        click(&busy, "a");
+        click(&busy, "b");
+        click(&busy, "a");
+
+        assert_eq!(numbers(&alone_numbers, "a"), numbers(&busy_numbers, "a"));
+        assert_ne!(numbers(&busy_numbers, "a"), numbers(&busy_numbers, "b"));
+    }
+
+    #[test]
+    fn randomseed_starts_the_numbers_over() {
+        let (lua, recorder) = scene(1, &[]);
+        lua.load(
+            r#"currentScene:add_entity("die", { type = "rect", scripts = { on_click = { string = [[
+                math.randomseed(5)
+                local first = { math.random(), math.random(6) }
+                math.randomseed(5)
+                emit("rolls", { first = first, again = { math.random(), math.random(6) }, seed = random.seed() })
+            ]] } } })"#,
+        )
+        .exec()
+        .unwrap();
+        click(&lua, "die");
+
+        let rolls = &recorder.events("rolls").unwrap()[0];
+        assert_eq!(rolls["first"], rolls["again"]);
+        assert_eq!(rolls["seed"], 5);
+    }
+
+    #[test]
+    fn saved_scenes_carry_on_with_the_same_numbers() {
+        let (lua, recorder) = scene(9, &["die"]);
+        click(&lua, "die");
+        let saved = dump_scene(&lua).unwrap();
+        click(&lua, "die");
+        click(&lua, "die");
+
+        load_scene_dump(&lua, &saved).unwrap();
+        click(&lua, "die");
+        click(&lua, "die");
+
+        let rolls = numbers(&recorder, "die");
+        assert_eq!(rolls[3..], rolls[1..3]);
+        assert_ne!(rolls[3], rolls[0]);
+    }
+}
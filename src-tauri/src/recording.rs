@@ -0,0 +1,517 @@
+use crate::collisions::{collisions_mut, Collisions};
+use crate::host::Host;
+use crate::input::InputEvent;
+use crate::logs::log_fault;
+use crate::lua_setup::{dump_scene, get_host, load_scene_dump, match_message};
+use crate::lua_types::{LuaError, LuaMessage};
+use crate::physics::{physics_mut, Physics};
+use crate::runner::headless_lua;
+use crate::scheduler::warn_unsaved_tasks;
+use mlua::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+use std::time::Instant;
+
+/// Bumped whenever older replay files can't be played any more.
+const REPLAY_VERSION: u32 = 1;
+
+/// Most differences to list when a replay doesn't end up where its recording did.
+const MAX_DIFFERENCES: usize = 10;
+
+/// A message that can change the scene, as it's kept in a recording.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Recorded {
+    Tick {
+        dt: f64,
+    },
+    Input {
+        event: InputEvent,
+    },
+    AddEntity {
+        id: String,
+        data: Value,
+    },
+    UpdateEntityId {
+        from: String,
+        to: String,
+        data: Value,
+    },
+    UpdateEntity {
+        id: String,
+        data: Value,
+    },
+    DeleteEntity {
+        id: String,
+    },
+    DuplicateEntity {
+        id: String,
+    },
+    RunScript {
+        id: String,
+        script: String,
+        data: Value,
+    },
+    InspectorSave {
+        id: String,
+        inspector: String,
+        scripts: Value,
+    },
+    ScriptErrorOptions {
+        auto_disable: bool,
+    },
+    ClearScriptErrors,
+    Eval {
+        code: String,
+        entity: Option<String>,
+    },
+    /// A scene was loaded, kept whole since the file it came from could change or move
+    Scene {
+        scene: String,
+    },
+}
+
+impl Recorded {
+    /// What to record for a message, if it can change the scene.
+    /// Loading a scene is recorded separately, once it's loaded.
+    pub fn from_message(msg: &LuaMessage) -> Option<Recorded> {
+        Some(match msg {
+            LuaMessage::Tick(dt) => Recorded::Tick { dt: *dt },
+            LuaMessage::Input(event) => Recorded::Input {
+                event: event.clone(),
+            },
+            LuaMessage::AddEntity(id, data, _) => Recorded::AddEntity {
+                id: id.clone(),
+                data: data.clone(),
+            },
+            LuaMessage::UpdateEntityId(from, to, data) => Recorded::UpdateEntityId {
+                from: from.clone(),
+                to: to.clone(),
+                data: data.clone(),
+            },
+            LuaMessage::UpdateEntity(id, data) => Recorded::UpdateEntity {
+                id: id.clone(),
+                data: data.clone(),
+            },
+            LuaMessage::DeleteEntity(id) => Recorded::DeleteEntity { id: id.clone() },
+            LuaMessage::DuplicateEntity(id) => Recorded::DuplicateEntity { id: id.clone() },
+            LuaMessage::RunScript(id, script, data, _) => Recorded::RunScript {
+                id: id.clone(),
+                script: script.clone(),
+                data: data.clone(),
+            },
+            LuaMessage::HandleInspectorSave(id, inspector, scripts, _) => Recorded::InspectorSave {
+                id: id.clone(),
+                inspector: inspector.clone(),
+                scripts: scripts.clone(),
+            },
+            LuaMessage::SetScriptErrorOptions(auto_disable) => Recorded::ScriptErrorOptions {
+                auto_disable: *auto_disable,
+            },
+            LuaMessage::ClearScriptErrors => Recorded::ClearScriptErrors,
+            LuaMessage::Eval(code, entity, _) => Recorded::Eval {
+                code: code.clone(),
+                entity: entity.clone(),
+            },
+            _ => return None,
+        })
+    }
+
+    /// The message to replay, with responses going to `responses` & `inspector_responses`
+    /// (which the replay ignores, but which mustn't be dropped for sending them to work).
+    fn into_message(
+        self,
+        responses: &mpsc::Sender<(bool, String)>,
+        inspector_responses: &mpsc::Sender<(bool, String, String)>,
+    ) -> Option<LuaMessage> {
+        Some(match self {
+            Recorded::Tick { dt } => LuaMessage::Tick(dt),
+            Recorded::Input { event } => LuaMessage::Input(event),
+            Recorded::AddEntity { id, data } => LuaMessage::AddEntity(id, data, responses.clone()),
+            Recorded::UpdateEntityId { from, to, data } => {
+                LuaMessage::UpdateEntityId(from, to, data)
+            }
+            Recorded::UpdateEntity { id, data } => LuaMessage::UpdateEntity(id, data),
+            Recorded::DeleteEntity { id } => LuaMessage::DeleteEntity(id),
+            Recorded::DuplicateEntity { id } => LuaMessage::DuplicateEntity(id),
+            Recorded::RunScript { id, script, data } => {
+                LuaMessage::RunScript(id, script, data, responses.clone())
+            }
+            Recorded::InspectorSave {
+                id,
+                inspector,
+                scripts,
+            } => {
+                LuaMessage::HandleInspectorSave(id, inspector, scripts, inspector_responses.clone())
+            }
+            Recorded::ScriptErrorOptions { auto_disable } => {
+                LuaMessage::SetScriptErrorOptions(auto_disable)
+            }
+            Recorded::ClearScriptErrors => LuaMessage::ClearScriptErrors,
+            Recorded::Eval { code, entity } => LuaMessage::Eval(code, entity, responses.clone()),
+            Recorded::Scene { .. } => return None,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    /// Seconds since recording started
+    time: f64,
+    #[serde(flatten)]
+    message: Recorded,
+}
+
+/// A replay file: where the scene started, everything that happened to it, and where it ended up.
+#[derive(Serialize, Deserialize)]
+struct Replay {
+    version: u32,
+    /// In the same format as a scene file
+    start: String,
+    // not saved with scenes, but needed to start from exactly where the recording did
+    #[serde(default = "Physics::new")]
+    physics: Physics,
+    #[serde(default = "Collisions::new")]
+    collisions: Collisions,
+    messages: Vec<Entry>,
+    end: String,
+}
+
+/// A recording in progress, kept in app data until it's stopped.
+struct Recorder {
+    started: Instant,
+    start: String,
+    physics: Physics,
+    collisions: Collisions,
+    messages: Vec<Entry>,
+}
+
+/// Where replays play, out of sight: nothing they emit or print reaches the app.
+struct Offstage {
+    lua_dir: PathBuf,
+}
+
+impl Host for Offstage {
+    fn emit(&self, _event: &str, _payload: Value) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn emit_to(&self, _target: &str, _event: &str, _payload: Value) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn lua_dir(&self) -> Result<PathBuf, String> {
+        Ok(self.lua_dir.clone())
+    }
+
+    fn show(&self, _line: &str) {}
+}
+
+pub fn is_recording(lua: &Lua) -> bool {
+    lua.app_data_ref::<Recorder>().is_some()
+}
+
+/// Start recording from the scene as it is now, which carries on untouched.
+///
+/// Anything that isn't saved with a scene (like running tweens) can't be recorded,
+/// so it's warned about, since the replay will play out without it.
+pub fn start_recording(lua: &Lua) -> Result<(), LuaError> {
+    if is_recording(lua) {
+        return Err(LuaError::FormatError(
+            "Already recording. Stop the recording first.".to_string(),
+        ));
+    }
+    warn_unsaved_tasks(lua, "Recording")?;
+    let recorder = Recorder {
+        started: Instant::now(),
+        start: dump_scene(lua)?,
+        physics: physics_mut(lua)?.clone(),
+        collisions: collisions_mut(lua)?.clone(),
+        messages: Vec::new(),
+    };
+    lua.set_app_data(recorder);
+    Ok(())
+}
+
+/// Add `message` to the recording, if there is one.
+pub fn record(lua: &Lua, message: Recorded) {
+    if let Some(mut recorder) = lua.app_data_mut::<Recorder>() {
+        let time = recorder.started.elapsed().as_secs_f64();
+        recorder.messages.push(Entry { time, message });
+    }
+}
+
+/// Record the scene that was just loaded, if recording.
+pub fn record_scene(lua: &Lua) -> Result<(), LuaError> {
+    if is_recording(lua) {
+        record(
+            lua,
+            Recorded::Scene {
+                scene: dump_scene(lua)?,
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Stop recording, saving the replay to `path`.
+pub fn stop_recording(lua: &Lua, path: &str) -> Result<(), LuaError> {
+    let recorder = lua
+        .remove_app_data::<Recorder>()
+        .ok_or_else(|| LuaError::FormatError("Nothing is being recorded.".to_string()))?;
+    let replay = Replay {
+        version: REPLAY_VERSION,
+        start: recorder.start,
+        physics: recorder.physics,
+        collisions: recorder.collisions,
+        messages: recorder.messages,
+        end: dump_scene(lua)?,
+    };
+    let json = serde_json::to_string_pretty(&replay)
+        .map_err(|e| LuaError::FormatError(format!("Couldn't write replay: {}", e)))?;
+    fs::write(path, json)
+        .map_err(|e| LuaError::FormatError(format!("Couldn't save replay to \"{}\": {}", path, e)))
+}
+
+/// Play back the replay at `path` as fast as possible, returning how the scene it ends up with
+/// differs from the one it was recorded with (so nothing, if it played back the same way).
+///
+/// It plays in a Lua state of its own without sound or gamepads, so the scene that's open
+/// (and any recording of it) carries on untouched.
+pub fn replay(lua: &Lua, path: &str) -> Result<Vec<String>, LuaError> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        LuaError::FormatError(format!("Couldn't open replay at \"{}\": {}", path, e))
+    })?;
+    let replay: Replay = serde_json::from_str(&content)
+        .map_err(|e| LuaError::FormatError(format!("Couldn't read replay: {}", e)))?;
+    if replay.version != REPLAY_VERSION {
+        return Err(LuaError::FormatError(format!(
+            "This replay is version {}, but only version {} replays can be played.",
+            replay.version, REPLAY_VERSION
+        )));
+    }
+
+    let lua_dir = get_host(lua)?
+        .lua_dir()
+        .map_err(LuaError::InitializationError)?;
+    let stage = headless_lua(Arc::new(Offstage { lua_dir }))?;
+    load_scene_dump(&stage, &replay.start)?;
+    *physics_mut(&stage)? = replay.physics;
+    *collisions_mut(&stage)? = replay.collisions;
+    play(&stage, replay.messages)?;
+    differences(&stage, &replay.end, &dump_scene(&stage)?)
+}
+
+fn play(lua: &Lua, messages: Vec<Entry>) -> Result<(), LuaError> {
+    let (responses, _response_rx) = mpsc::channel();
+    let (inspector_responses, _inspector_response_rx) = mpsc::channel();
+    for entry in messages {
+        if let Recorded::Scene { scene } = &entry.message {
+            load_scene_dump(lua, scene)?;
+            continue;
+        }
+        if let Some(msg) = entry.message.into_message(&responses, &inspector_responses) {
+            // just like when it was recorded, a failing message doesn't stop everything after it
+            if let Err(e) = match_message(lua, msg) {
+                log_fault(lua, "replay", &e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read a scene dump back into JSON, which compares tables regardless of key order.
+fn dump_as_json(lua: &Lua, dump: &str) -> Result<Value, LuaError> {
+    let serpent: LuaTable = lua
+        .globals()
+        .get::<_, LuaFunction>("require")?
+        .call("serpent")?;
+    let (success, loaded): (bool, LuaValue) = serpent.get::<_, LuaFunction>("load")?.call(dump)?;
+    if !success {
+        return Err(LuaError::FormatError("Couldn't read scene.".to_string()));
+    }
+    Ok(lua.from_value(loaded)?)
+}
+
+fn differences(lua: &Lua, expected: &str, actual: &str) -> Result<Vec<String>, LuaError> {
+    let mut found = Vec::new();
+    diff(
+        "scene",
+        &dump_as_json(lua, expected)?,
+        &dump_as_json(lua, actual)?,
+        &mut found,
+    );
+    Ok(found)
+}
+
+fn diff(path: &str, expected: &Value, actual: &Value, found: &mut Vec<String>) {
+    if found.len() >= MAX_DIFFERENCES {
+        return;
+    }
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            let mut keys: Vec<&String> = expected.keys().chain(actual.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                diff(
+                    &format!("{}.{}", path, key),
+                    expected.get(key).unwrap_or(&Value::Null),
+                    actual.get(key).unwrap_or(&Value::Null),
+                    found,
+                );
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            for i in 0..expected.len().max(actual.len()) {
+                diff(
+                    // numbered from 1, like Lua
+                    &format!("{}[{}]", path, i + 1),
+                    expected.get(i).unwrap_or(&Value::Null),
+                    actual.get(i).unwrap_or(&Value::Null),
+                    found,
+                );
+            }
+        }
+        _ if expected != actual => found.push(format!(
+            "{} was {} when recorded, but {} when replayed",
+            path, expected, actual
+        )),
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logs::logs_mut;
+    use crate::runner::LUA_DIR;
+    use crate::testing::Recorder as Events;
+    use std::path::Path;
+
+    /// Handle a message the way the Lua thread does, recording it if there's a recording.
+    fn send(lua: &Lua, msg: LuaMessage) {
+        let recorded = Recorded::from_message(&msg);
+        match_message(lua, msg).unwrap();
+        if let Some(recorded) = recorded {
+            record(lua, recorded);
+        }
+    }
+
+    fn key(key: &str, down: bool) -> LuaMessage {
+        let key = key.to_string();
+        LuaMessage::Input(match down {
+            true => InputEvent::KeyDown { key },
+            false => InputEvent::KeyUp { key },
+        })
+    }
+
+    #[test]
+    fn replays_end_up_where_their_recordings_did() {
+        let lua = headless_lua(Arc::new(Events::new(Path::new(LUA_DIR)))).unwrap();
+        lua.load(
+            r#"currentScene:add_entity("mover", { type = "rect", steps = 0, scripts = { on_tick = { string = [[
+                if input.is_down("right") then self.steps = self.steps + random.range(1, 6) end
+            ]] } } })"#,
+        )
+        .exec()
+        .unwrap();
+        let path = std::env::temp_dir().join(format!("stacks-replay-{}.json", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+
+        start_recording(&lua).unwrap();
+        send(&lua, LuaMessage::Tick(1.0 / 60.0));
+        send(&lua, key("ArrowRight", true));
+        for _ in 0..3 {
+            send(&lua, LuaMessage::Tick(1.0 / 60.0));
+        }
+        send(&lua, key("ArrowRight", false));
+        send(&lua, LuaMessage::Tick(1.0 / 60.0));
+        stop_recording(&lua, &path).unwrap();
+
+        let recorded = dump_scene(&lua).unwrap();
+        let differences = replay(&lua, &path);
+        let content = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(differences.unwrap(), Vec::<String>::new());
+        // the scene that's open is left as it was
+        assert_eq!(dump_scene(&lua).unwrap(), recorded);
+        let steps: i64 = lua
+            .load(r#"currentScene.entities.mover.steps"#)
+            .eval()
+            .unwrap();
+        assert!(steps >= 3, "the key press was never seen");
+
+        // and a replay that ends up somewhere else says where
+        let mut tampered: Value = serde_json::from_str(&content).unwrap();
+        lua.load("currentScene.entities.mover.steps = currentScene.entities.mover.steps + 1")
+            .exec()
+            .unwrap();
+        tampered["end"] = Value::String(dump_scene(&lua).unwrap());
+        fs::write(&path, tampered.to_string()).unwrap();
+        let differences = replay(&lua, &path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(
+            differences.unwrap(),
+            [format!(
+                "scene.entities.mover.steps was {} when recorded, but {} when replayed",
+                steps + 1,
+                steps
+            )]
+        );
+    }
+
+    #[test]
+    fn recording_leaves_the_scene_running() {
+        let lua = headless_lua(Arc::new(Events::new(Path::new(LUA_DIR)))).unwrap();
+        lua.load(
+            r#"local function block(x, scripts)
+                return { type = "rect", pos = { x = x, y = 0 }, size = { width = 10, height = 10 },
+                    collider = true, scripts = scripts }
+            end
+            currentScene:add_entity("a", block(0, { on_collision_enter = { string = [[
+                self.hits = (self.hits or 0) + 1
+            ]] } }))
+            currentScene:add_entity("b", block(5))
+            currentScene:add_entity("mover", block(500, { on_click = { string = [[
+                tween(self.pos, { x = 600 }, 1)
+            ]] } }))"#,
+        )
+        .exec()
+        .unwrap();
+        let path = std::env::temp_dir().join(format!("stacks-running-{}.json", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+
+        send(&lua, LuaMessage::Tick(1.0 / 60.0));
+        lua.load(r#"currentScene:run_protected("mover", "on_click", {})"#)
+            .exec()
+            .unwrap();
+        start_recording(&lua).unwrap();
+        for _ in 0..3 {
+            send(&lua, LuaMessage::Tick(1.0 / 60.0));
+        }
+        stop_recording(&lua, &path).unwrap();
+        let differences = replay(&lua, &path);
+        let _ = fs::remove_file(&path);
+
+        // the tween kept going, with a warning that it couldn't be recorded
+        let logs = logs_mut(&lua).unwrap().history();
+        assert_eq!(logs.len(), 1);
+        assert!(logs[0].message.starts_with("Recording leaves out"));
+        assert!(logs[0].message.ends_with("mover's on_click (tween)"));
+        let hits: i64 = lua
+            .load("return currentScene.entities.a.hits")
+            .eval()
+            .unwrap();
+        assert_eq!(hits, 1);
+
+        // so that's all the replay does differently; it knew a & b were already touching
+        let differences = differences.unwrap();
+        assert_eq!(differences.len(), 1);
+        assert!(differences[0].starts_with("scene.entities.mover.pos.x was "));
+    }
+}
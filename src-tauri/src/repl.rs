@@ -0,0 +1,234 @@
+use crate::lua_setup::get_scene;
+use crate::lua_types::LuaError;
+use crate::script_errors::root_cause;
+use crate::script_runner::with_script;
+use mlua::prelude::*;
+use mlua::{AppDataRefMut, HookTriggers};
+
+/// Number of snippets remembered for the console's history.
+const HISTORY_LENGTH: usize = 100;
+
+/// How deep into tables results are shown, so evaluating the scene doesn't print everything in it.
+const RESULT_DEPTH: u32 = 4;
+
+/// Lua instructions a snippet can run before it's stopped, so `while true do end` can't hang the app.
+/// Scripts it sets off in coroutines aren't counted, and the debugger hooks those itself.
+const MAX_INSTRUCTIONS: u32 = 10_000_000;
+
+/// Script name snippets run under, so anything they print is tagged as coming from the console.
+const CONSOLE_SCRIPT: &str = "console";
+
+pub struct Repl {
+    history: Vec<String>,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Repl {
+            history: Vec::new(),
+        }
+    }
+
+    fn remember(&mut self, code: &str) {
+        if self.history.last().is_some_and(|last| last == code) {
+            return;
+        }
+        if self.history.len() >= HISTORY_LENGTH {
+            self.history.remove(0);
+        }
+        self.history.push(code.to_string());
+    }
+
+    /// Oldest first.
+    pub fn history(&self) -> Vec<String> {
+        self.history.clone()
+    }
+}
+
+pub fn repl_mut(lua: &Lua) -> Result<AppDataRefMut<'_, Repl>, LuaError> {
+    lua.app_data_mut::<Repl>()
+        .ok_or_else(|| LuaError::InitializationError("Repl missing".to_string()))
+}
+
+/// Run a snippet against the live scene, as `self` if it's given an entity,
+/// returning what it evaluates to (or returns) pretty-printed.
+///
+/// Globals it sets stay set, so they can be used by the next snippet.
+pub fn eval(lua: &Lua, code: &str, entity: Option<&str>) -> Result<String, LuaError> {
+    if code.trim().is_empty() {
+        return Ok(String::new());
+    }
+    repl_mut(lua)?.remember(code);
+
+    let env = lua.create_table()?;
+    let metatable = lua.create_table()?;
+    metatable.set("__index", lua.globals())?;
+    metatable.set("__newindex", lua.globals())?;
+    env.set_metatable(Some(metatable));
+    if let Some(id) = entity {
+        let entities: LuaTable = get_scene(lua)?.get("entities")?;
+        if !entities.contains_key(id)? {
+            return Err(LuaError::FormatError(format!(
+                "Couldn't find entity \"{}\".",
+                id
+            )));
+        }
+        env.raw_set("self", entities.get::<_, LuaTable>(id)?)?;
+    }
+
+    // expressions first, so `self.pos` shows the position, then statements like `self.speed = 2`
+    let function = match lua
+        .load(format!("return {}", code))
+        .set_name("=console")
+        .set_environment(env.clone())
+        .into_function()
+    {
+        Ok(function) => function,
+        Err(_) => lua
+            .load(code)
+            .set_name("=console")
+            .set_environment(env)
+            .into_function()
+            .map_err(|e| match e {
+                mlua::Error::SyntaxError { message, .. } => LuaError::FormatError(message),
+                e => LuaError::LuaError(e),
+            })?,
+    };
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(MAX_INSTRUCTIONS),
+        |_, _| {
+            Err(mlua::Error::RuntimeError(format!(
+                "Stopped after {} instructions, in case it never ends",
+                MAX_INSTRUCTIONS
+            )))
+        },
+    );
+    let values: LuaResult<LuaMultiValue> = match entity {
+        Some(id) => with_script(lua, id, CONSOLE_SCRIPT, || function.call(())),
+        None => function.call(()),
+    };
+    lua.remove_hook();
+    // the traceback would only show the console's own calls
+    let values = values.map_err(|e| match e {
+        mlua::Error::RuntimeError(_) | mlua::Error::CallbackError { .. } => {
+            LuaError::FormatError(root_cause(&e))
+        }
+        e => LuaError::LuaError(e),
+    })?;
+
+    let shown = values
+        .into_iter()
+        .map(|value| pretty(lua, value))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(shown.join(", "))
+}
+
+/// A value as it would be written in Lua, with tables spread over several lines.
+fn pretty(lua: &Lua, value: LuaValue) -> Result<String, LuaError> {
+    Ok(match value {
+        LuaValue::String(s) => format!("{:?}", s.to_string_lossy()),
+        LuaValue::Table(_) => {
+            let serpent: LuaTable = lua
+                .globals()
+                .get::<_, LuaFunction>("require")?
+                .call("serpent")?;
+            let options = lua.create_table()?;
+            options.set("comment", false)?;
+            options.set("nocode", true)?;
+            options.set("maxlevel", RESULT_DEPTH)?;
+            serpent
+                .get::<_, LuaFunction>("block")?
+                .call::<_, String>((value, options))?
+        }
+        value => value.to_string()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{headless_lua, LUA_DIR};
+    use crate::testing::Recorder;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    fn lua() -> Lua {
+        let lua = headless_lua(Arc::new(Recorder::new(Path::new(LUA_DIR)))).unwrap();
+        lua.load(r#"currentScene:add_entity("player", { type = "rect", pos = { x = 3, y = 4 } })"#)
+            .exec()
+            .unwrap();
+        lua
+    }
+
+    #[test]
+    fn expressions_then_statements() {
+        let lua = lua();
+        assert_eq!(eval(&lua, "1 + 2", None).unwrap(), "3");
+        assert_eq!(eval(&lua, "\"hi\", nil", None).unwrap(), "\"hi\", nil");
+        assert_eq!(eval(&lua, "speed = 5", None).unwrap(), "");
+        assert_eq!(eval(&lua, "speed", None).unwrap(), "5");
+        assert_eq!(
+            eval(&lua, "if speed > 1 then return speed * 2 end", None).unwrap(),
+            "10"
+        );
+        assert!(matches!(
+            eval(&lua, "speed =", None),
+            Err(LuaError::FormatError(_))
+        ));
+        let error = eval(&lua, "error(\"nope\")", None)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("nope"), "{}", error);
+    }
+
+    #[test]
+    fn self_is_the_entity() {
+        let lua = lua();
+        assert_eq!(eval(&lua, "self.pos.x", Some("player")).unwrap(), "3");
+        eval(&lua, "self.speed = 2", Some("player")).unwrap();
+        let speed: f64 = lua
+            .load("return currentScene.entities.player.speed")
+            .eval()
+            .unwrap();
+        assert_eq!(speed, 2.0);
+        assert_eq!(eval(&lua, "self", None).unwrap(), "nil");
+
+        let error = eval(&lua, "self", Some("ghost")).err().unwrap().to_string();
+        assert!(
+            error.contains("Couldn't find entity \"ghost\""),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn snippets_that_never_end_are_stopped() {
+        let lua = lua();
+        let error = eval(&lua, "while true do end", None)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("Stopped after"), "{}", error);
+        // the limit is only on while a snippet runs
+        assert_eq!(eval(&lua, "1", None).unwrap(), "1");
+        lua.load("for i = 1, 2 * 10000000 do end").exec().unwrap();
+    }
+
+    #[test]
+    fn history_skips_repeats() {
+        let lua = lua();
+        for code in ["1", "1", "  ", "2", "1"] {
+            eval(&lua, code, None).unwrap();
+        }
+        eval(&lua, "self", Some("ghost")).unwrap_err();
+        assert_eq!(repl_mut(&lua).unwrap().history(), ["1", "2", "1", "self"]);
+
+        let mut repl = Repl::new();
+        for i in 0..HISTORY_LENGTH + 1 {
+            repl.remember(&i.to_string());
+        }
+        assert_eq!(repl.history().len(), HISTORY_LENGTH);
+        assert_eq!(repl.history()[0], "1");
+    }
+}
@@ -0,0 +1,482 @@
+use crate::audio::NullAudio;
+use crate::camera::update_camera;
+use crate::debugger::Debugger;
+use crate::gamepad::VirtualGamepads;
+use crate::host::Host;
+use crate::lua_setup::{create_lua, get_scene, match_message, Devices};
+use crate::lua_types::{LuaError, LuaMessage};
+use crate::pins::compile_expression;
+use crate::random::{random_mut, Random};
+use crate::script_errors::root_cause;
+use crate::testing::{report, run_scene_tests};
+use mlua::prelude::*;
+use serde_json::{json, Value};
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+
+const USAGE: &str = "Run a scene without a window, printing what happens as JSON lines.
+
+Usage: stacks-run <scene> [options]
+       stacks-run test <files or folders> [--resources <dir>]
+
+Options:
+  --ticks <n>        how many ticks to run, or the most to run with --until (default 60)
+  --until <expr>     stop once this Lua expression is true, e.g. \"player.pos.y > 500\"
+  --dt <seconds>     time between ticks (default 1/60)
+  --seed <n>         seed random numbers with this, instead of the scene's own seed
+  --log              include what scripts print & log
+  --resources <dir>  where the runtime's Lua modules are, if not where they were built
+  -h, --help         show this
+
+Exits with 1 if --until never became true, and 2 if the scene couldn't be run at all.
+
+`test` runs scene tests instead: every file ending in .test.lua in the folders given, and any files
+given. It exits with 1 if any of them fail.";
+
+/// Lua modules in the source tree, for when the runner isn't given any.
+pub(crate) const LUA_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/resources/lua");
+
+struct Options {
+    scene: PathBuf,
+    ticks: u64,
+    until: Option<String>,
+    dt: f64,
+    seed: Option<i64>,
+    log: bool,
+    resources: PathBuf,
+}
+
+/// The value after an option, e.g. the number after `--ticks`.
+pub(crate) fn value<T: FromStr>(option: &str, value: Option<String>) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    let value = value.ok_or_else(|| format!("{} needs a value", option))?;
+    value
+        .parse()
+        .map_err(|e| format!("Couldn't read {} {:?}: {}", option, value, e))
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        scene: PathBuf::new(),
+        ticks: 60,
+        until: None,
+        dt: 1.0 / 60.0,
+        seed: None,
+        log: false,
+        resources: PathBuf::from(LUA_DIR),
+    };
+    let mut scene = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ticks" => options.ticks = value(&arg, args.next())?,
+            "--until" => options.until = Some(value(&arg, args.next())?),
+            "--dt" => {
+                options.dt = value(&arg, args.next())?;
+                if !(options.dt.is_finite() && options.dt > 0.0) {
+                    return Err(format!(
+                        "--dt needs a positive number of seconds, not {}",
+                        options.dt
+                    ));
+                }
+            }
+            "--seed" => options.seed = Some(value(&arg, args.next())?),
+            "--log" => options.log = true,
+            "--resources" => options.resources = value(&arg, args.next())?,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+            _ if scene.is_none() => scene = Some(arg),
+            _ => return Err(format!("Only one scene can be run, but got {} too", arg)),
+        }
+    }
+    let scene = scene.ok_or("No scene given")?;
+    options.scene = fs::canonicalize(&scene)
+        .map_err(|e| format!("Couldn't find scene \"{}\": {}", scene, e))?;
+    Ok(options)
+}
+
+/// Prints events as JSON lines, tagged with the tick they happened on.
+struct Terminal {
+    lua_dir: PathBuf,
+    log: bool,
+    tick: AtomicU64,
+    script_errors: AtomicU32,
+    /// The last `scene_update`, for the entities at the end of the run
+    scene: Mutex<Option<Value>>,
+    /// Standard output, unless it's a test
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Terminal {
+    fn print(&self, line: Value) {
+        // nothing else can be done if the output's gone, e.g. piped into `head`
+        if let Ok(mut out) = self.out.lock() {
+            let _ = writeln!(out, "{}", line);
+        }
+    }
+}
+
+impl Host for Terminal {
+    // output is all JSON lines, and log entries are among them with `--log`
+    fn show(&self, _line: &str) {}
+
+    fn emit(&self, event: &str, payload: Value) -> Result<(), String> {
+        let tick = self.tick.load(Ordering::Relaxed);
+        match event {
+            // every tick, so only the last one is kept
+            "scene_update" => {
+                *self.scene.lock().map_err(|e| e.to_string())? = Some(payload);
+            }
+            "log_entry" => {
+                if self.log {
+                    self.print(json!({ "type": "log", "tick": tick, "entry": payload }));
+                }
+            }
+            _ => {
+                if event == "script_error" {
+                    self.script_errors.fetch_add(1, Ordering::Relaxed);
+                }
+                self.print(
+                    json!({ "type": "event", "tick": tick, "event": event, "data": payload }),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_to(&self, target: &str, event: &str, payload: Value) -> Result<(), String> {
+        self.print(json!({
+            "type": "event",
+            "tick": self.tick.load(Ordering::Relaxed),
+            "event": event,
+            "target": target,
+            "data": payload,
+        }));
+        Ok(())
+    }
+
+    fn lua_dir(&self) -> Result<PathBuf, String> {
+        Ok(self.lua_dir.clone())
+    }
+}
+
+/// Run a scene for the command line's options, without a window, sound card or gamepads.
+pub fn run_headless() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    if args.first().is_some_and(|arg| arg == "test") {
+        return run_tests(args.into_iter().skip(1));
+    }
+    ExitCode::from(run_scene(args, Box::new(io::stdout())))
+}
+
+/// The exit code for running a scene: 0 if it went as meant to, 1 if `--until` never became
+/// true, and 2 if it couldn't be run at all.
+fn run_scene(args: Vec<String>, out: Box<dyn Write + Send>) -> u8 {
+    let options = match parse_options(args.into_iter()) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    match run(&options, out) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(LuaError::FormatError(message)) => {
+            eprintln!("{}", message);
+            2
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            2
+        }
+    }
+}
+
+/// `stacks-run test`, printing how each test went.
+fn run_tests(mut args: impl Iterator<Item = String>) -> ExitCode {
+    let mut paths = Vec::new();
+    let mut lua_dir = PathBuf::from(LUA_DIR);
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "--resources" => value(&arg, args.next()).map(|dir| lua_dir = dir),
+            _ if arg.starts_with('-') => Err(format!("Unknown option {}", arg)),
+            _ => {
+                paths.push(PathBuf::from(arg));
+                Ok(())
+            }
+        };
+        if let Err(e) = parsed {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("No tests given\n\n{}", USAGE);
+        return ExitCode::from(2);
+    }
+
+    let results = run_scene_tests(&paths, &lua_dir);
+    print!("{}", report(&results));
+    match results.iter().any(|result| result.failure.is_some()) {
+        true => ExitCode::from(1),
+        false => ExitCode::SUCCESS,
+    }
+}
+
+/// A Lua state for running scenes outside the app, with no sound card or gamepads.
+pub(crate) fn headless_lua(host: Arc<dyn Host>) -> Result<Lua, LuaError> {
+    let (debugger, debug_commands) = Debugger::new();
+    let devices = Devices {
+        audio: |_| Box::new(NullAudio::new()),
+        gamepads: || Box::new(VirtualGamepads::new()),
+    };
+    let lua = create_lua(host, debugger, debug_commands, devices)?;
+    Ok(lua)
+}
+
+/// Whether the run finished how it was meant to, i.e. `--until` became true if it was given.
+fn run(options: &Options, out: Box<dyn Write + Send>) -> Result<bool, LuaError> {
+    let terminal = Arc::new(Terminal {
+        lua_dir: options.resources.clone(),
+        log: options.log,
+        tick: AtomicU64::new(0),
+        script_errors: AtomicU32::new(0),
+        scene: Mutex::new(None),
+        out: Mutex::new(out),
+    });
+    let lua = headless_lua(terminal.clone())?;
+
+    let (tx, rx) = mpsc::channel();
+    let path = options.scene.to_string_lossy().to_string();
+    match_message(&lua, LuaMessage::LoadScene(path, tx))?;
+    let (loaded, message) = rx
+        .recv()
+        .map_err(|e| LuaError::CommunicationError(e.to_string()))?;
+    if !loaded {
+        return Err(LuaError::FormatError(message));
+    }
+    if let Some(seed) = options.seed {
+        *random_mut(&lua)? = Random::with_seed(seed);
+    }
+
+    let until = match &options.until {
+        Some(expression) => Some(compile_expression(&lua, expression, "until")?),
+        None => None,
+    };
+    let mut met = false;
+    let mut until_error = None;
+    let mut ticks = 0;
+    while ticks < options.ticks && !met {
+        ticks += 1;
+        terminal.tick.store(ticks, Ordering::Relaxed);
+        // faults are reported & skipped over like they are in the app
+        if let Err(e) = match_message(&lua, LuaMessage::Tick(options.dt)) {
+            eprintln!("[lua thread] {}", e);
+        }
+        if let Some(until) = &until {
+            // an error isn't the end, since it can be about an entity that's yet to be added
+            (met, until_error) = match until.call::<_, LuaValue>(()) {
+                Ok(value) => (
+                    !matches!(value, LuaValue::Nil | LuaValue::Boolean(false)),
+                    None,
+                ),
+                Err(e) => (false, Some(root_cause(&e))),
+            };
+        }
+    }
+
+    // a scene that wasn't ticked hasn't sent an update yet
+    if ticks == 0 {
+        let scene = get_scene(&lua)?;
+        let view = update_camera(&lua)?;
+        scene
+            .get::<_, LuaFunction>("emit_update")?
+            .call::<_, ()>((scene, lua.to_value(&view)?))?;
+    }
+    let entities = terminal
+        .scene
+        .lock()
+        .map_err(|e| LuaError::CommunicationError(e.to_string()))?
+        .as_ref()
+        .and_then(|update| update.get("entities").cloned())
+        .unwrap_or_else(|| json!({}));
+    terminal.print(json!({
+        "type": "result",
+        "ticks": ticks,
+        "seconds": ticks as f64 * options.dt,
+        "seed": random_mut(&lua)?.seed(),
+        "until": until.as_ref().map(|_| met),
+        "until_error": until_error,
+        "script_errors": terminal.script_errors.load(Ordering::Relaxed),
+        "entities": entities,
+    }));
+    Ok(until.is_none() || met)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// Output that can still be read after the run's done with it.
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Output {
+        fn lines(&self) -> Vec<Value> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        }
+    }
+
+    fn scene(name: &str) -> String {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("scenes")
+            .join(name)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_options(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn exit_code(args: &[&str]) -> (u8, Output) {
+        let output = Output::default();
+        let args = args.iter().map(|arg| arg.to_string()).collect();
+        (run_scene(args, Box::new(output.clone())), output)
+    }
+
+    #[test]
+    fn options_have_defaults() {
+        let options = parse(&[&scene("door.lua")]).unwrap();
+        assert_eq!(options.scene, fs::canonicalize(scene("door.lua")).unwrap());
+        assert_eq!(options.ticks, 60);
+        assert_eq!(options.dt, 1.0 / 60.0);
+        assert_eq!(options.until, None);
+        assert_eq!(options.seed, None);
+        assert!(!options.log);
+
+        let options = parse(&[
+            "--ticks",
+            "5",
+            &scene("door.lua"),
+            "--dt",
+            "0.5",
+            "--seed",
+            "-3",
+            "--log",
+            "--until",
+            "door.open",
+        ])
+        .unwrap();
+        assert_eq!(options.ticks, 5);
+        assert_eq!(options.dt, 0.5);
+        assert_eq!(options.seed, Some(-3));
+        assert!(options.log);
+        assert_eq!(options.until.as_deref(), Some("door.open"));
+    }
+
+    #[test]
+    fn bad_options_are_rejected() {
+        let door = scene("door.lua");
+        for args in [
+            vec![],
+            vec![door.as_str(), "--bounce"],
+            vec![door.as_str(), door.as_str()],
+            vec![door.as_str(), "--ticks"],
+            vec![door.as_str(), "--ticks", "-1"],
+            vec!["missing.lua"],
+        ] {
+            assert!(parse(&args).is_err(), "{:?} was accepted", args);
+        }
+    }
+
+    #[test]
+    fn dt_has_to_be_positive() {
+        let door = scene("door.lua");
+        for dt in ["0", "-0.1", "NaN", "inf"] {
+            let error = parse(&[&door, "--dt", dt]).err().unwrap();
+            assert!(error.contains("--dt needs a positive number"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn exit_codes() {
+        let door = scene("door.lua");
+        assert_eq!(exit_code(&[&door, "--ticks", "2"]).0, 0);
+        assert_eq!(
+            exit_code(&[&door, "--ticks", "2", "--until", "door.pos.x == 400"]).0,
+            0
+        );
+        assert_eq!(
+            exit_code(&[&door, "--ticks", "2", "--until", "door.open"]).0,
+            1
+        );
+        assert_eq!(exit_code(&[&door, "--dt", "0"]).0, 2);
+        assert_eq!(exit_code(&[&door, "--until", "door.open =="]).0, 2);
+        assert_eq!(exit_code(&["missing.lua"]).0, 2);
+    }
+
+    #[test]
+    fn runs_print_json_lines() {
+        let (code, output) = exit_code(&[
+            &scene("ball.lua"),
+            "--ticks",
+            "60",
+            "--until",
+            "ball.landed",
+            "--log",
+        ]);
+        assert_eq!(code, 0);
+        let lines = output.lines();
+
+        let logs: Vec<_> = lines.iter().filter(|line| line["type"] == "log").collect();
+        assert_eq!(logs.len(), 10);
+        assert_eq!(logs[0]["tick"], 1);
+        assert_eq!(logs[0]["entry"]["message"], "10");
+        assert_eq!(logs[0]["entry"]["entity"], "ball");
+
+        let landed: Vec<_> = lines
+            .iter()
+            .filter(|line| line["type"] == "event" && line["event"] == "landed")
+            .collect();
+        assert_eq!(landed.len(), 1);
+        assert_eq!(landed[0]["tick"], 10);
+        assert_eq!(landed[0]["data"], json!({ "y": 100 }));
+
+        let result = lines.last().unwrap();
+        assert_eq!(result["type"], "result");
+        assert_eq!(result["ticks"], 10);
+        assert_eq!(result["seconds"], 10.0 / 60.0);
+        assert_eq!(result["seed"], 7);
+        assert_eq!(result["until"], true);
+        assert_eq!(result["script_errors"], 0);
+        assert_eq!(result["entities"]["ball"]["pos"]["y"], 100);
+    }
+}
@@ -0,0 +1,553 @@
+use crate::logs::{log, log_fault, Level};
+use crate::lua_setup::{get_entity, serialized_table};
+use crate::lua_types::LuaError;
+use crate::script_runner::{
+    check_condition, current_script, resume_script, run_entity_script, COROUTINE_BODY,
+};
+use crate::tweens::tweens_mut;
+use mlua::prelude::*;
+use mlua::AppDataRefMut;
+use serde::{Deserialize, Serialize};
+
+/// Most times an `every` timer runs in one tick to catch up, e.g. after a long pause.
+/// Any more are skipped, so a very short interval can't hold up the whole tick.
+const MAX_CATCH_UP: u32 = 16;
+
+pub enum Callback {
+    /// A Lua function, which can't be saved with the scene
+    Function(LuaRegistryKey),
+    /// A script on the owning entity, run by name with serialized data
+    Script { name: String, data: Option<String> },
+}
+
+pub enum TaskKind {
+    /// A script coroutine suspended by `wait`
+    Wait(LuaRegistryKey),
+    /// A script coroutine suspended by `wait_until`, with its condition
+    WaitUntil(LuaRegistryKey, LuaRegistryKey),
+    /// A timer set by `after` or `every`
+    Timer {
+        callback: Callback,
+        interval: Option<f64>,
+    },
+}
+
+pub struct Task {
+    pub id: u64,
+    /// Entity owning the task, which is cancelled along with it
+    pub entity: String,
+    /// Script the task came from, for error reporting
+    pub script: String,
+    pub due: f64,
+    pub kind: TaskKind,
+}
+
+/// The parts of a timer that survive being saved with a scene.
+#[derive(Serialize, Deserialize)]
+pub struct SavedTimer {
+    pub entity: String,
+    pub script: String,
+    pub callback: String,
+    // left out rather than saved as null, which a scene file can't hold
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    pub remaining: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<f64>,
+}
+
+pub struct Scheduler {
+    time: f64,
+    next_id: u64,
+    tasks: Vec<Task>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler {
+            time: 0.0,
+            next_id: 1,
+            tasks: Vec::new(),
+        }
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn new_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    pub fn add(&mut self, entity: &str, script: &str, delay: f64, kind: TaskKind) -> u64 {
+        let id = self.new_id();
+        self.insert(Task {
+            id,
+            entity: entity.to_string(),
+            script: script.to_string(),
+            due: self.time + delay.max(0.0),
+            kind,
+        });
+        id
+    }
+
+    /// Put back a task taken out by `advance`, keeping its ID.
+    pub fn insert(&mut self, task: Task) {
+        self.tasks.push(task);
+    }
+
+    pub fn cancel(&mut self, id: u64) -> bool {
+        let before = self.tasks.len();
+        self.tasks.retain(|task| task.id != id);
+        self.tasks.len() != before
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        self.tasks.iter().any(|task| task.id == id)
+    }
+
+    pub fn cancel_entity(&mut self, entity: &str) {
+        self.tasks.retain(|task| task.entity != entity);
+    }
+
+    pub fn rename_entity(&mut self, from: &str, to: &str) {
+        self.tasks
+            .iter_mut()
+            .filter(|task| task.entity == from)
+            .for_each(|task| task.entity = to.to_string());
+    }
+
+    pub fn clear(&mut self) {
+        self.tasks.clear();
+    }
+
+    /// Advance the clock, taking out every task that's due in the order they fell due.
+    pub fn advance(&mut self, dt: f64) -> Vec<Task> {
+        self.time += dt;
+        let time = self.time;
+        let (mut due, pending): (Vec<Task>, Vec<Task>) =
+            self.tasks.drain(..).partition(|task| task.due <= time);
+        self.tasks = pending;
+        due.sort_by(|a, b| a.due.total_cmp(&b.due).then(a.id.cmp(&b.id)));
+        due
+    }
+
+    /// Timers which run a script by name; functions and suspended scripts can't be saved.
+    pub fn saved_timers(&self) -> Vec<SavedTimer> {
+        self.tasks
+            .iter()
+            .filter_map(|task| match &task.kind {
+                TaskKind::Timer {
+                    callback: Callback::Script { name, data },
+                    interval,
+                } => Some(SavedTimer {
+                    entity: task.entity.clone(),
+                    script: task.script.clone(),
+                    callback: name.clone(),
+                    data: data.clone(),
+                    remaining: (task.due - self.time).max(0.0),
+                    interval: *interval,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// What `saved_timers` leaves out, like `door's on_click (wait)`.
+    pub fn unsaved_tasks(&self) -> Vec<String> {
+        self.tasks
+            .iter()
+            .filter_map(|task| {
+                let kind = match &task.kind {
+                    TaskKind::Wait(_) => "wait",
+                    TaskKind::WaitUntil(..) => "wait_until",
+                    TaskKind::Timer {
+                        callback: Callback::Function(_),
+                        interval,
+                    } => match interval {
+                        Some(_) => "every with a function",
+                        None => "after with a function",
+                    },
+                    TaskKind::Timer { .. } => return None,
+                };
+                Some(format!("{}'s {} ({})", task.entity, task.script, kind))
+            })
+            .collect()
+    }
+
+    pub fn restore_timers(&mut self, timers: Vec<SavedTimer>) {
+        for timer in timers {
+            self.add(
+                &timer.entity,
+                &timer.script,
+                timer.remaining,
+                TaskKind::Timer {
+                    callback: Callback::Script {
+                        name: timer.callback,
+                        data: timer.data,
+                    },
+                    interval: timer.interval,
+                },
+            );
+        }
+    }
+}
+
+pub fn scheduler_mut(lua: &Lua) -> Result<AppDataRefMut<'_, Scheduler>, LuaError> {
+    lua.app_data_mut::<Scheduler>()
+        .ok_or_else(|| LuaError::InitializationError("Scheduler missing".to_string()))
+}
+
+/// Everything running that can't be saved with a scene, described for warnings:
+/// scripts that are waiting, timers that call functions, and tweens.
+pub fn unsaved_tasks(lua: &Lua) -> Result<Vec<String>, LuaError> {
+    let mut unsaved = scheduler_mut(lua)?.unsaved_tasks();
+    unsaved.extend(tweens_mut(lua)?.unsaved_tweens());
+    Ok(unsaved)
+}
+
+/// Warn that `saving` (e.g. "Saving the scene") leaves out tasks that can't be saved,
+/// since the scene will carry on differently without them.
+pub fn warn_unsaved_tasks(lua: &Lua, saving: &str) -> Result<(), LuaError> {
+    let unsaved = unsaved_tasks(lua)?;
+    warn_left_out(lua, saving, &unsaved)
+}
+
+/// Warn that `saving` leaves out the `unsaved` tasks, from `unsaved_tasks`, if there are any.
+pub fn warn_left_out(lua: &Lua, saving: &str, unsaved: &[String]) -> Result<(), LuaError> {
+    if unsaved.is_empty() {
+        return Ok(());
+    }
+    log(
+        lua,
+        Level::Warn,
+        format!(
+            "{} leaves out scripts that are waiting, timers that call functions and tweens: {}",
+            saving,
+            unsaved.join(", ")
+        ),
+    )
+}
+
+/// How many times an `every` timer due at `due` runs by `time`, and when it's next due.
+fn repeats(due: f64, interval: f64, time: f64) -> (u32, f64) {
+    let mut runs = 1;
+    let mut next = due + interval;
+    while next <= time && runs < MAX_CATCH_UP {
+        runs += 1;
+        next += interval;
+    }
+    // too far behind to catch up, so skip ahead rather than falling further behind
+    if next <= time {
+        next += ((time - next) / interval).floor() * interval + interval;
+    }
+    (runs, next)
+}
+
+/// Run everything that falls due on this tick.
+pub fn run_due_tasks(lua: &Lua, dt: f64) -> Result<(), LuaError> {
+    let due = scheduler_mut(lua)?.advance(dt);
+    for task in due {
+        // one broken task shouldn't hold up the rest
+        if let Err(e) = run_task(lua, task) {
+            log_fault(lua, "scheduler", &e);
+        }
+    }
+    lua.expire_registry_values();
+    Ok(())
+}
+
+fn run_task(lua: &Lua, task: Task) -> Result<(), LuaError> {
+    if get_entity(lua, &task.entity).is_err() {
+        return Ok(());
+    }
+
+    match task.kind {
+        TaskKind::Wait(thread) => {
+            let thread: LuaThread = lua.registry_value(&thread)?;
+            resume_script(lua, &task.entity, &task.script, thread, ())?;
+        }
+        TaskKind::WaitUntil(thread, condition) => {
+            let condition_func = lua.registry_value::<LuaFunction>(&condition)?;
+            match check_condition(lua, &task.entity, &task.script, condition_func)? {
+                Some(true) => {
+                    let thread: LuaThread = lua.registry_value(&thread)?;
+                    resume_script(lua, &task.entity, &task.script, thread, ())?;
+                }
+                Some(false) => scheduler_mut(lua)?.insert(Task {
+                    kind: TaskKind::WaitUntil(thread, condition),
+                    ..task
+                }),
+                // a failing condition ends the wait, and the script with it
+                None => (),
+            }
+        }
+        TaskKind::Timer { callback, interval } => {
+            let (entity, script) = (task.entity.clone(), task.script.clone());
+            let function = match &callback {
+                Callback::Function(key) => Some(lua.registry_value::<LuaFunction>(key)?),
+                Callback::Script { .. } => None,
+            };
+            let named_script = match &callback {
+                Callback::Script { name, data } => Some((name.clone(), data.clone())),
+                Callback::Function(_) => None,
+            };
+
+            // reschedule before running, so the callback can cancel its own timer
+            let id = task.id;
+            let runs = match interval {
+                Some(interval) => {
+                    let mut scheduler = scheduler_mut(lua)?;
+                    let (runs, next) = repeats(task.due, interval, scheduler.time());
+                    scheduler.insert(Task {
+                        due: next,
+                        kind: TaskKind::Timer {
+                            callback,
+                            interval: Some(interval),
+                        },
+                        ..task
+                    });
+                    runs
+                }
+                None => 1,
+            };
+
+            for run in 0..runs {
+                // cancelled by an earlier run
+                if run > 0 && !scheduler_mut(lua)?.contains(id) {
+                    break;
+                }
+                if let Some(function) = &function {
+                    let thread = lua
+                        .create_thread(lua.named_registry_value::<LuaFunction>(COROUTINE_BODY)?)?;
+                    resume_script(lua, &entity, &script, thread, function.clone())?;
+                } else if let Some((name, data)) = &named_script {
+                    run_entity_script(lua, &entity, name, data.clone().into_lua(lua)?)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn set_globals(lua: &Lua) -> Result<(), LuaError> {
+    lua.set_app_data(Scheduler::new());
+
+    // waiting suspends the script's coroutine, which the scheduler resumes later
+    lua.load(
+        r#"
+        function wait(seconds)
+            if type(seconds) ~= "number" then error("wait needs a number of seconds", 2) end
+            return coroutine.yield("wait", seconds)
+        end
+
+        function wait_until(condition)
+            if type(condition) ~= "function" then error("wait_until needs a function", 2) end
+            return coroutine.yield("wait_until", condition)
+        end
+        "#,
+    )
+    .set_name("scheduler.lua")
+    .exec()
+    .map_err(|e| LuaError::InitializationError(format!("Failed to define wait: {}", e)))?;
+
+    lua.globals()
+        .set(
+            "after",
+            lua.create_function(
+                |l: &Lua, (seconds, callback, data): (f64, LuaValue, Option<LuaTable>)| {
+                    if !(seconds.is_finite() && seconds >= 0.0) {
+                        return Err(LuaError::FormatError(
+                            "after needs a number of seconds that's 0 or more".to_string(),
+                        )
+                        .into());
+                    }
+                    add_timer(l, seconds, callback, data, None)
+                },
+            )?,
+        )
+        .map_err(|e| LuaError::InitializationError(format!("Failed to set after: {}", e)))?;
+
+    lua.globals()
+        .set(
+            "every",
+            lua.create_function(
+                |l: &Lua, (seconds, callback, data): (f64, LuaValue, Option<LuaTable>)| {
+                    if !(seconds.is_finite() && seconds > 0.0) {
+                        return Err(LuaError::FormatError(
+                            "every needs a positive number of seconds".to_string(),
+                        )
+                        .into());
+                    }
+                    add_timer(l, seconds, callback, data, Some(seconds))
+                },
+            )?,
+        )
+        .map_err(|e| LuaError::InitializationError(format!("Failed to set every: {}", e)))?;
+
+    lua.globals()
+        .set(
+            "cancel",
+            lua.create_function(|l: &Lua, id: u64| {
+                Ok(scheduler_mut(l)?.cancel(id) || tweens_mut(l)?.cancel(id))
+            })?,
+        )
+        .map_err(|e| LuaError::InitializationError(format!("Failed to set cancel: {}", e)))?;
+
+    Ok(())
+}
+
+fn add_timer(
+    lua: &Lua,
+    seconds: f64,
+    callback: LuaValue,
+    data: Option<LuaTable>,
+    interval: Option<f64>,
+) -> LuaResult<u64> {
+    let (entity, script) = current_script(lua).ok_or_else(|| {
+        LuaError::FormatError("Timers can only be set from an entity's script".to_string())
+    })?;
+
+    let callback = match callback {
+        LuaValue::Function(function) => Callback::Function(lua.create_registry_value(function)?),
+        LuaValue::String(name) => Callback::Script {
+            name: name.to_str()?.to_string(),
+            data: match data {
+                Some(table) => Some(serialized_table(lua, &table)?.to_str()?.to_string()),
+                None => None,
+            },
+        },
+        other => {
+            return Err(LuaError::FormatError(format!(
+                "Timer callback must be a function or a script name, not {}.",
+                other.type_name()
+            ))
+            .into())
+        }
+    };
+
+    Ok(scheduler_mut(lua)?.add(
+        &entity,
+        &script,
+        seconds,
+        TaskKind::Timer { callback, interval },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{headless_lua, LUA_DIR};
+    use crate::testing::Recorder;
+    use serde_json::json;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    fn timer(interval: Option<f64>) -> TaskKind {
+        TaskKind::Timer {
+            callback: Callback::Script {
+                name: "on_timer".to_string(),
+                data: None,
+            },
+            interval,
+        }
+    }
+
+    fn ids(tasks: &[Task]) -> Vec<u64> {
+        tasks.iter().map(|task| task.id).collect()
+    }
+
+    #[test]
+    fn tasks_come_due_in_order() {
+        let mut scheduler = Scheduler::new();
+        let late = scheduler.add("a", "on_click", 0.5, timer(None));
+        let early = scheduler.add("b", "on_click", 0.2, timer(None));
+        let tied = scheduler.add("c", "on_click", 0.2, timer(None));
+
+        assert!(scheduler.advance(0.1).is_empty());
+        assert_eq!(ids(&scheduler.advance(0.5)), [early, tied, late]);
+        assert!(scheduler.advance(1.0).is_empty());
+    }
+
+    #[test]
+    fn cancelled_tasks_never_come_due() {
+        let mut scheduler = Scheduler::new();
+        let cancelled = scheduler.add("a", "on_click", 0.1, timer(None));
+        let kept = scheduler.add("b", "on_click", 0.1, timer(None));
+        scheduler.add("gone", "on_click", 0.1, timer(None));
+
+        assert!(scheduler.cancel(cancelled));
+        assert!(!scheduler.cancel(cancelled));
+        scheduler.cancel_entity("gone");
+        assert_eq!(ids(&scheduler.advance(0.1)), [kept]);
+    }
+
+    #[test]
+    fn timers_catch_up_after_long_ticks() {
+        assert_eq!(repeats(1.0, 0.5, 1.2), (1, 1.5));
+        assert_eq!(repeats(1.0, 0.25, 2.1), (5, 2.25));
+        // never more than MAX_CATCH_UP runs, but still due in step with the interval
+        let (runs, next) = repeats(0.0, 0.001, 100.0);
+        assert_eq!(runs, MAX_CATCH_UP);
+        assert!(next > 100.0 && next <= 100.001 + 1e-9);
+    }
+
+    #[test]
+    fn waits_and_function_timers_cant_be_saved() {
+        let lua = Lua::new();
+        let function = lua.create_function(|_, ()| Ok(())).unwrap();
+        let mut scheduler = Scheduler::new();
+        scheduler.add("door", "on_click", 1.0, timer(Some(1.0)));
+        scheduler.add(
+            "door",
+            "on_tick",
+            1.0,
+            TaskKind::Wait(lua.create_registry_value(function.clone()).unwrap()),
+        );
+        scheduler.add(
+            "lamp",
+            "on_click",
+            1.0,
+            TaskKind::Timer {
+                callback: Callback::Function(lua.create_registry_value(function).unwrap()),
+                interval: None,
+            },
+        );
+
+        assert_eq!(scheduler.saved_timers().len(), 1);
+        assert_eq!(
+            scheduler.unsaved_tasks(),
+            [
+                "door's on_tick (wait)",
+                "lamp's on_click (after with a function)"
+            ]
+        );
+    }
+
+    #[test]
+    fn timers_need_sensible_seconds() {
+        let recorder = Arc::new(Recorder::new(Path::new(LUA_DIR)));
+        let lua = headless_lua(recorder.clone()).unwrap();
+        lua.load(
+            r#"currentScene:add_entity("clock", { type = "rect", scripts = { on_click = { string = [[
+                local function works(f, seconds) return (pcall(f, seconds, function() end)) end
+                emit("timers", {
+                    after = { works(after, 0), works(after, -1), works(after, 0/0), works(after, math.huge) },
+                    every = { works(every, 1), works(every, 0), works(every, 0/0), works(every, math.huge) },
+                })
+            ]] } } })
+            currentScene:run_protected("clock", "on_click", {})"#,
+        )
+        .exec()
+        .unwrap();
+        assert_eq!(
+            recorder.events("timers").unwrap(),
+            vec![json!({
+                "after": [true, false, false, false],
+                "every": [true, false, false, false],
+            })]
+        );
+    }
+}
@@ -0,0 +1,411 @@
+/// What sort of thing an `Item` is, which editors show as an icon.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Function,
+    /// Called with `:`, like `self:get_tile(1, 1)`
+    Method,
+    Table,
+    Field,
+    Event,
+}
+
+/// Something scripts can use: a global, a property of an entity, or an event & the `data` it comes with.
+#[derive(Clone, Copy, Debug)]
+pub struct Item {
+    pub name: &'static str,
+    pub kind: Kind,
+    /// How it's written, like `message(target, event, data)`
+    pub signature: &'static str,
+    pub doc: &'static str,
+    /// The entity types it's for, or every type if it's empty
+    pub types: &'static [&'static str],
+    /// What's in it if it's a table, or in `data` if it's an event
+    pub fields: &'static [Item],
+}
+
+impl Item {
+    /// Whether an entity of this type has it, counting every type if it's not known.
+    pub fn applies_to(&self, entity_type: Option<&str>) -> bool {
+        self.types.is_empty() || entity_type.is_none_or(|t| self.types.contains(&t))
+    }
+
+    pub fn field(&self, name: &str) -> Option<&'static Item> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    const fn only(self, types: &'static [&'static str]) -> Item {
+        Item { types, ..self }
+    }
+}
+
+const fn item(
+    name: &'static str,
+    kind: Kind,
+    signature: &'static str,
+    doc: &'static str,
+    fields: &'static [Item],
+) -> Item {
+    Item {
+        name,
+        kind,
+        signature,
+        doc,
+        types: &[],
+        fields,
+    }
+}
+
+const fn function(name: &'static str, signature: &'static str, doc: &'static str) -> Item {
+    item(name, Kind::Function, signature, doc, &[])
+}
+
+const fn method(name: &'static str, signature: &'static str, doc: &'static str) -> Item {
+    item(name, Kind::Method, signature, doc, &[])
+}
+
+const fn table(name: &'static str, doc: &'static str, fields: &'static [Item]) -> Item {
+    item(name, Kind::Table, name, doc, fields)
+}
+
+const fn field(name: &'static str, doc: &'static str) -> Item {
+    item(name, Kind::Field, name, doc, &[])
+}
+
+const fn built_in(name: &'static str, doc: &'static str, data: &'static [Item]) -> Item {
+    item(name, Kind::Event, name, doc, data)
+}
+
+const XY: &[Item] = &[
+    field("x", "How far from the left."),
+    field("y", "How far from the top."),
+];
+
+const SIZE: &[Item] = &[field("width", "How wide."), field("height", "How tall.")];
+
+const SHEET: &[Item] = &[
+    field("columns", "How many frames across the image is cut into."),
+    field("rows", "How many frames down the image is cut into."),
+];
+
+const INPUT: &[Item] = &[
+    function(
+        "is_down",
+        "input.is_down(key)",
+        "Whether a key is being held down, like `\"left\"` or `\"space\"`.",
+    ),
+    function(
+        "was_pressed",
+        "input.was_pressed(key)",
+        "Whether a key was pressed since the last tick.",
+    ),
+    function(
+        "was_released",
+        "input.was_released(key)",
+        "Whether a key was let go since the last tick.",
+    ),
+    function(
+        "is_mouse_down",
+        "input.is_mouse_down(button)",
+        "Whether a mouse button is being held down. `button` is `\"left\"` if you leave it out.",
+    ),
+    function(
+        "was_mouse_pressed",
+        "input.was_mouse_pressed(button)",
+        "Whether a mouse button was pressed since the last tick.",
+    ),
+    function(
+        "was_mouse_released",
+        "input.was_mouse_released(button)",
+        "Whether a mouse button was let go since the last tick.",
+    ),
+    function(
+        "mouse_pos",
+        "input.mouse_pos()",
+        "Where the mouse is in the scene, like `{ x = 100, y = 200 }`.",
+    ),
+    function(
+        "mouse_wheel",
+        "input.mouse_wheel()",
+        "How far the mouse wheel scrolled since the last tick, like `{ x = 0, y = 3 }`.",
+    ),
+    function(
+        "hovered",
+        "input.hovered()",
+        "The ID of the entity under the mouse, if there is one.",
+    ),
+    function(
+        "gamepads",
+        "input.gamepads()",
+        "A list of the connected gamepads' numbers.",
+    ),
+    function(
+        "is_gamepad_down",
+        "input.is_gamepad_down(button, pad)",
+        "Whether a gamepad button is being held down, on `pad` or any gamepad.",
+    ),
+    function(
+        "was_gamepad_pressed",
+        "input.was_gamepad_pressed(button, pad)",
+        "Whether a gamepad button was pressed since the last tick.",
+    ),
+    function(
+        "was_gamepad_released",
+        "input.was_gamepad_released(button, pad)",
+        "Whether a gamepad button was let go since the last tick.",
+    ),
+    function(
+        "gamepad_axis",
+        "input.gamepad_axis(axis, pad)",
+        "How far a stick or trigger is pushed, from `-1` to `1`, like `\"left_x\"`.",
+    ),
+];
+
+const SOUND: &[Item] = &[
+    function("play", "sound.play(name, options)", "Plays a sound effect from a file next to the scene. `options` can have a `volume` and whether to `loop`. Returns a handle."),
+    function("music", "sound.music(name, options)", "Plays a music track, stopping the last one. Music loops unless `options` has `loop = false`. Returns a handle."),
+    function("stop", "sound.stop(handle)", "Stops a sound early."),
+    function("stop_music", "sound.stop_music()", "Stops the music."),
+    function("stop_all", "sound.stop_all()", "Stops every sound, and the music."),
+    function("is_playing", "sound.is_playing(handle)", "Whether a sound is still going."),
+];
+
+const RANDOM: &[Item] = &[
+    function("range", "random.range(min, max)", "A number from `min` to `max`. Whole numbers give a whole number, like a dice roll with `random.range(1, 6)`."),
+    function("choice", "random.choice(list)", "One of the things in a list."),
+    function("chance", "random.chance(p)", "`true` some of the time, from `0` (never) to `1` (always)."),
+    function("seed", "random.seed()", "The seed random numbers come from, which is saved with the scene."),
+    function("set_seed", "random.set_seed(n)", "Changes the seed random numbers come from."),
+];
+
+const LOG: &[Item] = &[
+    function("info", "log.info(...)", "The same as `print`."),
+    function("warn", "log.warn(...)", "Logs a warning to the console."),
+    function(
+        "error",
+        "log.error(...)",
+        "Logs an error to the console, without stopping the script.",
+    ),
+];
+
+const CAMERA: &[Item] = &[
+    field("x", "The point shown in the middle of the canvas, from left to right."),
+    field("y", "The point shown in the middle of the canvas, from top to bottom."),
+    field("zoom", "How big things look. `2` makes everything twice as big. Defaults to `1`."),
+    field("rotation", "How far the camera is turned, in degrees."),
+    field("follow", "The ID of an entity to keep in the middle of the canvas, or `nil`."),
+    table("bounds", "An area the camera won't look outside of, like `{ x = 0, y = 0, width = 3000, height = 720 }`.", &[
+        field("x", "The left edge."),
+        field("y", "The top edge."),
+        field("width", "How wide the area is."),
+        field("height", "How tall the area is."),
+    ]),
+];
+
+const CANVAS: &[Item] = &[
+    field(
+        "width",
+        "How wide the scene window's canvas is. Defaults to `1280`.",
+    ),
+    field(
+        "height",
+        "How tall the scene window's canvas is. Defaults to `720`.",
+    ),
+];
+
+/// Everything scripts can use without being given it, besides Lua's own.
+pub const GLOBALS: &[Item] = &[
+    function("broadcast", "broadcast(event, data)", "Runs the `event` script on every entity that has one, passing `data` along."),
+    function("message", "message(target, event, data)", "Runs the `event` script on the entity called `target`, passing `data` along."),
+    function("emit", "emit(event, data)", "Sends `event` to the app, with `data`. `stacks-run` prints these, and scene tests can check them with `events(event)`."),
+    function("emit_to", "emit_to(event, window, data)", "Sends `event` to one of the app's windows, like `\"inspector\"`."),
+    function("wait", "wait(seconds)", "Pauses the script, picking up where it left off once the time has passed."),
+    function("wait_until", "wait_until(condition)", "Pauses the script until `condition`, a function, returns true. It's checked every tick."),
+    function("after", "after(seconds, callback, data)", "Runs `callback` once, after `seconds`. The callback can be a function, or the name of another script on the same entity, which gets `data`. Returns an ID to `cancel` it with."),
+    function("every", "every(seconds, callback, data)", "Like `after`, but runs `callback` again every `seconds` until it's cancelled."),
+    function("cancel", "cancel(id)", "Stops a timer or tween, from `after`, `every`, `tween` or `sequence`."),
+    function("tween", "tween(target, values, seconds, easing, on_complete)", "Smoothly changes the numbers in `target` to `values`, like `tween(self.pos, { x = 300 }, 0.5, \"ease_out_quad\")`. Returns an ID to `cancel` it with."),
+    function("sequence", "sequence(steps, on_complete)", "Runs steps one after the other: tweens written as tables of `tween`'s arguments, seconds to pause, or functions to call."),
+    function("tween_running", "tween_running(id)", "Whether a tween or sequence is still going."),
+    function("touching", "touching(id)", "A list of the IDs of the entities touching an entity right now, like `touching(self.id)`."),
+    function("gravity", "gravity()", "How gravity pulls, like `{ x = 0, y = 980 }`."),
+    function("set_gravity", "set_gravity(x, y)", "Changes how gravity pulls, for the whole scene."),
+    function("print", "print(...)", "Prints to the console's `Log` tab, tagged with the entity & script it came from."),
+    table("input", "What's happening with the keyboard, mouse & gamepads right now.", INPUT),
+    table("sound", "Plays `.wav` and `.ogg` files from next to the scene.", SOUND),
+    table("random", "Random numbers that come out the same every time the scene's loaded.", RANDOM),
+    table("log", "Logging to the console, at different levels.", LOG),
+    table("camera", "Which part of the world is shown on the canvas. Saved with the scene.", CAMERA),
+    table("canvas", "The size of the scene window's canvas. Saved with the scene.", CANVAS),
+];
+
+/// Properties entities have, and the types of entity each one is for.
+pub const PROPERTIES: &[Item] = &[
+    field("id", "The entity's name, which is unique in the scene. Messages use it to find the entity."),
+    field("type", "What sort of entity it is: `\"rect\"`, `\"text\"`, `\"svg\"`, `\"sprite\"`, `\"tilemap\"` or `\"text_input\"`."),
+    table("pos", "Where the entity's top left corner is in the scene.", XY),
+    table("size", "How big the entity is.", SIZE).only(&["rect", "svg", "sprite", "text_input"]),
+    field("selectable", "Whether it can be selected, dragged & rotated in the scene."),
+    field("rotation", "The angle it's turned to, in degrees. Defaults to `0`."),
+    field("layer", "Entities on higher layers show in front of lower ones. Defaults to `0`, which is the lowest."),
+    field("collider", "Lets it bump into others: `\"rect\"` (or `true`), `\"circle\"`, or a table like `{ shape = \"circle\", radius = 10 }`."),
+    table("body", "Makes it fall, slide & bounce on its own. Needs a `collider` to bump into things.", &[
+        field("type", "`\"dynamic\"` (the default) for things that move, or `\"static\"` for things that stay put."),
+        table("velocity", "How fast it's moving, in pixels per second.", XY),
+        field("angular_velocity", "How fast it spins, in degrees per second."),
+        field("mass", "How heavy it is, compared to other bodies. Defaults to `1`."),
+        field("friction", "How much it slows down things sliding along it. Defaults to `0.3`."),
+        field("bounce", "How much of its speed it keeps when it hits something, from `0` to `1`. Defaults to `0`."),
+        field("gravity_scale", "How strongly gravity pulls it. `0` makes it float. Defaults to `1`."),
+    ]),
+    field("color", "Its colour, like `\"#ff0000\"`.").only(&["rect", "text_input"]),
+    field("content", "What it shows: the text of a text or text input, or the markup inside an svg.").only(&["text", "svg", "text_input"]),
+    field("font_size", "How big its text is.").only(&["text", "text_input"]),
+    field("placeholder", "What the input shows when it's empty.").only(&["text_input"]),
+    field("disabled", "Stops the input being typed in.").only(&["text_input"]),
+    field("image", "The picture to show, from next to the scene file, like `\"images/hero.png\"`.").only(&["sprite"]),
+    table("sheet", "Cuts the image into a grid of frames, numbered from `1`.", SHEET).only(&["sprite", "tilemap"]),
+    field("frame", "Which frame of the sheet is showing. Defaults to `1`.").only(&["sprite"]),
+    field("animations", "Lists of frames to play, by name, like `walk = { frames = { 1, 2, 3 }, fps = 8 }`.").only(&["sprite"]),
+    field("animation", "The name of the animation that's playing. Set it to play one.").only(&["sprite"]),
+    field("flip_x", "Mirrors the picture left to right.").only(&["sprite"]),
+    field("flip_y", "Turns the picture upside down.").only(&["sprite"]),
+    field("tileset", "The image tiles are cut from, from next to the scene file.").only(&["tilemap"]),
+    table("tile_size", "How big each tile is in the scene.", SIZE).only(&["tilemap"]),
+    field("columns", "How many tiles wide the map is.").only(&["tilemap"]),
+    field("rows", "How many tiles tall the map is.").only(&["tilemap"]),
+    field("tiles", "Which tile goes where, row after row. `0` is empty.").only(&["tilemap"]),
+    method("get_tile", "self:get_tile(column, row)", "The tile at a column & row, counting from `1` at the top left. `0` is empty.").only(&["tilemap"]),
+    method("set_tile", "self:set_tile(column, row, tile)", "Changes the tile at a column & row, counting from `1` at the top left.").only(&["tilemap"]),
+];
+
+const KEY: &[Item] = &[field(
+    "key",
+    "The key's name, like `\"a\"`, `\"space\"` or `\"left\"`.",
+)];
+
+const MOUSE: &[Item] = &[
+    field("x", "Where the mouse was in the scene, from left to right."),
+    field("y", "Where the mouse was in the scene, from top to bottom."),
+];
+
+const BUTTON: &[Item] = &[
+    field("button", "`\"left\"`, `\"middle\"` or `\"right\"`."),
+    field("x", "Where the mouse was in the scene, from left to right."),
+    field("y", "Where the mouse was in the scene, from top to bottom."),
+];
+
+const TEXT: &[Item] = &[field("text", "The text in the input.")];
+
+const PAD: &[Item] = &[field("pad", "The gamepad's number, counting from `1`.")];
+
+const OTHER: &[Item] = &[field("other", "The ID of the entity it touched.")];
+
+/// Events the runtime runs scripts for, and what's in their `data`.
+pub const EVENTS: &[Item] = &[
+    built_in("on_click", "Runs when the entity is double clicked.", &[]),
+    built_in(
+        "on_tick",
+        "Runs every tick, after physics has moved things.",
+        &[],
+    ),
+    built_in(
+        "on_key_down",
+        "Runs on every entity with the script when a key is pressed.",
+        KEY,
+    ),
+    built_in(
+        "on_key_up",
+        "Runs on every entity with the script when a key is let go.",
+        KEY,
+    ),
+    built_in(
+        "on_mouse_enter",
+        "Runs when the mouse moves onto the entity.",
+        MOUSE,
+    ),
+    built_in(
+        "on_mouse_leave",
+        "Runs when the mouse moves off the entity.",
+        MOUSE,
+    ),
+    built_in(
+        "on_mouse_down",
+        "Runs when a mouse button is pressed over the entity.",
+        BUTTON,
+    ),
+    built_in(
+        "on_mouse_up",
+        "Runs when a mouse button is let go over the entity.",
+        BUTTON,
+    ),
+    built_in(
+        "on_change",
+        "Runs every time a character is typed into or deleted from the input.",
+        TEXT,
+    )
+    .only(&["text_input"]),
+    built_in(
+        "on_submit",
+        "Runs when enter is pressed in the input.",
+        TEXT,
+    )
+    .only(&["text_input"]),
+    built_in(
+        "on_animation_end",
+        "Runs when an animation that doesn't loop finishes.",
+        &[field(
+            "animation",
+            "The name of the animation that finished.",
+        )],
+    )
+    .only(&["sprite"]),
+    built_in(
+        "on_gamepad_button",
+        "Runs on every entity with the script when a gamepad button is pressed or let go.",
+        &[
+            field("pad", "The gamepad's number, counting from `1`."),
+            field("button", "The button, like `\"a\"` or `\"dpad_up\"`."),
+            field(
+                "down",
+                "`true` if it was pressed, or `false` if it was let go.",
+            ),
+        ],
+    ),
+    built_in(
+        "on_gamepad_connected",
+        "Runs on every entity with the script when a gamepad is plugged in.",
+        PAD,
+    ),
+    built_in(
+        "on_gamepad_disconnected",
+        "Runs on every entity with the script when a gamepad is unplugged.",
+        PAD,
+    ),
+    built_in(
+        "on_collision_enter",
+        "Runs when the entity starts touching another. Both need a `collider`.",
+        OTHER,
+    ),
+    built_in(
+        "on_collision_stay",
+        "Runs every tick the entity is still touching another.",
+        OTHER,
+    ),
+    built_in(
+        "on_collision_exit",
+        "Runs once the entity has stopped touching another.",
+        OTHER,
+    ),
+];
+
+pub fn global(name: &str) -> Option<&'static Item> {
+    GLOBALS.iter().find(|item| item.name == name)
+}
+
+pub fn property(name: &str) -> Option<&'static Item> {
+    PROPERTIES.iter().find(|item| item.name == name)
+}
+
+pub fn event(name: &str) -> Option<&'static Item> {
+    EVENTS.iter().find(|item| item.name == name)
+}
@@ -0,0 +1,288 @@
+use crate::scripts::{parse_chunk_name, split_location};
+use mlua::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Identical errors reported within this window are folded into a single event.
+const DEDUPE_WINDOW: Duration = Duration::from_secs(5);
+/// Maximum number of error events emitted per second, across all scripts.
+const MAX_EVENTS_PER_SECOND: u32 = 10;
+/// Number of emitted errors kept around for consoles that open later.
+const HISTORY_LENGTH: usize = 100;
+/// Number of failures after which a script is disabled, if auto-disabling is on.
+const AUTO_DISABLE_THRESHOLD: u32 = 3;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ScriptError {
+    pub entity: String,
+    pub script: String,
+    pub line: Option<u32>,
+    pub message: String,
+    pub traceback: String,
+    /// Number of occurrences folded into this event by de-duplication
+    pub count: u32,
+    /// Whether the script was disabled because of this error
+    pub disabled: bool,
+}
+
+struct Occurrence {
+    last_emitted: Option<Instant>,
+    pending: u32,
+}
+
+pub struct ScriptErrors {
+    occurrences: HashMap<(String, String, Option<u32>, String), Occurrence>,
+    window_start: Instant,
+    emitted_in_window: u32,
+    history: VecDeque<ScriptError>,
+    pub auto_disable: bool,
+}
+
+impl ScriptErrors {
+    pub fn new() -> Self {
+        ScriptErrors {
+            occurrences: HashMap::new(),
+            window_start: Instant::now(),
+            emitted_in_window: 0,
+            history: VecDeque::new(),
+            auto_disable: false,
+        }
+    }
+
+    /// Whether a script that has failed `failures` times should now be disabled.
+    pub fn should_disable(&self, failures: u32) -> bool {
+        self.auto_disable && failures >= AUTO_DISABLE_THRESHOLD
+    }
+
+    /// Record an error, returning it if it should be emitted now.
+    /// Duplicates and errors over the rate limit are counted, and folded into the next emitted occurrence.
+    pub fn report(&mut self, error: ScriptError) -> Option<ScriptError> {
+        self.report_at(error, Instant::now())
+    }
+
+    fn report_at(&mut self, mut error: ScriptError, now: Instant) -> Option<ScriptError> {
+        let key = (
+            error.entity.clone(),
+            error.script.clone(),
+            error.line,
+            error.message.clone(),
+        );
+        let occurrence = self.occurrences.entry(key).or_insert(Occurrence {
+            last_emitted: None,
+            pending: 0,
+        });
+        occurrence.pending += 1;
+
+        // Disabling a script is always worth hearing about
+        if !error.disabled {
+            if occurrence
+                .last_emitted
+                .is_some_and(|last| now.duration_since(last) < DEDUPE_WINDOW)
+            {
+                return None;
+            }
+
+            if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+                self.window_start = now;
+                self.emitted_in_window = 0;
+            }
+            if self.emitted_in_window >= MAX_EVENTS_PER_SECOND {
+                return None;
+            }
+        }
+
+        self.emitted_in_window += 1;
+        error.count = occurrence.pending;
+        occurrence.pending = 0;
+        occurrence.last_emitted = Some(now);
+
+        if self.history.len() >= HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(error.clone());
+        Some(error)
+    }
+
+    pub fn history(&self) -> Vec<ScriptError> {
+        self.history.iter().cloned().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.occurrences.clear();
+        self.history.clear();
+    }
+}
+
+/// Message handler for `xpcall`, describing a raised error as a table of
+/// `message`, `line` (in the innermost script chunk) and `traceback`.
+pub fn error_handler<'lua>(lua: &'lua Lua, err: LuaValue<'lua>) -> LuaResult<LuaTable<'lua>> {
+    let mut line: Option<u32> = None;
+    let mut traceback = String::from("stack traceback:");
+
+    // level 0 is this handler
+    let mut level = 1;
+    while let Some(debug) = lua.inspect_stack(level) {
+        level += 1;
+        let source = debug.source();
+        let short_src = source.short_src.as_deref().unwrap_or("?").to_string();
+        let curr_line = debug.curr_line();
+        let name = debug.names().name.map(|n| format!("function '{}'", n));
+
+        if source.what == "C" {
+            traceback.push_str(&format!(
+                "\n\t[C]: in {}",
+                name.unwrap_or_else(|| "?".to_string())
+            ));
+            continue;
+        }
+
+        let is_script = source
+            .source
+            .as_deref()
+            .is_some_and(|s| parse_chunk_name(s).is_some());
+        if line.is_none() && is_script && curr_line > 0 {
+            line = Some(curr_line as u32);
+        }
+        traceback.push_str(&format!(
+            "\n\t{}:{}: in {}",
+            short_src,
+            curr_line,
+            name.unwrap_or_else(|| match source.what {
+                "main" => "main chunk".to_string(),
+                _ => "function".to_string(),
+            })
+        ));
+    }
+
+    let mut message = error_message(&err);
+    if let Some((chunk, msg_line, msg)) = split_location(&message) {
+        // errors raised before the script ran (i.e. compile errors) only have the line in the message
+        if line.is_none() && parse_chunk_name(&format!("={}", chunk)).is_some() {
+            line = Some(msg_line);
+        }
+        message = msg.to_string();
+    }
+
+    let details = lua.create_table()?;
+    details.set("message", message)?;
+    details.set("line", line)?;
+    details.set("traceback", traceback)?;
+    Ok(details)
+}
+
+fn error_message(err: &LuaValue) -> String {
+    match err {
+        LuaValue::String(s) => s.to_string_lossy().to_string(),
+        LuaValue::Error(e) => root_cause(e),
+        LuaValue::Nil => "nil".to_string(),
+        other => format!("(error object is a {} value)", other.type_name()),
+    }
+}
+
+/// The message an error started out with, without what it picked up passing through Rust.
+pub fn root_cause(err: &mlua::Error) -> String {
+    match err {
+        mlua::Error::CallbackError { cause, .. } => root_cause(cause),
+        // errors passing back through Rust callbacks pick up a traceback of their own
+        mlua::Error::RuntimeError(msg) => msg
+            .split("\nstack traceback:")
+            .next()
+            .unwrap_or(msg.as_str())
+            .to_string(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(entity: &str, line: u32) -> ScriptError {
+        ScriptError {
+            entity: entity.to_string(),
+            script: "on_tick".to_string(),
+            line: Some(line),
+            message: "attempt to index a nil value".to_string(),
+            traceback: String::new(),
+            count: 0,
+            disabled: false,
+        }
+    }
+
+    fn at(start: Instant, millis: u64) -> Instant {
+        start + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn repeats_are_folded_into_the_next_report() {
+        let mut errors = ScriptErrors::new();
+        let start = Instant::now();
+        assert_eq!(
+            errors.report_at(error("a", 1), at(start, 0)).unwrap().count,
+            1
+        );
+        for millis in [100, 200, 4900] {
+            assert!(errors.report_at(error("a", 1), at(start, millis)).is_none());
+        }
+        // a different line isn't the same error
+        assert!(errors.report_at(error("a", 2), at(start, 300)).is_some());
+        let folded = errors.report_at(error("a", 1), at(start, 5000)).unwrap();
+        assert_eq!(folded.count, 4);
+    }
+
+    #[test]
+    fn reports_are_limited_per_second() {
+        let mut errors = ScriptErrors::new();
+        let start = Instant::now();
+        let reported = (0..15)
+            .filter(|&i| {
+                errors
+                    .report_at(error(&format!("e{}", i), 1), at(start, 10))
+                    .is_some()
+            })
+            .count();
+        assert_eq!(reported, MAX_EVENTS_PER_SECOND as usize);
+
+        // over the limit isn't the same as a duplicate, so it's reported once there's room
+        let late = errors.report_at(error("e14", 1), at(start, 1100)).unwrap();
+        assert_eq!(late.count, 2);
+    }
+
+    #[test]
+    fn disabling_is_reported_over_the_limits() {
+        let mut errors = ScriptErrors::new();
+        let start = Instant::now();
+        errors.report_at(error("a", 1), at(start, 0));
+        let disabled = ScriptError {
+            disabled: true,
+            ..error("a", 1)
+        };
+        assert!(errors.report_at(disabled, at(start, 10)).unwrap().disabled);
+    }
+
+    #[test]
+    fn history_keeps_the_latest() {
+        let mut errors = ScriptErrors::new();
+        let start = Instant::now();
+        for i in 0..HISTORY_LENGTH as u64 + 5 {
+            // a second apart, to stay under the rate limit
+            errors.report_at(error(&format!("e{}", i), 1), at(start, i * 1000));
+        }
+        let history = errors.history();
+        assert_eq!(history.len(), HISTORY_LENGTH);
+        assert_eq!(history[0].entity, "e5");
+
+        errors.clear();
+        assert!(errors.history().is_empty());
+    }
+
+    #[test]
+    fn scripts_are_disabled_after_repeated_failures() {
+        let mut errors = ScriptErrors::new();
+        assert!(!errors.should_disable(AUTO_DISABLE_THRESHOLD));
+        errors.auto_disable = true;
+        assert!(!errors.should_disable(AUTO_DISABLE_THRESHOLD - 1));
+        assert!(errors.should_disable(AUTO_DISABLE_THRESHOLD));
+    }
+}
@@ -0,0 +1,181 @@
+use crate::debugger::hook_thread;
+use crate::lua_setup::{get_entity, get_scene};
+use crate::lua_types::LuaError;
+use crate::profiler::profile_script;
+use crate::scheduler::{scheduler_mut, TaskKind};
+use mlua::prelude::*;
+
+/// Registry name of the function every script coroutine runs, which calls its
+/// arguments under `xpcall` so errors come back described by `script_error_handler`.
+pub const COROUTINE_BODY: &str = "stacks.coroutine_body";
+
+/// Entity & script currently running, innermost last.
+/// Lets Rust functions called from a script know who called them.
+struct ScriptStack(Vec<(String, String)>);
+
+pub fn set_globals(lua: &Lua) -> Result<(), LuaError> {
+    lua.set_app_data(ScriptStack(Vec::new()));
+
+    let body: LuaFunction = lua
+        .load("return function(f, ...) return xpcall(f, script_error_handler, ...) end")
+        .set_name("coroutine_body.lua")
+        .eval()
+        .map_err(|e| {
+            LuaError::InitializationError(format!("Failed to create coroutine body: {}", e))
+        })?;
+    lua.set_named_registry_value(COROUTINE_BODY, body)?;
+
+    lua.globals()
+        .set(
+            "run_entity_script",
+            lua.create_function(|l: &Lua, (id, script, data): (String, String, LuaValue)| {
+                Ok(run_entity_script(l, &id, &script, data)?)
+            })?,
+        )
+        .map_err(|e| {
+            LuaError::InitializationError(format!("Failed to set script runner: {}", e))
+        })?;
+
+    Ok(())
+}
+
+/// The entity & script whose code is running right now, if any.
+pub fn current_script(lua: &Lua) -> Option<(String, String)> {
+    lua.app_data_ref::<ScriptStack>()?.0.last().cloned()
+}
+
+/// Run an entity's script in a new coroutine, so that it can wait.
+/// Returns whether it succeeded, or the error message if it failed.
+pub fn run_entity_script(
+    lua: &Lua,
+    id: &str,
+    script: &str,
+    data: LuaValue,
+) -> Result<(bool, Option<String>), LuaError> {
+    let entity = match get_entity(lua, id) {
+        Ok(entity) => entity,
+        Err(_) => return Ok((false, Some(format!("Couldn't find entity \"{}\".", id)))),
+    };
+
+    let thread = lua.create_thread(lua.named_registry_value::<LuaFunction>(COROUTINE_BODY)?)?;
+    resume_script(
+        lua,
+        id,
+        script,
+        thread,
+        (
+            entity.get::<_, LuaFunction>("run_script")?,
+            entity,
+            id,
+            script,
+            data,
+        ),
+    )
+}
+
+/// Run an entity's script for an event, if it has one.
+pub fn run_listener(lua: &Lua, id: &str, event: &str, data: LuaValue) -> Result<(), LuaError> {
+    let Ok(entity) = get_entity(lua, id) else {
+        return Ok(());
+    };
+    if entity.get::<_, LuaTable>("scripts")?.contains_key(event)? {
+        run_entity_script(lua, id, event, data)?;
+    }
+    Ok(())
+}
+
+/// Resume a script's coroutine until it finishes or waits again.
+pub fn resume_script<'lua>(
+    lua: &'lua Lua,
+    id: &str,
+    script: &str,
+    thread: LuaThread<'lua>,
+    args: impl IntoLuaMulti<'lua>,
+) -> Result<(bool, Option<String>), LuaError> {
+    hook_thread(lua, &thread)?;
+    let result = with_script(lua, id, script, || thread.resume::<_, LuaMultiValue>(args))?;
+
+    let mut values = result.into_iter();
+    if thread.status() == LuaThreadStatus::Resumable {
+        // suspended by wait or wait_until; any other yield waits for the next tick
+        let kind = match (values.next(), values.next()) {
+            (Some(LuaValue::String(kind)), Some(LuaValue::Function(condition)))
+                if kind == "wait_until" =>
+            {
+                (
+                    0.0,
+                    TaskKind::WaitUntil(
+                        lua.create_registry_value(thread)?,
+                        lua.create_registry_value(condition)?,
+                    ),
+                )
+            }
+            (Some(LuaValue::String(kind)), Some(seconds)) if kind == "wait" => (
+                lua.unpack::<f64>(seconds).unwrap_or(0.0),
+                TaskKind::Wait(lua.create_registry_value(thread)?),
+            ),
+            _ => (0.0, TaskKind::Wait(lua.create_registry_value(thread)?)),
+        };
+        scheduler_mut(lua)?.add(id, script, kind.0, kind.1);
+        return Ok((true, None));
+    }
+
+    // finished, with the results of xpcall
+    match (values.next(), values.next()) {
+        (Some(LuaValue::Boolean(true)), _) => Ok((true, None)),
+        (_, err) => {
+            let err = err.unwrap_or(LuaNil);
+            let message = err
+                .as_table()
+                .and_then(|details| details.get::<_, String>("message").ok());
+            report_failure(lua, id, script, err)?;
+            Ok((false, message))
+        }
+    }
+}
+
+/// Check a `wait_until` condition, returning `None` if it raised an error.
+pub fn check_condition(
+    lua: &Lua,
+    id: &str,
+    script: &str,
+    condition: LuaFunction,
+) -> Result<Option<bool>, LuaError> {
+    let (success, result): (bool, LuaValue) = with_script(lua, id, script, || {
+        lua.named_registry_value::<LuaFunction>(COROUTINE_BODY)?
+            .call(condition)
+    })?;
+
+    if success {
+        Ok(Some(result.as_boolean().unwrap_or(!result.is_nil())))
+    } else {
+        report_failure(lua, id, script, result)?;
+        Ok(None)
+    }
+}
+
+/// Count a script's failure and report it to the script error channel.
+pub fn report_failure(lua: &Lua, id: &str, script: &str, err: LuaValue) -> Result<(), LuaError> {
+    let scene = get_scene(lua)?;
+    scene
+        .get::<_, LuaFunction>("report_failure")?
+        .call::<_, ()>((scene, id, script, err))
+        .map_err(|e| LuaError::LuaError(e))
+}
+
+/// Run `f` as the given entity's script, so whatever it calls knows who it's running for.
+pub fn with_script<R>(
+    lua: &Lua,
+    id: &str,
+    script: &str,
+    f: impl FnOnce() -> LuaResult<R>,
+) -> LuaResult<R> {
+    if let Some(mut stack) = lua.app_data_mut::<ScriptStack>() {
+        stack.0.push((id.to_string(), script.to_string()));
+    }
+    let result = profile_script(lua, id, script, f);
+    if let Some(mut stack) = lua.app_data_mut::<ScriptStack>() {
+        stack.0.pop();
+    }
+    result
+}
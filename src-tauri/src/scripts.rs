@@ -0,0 +1,214 @@
+use mlua::prelude::*;
+use serde::Serialize;
+use std::path::Path;
+
+/// Binds the script arguments. Kept on the script's first line, so that line numbers
+/// in the compiled chunk are exactly the line numbers in the inspector's script editor.
+pub const SCRIPT_PREAMBLE: &str = "local self, data = ... ; ";
+
+/// A compile error in a script, located for the script editor.
+/// Lines and columns are 1-based, and count characters rather than bytes.
+#[derive(Serialize, Clone, Debug)]
+pub struct Diagnostic {
+    pub entity: String,
+    pub script: String,
+    pub line: u32,
+    pub column: u32,
+    pub message: String,
+}
+
+/// Name a script chunk is compiled under, as shown in errors and tracebacks.
+pub fn chunk_name(entity: &str, script: &str) -> String {
+    format!("{}:{}", entity, script)
+}
+
+/// Recover the entity ID and script name from a chunk's source (`=entity:script`).
+pub fn parse_chunk_name(source: &str) -> Option<(&str, &str)> {
+    source.strip_prefix('=')?.rsplit_once(':')
+}
+
+/// The entity ID and script name a file's named after, like `player.on_tick.lua`.
+pub fn script_file_name(path: &Path) -> Option<(String, String)> {
+    let stem = path.file_stem()?.to_str()?;
+    let (entity, script) = stem.rsplit_once('.')?;
+    Some((entity.to_string(), script.to_string()))
+}
+
+/// Compile a script into a function taking `(self, data)`.
+pub fn compile_script<'lua>(
+    lua: &'lua Lua,
+    entity: &str,
+    script: &str,
+    source: &str,
+) -> LuaResult<LuaFunction<'lua>> {
+    lua.load(format!("{}{}", SCRIPT_PREAMBLE, source))
+        .set_name(format!("={}", chunk_name(entity, script)))
+        .into_function()
+}
+
+/// The message of a compile error, as Lua reports it.
+pub fn compile_error_message(err: &mlua::Error) -> String {
+    match err {
+        mlua::Error::SyntaxError { message, .. } => message.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Split a located Lua error message like `test1:on_click:3: '=' expected near 'x'`
+/// into its chunk name, line and message.
+pub fn split_location(msg: &str) -> Option<(&str, u32, &str)> {
+    for (i, _) in msg.match_indices(':') {
+        let rest = &msg[i + 1..];
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            continue;
+        }
+        if let Some(message) = rest[digits..].strip_prefix(": ") {
+            if let Ok(line) = rest[..digits].parse() {
+                return Some((&msg[..i], line, message));
+            }
+        }
+    }
+    None
+}
+
+/// Compile a script without loading it, returning the error if it doesn't compile.
+pub fn check_script(lua: &Lua, entity: &str, script: &str, source: &str) -> Option<Diagnostic> {
+    let err = compile_script(lua, entity, script, source).err()?;
+    let full_message = compile_error_message(&err);
+    let (line, message) = match split_location(&full_message) {
+        Some((_, line, message)) => (line, message.to_string()),
+        None => (1, full_message.clone()),
+    };
+    let fails_the_same = |code: &str| {
+        compile_script(lua, entity, script, code)
+            .err()
+            .is_some_and(|e| compile_error_message(&e) == full_message)
+    };
+
+    Some(Diagnostic {
+        entity: entity.to_string(),
+        script: script.to_string(),
+        line,
+        column: error_column(source, line, &message, fails_the_same),
+        message,
+    })
+}
+
+/// Lua doesn't report columns, so locate the token a syntax error is reported "near".
+/// When the token's on the line more than once, it's the first one where the code up to
+/// and including it (checked with `fails_the_same`) already fails with the same error.
+fn error_column(
+    source: &str,
+    line: u32,
+    message: &str,
+    fails_the_same: impl Fn(&str) -> bool,
+) -> u32 {
+    let line_start: usize = source
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1) as usize)
+        .map(str::len)
+        .sum();
+    let text = source[line_start..].lines().next().unwrap_or("");
+    let column_of = |byte: usize| text[..byte].chars().count() as u32 + 1;
+
+    match message.rsplit_once(" near ") {
+        Some((_, "<eof>")) => column_of(text.len()),
+        Some((_, near)) => {
+            let Some(token) = near.strip_prefix('\'').and_then(|n| n.strip_suffix('\'')) else {
+                return 1;
+            };
+            let found: Vec<usize> = text.match_indices(token).map(|(byte, _)| byte).collect();
+            found
+                .iter()
+                .find(|&&byte| fails_the_same(&source[..line_start + byte + token.len()]))
+                .or(found.last())
+                .map(|&byte| column_of(byte))
+                .unwrap_or(1)
+        }
+        // otherwise point at the start of the line's code
+        None => column_of(text.len() - text.trim_start().len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(entity: &str, source: &str) -> Option<(u32, u32, String)> {
+        check_script(&Lua::new(), entity, "on_click", source)
+            .map(|diagnostic| (diagnostic.line, diagnostic.column, diagnostic.message))
+    }
+
+    #[test]
+    fn splits_locations_off_messages() {
+        assert_eq!(
+            split_location("test1:on_click:3: '=' expected near 'x'"),
+            Some(("test1:on_click", 3, "'=' expected near 'x'"))
+        );
+        // entity IDs can have colons & numbers in them, and messages can mention other locations
+        assert_eq!(
+            split_location("level:2:on_tick:14: bad argument: see other:5: here"),
+            Some(("level:2:on_tick", 14, "bad argument: see other:5: here"))
+        );
+        assert_eq!(split_location("no location here"), None);
+    }
+
+    #[test]
+    fn parses_chunk_names() {
+        assert_eq!(
+            parse_chunk_name("=player:on_tick"),
+            Some(("player", "on_tick"))
+        );
+        assert_eq!(
+            parse_chunk_name("=ui:button:on_click"),
+            Some(("ui:button", "on_click"))
+        );
+        assert_eq!(parse_chunk_name("player:on_tick"), None);
+        assert_eq!(parse_chunk_name("=main"), None);
+    }
+
+    #[test]
+    fn scripts_that_compile_have_no_diagnostic() {
+        assert_eq!(check("door", "self.open = not self.open"), None);
+    }
+
+    #[test]
+    fn points_at_the_token_that_failed() {
+        assert_eq!(
+            check("door", "local open = true\nx = = 1 = 2"),
+            Some((2, 5, "unexpected symbol near '='".to_string()))
+        );
+        // `x` is in `max` too
+        assert_eq!(
+            check("door", "for max x"),
+            Some((1, 9, "'=' or 'in' expected near 'x'".to_string()))
+        );
+    }
+
+    #[test]
+    fn errors_at_the_end_point_past_the_last_line() {
+        assert_eq!(
+            check("door", "if self.open then\n    self.open = false"),
+            Some((
+                2,
+                22,
+                "'end' expected (to close 'if' at line 1) near <eof>".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn entity_ids_can_have_colons() {
+        let diagnostic = check_script(&Lua::new(), "ui:button", "on_click", "x = = 1").unwrap();
+        assert_eq!(
+            (
+                diagnostic.entity.as_str(),
+                diagnostic.line,
+                diagnostic.column
+            ),
+            ("ui:button", 1, 5)
+        );
+        assert_eq!(diagnostic.message, "unexpected symbol near '='");
+    }
+}
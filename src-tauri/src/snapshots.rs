@@ -0,0 +1,316 @@
+use crate::camera::{Camera, Canvas};
+use crate::collisions::{collisions_mut, Collisions};
+use crate::lua_setup::{dump_scene, load_scene_dump};
+use crate::lua_types::LuaError;
+use crate::physics::{physics_mut, Physics};
+use crate::random::{random_mut, Random};
+use crate::recording::is_recording;
+use crate::scheduler::{unsaved_tasks, warn_left_out};
+use mlua::prelude::*;
+use mlua::AppDataRefMut;
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// Ticks between snapshots, since dumping a busy scene every tick soon adds up.
+const SNAPSHOT_EVERY: u64 = 6;
+
+/// Snapshots to keep, which is ten seconds at 60 ticks a second.
+const MAX_SNAPSHOTS: usize = 100;
+
+/// The scene as it was at the end of a tick.
+struct Snapshot {
+    tick: u64,
+    /// Seconds since the scene was loaded
+    time: f64,
+    /// In the same format as a scene file
+    scene: String,
+    // not saved with scenes, but needed to carry on from here exactly as the scene did
+    random: Random,
+    collisions: Collisions,
+    physics: Physics,
+    /// What was running that can't be saved, so resuming here can say what it leaves out
+    unsaved: Vec<String>,
+}
+
+/// Recent history of the scene, for looking back through & carrying on from an earlier point.
+pub struct Snapshots {
+    history: VecDeque<Snapshot>,
+    tick: u64,
+    time: f64,
+    /// Tick being looked at, which pauses the scene until it's resumed
+    viewing: Option<u64>,
+}
+
+/// What history there is, for the frontend to scrub through.
+#[derive(Serialize)]
+pub struct Timeline {
+    pub first: Option<u64>,
+    pub last: Option<u64>,
+    pub viewing: Option<u64>,
+    /// Seconds since the scene was loaded, at the tick being looked at
+    pub time: Option<f64>,
+    /// Ticks between snapshots
+    pub every: u64,
+}
+
+impl Snapshots {
+    pub fn new() -> Self {
+        Snapshots {
+            history: VecDeque::new(),
+            tick: 0,
+            time: 0.0,
+            viewing: None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.viewing.is_some()
+    }
+
+    /// The last snapshot taken by `tick`, since they're only taken every few ticks.
+    fn find(&self, tick: u64) -> Result<&Snapshot, LuaError> {
+        self.history
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.tick <= tick)
+            .ok_or_else(|| LuaError::FormatError(format!("There's no snapshot of tick {}.", tick)))
+    }
+
+    pub fn timeline(&self) -> Timeline {
+        Timeline {
+            first: self.history.front().map(|snapshot| snapshot.tick),
+            last: self.history.back().map(|snapshot| snapshot.tick),
+            viewing: self.viewing,
+            time: self
+                .viewing
+                .and_then(|tick| self.find(tick).ok())
+                .map(|snapshot| snapshot.time),
+            every: SNAPSHOT_EVERY,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Snapshots::new();
+    }
+}
+
+pub fn snapshots_mut(lua: &Lua) -> Result<AppDataRefMut<'_, Snapshots>, LuaError> {
+    lua.app_data_mut::<Snapshots>()
+        .ok_or_else(|| LuaError::InitializationError("Snapshots missing".to_string()))
+}
+
+/// Count a tick `dt` seconds long, remembering the scene as it is at the end of it
+/// every `SNAPSHOT_EVERY` ticks.
+pub fn take_snapshot(lua: &Lua, dt: f64) -> Result<(), LuaError> {
+    let (tick, time) = {
+        let mut snapshots = snapshots_mut(lua)?;
+        snapshots.tick += 1;
+        snapshots.time += dt;
+        (snapshots.tick, snapshots.time)
+    };
+    if tick % SNAPSHOT_EVERY != 0 {
+        return Ok(());
+    }
+    let snapshot = Snapshot {
+        tick,
+        time,
+        scene: dump_scene(lua)?,
+        random: random_mut(lua)?.clone(),
+        collisions: collisions_mut(lua)?.clone(),
+        physics: physics_mut(lua)?.clone(),
+        unsaved: unsaved_tasks(lua)?,
+    };
+    let mut snapshots = snapshots_mut(lua)?;
+    if snapshots.history.len() >= MAX_SNAPSHOTS {
+        snapshots.history.pop_front();
+    }
+    snapshots.history.push_back(snapshot);
+    Ok(())
+}
+
+/// A scene holding a snapshot, separate from the one that's running.
+fn past_scene<'lua>(
+    lua: &'lua Lua,
+    dump: &str,
+) -> Result<(LuaTable<'lua>, LuaTable<'lua>), LuaError> {
+    let class: LuaTable = lua
+        .globals()
+        .get::<_, LuaFunction>("require")?
+        .call("Scene")?;
+    let past: LuaTable = class.get::<_, LuaFunction>("new")?.call(class)?;
+    let loaded: LuaTable = past
+        .get::<_, LuaFunction>("load_dump")?
+        .call((past.clone(), dump))?;
+    Ok((past, loaded))
+}
+
+fn check_not_recording(lua: &Lua) -> Result<(), LuaError> {
+    match is_recording(lua) {
+        true => Err(LuaError::FormatError(
+            "Stop recording before going back in time.".to_string(),
+        )),
+        false => Ok(()),
+    }
+}
+
+/// Pause the scene and show it as it was at `tick`.
+pub fn view_snapshot(lua: &Lua, tick: u64) -> Result<(), LuaError> {
+    check_not_recording(lua)?;
+    let (tick, dump) = {
+        let snapshots = snapshots_mut(lua)?;
+        let snapshot = snapshots.find(tick)?;
+        (snapshot.tick, snapshot.scene.clone())
+    };
+    snapshots_mut(lua)?.viewing = Some(tick);
+
+    let (past, loaded) = past_scene(lua, &dump)?;
+    let camera: Camera = match loaded.get::<_, LuaValue>("camera")? {
+        LuaNil => Camera::default(),
+        camera => lua.from_value(camera)?,
+    };
+    let canvas: Canvas = match loaded.get::<_, LuaValue>("canvas")? {
+        LuaNil => Canvas::default(),
+        canvas => lua.from_value(canvas)?,
+    };
+    past.get::<_, LuaFunction>("emit_update")?
+        .call::<_, ()>((past, lua.to_value(&camera.view(canvas))?))?;
+    Ok(())
+}
+
+/// An entity as it was at `tick`, in the same format the inspector shows it in.
+pub fn snapshot_entity(lua: &Lua, tick: u64, id: &str) -> Result<String, LuaError> {
+    let dump = snapshots_mut(lua)?.find(tick)?.scene.clone();
+    let (past, _) = past_scene(lua, &dump)?;
+    let entities: LuaTable = past.get("entities")?;
+    if !entities.contains_key(id)? {
+        return Err(LuaError::FormatError(format!(
+            "There was no entity called {} at tick {}.",
+            id, tick
+        )));
+    }
+    Ok(past
+        .get::<_, LuaFunction>("entity_as_block_string")?
+        .call((past, id))?)
+}
+
+/// Carry on from the snapshot at `tick`, forgetting everything after it,
+/// or from where the scene was paused if there's no `tick`.
+///
+/// Anything that isn't saved with a scene (like tweens) can't be carried on with,
+/// so it's left out with a warning saying what was running at the time.
+pub fn resume(lua: &Lua, tick: Option<u64>) -> Result<(), LuaError> {
+    check_not_recording(lua)?;
+    let Some(tick) = tick else {
+        snapshots_mut(lua)?.viewing = None;
+        return Ok(());
+    };
+
+    let (tick, scene) = {
+        let snapshots = snapshots_mut(lua)?;
+        let snapshot = snapshots.find(tick)?;
+        (snapshot.tick, snapshot.scene.clone())
+    };
+    // loading a scene starts its history over, so this one is put back afterwards
+    let mut snapshots = std::mem::replace(&mut *snapshots_mut(lua)?, Snapshots::new());
+    if let Err(e) = load_scene_dump(lua, &scene) {
+        *snapshots_mut(lua)? = snapshots;
+        return Err(e);
+    }
+    snapshots.history.retain(|snapshot| snapshot.tick <= tick);
+    let snapshot = snapshots.find(tick)?;
+    *random_mut(lua)? = snapshot.random.clone();
+    *collisions_mut(lua)? = snapshot.collisions.clone();
+    *physics_mut(lua)? = snapshot.physics.clone();
+    let unsaved = snapshot.unsaved.clone();
+    snapshots.time = snapshot.time;
+    snapshots.tick = tick;
+    snapshots.viewing = None;
+    *snapshots_mut(lua)? = snapshots;
+    warn_left_out(lua, &format!("Resuming from tick {}", tick), &unsaved)
+}
+
+pub fn set_globals(lua: &Lua) -> Result<(), LuaError> {
+    lua.set_app_data(Snapshots::new());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logs::{logs_mut, Level};
+    use crate::lua_setup::match_message;
+    use crate::lua_types::LuaMessage;
+    use crate::runner::{headless_lua, LUA_DIR};
+    use crate::testing::Recorder;
+    use crate::tweens::tweens_mut;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    fn scene() -> Lua {
+        let lua = headless_lua(Arc::new(Recorder::new(Path::new(LUA_DIR)))).unwrap();
+        lua.load(
+            r#"currentScene:add_entity("door", { type = "rect", pos = { x = 0, y = 0 }, scripts = {
+                on_click = { string = [[ tween(self.pos, { x = 100 }, 10) ]] },
+            } })"#,
+        )
+        .exec()
+        .unwrap();
+        lua
+    }
+
+    fn tick(lua: &Lua, ticks: u64) {
+        for _ in 0..ticks {
+            match_message(lua, LuaMessage::Tick(1.0 / 60.0)).unwrap();
+        }
+    }
+
+    #[test]
+    fn snapshots_are_taken_every_few_ticks() {
+        let lua = scene();
+        tick(&lua, SNAPSHOT_EVERY * 3 + 1);
+        let timeline = snapshots_mut(&lua).unwrap().timeline();
+        assert_eq!(timeline.first, Some(SNAPSHOT_EVERY));
+        assert_eq!(timeline.last, Some(SNAPSHOT_EVERY * 3));
+
+        // ticks in between show the snapshot before them
+        view_snapshot(&lua, SNAPSHOT_EVERY * 2 + 1).unwrap();
+        assert_eq!(
+            snapshots_mut(&lua).unwrap().timeline().viewing,
+            Some(SNAPSHOT_EVERY * 2)
+        );
+        assert!(view_snapshot(&lua, SNAPSHOT_EVERY - 1).is_err());
+    }
+
+    #[test]
+    fn resuming_warns_about_what_it_leaves_out() {
+        let lua = scene();
+        tick(&lua, SNAPSHOT_EVERY);
+        lua.load(r#"currentScene:run_protected("door", "on_click", {})"#)
+            .exec()
+            .unwrap();
+        tick(&lua, SNAPSHOT_EVERY * 2);
+
+        // nothing was running yet
+        resume(&lua, Some(SNAPSHOT_EVERY)).unwrap();
+        assert!(logs_mut(&lua).unwrap().history().is_empty());
+        assert!(tweens_mut(&lua).unwrap().unsaved_tweens().is_empty());
+        tick(&lua, SNAPSHOT_EVERY);
+
+        lua.load(r#"currentScene:run_protected("door", "on_click", {})"#)
+            .exec()
+            .unwrap();
+        tick(&lua, SNAPSHOT_EVERY);
+        resume(&lua, Some(SNAPSHOT_EVERY * 3)).unwrap();
+        let logs = logs_mut(&lua).unwrap().history();
+        assert_eq!(logs.len(), 1);
+        assert!(matches!(logs[0].level, Level::Warn));
+        assert_eq!(
+            logs[0].message,
+            format!(
+                "Resuming from tick {} leaves out scripts that are waiting, timers that call \
+                 functions and tweens: door's on_click (tween)",
+                SNAPSHOT_EVERY * 3
+            )
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use crate::lua_setup::get_scene;
+use crate::lua_types::LuaError;
+use crate::script_runner::run_listener;
+use mlua::prelude::*;
+
+/// Frames per second for animations that don't say.
+const DEFAULT_FPS: f64 = 10.0;
+
+/// Move a sprite's animation along by `dt` seconds, returning its name if it just finished.
+///
+/// Animations are stored on the entity as `animation = { name = "walk", step = 1, time = 0 }`,
+/// so they pick up where they left off when the scene is saved & loaded.
+/// Setting `animation` to just a name starts that animation from the beginning.
+fn advance_sprite(lua: &Lua, entity: &LuaTable, dt: f64) -> LuaResult<Option<String>> {
+    let (name, state) = match entity.get::<_, LuaValue>("animation")? {
+        LuaValue::String(name) => {
+            let state = lua.create_table()?;
+            state.set("name", name.clone())?;
+            entity.set("animation", state.clone())?;
+            (name.to_str()?.to_string(), state)
+        }
+        LuaValue::Table(state) => (state.get::<_, String>("name")?, state),
+        _ => return Ok(None),
+    };
+
+    // a sprite playing an animation it doesn't have just doesn't animate
+    let Some(animation) = entity
+        .get::<_, Option<LuaTable>>("animations")?
+        .map(|animations| animations.get::<_, Option<LuaTable>>(name.as_str()))
+        .transpose()?
+        .flatten()
+    else {
+        return Ok(None);
+    };
+    let frames: Vec<i64> = animation
+        .get::<_, Option<Vec<i64>>>("frames")?
+        .unwrap_or_default();
+    if frames.is_empty() {
+        return Ok(None);
+    }
+    let fps = animation
+        .get::<_, Option<f64>>("fps")?
+        .unwrap_or(DEFAULT_FPS);
+    let looping = animation.get::<_, Option<bool>>("loop")?.unwrap_or(true);
+
+    // steps are numbered from 1, like the frames they point at
+    let mut step = (state.get::<_, Option<usize>>("step")?.unwrap_or(1).max(1) - 1) % frames.len();
+    let mut time = state.get::<_, Option<f64>>("time")?.unwrap_or(0.0) + dt;
+    let mut finished = false;
+    if fps > 0.0 {
+        while time >= 1.0 / fps {
+            time -= 1.0 / fps;
+            if step + 1 < frames.len() {
+                step += 1;
+            } else if looping {
+                step = 0;
+            } else {
+                finished = true;
+                break;
+            }
+        }
+    }
+
+    entity.set("frame", frames[step])?;
+    if finished {
+        entity.set("animation", LuaValue::Nil)?;
+        return Ok(Some(name));
+    }
+    state.set("step", step + 1)?;
+    state.set("time", time)?;
+    Ok(None)
+}
+
+/// Move every sprite's animation along, and run `on_animation_end` for those that finished.
+pub fn advance_sprites(lua: &Lua, dt: f64) -> Result<(), LuaError> {
+    let mut finished = Vec::new();
+    for pair in get_scene(lua)?
+        .get::<_, LuaTable>("entities")?
+        .pairs::<String, LuaTable>()
+    {
+        let (id, entity) = pair?;
+        if !matches!(entity.get::<_, Option<String>>("type"), Ok(Some(t)) if t == "sprite") {
+            continue;
+        }
+        // like a broken body, a broken animation just doesn't play
+        if let Ok(Some(animation)) = advance_sprite(lua, &entity, dt) {
+            finished.push((id, animation));
+        }
+    }
+
+    // scripts could add or remove entities, so they wait until every sprite has been moved along
+    finished.sort();
+    for (id, animation) in finished {
+        let data = lua.create_table()?;
+        data.set("animation", animation)?;
+        run_listener(lua, &id, "on_animation_end", LuaValue::Table(data))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{headless_lua, LUA_DIR};
+    use crate::testing::Recorder;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    /// A sprite on a sheet of 4 by 2 frames, walking along the bottom row & jumping once.
+    fn lua() -> Lua {
+        let lua = headless_lua(Arc::new(Recorder::new(Path::new(LUA_DIR)))).unwrap();
+        lua.load(
+            r#"
+            currentScene:add_entity("player", {
+                type = "sprite",
+                image = "player.png",
+                sheet = { columns = 4, rows = 2 },
+                frame = 1,
+                animations = {
+                    walk = { frames = { 5, 6, 7, 8 }, fps = 4 },
+                    jump = { frames = { 2, 3 }, fps = 4, loop = false },
+                },
+                animation = "walk",
+                scripts = {
+                    on_animation_end = { string = [[
+                        self.ended = (self.ended or 0) + 1
+                        self.last_ended = data.animation
+                    ]] },
+                },
+            })
+            "#,
+        )
+        .exec()
+        .unwrap();
+        lua
+    }
+
+    fn player<'lua, T: FromLuaMulti<'lua>>(lua: &'lua Lua, field: &str) -> T {
+        lua.load(format!("return currentScene.entities.player.{}", field))
+            .eval()
+            .unwrap()
+    }
+
+    #[test]
+    fn animations_pick_frames_from_the_sheet() {
+        let lua = &lua();
+        advance_sprites(lua, 0.0).unwrap();
+        // the first of the animation's frames, which is the start of the sheet's second row
+        assert_eq!(player::<i64>(lua, "frame"), 5);
+        assert_eq!(player::<String>(lua, "animation.name"), "walk");
+
+        lua.load(r#"currentScene.entities.player.animations.walk.frames = { 8, 1, 8 }"#)
+            .exec()
+            .unwrap();
+        advance_sprites(lua, 0.25).unwrap();
+        assert_eq!(player::<i64>(lua, "frame"), 1);
+    }
+
+    #[test]
+    fn frames_move_along_with_time() {
+        let lua = &lua();
+        advance_sprites(lua, 0.125).unwrap();
+        assert_eq!(player::<i64>(lua, "frame"), 5);
+        advance_sprites(lua, 0.125).unwrap();
+        assert_eq!(player::<i64>(lua, "frame"), 6);
+        // a long tick skips frames, rather than slowing the animation down
+        advance_sprites(lua, 0.5).unwrap();
+        assert_eq!(player::<i64>(lua, "frame"), 8);
+        assert_eq!(player::<usize>(lua, "animation.step"), 4);
+        assert_eq!(player::<f64>(lua, "animation.time"), 0.0);
+    }
+
+    #[test]
+    fn looping_animations_start_over() {
+        let lua = &lua();
+        advance_sprites(lua, 0.75).unwrap();
+        assert_eq!(player::<i64>(lua, "frame"), 8);
+        advance_sprites(lua, 0.25).unwrap();
+        assert_eq!(player::<i64>(lua, "frame"), 5);
+        assert_eq!(player::<Option<i64>>(lua, "ended"), None);
+    }
+
+    #[test]
+    fn other_animations_end_once() {
+        let lua = &lua();
+        lua.load(r#"currentScene.entities.player.animation = "jump""#)
+            .exec()
+            .unwrap();
+        advance_sprites(lua, 0.25).unwrap();
+        assert_eq!(player::<i64>(lua, "frame"), 3);
+        assert_eq!(player::<Option<i64>>(lua, "ended"), None);
+
+        advance_sprites(lua, 0.25).unwrap();
+        assert_eq!(player::<i64>(lua, "ended"), 1);
+        assert_eq!(player::<String>(lua, "last_ended"), "jump");
+        // it stays on its last frame once it's done
+        assert_eq!(player::<i64>(lua, "frame"), 3);
+        assert_eq!(player::<LuaValue>(lua, "animation"), LuaNil);
+
+        for _ in 0..10 {
+            advance_sprites(lua, 0.25).unwrap();
+        }
+        assert_eq!(player::<i64>(lua, "ended"), 1);
+        assert_eq!(player::<i64>(lua, "frame"), 3);
+    }
+}
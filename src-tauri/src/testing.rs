@@ -0,0 +1,386 @@
+use crate::host::Host;
+use crate::input::{input_mut, InputEvent};
+use crate::lua_setup::match_message;
+use crate::lua_types::{LuaError, LuaMessage};
+use crate::runner::headless_lua;
+use crate::script_errors::root_cause;
+use mlua::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{mpsc, Arc, Mutex};
+
+/// What test files are called, e.g. `door.test.lua`.
+const TEST_SUFFIX: &str = ".test.lua";
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TestResult {
+    pub file: String,
+    pub name: String,
+    /// Why it failed, starting with the line it failed on
+    pub failure: Option<String>,
+}
+
+/// Keeps everything the scene emits, for tests to check.
+pub(crate) struct Recorder {
+    lua_dir: PathBuf,
+    events: Mutex<Vec<(String, Value)>>,
+    /// Script errors that haven't failed a test yet
+    script_errors: Mutex<Vec<Value>>,
+}
+
+impl Recorder {
+    pub(crate) fn new(lua_dir: &Path) -> Self {
+        Recorder {
+            lua_dir: lua_dir.to_path_buf(),
+            events: Mutex::new(Vec::new()),
+            script_errors: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The data of every `name` event so far, oldest first.
+    pub(crate) fn events(&self, name: &str) -> Result<Vec<Value>, String> {
+        Ok(self
+            .events
+            .lock()
+            .map_err(|e| e.to_string())?
+            .iter()
+            .filter(|(event, _)| event == name)
+            .map(|(_, data)| data.clone())
+            .collect())
+    }
+}
+
+impl Host for Recorder {
+    fn emit(&self, event: &str, payload: Value) -> Result<(), String> {
+        // sent every tick, and the entities can be looked at directly
+        if event == "scene_update" {
+            return Ok(());
+        }
+        if event == "script_error" {
+            self.script_errors
+                .lock()
+                .map_err(|e| e.to_string())?
+                .push(payload.clone());
+        }
+        self.events
+            .lock()
+            .map_err(|e| e.to_string())?
+            .push((event.to_string(), payload));
+        Ok(())
+    }
+
+    fn emit_to(&self, _target: &str, event: &str, payload: Value) -> Result<(), String> {
+        self.emit(event, payload)
+    }
+
+    fn lua_dir(&self) -> Result<PathBuf, String> {
+        Ok(self.lua_dir.clone())
+    }
+
+    // test reports are printed on their own
+    fn show(&self, _line: &str) {}
+}
+
+/// Every test file in `paths`, looking through folders for files ending in `.test.lua`.
+fn find_test_files(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = fs::read_dir(path)
+                .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+                .unwrap_or_default();
+            entries.sort();
+            let (dirs, tests): (Vec<_>, Vec<_>) = entries.into_iter().partition(|e| e.is_dir());
+            files.extend(tests.into_iter().filter(|file| {
+                file.file_name()
+                    .is_some_and(|name| name.to_string_lossy().ends_with(TEST_SUFFIX))
+            }));
+            files.extend(find_test_files(&dirs));
+        } else {
+            files.push(path.clone());
+        }
+    }
+    files
+}
+
+/// Run the tests in each file & folder, with the runtime's Lua modules from `lua_dir`.
+pub fn run_scene_tests(paths: &[PathBuf], lua_dir: &Path) -> Vec<TestResult> {
+    find_test_files(paths)
+        .iter()
+        .flat_map(|file| run_test_file(file, lua_dir))
+        .collect()
+}
+
+/// Run each test in a file in a Lua state of its own, so nothing one test does can change another.
+pub fn run_test_file(path: &Path, lua_dir: &Path) -> Vec<TestResult> {
+    let file = path.display().to_string();
+    let failed = |failure: String| TestResult {
+        file: file.clone(),
+        name: String::new(),
+        failure: Some(failure),
+    };
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => return vec![failed(format!("Couldn't read test file: {}", e))],
+    };
+
+    let mut results = Vec::new();
+    let mut index = 0;
+    loop {
+        match run_test(path, &file, &source, lua_dir, index) {
+            Ok((count, Some(result))) => {
+                results.push(result);
+                index += 1;
+                if index >= count {
+                    break;
+                }
+            }
+            Ok((_, None)) => {
+                results.push(failed("No tests in file".to_string()));
+                break;
+            }
+            Err(e) => {
+                results.push(failed(e));
+                break;
+            }
+        }
+    }
+    results
+}
+
+/// Run a test file in a fresh scene, running only the test at `index`.
+/// Gives how many tests there are, and how the test went if there was one to run.
+fn run_test(
+    path: &Path,
+    file: &str,
+    source: &str,
+    lua_dir: &Path,
+    index: usize,
+) -> Result<(usize, Option<TestResult>), String> {
+    let recorder = Arc::new(Recorder::new(lua_dir));
+    let lua = headless_lua(recorder.clone()).map_err(|e| e.to_string())?;
+    let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+    let env = test_environment(&lua, recorder, dir).map_err(|e| e.to_string())?;
+
+    // `test` just counts the tests, keeping hold of the one to run
+    let count = Rc::new(RefCell::new(0));
+    let selected = Rc::new(RefCell::new(None));
+    let (test_count, test_selected) = (count.clone(), selected.clone());
+    let test = lua
+        .create_function(move |l: &Lua, (name, test): (String, LuaFunction)| {
+            let mut count = test_count.borrow_mut();
+            if *count == index {
+                *test_selected.borrow_mut() = Some((name, l.create_registry_value(test)?));
+            }
+            *count += 1;
+            Ok(())
+        })
+        .map_err(|e| e.to_string())?;
+    env.set("test", test).map_err(|e| e.to_string())?;
+
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    lua.load(source)
+        .set_name(format!("={}", name))
+        .set_environment(env)
+        .exec()
+        .map_err(|e| match e {
+            mlua::Error::SyntaxError { message, .. } => message,
+            e => root_cause(&e),
+        })?;
+
+    let count = *count.borrow();
+    let Some((name, key)) = selected.borrow_mut().take() else {
+        return Ok((count, None));
+    };
+    let failure = lua
+        .registry_value::<LuaFunction>(&key)
+        .and_then(|test| test.call::<_, ()>(()))
+        .err()
+        .map(|e| root_cause(&e));
+    Ok((
+        count,
+        Some(TestResult {
+            file: file.to_string(),
+            name,
+            failure,
+        }),
+    ))
+}
+
+/// Globals for test files: the functions in `testing.lua`, over what they need from the runtime.
+fn test_environment<'lua>(
+    lua: &'lua Lua,
+    recorder: Arc<Recorder>,
+    dir: PathBuf,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let harness = lua.create_table()?;
+
+    harness.set(
+        "load_scene",
+        lua.create_function(move |l: &Lua, path: String| {
+            let (tx, rx) = mpsc::channel();
+            let path = dir.join(path).to_string_lossy().to_string();
+            match_message(l, LuaMessage::LoadScene(path, tx))?;
+            rx.recv()
+                .map_err(|e| LuaError::CommunicationError(e.to_string()).into())
+        })?,
+    )?;
+
+    harness.set(
+        "tick",
+        lua.create_function(|l: &Lua, dt: f64| Ok(match_message(l, LuaMessage::Tick(dt))?))?,
+    )?;
+
+    harness.set(
+        "input",
+        lua.create_function(|l: &Lua, event: LuaValue| {
+            let event: InputEvent = l.from_value(event)?;
+            input_mut(l)?.push(event);
+            Ok(())
+        })?,
+    )?;
+
+    let events_recorder = recorder.clone();
+    harness.set(
+        "events",
+        lua.create_function(move |l: &Lua, name: String| {
+            let events = events_recorder
+                .events(&name)
+                .map_err(LuaError::CommunicationError)?;
+            l.to_value(&events)
+        })?,
+    )?;
+
+    let cleared_recorder = recorder.clone();
+    harness.set(
+        "clear_events",
+        lua.create_function(move |_: &Lua, ()| {
+            cleared_recorder
+                .events
+                .lock()
+                .map_err(|e| LuaError::CommunicationError(e.to_string()))?
+                .clear();
+            Ok(())
+        })?,
+    )?;
+
+    harness.set(
+        "take_script_errors",
+        lua.create_function(move |l: &Lua, ()| {
+            let errors: Vec<Value> = recorder
+                .script_errors
+                .lock()
+                .map_err(|e| LuaError::CommunicationError(e.to_string()))?
+                .drain(..)
+                .collect();
+            l.to_value(&errors)
+        })?,
+    )?;
+
+    let testing: LuaFunction = lua
+        .globals()
+        .get::<_, LuaFunction>("require")?
+        .call("testing")?;
+    Ok(testing.call(harness)?)
+}
+
+/// How each test went, and how many passed, for printing.
+pub fn report(results: &[TestResult]) -> String {
+    let mut text = String::new();
+    for result in results {
+        let name = match result.name.is_empty() {
+            true => result.file.clone(),
+            false => format!("{}: {}", result.file, result.name),
+        };
+        match &result.failure {
+            None => {
+                let _ = writeln!(text, "ok    {}", name);
+            }
+            Some(failure) => {
+                let _ = writeln!(text, "FAIL  {}", name);
+                for line in failure.lines() {
+                    let _ = writeln!(text, "      {}", line);
+                }
+            }
+        }
+    }
+    let failed = results.iter().filter(|r| r.failure.is_some()).count();
+    let _ = writeln!(
+        text,
+        "\n{} passed, {} failed",
+        results.len() - failed,
+        failed
+    );
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::LUA_DIR;
+
+    /// Run a test file with these contents, next to the door scene in `tests/scenes`.
+    fn run(name: &str, source: &str) -> Vec<TestResult> {
+        let dir = std::env::temp_dir().join(format!("stacks-testing-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let scenes = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("scenes");
+        fs::copy(scenes.join("door.lua"), dir.join("door.lua")).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, source).unwrap();
+        run_test_file(&path, Path::new(LUA_DIR))
+    }
+
+    fn failures(results: &[TestResult]) -> Vec<Option<&str>> {
+        results.iter().map(|r| r.failure.as_deref()).collect()
+    }
+
+    #[test]
+    fn failures_point_at_the_test_line() {
+        let results = run(
+            "lines.test.lua",
+            "scene(\"door.lua\")\n\
+             test(\"passes\", function() assert_eq(entity(\"door\").open, false) end)\n\
+             test(\"fails\", function()\n    click(\"door\")\n    assert_eq(entity(\"door\").open, false)\nend)\n",
+        );
+        assert_eq!(
+            failures(&results),
+            vec![None, Some("lines.test.lua:5: expected false, got true")]
+        );
+    }
+
+    #[test]
+    fn script_errors_fail_with_the_script_line() {
+        let results = run(
+            "scripts.test.lua",
+            "test(\"ticks\", function()\n\
+             add(\"bomb\", { type = \"rect\", scripts = { on_tick = \"local x = 1\\nerror('boom')\" } })\n\
+             tick(2)\nend)\n",
+        );
+        assert_eq!(
+            failures(&results),
+            vec![Some(
+                "scripts.test.lua:3: bomb's on_tick script failed on line 2: boom"
+            )]
+        );
+    }
+
+    #[test]
+    fn each_test_gets_its_own_scene() {
+        let results = run(
+            "isolated.test.lua",
+            "test(\"adds\", function() add(\"box\", { type = \"rect\" }) end)\n\
+             test(\"adds again\", function() add(\"box\", { type = \"rect\" }) end)\n",
+        );
+        assert_eq!(failures(&results), vec![None, None]);
+    }
+}
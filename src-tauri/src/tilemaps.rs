@@ -0,0 +1,199 @@
+use crate::geometry::{Shape, Vec2};
+use mlua::prelude::*;
+use std::collections::HashSet;
+
+/// Rectangles covering a tilemap's solid tiles, if it has opted in with a `collider`:
+/// `true` for every tile that isn't empty, or a table like `{ tiles = { 1, 2 } }` for just those tiles.
+///
+/// Solid tiles next to each other in a row share one rectangle, so things sliding along a floor
+/// don't catch on the edges between its tiles.
+pub fn tile_shapes(entity: &LuaTable) -> LuaResult<Vec<Shape>> {
+    let solid: Option<HashSet<i64>> = match entity.get::<_, LuaValue>("collider")? {
+        LuaValue::Boolean(true) => None,
+        LuaValue::Table(collider) => collider
+            .get::<_, Option<Vec<i64>>>("tiles")?
+            .map(|tiles| tiles.into_iter().collect()),
+        _ => return Ok(Vec::new()),
+    };
+    let is_solid =
+        |tile: i64| tile != 0 && solid.as_ref().is_none_or(|solid| solid.contains(&tile));
+
+    let columns: usize = entity.get("columns")?;
+    if columns == 0 {
+        return Ok(Vec::new());
+    }
+    let tile_size: LuaTable = entity.get("tile_size")?;
+    let tile_size = Vec2::new(tile_size.get("width")?, tile_size.get("height")?);
+    let pos: LuaTable = entity.get("pos")?;
+    let origin = Vec2::new(pos.get("x")?, pos.get("y")?);
+    let tiles: Vec<i64> = entity
+        .get::<_, Option<Vec<i64>>>("tiles")?
+        .unwrap_or_default();
+
+    let mut shapes = Vec::new();
+    for (row, tiles) in tiles.chunks(columns).enumerate() {
+        let mut column = 0;
+        while column < tiles.len() {
+            if !is_solid(tiles[column]) {
+                column += 1;
+                continue;
+            }
+            let start = column;
+            while column < tiles.len() && is_solid(tiles[column]) {
+                column += 1;
+            }
+            let half_size = Vec2::new((column - start) as f64 * tile_size.x, tile_size.y) * 0.5;
+            shapes.push(Shape::Rect {
+                center: origin
+                    + Vec2::new(start as f64 * tile_size.x, row as f64 * tile_size.y)
+                    + half_size,
+                half_size,
+                angle: 0.0,
+            });
+        }
+    }
+    Ok(shapes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{headless_lua, LUA_DIR};
+    use crate::testing::Recorder;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    /// A tilemap 4 tiles wide & 2 tall, at 100, 50 with 10 pixel tiles.
+    fn lua(tiles: &str) -> Lua {
+        let lua = headless_lua(Arc::new(Recorder::new(Path::new(LUA_DIR)))).unwrap();
+        lua.load(format!(
+            r#"
+            currentScene:add_entity("map", {{
+                type = "tilemap",
+                pos = {{ x = 100, y = 50 }},
+                tile_size = {{ width = 10, height = 10 }},
+                columns = 4,
+                rows = 2,
+                tiles = {},
+            }})
+            map = currentScene.entities.map
+            "#,
+            tiles
+        ))
+        .exec()
+        .unwrap();
+        lua
+    }
+
+    /// Each rectangle's corner & size, which are easier to check than the middle.
+    fn rects(lua: &Lua, collider: &str) -> Vec<(f64, f64, f64, f64)> {
+        lua.load(format!("map.collider = {}", collider))
+            .exec()
+            .unwrap();
+        let map: LuaTable = lua.globals().get("map").unwrap();
+        tile_shapes(&map)
+            .unwrap()
+            .into_iter()
+            .map(|shape| match shape {
+                Shape::Rect {
+                    center, half_size, ..
+                } => {
+                    let corner = center - half_size;
+                    (corner.x, corner.y, half_size.x * 2.0, half_size.y * 2.0)
+                }
+                Shape::Circle { .. } => panic!("tiles are rectangles"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tiles_are_saved_as_runs() {
+        let lua = lua("{ 0, 0, 0, 0, 3, 3, 1, 12 }");
+        let (saved, tiles): (String, Vec<i64>) = lua
+            .load(
+                r#"
+                local saved = map:serializable().tiles
+                currentScene:add_entity("copy", { type = "tilemap", columns = 4, rows = 2, tiles = saved })
+                return saved, currentScene.entities.copy.tiles
+                "#,
+            )
+            .eval()
+            .unwrap();
+        assert_eq!(saved, "0*4,3*2,1,12");
+        assert_eq!(tiles, [0, 0, 0, 0, 3, 3, 1, 12]);
+
+        // spaces & line breaks are fine, for anyone writing them by hand
+        let tiles: Vec<i64> = lua
+            .load(r#"map:update({ tiles = "1*2, 0\n2" }); return map.tiles"#)
+            .eval()
+            .unwrap();
+        assert_eq!(tiles, [1, 1, 0, 2]);
+        let empty: String = lua
+            .load(r#"map.tiles = {}; return map:serializable().tiles"#)
+            .eval()
+            .unwrap();
+        assert_eq!(empty, "");
+
+        let error = lua
+            .load(r#"map:update({ tiles = "1,grass" })"#)
+            .exec()
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("\"grass\" isn't a tile"), "{}", error);
+    }
+
+    #[test]
+    fn tiles_outside_the_map() {
+        let lua = lua("nil");
+        let tiles: Vec<Option<i64>> = lua
+            .load("return { map:get_tile(1, 1), map:get_tile(0, 1) or -1, map:get_tile(5, 1) or -1, map:get_tile(1, 3) or -1 }")
+            .eval()
+            .unwrap();
+        assert_eq!(tiles, [Some(0), Some(-1), Some(-1), Some(-1)]);
+
+        // setting a tile past the end fills in the ones before it
+        let tiles: Vec<i64> = lua
+            .load("map:set_tile(2, 2, 5); return map.tiles")
+            .eval()
+            .unwrap();
+        assert_eq!(tiles, [0, 0, 0, 0, 0, 5]);
+        let tile: i64 = lua.load("return map:get_tile(2, 2)").eval().unwrap();
+        assert_eq!(tile, 5);
+
+        for (set, error) in [
+            ("map:set_tile(5, 1, 1)", "Tile 5, 1 is outside the tilemap."),
+            ("map:set_tile(1, 0, 1)", "Tile 1, 0 is outside the tilemap."),
+            ("map:set_tile(1, 1, 1.5)", "Tiles must be whole numbers."),
+        ] {
+            let message = lua.load(set).exec().unwrap_err().to_string();
+            assert!(message.contains(error), "{}: {}", set, message);
+        }
+    }
+
+    #[test]
+    fn runs_of_solid_tiles_share_a_rectangle() {
+        let lua = lua("{ 1, 1, 0, 1, 0, 2, 2, 2 }");
+        assert_eq!(rects(&lua, "nil"), []);
+        assert_eq!(
+            rects(&lua, "true"),
+            [
+                (100.0, 50.0, 20.0, 10.0),
+                (130.0, 50.0, 10.0, 10.0),
+                (110.0, 60.0, 30.0, 10.0),
+            ]
+        );
+        // runs are only of tiles that are solid, not of tiles that are the same
+        assert_eq!(
+            rects(&lua, "{ tiles = { 2 } }"),
+            [(110.0, 60.0, 30.0, 10.0)]
+        );
+        assert_eq!(rects(&lua, "{ tiles = { 3 } }"), []);
+
+        // a short last row only has the tiles that are there, and runs don't wrap onto the next row
+        lua.load("map.tiles = { 0, 0, 1, 1, 1 }").exec().unwrap();
+        assert_eq!(
+            rects(&lua, "true"),
+            [(120.0, 50.0, 20.0, 10.0), (100.0, 60.0, 10.0, 10.0)]
+        );
+    }
+}
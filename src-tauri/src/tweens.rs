@@ -0,0 +1,603 @@
+use crate::logs::log_fault;
+use crate::lua_setup::get_entity;
+use crate::lua_types::LuaError;
+use crate::scheduler::scheduler_mut;
+use crate::script_runner::{current_script, resume_script, COROUTINE_BODY};
+use mlua::prelude::*;
+use mlua::AppDataRefMut;
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, Debug)]
+pub enum EaseMode {
+    In,
+    Out,
+    InOut,
+}
+
+/// An easing curve, mapping progress through a tween (0 to 1) to progress along its path.
+#[derive(Clone, Copy, Debug)]
+pub enum Easing {
+    Linear,
+    Quad(EaseMode),
+    Cubic(EaseMode),
+    Sine(EaseMode),
+    Back(EaseMode),
+    Elastic(EaseMode),
+    Bounce(EaseMode),
+}
+
+impl Easing {
+    /// Parse an easing name like `linear`, `ease_out_quad` or `ease_in_out_sine`.
+    pub fn from_name(name: &str) -> Option<Easing> {
+        if name == "linear" {
+            return Some(Easing::Linear);
+        }
+        let rest = name.strip_prefix("ease_")?;
+        let (mode, curve) = if let Some(curve) = rest.strip_prefix("in_out_") {
+            (EaseMode::InOut, curve)
+        } else if let Some(curve) = rest.strip_prefix("in_") {
+            (EaseMode::In, curve)
+        } else {
+            (EaseMode::Out, rest.strip_prefix("out_")?)
+        };
+
+        match curve {
+            "quad" => Some(Easing::Quad(mode)),
+            "cubic" => Some(Easing::Cubic(mode)),
+            "sine" => Some(Easing::Sine(mode)),
+            "back" => Some(Easing::Back(mode)),
+            "elastic" => Some(Easing::Elastic(mode)),
+            "bounce" => Some(Easing::Bounce(mode)),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, t: f64) -> f64 {
+        let (ease_in, mode): (fn(f64) -> f64, EaseMode) = match *self {
+            Easing::Linear => return t,
+            Easing::Quad(mode) => (|t| t * t, mode),
+            Easing::Cubic(mode) => (|t| t * t * t, mode),
+            Easing::Sine(mode) => (|t| 1.0 - (t * PI / 2.0).cos(), mode),
+            Easing::Back(mode) => (|t| 2.70158 * t * t * t - 1.70158 * t * t, mode),
+            Easing::Elastic(mode) => (
+                |t| {
+                    if t <= 0.0 || t >= 1.0 {
+                        return t;
+                    }
+                    -(2f64.powf(10.0 * t - 10.0)) * ((t * 10.0 - 10.75) * (2.0 * PI / 3.0)).sin()
+                },
+                mode,
+            ),
+            Easing::Bounce(mode) => (|t| 1.0 - bounce_out(1.0 - t), mode),
+        };
+
+        // every curve is defined easing in, and mirrored for the other modes
+        match mode {
+            EaseMode::In => ease_in(t),
+            EaseMode::Out => 1.0 - ease_in(1.0 - t),
+            EaseMode::InOut if t < 0.5 => ease_in(2.0 * t) / 2.0,
+            EaseMode::InOut => 1.0 - ease_in(2.0 - 2.0 * t) / 2.0,
+        }
+    }
+}
+
+fn bounce_out(t: f64) -> f64 {
+    let (n, d) = (7.5625, 2.75);
+    if t < 1.0 / d {
+        n * t * t
+    } else if t < 2.0 / d {
+        let t = t - 1.5 / d;
+        n * t * t + 0.75
+    } else if t < 2.5 / d {
+        let t = t - 2.25 / d;
+        n * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / d;
+        n * t * t + 0.984375
+    }
+}
+
+/// One step of a sequence.
+enum Step {
+    /// Animate the numbers in `target` towards those in `props`
+    Tween {
+        target: LuaRegistryKey,
+        props: LuaRegistryKey,
+        duration: f64,
+        easing: Easing,
+        on_complete: Option<LuaRegistryKey>,
+    },
+    /// Do nothing for a while
+    Pause(f64),
+    /// Call a function, then carry on straight away
+    Call(LuaRegistryKey),
+}
+
+/// A single number being animated, in `table[key]`.
+struct Field {
+    table: LuaRegistryKey,
+    key: String,
+    from: f64,
+    to: f64,
+}
+
+/// The step a tween is part way through.
+struct Active {
+    fields: Vec<Field>,
+    duration: f64,
+    elapsed: f64,
+    easing: Easing,
+    on_complete: Option<LuaRegistryKey>,
+}
+
+pub struct Tween {
+    id: u64,
+    entity: String,
+    script: String,
+    steps: VecDeque<Step>,
+    active: Option<Active>,
+    on_complete: Option<LuaRegistryKey>,
+}
+
+pub struct Tweens {
+    tweens: Vec<Tween>,
+    /// Tween being advanced, which is out of the list while its callbacks run
+    running: Option<u64>,
+}
+
+impl Tweens {
+    pub fn new() -> Self {
+        Tweens {
+            tweens: Vec::new(),
+            running: None,
+        }
+    }
+
+    pub fn cancel(&mut self, id: u64) -> bool {
+        if self.running == Some(id) {
+            self.running = None;
+            return true;
+        }
+        let before = self.tweens.len();
+        self.tweens.retain(|tween| tween.id != id);
+        self.tweens.len() != before
+    }
+
+    pub fn is_running(&self, id: u64) -> bool {
+        self.running == Some(id) || self.tweens.iter().any(|tween| tween.id == id)
+    }
+
+    /// Every tween, described like `Scheduler::unsaved_tasks`, since tweens aren't saved with scenes.
+    pub fn unsaved_tweens(&self) -> Vec<String> {
+        self.tweens
+            .iter()
+            .map(|tween| format!("{}'s {} (tween)", tween.entity, tween.script))
+            .collect()
+    }
+
+    pub fn cancel_entity(&mut self, entity: &str) {
+        self.tweens.retain(|tween| tween.entity != entity);
+    }
+
+    pub fn rename_entity(&mut self, from: &str, to: &str) {
+        self.tweens
+            .iter_mut()
+            .filter(|tween| tween.entity == from)
+            .for_each(|tween| tween.entity = to.to_string());
+    }
+
+    pub fn clear(&mut self) {
+        self.tweens.clear();
+    }
+}
+
+pub fn tweens_mut(lua: &Lua) -> Result<AppDataRefMut<'_, Tweens>, LuaError> {
+    lua.app_data_mut::<Tweens>()
+        .ok_or_else(|| LuaError::InitializationError("Tweens missing".to_string()))
+}
+
+/// Move every tween along by `dt` seconds.
+pub fn advance_tweens(lua: &Lua, dt: f64) -> Result<(), LuaError> {
+    let ids: Vec<u64> = tweens_mut(lua)?
+        .tweens
+        .iter()
+        .map(|tween| tween.id)
+        .collect();
+    for id in ids {
+        // take the tween out while it runs, since its callbacks can start & cancel tweens
+        let tween = {
+            let mut tweens = tweens_mut(lua)?;
+            let Some(index) = tweens.tweens.iter().position(|tween| tween.id == id) else {
+                continue;
+            };
+            tweens.running = Some(id);
+            tweens.tweens.remove(index)
+        };
+
+        let result = advance_tween(lua, tween, dt);
+        let mut tweens = tweens_mut(lua)?;
+        let still_running = tweens.running.take() == Some(id);
+        match result {
+            Ok(Some(tween)) if still_running => tweens.tweens.push(tween),
+            Ok(_) => (),
+            Err(e) => log_fault(lua, "tweens", &e),
+        }
+    }
+    Ok(())
+}
+
+/// Advance one tween, returning it if it isn't finished.
+fn advance_tween(lua: &Lua, mut tween: Tween, dt: f64) -> Result<Option<Tween>, LuaError> {
+    if get_entity(lua, &tween.entity).is_err() {
+        return Ok(None);
+    }
+
+    let mut remaining = dt;
+    loop {
+        let mut active = match tween.active.take() {
+            Some(active) => active,
+            None => match tween.steps.pop_front() {
+                Some(Step::Tween {
+                    target,
+                    props,
+                    duration,
+                    easing,
+                    on_complete,
+                }) => Active {
+                    fields: start_fields(
+                        lua,
+                        lua.registry_value(&target)?,
+                        lua.registry_value(&props)?,
+                    )?,
+                    duration,
+                    elapsed: 0.0,
+                    easing,
+                    on_complete,
+                },
+                Some(Step::Pause(duration)) => Active {
+                    fields: Vec::new(),
+                    duration,
+                    elapsed: 0.0,
+                    easing: Easing::Linear,
+                    on_complete: None,
+                },
+                Some(Step::Call(callback)) => {
+                    if !run_callback(lua, &tween, &callback)? {
+                        return Ok(None);
+                    }
+                    continue;
+                }
+                None => {
+                    if let Some(callback) = &tween.on_complete {
+                        run_callback(lua, &tween, callback)?;
+                    }
+                    return Ok(None);
+                }
+            },
+        };
+
+        active.elapsed += remaining;
+        let progress = match active.duration > 0.0 {
+            true => (active.elapsed / active.duration).min(1.0),
+            false => 1.0,
+        };
+        let eased = active.easing.apply(progress);
+        for field in &active.fields {
+            lua.registry_value::<LuaTable>(&field.table)?.set(
+                field.key.as_str(),
+                field.from + (field.to - field.from) * eased,
+            )?;
+        }
+
+        if progress < 1.0 {
+            tween.active = Some(active);
+            return Ok(Some(tween));
+        }
+        // carry the leftover time into the next step, so sequences don't drift
+        remaining = (active.elapsed - active.duration).max(0.0);
+        if let Some(callback) = &active.on_complete {
+            if !run_callback(lua, &tween, callback)? {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// The fields a step animates, starting from their current values.
+/// Nested tables in `props` animate the matching nested tables in `target`.
+fn start_fields(lua: &Lua, target: LuaTable, props: LuaTable) -> Result<Vec<Field>, LuaError> {
+    let mut fields = Vec::new();
+    for pair in props.pairs::<String, LuaValue>() {
+        let (key, value) = pair?;
+        match value {
+            LuaValue::Table(nested) => {
+                fields.extend(start_fields(lua, target.get(key.as_str())?, nested)?)
+            }
+            value => {
+                let to: f64 = lua.unpack(value)?;
+                fields.push(Field {
+                    table: lua.create_registry_value(target.clone())?,
+                    // if the field stopped being a number in the meantime, jump straight there
+                    from: target.get::<_, Option<f64>>(key.as_str())?.unwrap_or(to),
+                    key,
+                    to,
+                });
+            }
+        }
+    }
+    Ok(fields)
+}
+
+/// Run one of a tween's callbacks, returning whether the tween is still running afterwards.
+fn run_callback(lua: &Lua, tween: &Tween, callback: &LuaRegistryKey) -> Result<bool, LuaError> {
+    let thread = lua.create_thread(lua.named_registry_value::<LuaFunction>(COROUTINE_BODY)?)?;
+    resume_script(
+        lua,
+        &tween.entity,
+        &tween.script,
+        thread,
+        lua.registry_value::<LuaFunction>(callback)?,
+    )?;
+    Ok(tweens_mut(lua)?.running == Some(tween.id))
+}
+
+/// Check that every value in `props` is a number matching a number in `target`,
+/// so mistakes are raised where the tween is created rather than when it starts.
+fn check_props(target: &LuaTable, props: &LuaTable) -> LuaResult<()> {
+    for pair in props.clone().pairs::<LuaValue, LuaValue>() {
+        let (key, value) = pair?;
+        let LuaValue::String(name) = &key else {
+            return Err(LuaError::FormatError(
+                "Tween properties must have string keys.".to_string(),
+            )
+            .into());
+        };
+        let name = name.to_str()?;
+        match (value, target.get::<_, LuaValue>(name)?) {
+            (LuaValue::Table(nested), LuaValue::Table(nested_target)) => {
+                check_props(&nested_target, &nested)?
+            }
+            (
+                LuaValue::Integer(_) | LuaValue::Number(_),
+                LuaValue::Integer(_) | LuaValue::Number(_),
+            ) => (),
+            (LuaValue::Integer(_) | LuaValue::Number(_), current) => {
+                return Err(LuaError::FormatError(format!(
+                    "Can't tween \"{}\", which is {} rather than a number.",
+                    name,
+                    current.type_name()
+                ))
+                .into())
+            }
+            (LuaValue::Table(_), current) => {
+                return Err(LuaError::FormatError(format!(
+                    "Can't tween into \"{}\", which is {} rather than a table.",
+                    name,
+                    current.type_name()
+                ))
+                .into())
+            }
+            (value, _) => {
+                return Err(LuaError::FormatError(format!(
+                    "Tween target for \"{}\" must be a number, not {}.",
+                    name,
+                    value.type_name()
+                ))
+                .into())
+            }
+        }
+    }
+    Ok(())
+}
+
+fn tween_step(
+    lua: &Lua,
+    target: LuaTable,
+    props: LuaTable,
+    duration: f64,
+    easing: Option<String>,
+    on_complete: Option<LuaFunction>,
+) -> LuaResult<Step> {
+    check_props(&target, &props)?;
+    let easing = match easing {
+        Some(name) => Easing::from_name(&name).ok_or_else(|| {
+            LuaError::FormatError(format!(
+                "Unknown easing \"{}\". Try \"linear\", or e.g. \"ease_out_quad\".",
+                name
+            ))
+        })?,
+        None => Easing::Linear,
+    };
+
+    Ok(Step::Tween {
+        target: lua.create_registry_value(target)?,
+        props: lua.create_registry_value(props)?,
+        duration: duration.max(0.0),
+        easing,
+        on_complete: on_complete
+            .map(|f| lua.create_registry_value(f))
+            .transpose()?,
+    })
+}
+
+fn start_tween(
+    lua: &Lua,
+    steps: VecDeque<Step>,
+    on_complete: Option<LuaFunction>,
+) -> LuaResult<u64> {
+    let (entity, script) = current_script(lua).ok_or_else(|| {
+        LuaError::FormatError("Tweens can only be started from an entity's script".to_string())
+    })?;
+    let on_complete = on_complete
+        .map(|f| lua.create_registry_value(f))
+        .transpose()?;
+
+    // IDs are shared with timers, so `cancel` works on both
+    let id = scheduler_mut(lua)?.new_id();
+    tweens_mut(lua)?.tweens.push(Tween {
+        id,
+        entity,
+        script,
+        steps,
+        active: None,
+        on_complete,
+    });
+    Ok(id)
+}
+
+pub fn set_globals(lua: &Lua) -> Result<(), LuaError> {
+    lua.set_app_data(Tweens::new());
+
+    lua.globals()
+        .set(
+            "tween",
+            lua.create_function(
+                |l: &Lua,
+                 (target, props, duration, easing, on_complete): (
+                    LuaTable,
+                    LuaTable,
+                    f64,
+                    Option<String>,
+                    Option<LuaFunction>,
+                )| {
+                    let step = tween_step(l, target, props, duration, easing, None)?;
+                    start_tween(l, VecDeque::from([step]), on_complete)
+                },
+            )?,
+        )
+        .map_err(|e| LuaError::InitializationError(format!("Failed to set tween: {}", e)))?;
+
+    // steps are `{target, props, duration, easing, on_complete}`, a number of seconds to pause, or a function to call
+    lua.globals()
+        .set(
+            "sequence",
+            lua.create_function(
+                |l: &Lua, (steps, on_complete): (LuaTable, Option<LuaFunction>)| {
+                    let steps = steps
+                        .sequence_values::<LuaValue>()
+                        .map(|step| match step? {
+                            LuaValue::Integer(seconds) => {
+                                Ok(Step::Pause((seconds as f64).max(0.0)))
+                            }
+                            LuaValue::Number(seconds) => Ok(Step::Pause(seconds.max(0.0))),
+                            LuaValue::Function(f) => Ok(Step::Call(l.create_registry_value(f)?)),
+                            LuaValue::Table(step) => {
+                                let (target, props, duration, easing, on_complete) = (
+                                    step.get(1)?,
+                                    step.get(2)?,
+                                    step.get(3)?,
+                                    step.get(4)?,
+                                    step.get(5)?,
+                                );
+                                tween_step(l, target, props, duration, easing, on_complete)
+                            }
+                            other => Err(LuaError::FormatError(format!(
+                                "Sequence steps must be tweens, pauses or functions, not {}.",
+                                other.type_name()
+                            ))
+                            .into()),
+                        })
+                        .collect::<LuaResult<VecDeque<Step>>>()?;
+                    start_tween(l, steps, on_complete)
+                },
+            )?,
+        )
+        .map_err(|e| LuaError::InitializationError(format!("Failed to set sequence: {}", e)))?;
+
+    lua.globals()
+        .set(
+            "tween_running",
+            lua.create_function(|l: &Lua, id: u64| Ok(tweens_mut(l)?.is_running(id)))?,
+        )
+        .map_err(|e| {
+            LuaError::InitializationError(format!("Failed to set tween_running: {}", e))
+        })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{headless_lua, LUA_DIR};
+    use crate::testing::Recorder;
+    use serde_json::json;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    #[test]
+    fn easings_start_at_0_and_end_at_1() {
+        let mut names = vec!["linear".to_string()];
+        for mode in ["in_", "out_", "in_out_"] {
+            for curve in ["quad", "cubic", "sine", "back", "elastic", "bounce"] {
+                names.push(format!("ease_{}{}", mode, curve));
+            }
+        }
+        for name in names {
+            let easing = Easing::from_name(&name).unwrap();
+            assert!(easing.apply(0.0).abs() < 1e-9, "{} starts off 0", name);
+            assert!(
+                (easing.apply(1.0) - 1.0).abs() < 1e-9,
+                "{} ends off 1",
+                name
+            );
+        }
+        assert!(Easing::from_name("ease_in_wobble").is_none());
+        assert!(Easing::from_name("ease_quad").is_none());
+    }
+
+    #[test]
+    fn cancelling_a_sequence_stops_it_mid_step() {
+        let recorder = Arc::new(Recorder::new(Path::new(LUA_DIR)));
+        let lua = headless_lua(recorder.clone()).unwrap();
+        lua.load(
+            r#"currentScene:add_entity("box", { type = "rect", scripts = {
+                on_click = { string = [[
+                    self.slide = { x = 0 }
+                    self.sequence = sequence({
+                        { self.slide, { x = 10 }, 1 },
+                        function() emit("called", {}) end,
+                        { self.slide, { x = 20 }, 1 },
+                    }, function() emit("finished", {}) end)
+                ]] },
+                on_stop = { string = [[
+                    emit("stopped", {
+                        before = tween_running(self.sequence),
+                        cancelled = cancel(self.sequence),
+                        after = tween_running(self.sequence),
+                    })
+                ]] },
+                on_check = { string = [[ emit("x", { x = self.slide.x }) ]] },
+            } })
+            currentScene:run_protected("box", "on_click", {})"#,
+        )
+        .exec()
+        .unwrap();
+        let run = |script: &str| {
+            lua.load(format!(
+                r#"currentScene:run_protected("box", "{}", {{}})"#,
+                script
+            ))
+            .exec()
+            .unwrap()
+        };
+
+        advance_tweens(&lua, 0.5).unwrap();
+        run("on_stop");
+        run("on_check");
+        advance_tweens(&lua, 2.0).unwrap();
+        run("on_check");
+
+        assert_eq!(
+            recorder.events("stopped").unwrap(),
+            vec![json!({ "before": true, "cancelled": true, "after": false })]
+        );
+        assert_eq!(
+            recorder.events("x").unwrap(),
+            vec![json!({ "x": 5.0 }), json!({ "x": 5.0 })]
+        );
+        assert!(recorder.events("called").unwrap().is_empty());
+        assert!(recorder.events("finished").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+-- One entity of every type, with the properties each one can have, and everything saved alongside them
+{
+    version = 2,
+    entities = {
+        box = {
+            type = "rect",
+            pos = { x = 100, y = 80 },
+            size = { width = 200, height = 100 },
+            color = "#ff0000",
+            rotation = 45,
+            layer = 2,
+            selectable = true,
+            collider = true,
+            body = { velocity = { x = 0, y = 0 }, gravity = 1, bounce = 0.5 },
+            scripts = { flash = { string = [[self.color = data.times > 1 and "#ffffff" or "#ff0000"]] } },
+        },
+        title = {
+            type = "text",
+            pos = { x = 100, y = 20 },
+            content = "Hello world!",
+            font_size = 32,
+        },
+        circle = {
+            type = "svg",
+            pos = { x = 600, y = 200 },
+            size = { width = 100, height = 100 },
+            content = [[<circle cx="50" cy="50" r="50" fill="olive" />]],
+        },
+        hero = {
+            type = "sprite",
+            pos = { x = 0, y = 400 },
+            size = { width = 64, height = 64 },
+            image = "images/hero.png",
+            sheet = { columns = 4, rows = 2 },
+            frame = 3,
+            animations = {
+                walk = { frames = { 1, 2, 3, 4 }, fps = 8 },
+                jump = { frames = { 5, 6, 7 }, fps = 12, loop = false },
+            },
+            animation = { name = "walk", step = 3, time = 0.0625 },
+            flip_x = true,
+            flip_y = false,
+            scripts = { blink = { string = "self.hidden = not self.hidden" } },
+        },
+        level = {
+            type = "tilemap",
+            pos = { x = 0, y = 0 },
+            tileset = "images/tiles.png",
+            sheet = { columns = 8, rows = 8 },
+            tile_size = { width = 32, height = 32 },
+            columns = 10,
+            rows = 3,
+            tiles = "0*20,1*4,2,3,1*4",
+        },
+        name_input = {
+            type = "text_input",
+            pos = { x = 100, y = 600 },
+            size = { width = 200, height = 50 },
+            content = "Ada",
+            placeholder = "Your name",
+            font_size = 16,
+            color = "#dddddd",
+            disabled = false,
+            scripts = {
+                on_change = { string = [[self.content = data.text]] },
+                on_submit = { string = [[broadcast("named", { name = data.text })]] },
+            },
+        },
+    },
+    timers = {
+        { entity = "box", script = "on_click", callback = "flash", data = "{times = 2}", remaining = 0.75 },
+        { entity = "hero", script = "on_tick", callback = "blink", remaining = 0.25, interval = 0.5 },
+    },
+    camera = {
+        x = 640,
+        y = 360,
+        zoom = 2,
+        rotation = 15,
+        follow = "hero",
+        bounds = { x = 0, y = 0, width = 2560, height = 720 },
+    },
+    canvas = { width = 1280, height = 720 },
+    seed = 12345,
+}
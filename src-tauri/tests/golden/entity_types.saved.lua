@@ -0,0 +1 @@
+do local _={camera={bounds={height=720,width=2560,x=0,y=0},follow="hero",rotation=15,x=640,y=360,zoom=2},canvas={height=720,width=1280},entities={box={body={bounce=0.5,gravity=1,velocity={x=0,y=0}},collider=true,color="#ff0000",layer=2,pos={x=100,y=80},rotation=45,scripts={flash={string=[[self.color = data.times > 1 and "#ffffff" or "#ff0000"]]}},selectable=true,size={height=100,width=200},type="rect"},circle={content=[[<circle cx="50" cy="50" r="50" fill="olive" />]],pos={x=600,y=200},size={height=100,width=100},type="svg"},hero={animation={name="walk",step=3,time=0.0625},animations={jump={fps=12,frames={5,6,7},loop=false},walk={fps=8,frames={1,2,3,4}}},flip_x=true,flip_y=false,frame=3,image="images/hero.png",pos={x=0,y=400},scripts={blink={string="self.hidden = not self.hidden"}},sheet={columns=4,rows=2},size={height=64,width=64},type="sprite"},level={columns=10,pos={x=0,y=0},rows=3,sheet={columns=8,rows=8},tile_size={height=32,width=32},tiles="0*20,1*4,2,3,1*4",tileset="images/tiles.png",type="tilemap"},name_input={color="#dddddd",content="Ada",disabled=false,font_size=16,placeholder="Your name",pos={x=100,y=600},scripts={on_change={string="self.content = data.text"},on_submit={string=[[broadcast("named", { name = data.text })]]}},size={height=50,width=200},type="text_input"},title={content="Hello world!",font_size=32,pos={x=100,y=20},type="text"}},gravity={x=0,y=980},seed=12345,timers={{callback="flash",data="{times = 2}",entity="box",remaining=0.75,script="on_click"},{callback="blink",entity="hero",interval=0.5,remaining=0.25,script="on_tick"}},version=2};return _;end
\ No newline at end of file
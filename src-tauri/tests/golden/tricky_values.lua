@@ -0,0 +1,66 @@
+-- Values that are easy to get wrong when saving, or when showing an entity in the inspector
+{
+    version = 2,
+    entities = {
+        numbers = {
+            type = "rect",
+            pos = { x = -12.5, y = 0.1 },
+            size = { width = 1e-7, height = 123456789012 },
+            color = "#000000",
+            counts = { 0, -1, 2.25, 1 / 3 },
+        },
+        nested = {
+            type = "rect",
+            pos = { x = 0, y = 0 },
+            size = { width = 10, height = 10 },
+            color = "#00ff00",
+            inventory = {
+                items = {
+                    { name = "Sword", stats = { damage = { min = 1, max = 6 }, tags = { "sharp", "heavy" } } },
+                    { name = "Rope", stats = {} },
+                },
+                gold = 0,
+                open = false,
+            },
+            empty = {},
+            ["has spaces"] = "quoted key",
+            ["end"] = "keyword key",
+        },
+        strings = {
+            type = "text",
+            pos = { x = 0, y = 0 },
+            content = "line one\n  indented line two\n\ttabbed line three\n",
+            quotes = [[both "double" and 'single' quotes]],
+            brackets = "a ]] in the middle, and ]=] too",
+            leading_newline = "\nstarts on the second line",
+            trailing_spaces = "spaces after   ",
+            unicode = "héllo wörld ✓ 日本語",
+            backslashes = [[C:\games\stacks\]],
+            blank = "",
+            windows_lines = "first\r\nsecond\r\n",
+        },
+        scripted = {
+            type = "rect",
+            pos = { x = 0, y = 0 },
+            size = { width = 10, height = 10 },
+            color = "#0000ff",
+            scripts = {
+                on_tick = {
+                    string = [==[
+-- a script with a comment first
+if self.pos.x > 100 then
+    self.pos.x = 0
+    print("wrapped around: " .. tostring(self.pos.x))
+end
+local text = [[long string with ]] .. "\"quotes\""
+]==],
+                },
+                on_click = { string = "  self.color = '#ffffff'" },
+            },
+        },
+    },
+    timers = {},
+    camera = { zoom = 1, rotation = 0 },
+    canvas = { width = 1280, height = 720 },
+    seed = 1,
+}
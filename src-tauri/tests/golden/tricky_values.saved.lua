@@ -0,0 +1,12 @@
+do local _={camera={rotation=0,zoom=1},canvas={height=720,width=1280},entities={nested={color="#00ff00",empty={},["end"]="keyword key",["has spaces"]="quoted key",inventory={gold=0,items={{name="Sword",stats={damage={max=6,min=1},tags={"sharp","heavy"}}},{name="Rope",stats={}}},open=false},pos={x=0,y=0},size={height=10,width=10},type="rect"},numbers={color="#000000",counts={0,-1,2.25,0.33333333333333331},pos={x=-12.5,y=0.10000000000000001},size={height=123456789012,width=9.9999999999999995e-08},type="rect"},scripted={color="#0000ff",pos={x=0,y=0},scripts={on_click={string=[[  self.color = '#ffffff']]},on_tick={string=[=[-- a script with a comment first
+if self.pos.x > 100 then
+    self.pos.x = 0
+    print("wrapped around: " .. tostring(self.pos.x))
+end
+local text = [[long string with ]] .. "\"quotes\""
+]=]}},size={height=10,width=10},type="rect"},strings={backslashes="C:\\games\\stacks\\",blank="",brackets="a ]] in the middle, and ]=] too",content=[[line one
+  indented line two
+	tabbed line three
+]],leading_newline=[[
+
+starts on the second line]],pos={x=0,y=0},quotes=[[both "double" and 'single' quotes]],trailing_spaces="spaces after   ",type="text",unicode="héllo wörld ✓ 日本語",windows_lines="first\13\nsecond\13\n"}},gravity={x=0,y=980},seed=1,timers={},version=2};return _;end
\ No newline at end of file
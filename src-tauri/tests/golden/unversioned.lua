@@ -0,0 +1,11 @@
+-- A scene from before scenes had versions, which were just their entities
+{
+    test1 = { type = "text", pos = { x = 100, y = 100 }, content = "vibing\n\nnext line" },
+    test2 = {
+        type = "text",
+        pos = { x = 200, y = 100 },
+        content = "u can do it",
+        selectable = true,
+        scripts = { on_click = { string = "self.pos = { x = self.pos.x + 20, y = self.pos.y }" } },
+    },
+}
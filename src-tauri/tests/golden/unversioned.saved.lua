@@ -0,0 +1,3 @@
+do local _={camera={rotation=0,zoom=1},canvas={height=720,width=1280},entities={test1={content=[[vibing
+
+next line]],pos={x=100,y=100},type="text"},test2={content="u can do it",pos={x=200,y=100},scripts={on_click={string="self.pos = { x = self.pos.x + 20, y = self.pos.y }"}},selectable=true,type="text"}},gravity={x=0,y=980},seed=1,timers={},version=2};return _;end
\ No newline at end of file
@@ -0,0 +1,18 @@
+//! Runs the scene tests in `tests/scenes`, the same as `stacks-run test tests/scenes` does.
+
+use std::path::Path;
+
+#[test]
+fn scene_tests_pass() {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let results = stacks_lib::run_scene_tests(
+        &[manifest.join("tests").join("scenes")],
+        &manifest.join("resources").join("lua"),
+    );
+    assert!(!results.is_empty(), "No scene tests found");
+    assert!(
+        results.iter().all(|result| result.failure.is_none()),
+        "\n{}",
+        stacks_lib::report_scene_tests(&results)
+    );
+}
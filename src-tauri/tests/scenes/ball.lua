@@ -0,0 +1,7 @@
+do local _={version=2,entities={ball={type="rect",pos={x=100,y=0},size={width=20,height=20},color="#d33",scripts={on_tick={string=[[if self.landed then return end
+self.pos.y = self.pos.y + 10
+print(self.pos.y)
+if self.pos.y >= 100 then
+    self.landed = true
+    emit("landed", { y = self.pos.y })
+end]]}}}},canvas={width=1280,height=720},camera={rotation=0,zoom=1},timers={},seed=7};return _;end
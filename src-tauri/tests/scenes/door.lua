@@ -0,0 +1,4 @@
+do local _={version=2,entities={door={type="rect",pos={x=400,y=200},size={width=60,height=120},color="#8b5a2b",open=false,scripts={on_click={string=[[self.open = not self.open
+emit("door_toggled", { open = self.open })]]},lock={string=[[self.locked = true]]},knock={string=[[if self.locked then return end
+self.open = true
+after(1, function() self.open = false end)]]}}},bell={type="text",pos={x=300,y=200},content="ring",scripts={on_click={string=[[broadcast("knock")]]}}}},canvas={width=1280,height=720},camera={rotation=0,zoom=1},timers={},seed=1};return _;end
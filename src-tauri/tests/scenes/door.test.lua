@@ -0,0 +1,28 @@
+scene("door.lua")
+
+test("starts closed", function()
+    assert_eq(entity("door").open, false)
+end)
+
+test("opens and closes when clicked", function()
+    click("door")
+    assert_eq(entity("door").open, true)
+    click("door")
+    assert_eq(entity("door").open, false)
+    assert_eq(events("door_toggled"), { { open = true }, { open = false } })
+end)
+
+test("ringing the bell opens it for a second", function()
+    click("bell")
+    assert_eq(entity("door").open, true)
+    tick(30)
+    assert_eq(entity("door").open, true, "still open after half a second")
+    tick(31)
+    assert_eq(entity("door").open, false)
+end)
+
+test("stays shut once it's locked", function()
+    message("door", "lock")
+    broadcast("knock")
+    assert_eq(entity("door").open, false)
+end)
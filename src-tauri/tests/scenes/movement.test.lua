@@ -0,0 +1,46 @@
+-- scenes can be built up in the test itself, without a scene file
+local function add_player()
+    add("player", {
+        type = "rect",
+        pos = { x = 0, y = 0 },
+        size = { width = 10, height = 10 },
+        scripts = {
+            on_tick = [[
+if input.is_down("right") then self.pos.x = self.pos.x + 5 end
+if input.is_down("left") then self.pos.x = self.pos.x - 5 end
+]],
+            on_key_down = [[if data.key == "space" then self.jumps = (self.jumps or 0) + 1 end]],
+        },
+    })
+end
+
+test("moves while an arrow key is held", function()
+    add_player()
+    input({ type = "key_down", key = "ArrowRight" })
+    tick(4)
+    assert_eq(entity("player").pos, { x = 20, y = 0 })
+    input({ type = "key_up", key = "ArrowRight" })
+    tick(4)
+    assert_eq(entity("player").pos.x, 20)
+end)
+
+test("jumps on space", function()
+    add_player()
+    input({ type = "key_down", key = " " })
+    tick()
+    assert_eq(entity("player").jumps, 1)
+end)
+
+test("tweens reach where they're going", function()
+    add("box", {
+        type = "rect",
+        pos = { x = 0, y = 0 },
+        size = { width = 10, height = 10 },
+        scripts = { on_click = [[tween(self.pos, { x = 100 }, 0.5)]] },
+    })
+    click("box")
+    tick(15)
+    assert_near(entity("box").pos.x, 50, 0.01, "halfway there")
+    tick(20)
+    assert_near(entity("box").pos.x, 100)
+end)
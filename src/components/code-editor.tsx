@@ -1,4 +1,6 @@
 import AceEditor from "react-ace";
+import { Ace } from "ace-builds";
+import { useEffect, useRef } from "preact/hooks";
 import { writeText, readText } from "@tauri-apps/plugin-clipboard-manager";
 
 import "ace-builds/src-noconflict/mode-lua";
@@ -6,12 +8,37 @@ import "ace-builds/src-noconflict/theme-github_light_default";
 import "ace-builds/src-noconflict/theme-cloud9_night";
 import "ace-builds/src-noconflict/ext-language_tools";
 
+export interface Diagnostic {
+  line: number;
+  column: number;
+  message: string;
+}
+
 export default function CodeEditor(props: {
   name: string;
   value: string;
   onChange: (value: string) => void;
   theme: "light" | "dark";
+  diagnostics?: Diagnostic[];
+  breakpoints?: number[];
+  onToggleBreakpoint?: (line: number) => void;
+  // line the debugger is paused on
+  pausedLine?: number;
 }) {
+  // diagnostics are 1-based, ace rows & columns are 0-based
+  const lines = props.value.split("\n");
+  const diagnostics = props.diagnostics || [];
+  const editor = useRef<Ace.Editor>();
+  // the gutter handler is only added once, so it reads the latest callback
+  const onToggleBreakpoint = useRef(props.onToggleBreakpoint);
+  onToggleBreakpoint.current = props.onToggleBreakpoint;
+
+  useEffect(() => {
+    editor.current?.session.setBreakpoints(
+      (props.breakpoints || []).map((line) => line - 1),
+    );
+  }, [props.breakpoints, editor.current]);
+
   return (
     <div class="overflow-auto size-full">
       <AceEditor
@@ -21,7 +48,46 @@ export default function CodeEditor(props: {
         mode="lua"
         value={props.value}
         onChange={props.onChange}
+        onLoad={(loaded) => {
+          editor.current = loaded;
+          loaded.on("gutterclick", (e: any) => {
+            if (!onToggleBreakpoint.current) return;
+            onToggleBreakpoint.current(e.getDocumentPosition().row + 1);
+            e.stop();
+          });
+        }}
         theme={props.theme == "light" ? "github_light_default" : "cloud9_night"}
+        annotations={diagnostics.map((diagnostic) => ({
+          row: diagnostic.line - 1,
+          column: diagnostic.column - 1,
+          text: diagnostic.message,
+          type: "error",
+        }))}
+        markers={[
+          ...diagnostics.map((diagnostic) => ({
+            startRow: diagnostic.line - 1,
+            startCol: diagnostic.column - 1,
+            endRow: diagnostic.line - 1,
+            endCol: Math.max(
+              diagnostic.column,
+              (lines[diagnostic.line - 1] || "").length,
+            ),
+            className: "script-error-marker",
+            type: "text" as const,
+          })),
+          ...(props.pausedLine
+            ? [
+                {
+                  startRow: props.pausedLine - 1,
+                  startCol: 0,
+                  endRow: props.pausedLine - 1,
+                  endCol: 1,
+                  className: "paused-line-marker",
+                  type: "fullLine" as const,
+                },
+              ]
+            : []),
+        ]}
         setOptions={{
           tabSize: 2,
           enableBasicAutocompletion: true,
@@ -10,7 +10,9 @@ interface TabBarProps {
 }
 
 export default function TabBar(props: TabBarProps) {
-  const [activeTab, setActiveTab] = useState(props.activeTab || 0);
+  const [ownActiveTab, setActiveTab] = useState(props.activeTab || 0);
+  // follows activeTab if it's given, so the tab can be changed from outside too
+  const activeTab = props.activeTab ?? ownActiveTab;
 
   const handleTabClick = (index: number) => {
     setActiveTab(index);
@@ -0,0 +1,157 @@
+import { render } from "preact";
+import "../style.css";
+import { listen } from "@tauri-apps/api/event";
+import { invoke } from "@tauri-apps/api/core";
+import { useEffect, useState } from "preact/hooks";
+import { AlertCircle, Slash, Trash2 } from "preact-feather";
+import TabBar from "../components/tab-bar/tab-bar";
+import TabItem from "../components/tab-bar/tab-item";
+import DebuggerPanel from "./debugger-panel";
+import LogPanel from "./log-panel";
+import PinsPanel from "./pins-panel";
+import ProfilerPanel from "./profiler-panel";
+import ReplPanel from "./repl-panel";
+
+interface ScriptError {
+  entity: string;
+  script: string;
+  line?: number;
+  message: string;
+  traceback: string;
+  count: number;
+  disabled: boolean;
+}
+
+function ErrorItem(props: { error: ScriptError }) {
+  const [expanded, setExpanded] = useState(false);
+  const error = props.error;
+
+  return (
+    <li
+      class="flex flex-col p-1 border-b border-border font-mono text-sm select-text"
+      onClick={() => setExpanded(!expanded)}
+    >
+      <div class="flex flex-row gap-2 items-start">
+        <div class="shrink-0 text-red-600/75">
+          {error.disabled ? <Slash size={16} /> : <AlertCircle size={16} />}
+        </div>
+        <span class="text-tertiary shrink-0">
+          {error.entity}:{error.script}
+          {error.line != undefined && `:${error.line}`}
+        </span>
+        <span class="grow whitespace-pre-wrap break-words">
+          {error.message}
+        </span>
+        {error.count > 1 && (
+          <span class="shrink-0 px-1 rounded-sm bg-secondary text-tertiary">
+            ×{error.count}
+          </span>
+        )}
+      </div>
+      {error.disabled && (
+        <span class="pl-6 text-tertiary">
+          Script disabled after repeated errors. Save it again to re-enable.
+        </span>
+      )}
+      {expanded && (
+        <pre class="pl-6 text-tertiary whitespace-pre-wrap">
+          {error.traceback}
+        </pre>
+      )}
+    </li>
+  );
+}
+
+export default function ConsoleWindow() {
+  const [errors, setErrors] = useState<ScriptError[]>([]);
+  const [autoDisable, setAutoDisable] = useState(false);
+  const [activeTab, setActiveTab] = useState(0);
+
+  useEffect(() => {
+    let listeners: (() => void)[] = [];
+
+    (async () => {
+      setErrors(await invoke<ScriptError[]>("get_script_errors"));
+      listeners.push(
+        await listen<ScriptError>("script_error", (e) =>
+          setErrors((errors) => [...errors, e.payload]),
+        ),
+      );
+      listeners.push(await listen("debugger_paused", () => setActiveTab(5)));
+    })();
+
+    return () => listeners.forEach((unsubscribe) => unsubscribe());
+  }, []);
+
+  return (
+    <div class="w-screen h-screen flex flex-col">
+      <TabBar activeTab={activeTab} onTabChange={setActiveTab}>
+        <TabItem>Errors</TabItem>
+        <TabItem>Log</TabItem>
+        <TabItem>Lua</TabItem>
+        <TabItem>Pins</TabItem>
+        <TabItem>Profiler</TabItem>
+        <TabItem>Debugger</TabItem>
+      </TabBar>
+      <div
+        class={`flex-1 flex flex-col min-h-0 ${activeTab == 1 ? "" : "hidden"}`}
+      >
+        <LogPanel />
+      </div>
+      <div
+        class={`flex-1 flex flex-col min-h-0 ${activeTab == 2 ? "" : "hidden"}`}
+      >
+        <ReplPanel />
+      </div>
+      <div
+        class={`flex-1 flex flex-col min-h-0 ${activeTab == 3 ? "" : "hidden"}`}
+      >
+        <PinsPanel />
+      </div>
+      <div
+        class={`flex-1 flex flex-col min-h-0 ${activeTab == 4 ? "" : "hidden"}`}
+      >
+        <ProfilerPanel active={activeTab == 4} />
+      </div>
+      <div
+        class={`flex-1 flex flex-col min-h-0 ${activeTab == 5 ? "" : "hidden"}`}
+      >
+        <DebuggerPanel />
+      </div>
+      <div
+        class={`flex flex-row gap-2 p-1 items-center text-sm bg-secondary border-b border-border ${activeTab == 0 ? "" : "hidden"}`}
+      >
+        <label class="flex flex-row gap-1 items-center grow">
+          <input
+            type="checkbox"
+            checked={autoDisable}
+            onChange={(e) => {
+              setAutoDisable(e.currentTarget.checked);
+              invoke("set_script_error_options", {
+                autoDisable: e.currentTarget.checked,
+              });
+            }}
+          />
+          Disable scripts that keep failing
+        </label>
+        <button
+          class="p-1 text-tertiary hover:text-text-color"
+          title="Clear"
+          onClick={() => {
+            invoke("clear_script_errors");
+            setErrors([]);
+          }}
+        >
+          <Trash2 size={16} />
+        </button>
+      </div>
+      <ul class={`flex-1 overflow-y-auto ${activeTab == 0 ? "" : "hidden"}`}>
+        {errors.map((error) => (
+          <ErrorItem error={error} />
+        ))}
+      </ul>
+    </div>
+  );
+}
+
+render(<ConsoleWindow />, document.getElementById("root")!);
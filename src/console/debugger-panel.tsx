@@ -0,0 +1,211 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { useEffect, useState } from "preact/hooks";
+import {
+  ArrowDownRight,
+  ArrowRight,
+  ArrowUpLeft,
+  Pause,
+  Play,
+  X,
+} from "preact-feather";
+
+interface Frame {
+  name: string;
+  entity?: string;
+  script?: string;
+  source: string;
+  line: number;
+}
+
+interface Variable {
+  name: string;
+  value: string;
+}
+
+interface Watch {
+  expression: string;
+  value: string;
+  error: boolean;
+}
+
+interface Paused {
+  reason: "breakpoint" | "step" | "pause";
+  entity: string;
+  script: string;
+  line: number;
+  frames: Frame[];
+  locals: Variable[];
+  watches: Watch[];
+}
+
+function Section(props: { title: string; children: any }) {
+  return (
+    <div class="flex flex-col border-b border-border">
+      <span class="px-1 bg-secondary text-tertiary">{props.title}</span>
+      {props.children}
+    </div>
+  );
+}
+
+export default function DebuggerPanel() {
+  const [paused, setPaused] = useState<Paused | null>(null);
+  const [frame, setFrame] = useState(0);
+  const [locals, setLocals] = useState<Variable[]>([]);
+  const [watches, setWatches] = useState<string[]>([]);
+  const [newWatch, setNewWatch] = useState("");
+  const [expression, setExpression] = useState("");
+  const [result, setResult] = useState<[boolean, string]>();
+
+  useEffect(() => {
+    let listeners: (() => void)[] = [];
+    const showPause = (pause: Paused | null) => {
+      setPaused(pause);
+      setFrame(0);
+      setLocals(pause?.locals || []);
+    };
+
+    (async () => {
+      setWatches(await invoke<string[]>("get_watches"));
+      showPause(await invoke<Paused | null>("get_paused"));
+      listeners.push(
+        await listen<Paused>("debugger_paused", (e) => showPause(e.payload)),
+      );
+      listeners.push(await listen("debugger_resumed", () => showPause(null)));
+    })();
+
+    return () => listeners.forEach((unsubscribe) => unsubscribe());
+  }, []);
+
+  const selectFrame = async (index: number) => {
+    setFrame(index);
+    setLocals(await invoke<Variable[]>("get_locals", { frame: index }));
+  };
+
+  const updateWatches = (updated: string[]) => {
+    setWatches(updated);
+    invoke("set_watches", { watches: updated });
+  };
+
+  const button = (title: string, icon: any, onClick: () => void) => (
+    <button
+      class="p-1 text-tertiary hover:text-text-color disabled:opacity-50"
+      title={title}
+      onClick={onClick}
+    >
+      {icon}
+    </button>
+  );
+
+  const step = (action: string) => invoke("debugger_step", { action });
+
+  return (
+    <div class="flex flex-col flex-1 overflow-y-auto font-mono text-sm select-text">
+      <div class="flex flex-row gap-1 p-1 items-center bg-secondary border-b border-border">
+        {paused ? (
+          <>
+            {button("Continue", <Play size={16} />, () => step("continue"))}
+            {button("Step over", <ArrowRight size={16} />, () =>
+              step("over"),
+            )}
+            {button("Step in", <ArrowDownRight size={16} />, () => step("in"))}
+            {button("Step out", <ArrowUpLeft size={16} />, () => step("out"))}
+            <span class="grow text-tertiary">
+              Paused at {paused.entity}:{paused.script}:{paused.line}
+              {paused.reason == "pause" ? "" : ` (${paused.reason})`}
+            </span>
+          </>
+        ) : (
+          <>
+            {button("Pause", <Pause size={16} />, () =>
+              invoke("pause_scripts"),
+            )}
+            <span class="grow text-tertiary">
+              Running. Click a script's line numbers in the Inspector to add a
+              breakpoint.
+            </span>
+          </>
+        )}
+      </div>
+      {paused && (
+        <>
+          <Section title="Call stack">
+            {paused.frames.map((f, index) => (
+              <button
+                class={`px-1 text-left ${index == frame ? "text-accent" : ""}`}
+                onClick={() => selectFrame(index)}
+              >
+                {f.name} <span class="text-tertiary">{f.source}:{f.line}</span>
+              </button>
+            ))}
+          </Section>
+          <Section title="Locals">
+            {locals.map((variable) => (
+              <span class="px-1 whitespace-pre-wrap break-words">
+                {variable.name} = {variable.value}
+              </span>
+            ))}
+          </Section>
+        </>
+      )}
+      <Section title="Watches">
+        {watches.map((watch, index) => {
+          const value = paused?.watches.find((w) => w.expression == watch);
+          return (
+            <div class="flex flex-row gap-1 px-1 items-start">
+              <span class="grow whitespace-pre-wrap break-words">
+                {watch}
+                {value && (
+                  <span class={value.error ? "text-red-600/75" : ""}>
+                    {" "}
+                    = {value.value}
+                  </span>
+                )}
+              </span>
+              {button("Remove", <X size={14} />, () =>
+                updateWatches(watches.filter((_, i) => i != index)),
+              )}
+            </div>
+          );
+        })}
+        <input
+          class="px-1 bg-transparent"
+          placeholder="Add a watch, like self.pos.x"
+          value={newWatch}
+          onInput={(e) => setNewWatch(e.currentTarget.value)}
+          onKeyDown={(e) => {
+            if (e.key != "Enter" || !newWatch.trim()) return;
+            updateWatches([...watches, newWatch.trim()]);
+            setNewWatch("");
+          }}
+        />
+      </Section>
+      {paused && (
+        <Section title="Evaluate">
+          <input
+            class="px-1 bg-transparent"
+            placeholder="An expression or statement, run in the selected frame"
+            value={expression}
+            onInput={(e) => setExpression(e.currentTarget.value)}
+            onKeyDown={async (e) => {
+              if (e.key != "Enter") return;
+              setResult(
+                await invoke<[boolean, string]>("evaluate_in_frame", {
+                  expression,
+                  frame,
+                }),
+              );
+            }}
+          />
+          {result && (
+            <span
+              class={`px-1 whitespace-pre-wrap break-words ${result[0] ? "" : "text-red-600/75"}`}
+            >
+              {result[1]}
+            </span>
+          )}
+        </Section>
+      )}
+    </div>
+  );
+}
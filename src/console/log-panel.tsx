@@ -0,0 +1,154 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { message, save } from "@tauri-apps/plugin-dialog";
+import { useEffect, useLayoutEffect, useRef, useState } from "preact/hooks";
+import { Download, Trash2 } from "preact-feather";
+
+type Level = "info" | "warn" | "error";
+
+interface LogEntry {
+  time: number;
+  level: Level;
+  entity?: string;
+  script?: string;
+  message: string;
+}
+
+// the same number the Lua thread keeps
+const HISTORY_LENGTH = 1000;
+
+const LEVEL_COLORS: Record<Level, string> = {
+  info: "text-tertiary",
+  warn: "text-yellow-600/75",
+  error: "text-red-600/75",
+};
+
+function formatTime(time: number) {
+  const date = new Date(time);
+  const pad = (n: number, width = 2) => n.toString().padStart(width, "0");
+  return `${pad(date.getHours())}:${pad(date.getMinutes())}:${pad(date.getSeconds())}.${pad(date.getMilliseconds(), 3)}`;
+}
+
+export default function LogPanel() {
+  const [entries, setEntries] = useState<LogEntry[]>([]);
+  const [levels, setLevels] = useState<Level[]>(["info", "warn", "error"]);
+  const [filter, setFilter] = useState("");
+  const list = useRef<HTMLUListElement>(null);
+  const following = useRef(true);
+
+  useEffect(() => {
+    let listeners: (() => void)[] = [];
+
+    (async () => {
+      setEntries(await invoke<LogEntry[]>("get_logs"));
+      listeners.push(
+        await listen<LogEntry>("log_entry", (e) =>
+          setEntries((entries) =>
+            [...entries, e.payload].slice(-HISTORY_LENGTH),
+          ),
+        ),
+      );
+    })();
+
+    return () => listeners.forEach((unsubscribe) => unsubscribe());
+  }, []);
+
+  // keep up with new entries, unless the list's been scrolled back to read something
+  useLayoutEffect(() => {
+    if (following.current && list.current) {
+      list.current.scrollTop = list.current.scrollHeight;
+    }
+  }, [entries]);
+
+  const exportLog = async () => {
+    const path = await save({
+      filters: [{ name: "log", extensions: ["log", "txt"] }],
+    });
+    if (!path) return;
+    const [success, msg] = await invoke<[boolean, string]>("export_logs", {
+      path,
+    });
+    if (!success) message(msg, { title: `Error`, kind: "error" });
+  };
+
+  const search = filter.toLowerCase();
+  const shown = entries.filter(
+    (entry) =>
+      levels.includes(entry.level) &&
+      (!search ||
+        entry.message.toLowerCase().includes(search) ||
+        `${entry.entity}:${entry.script}`.toLowerCase().includes(search)),
+  );
+
+  return (
+    <>
+      <div class="flex flex-row gap-2 p-1 items-center text-sm bg-secondary border-b border-border">
+        {(["info", "warn", "error"] as Level[]).map((level) => (
+          <label class="flex flex-row gap-1 items-center">
+            <input
+              type="checkbox"
+              checked={levels.includes(level)}
+              onChange={(e) =>
+                setLevels(
+                  e.currentTarget.checked
+                    ? [...levels, level]
+                    : levels.filter((l) => l != level),
+                )
+              }
+            />
+            {level}
+          </label>
+        ))}
+        <input
+          class="grow px-1 bg-transparent"
+          placeholder="Filter"
+          value={filter}
+          onInput={(e) => setFilter(e.currentTarget.value)}
+        />
+        <button
+          class="p-1 text-tertiary hover:text-text-color"
+          title="Export"
+          onClick={exportLog}
+        >
+          <Download size={16} />
+        </button>
+        <button
+          class="p-1 text-tertiary hover:text-text-color"
+          title="Clear"
+          onClick={() => {
+            invoke("clear_logs");
+            setEntries([]);
+          }}
+        >
+          <Trash2 size={16} />
+        </button>
+      </div>
+      <ul
+        ref={list}
+        class="flex-1 overflow-y-auto font-mono text-sm select-text"
+        onScroll={(e) => {
+          const el = e.currentTarget;
+          following.current =
+            el.scrollHeight - el.scrollTop - el.clientHeight < 8;
+        }}
+      >
+        {shown.map((entry) => (
+          <li class="flex flex-row gap-2 px-1 items-start border-b border-border">
+            <span class="shrink-0 text-tertiary">{formatTime(entry.time)}</span>
+            <span class={`shrink-0 w-10 ${LEVEL_COLORS[entry.level]}`}>
+              {entry.level}
+            </span>
+            {entry.entity && (
+              <span class="shrink-0 text-tertiary">
+                {entry.entity}:{entry.script}
+              </span>
+            )}
+            <span class="grow whitespace-pre-wrap break-words">
+              {entry.message}
+            </span>
+          </li>
+        ))}
+      </ul>
+    </>
+  );
+}
@@ -0,0 +1,35 @@
+import { WebviewWindow } from "@tauri-apps/api/webviewWindow";
+
+let opening = false;
+
+export default async function openConsole(focus: boolean) {
+  // If window already exists, just focus it
+  const existing = await WebviewWindow.getByLabel("console");
+  if (existing) {
+    if (focus) existing.setFocus();
+    return;
+  }
+  // errors tend to arrive in bursts, only create the window once
+  if (opening) return;
+  opening = true;
+
+  const consoleWindow = new WebviewWindow("console", {
+    title: "Console",
+    url: "src/console/console.html",
+    width: 500,
+    height: 300,
+    resizable: true,
+    minWidth: 300,
+    minHeight: 150,
+    focus: focus,
+    backgroundColor: window
+      .getComputedStyle(document.body)
+      .getPropertyValue("--background-color"),
+  });
+
+  consoleWindow.once("tauri://created", () => (opening = false));
+  consoleWindow.once("tauri://error", (e) => {
+    opening = false;
+    console.error("Console webview had ERROR!", e);
+  });
+}
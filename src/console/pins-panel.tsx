@@ -0,0 +1,150 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { useEffect, useState } from "preact/hooks";
+import { X } from "preact-feather";
+
+interface Pin {
+  id: number;
+  expression: string;
+}
+
+interface Sample {
+  id: number;
+  value?: number | boolean | string | null;
+  error?: string | null;
+}
+
+// samples kept for each graph, which is five seconds of ticks
+const GRAPH_LENGTH = 300;
+const GRAPH_WIDTH = 160;
+const GRAPH_HEIGHT = 32;
+
+function Graph(props: { values: number[] }) {
+  if (props.values.length < 2) return null;
+  const min = Math.min(...props.values);
+  const max = Math.max(...props.values);
+  const range = max - min || 1;
+  const points = props.values
+    .map(
+      (value, index) =>
+        `${(index / (GRAPH_LENGTH - 1)) * GRAPH_WIDTH},${GRAPH_HEIGHT - ((value - min) / range) * GRAPH_HEIGHT}`,
+    )
+    .join(" ");
+  return (
+    <svg
+      class="shrink-0 text-accent"
+      width={GRAPH_WIDTH}
+      height={GRAPH_HEIGHT}
+      viewBox={`0 -1 ${GRAPH_WIDTH} ${GRAPH_HEIGHT + 2}`}
+    >
+      <title>
+        {min} to {max}
+      </title>
+      <polyline
+        points={points}
+        fill="none"
+        stroke="currentColor"
+        stroke-width="1"
+      />
+    </svg>
+  );
+}
+
+// expressions sampled every tick, shown as their latest value & a graph of the last few seconds
+export default function PinsPanel() {
+  const [pins, setPins] = useState<Pin[]>([]);
+  const [latest, setLatest] = useState<Map<number, Sample>>(new Map());
+  const [history, setHistory] = useState<Map<number, number[]>>(new Map());
+  const [expression, setExpression] = useState("");
+  const [error, setError] = useState("");
+
+  useEffect(() => {
+    let listeners: (() => void)[] = [];
+
+    (async () => {
+      setPins(await invoke<Pin[]>("get_pins"));
+      listeners.push(
+        await listen<Sample[]>("pin_samples", (e) => {
+          setLatest(new Map(e.payload.map((sample) => [sample.id, sample])));
+          setHistory((history) => {
+            const updated = new Map<number, number[]>();
+            for (const sample of e.payload) {
+              const value =
+                typeof sample.value == "boolean"
+                  ? Number(sample.value)
+                  : sample.value;
+              // anything that isn't a number starts the graph over
+              updated.set(
+                sample.id,
+                typeof value == "number"
+                  ? [...(history.get(sample.id) ?? []), value].slice(
+                      -GRAPH_LENGTH,
+                    )
+                  : [],
+              );
+            }
+            return updated;
+          });
+        }),
+      );
+    })();
+
+    return () => listeners.forEach((unsubscribe) => unsubscribe());
+  }, []);
+
+  const addPin = async () => {
+    if (!expression.trim()) return;
+    try {
+      const pin = await invoke<Pin>("pin_expression", { expression });
+      setPins((pins) => [...pins, pin]);
+      setExpression("");
+      setError("");
+    } catch (e) {
+      setError(String(e));
+    }
+  };
+
+  const removePin = (id: number) => {
+    invoke("unpin_expression", { id });
+    setPins((pins) => pins.filter((pin) => pin.id != id));
+  };
+
+  return (
+    <div class="flex-1 flex flex-col overflow-y-auto font-mono text-sm select-text">
+      {pins.map((pin) => {
+        const sample = latest.get(pin.id);
+        return (
+          <div class="flex flex-row gap-2 p-1 items-center border-b border-border">
+            <div class="flex flex-col grow min-w-0">
+              <span class="text-tertiary break-words">{pin.expression}</span>
+              <span
+                class={`break-words ${sample?.error ? "text-red-600/75" : ""}`}
+              >
+                {sample?.error ??
+                  (sample == undefined ? "…" : String(sample.value ?? "nil"))}
+              </span>
+            </div>
+            <Graph values={history.get(pin.id) ?? []} />
+            <button
+              class="p-1 text-tertiary hover:text-text-color"
+              title="Unpin"
+              onClick={() => removePin(pin.id)}
+            >
+              <X size={14} />
+            </button>
+          </div>
+        );
+      })}
+      <input
+        class="p-1 bg-transparent"
+        placeholder="Pin an expression, like player.velocity.y"
+        value={expression}
+        onInput={(e) => setExpression(e.currentTarget.value)}
+        onKeyDown={(e) => e.key == "Enter" && addPin()}
+      />
+      {error && (
+        <span class="px-1 text-red-600/75 whitespace-pre-wrap">{error}</span>
+      )}
+    </div>
+  );
+}
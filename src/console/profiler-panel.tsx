@@ -0,0 +1,136 @@
+import { invoke } from "@tauri-apps/api/core";
+import { message, save } from "@tauri-apps/plugin-dialog";
+import { useEffect, useState } from "preact/hooks";
+import { Circle, Download, RotateCcw, Square } from "preact-feather";
+
+interface Timing {
+  calls: number;
+  total_ms: number;
+  self_ms: number;
+  average_ms: number;
+  max_ms: number;
+}
+
+interface ProfileReport {
+  enabled: boolean;
+  seconds: number;
+  phases: (Timing & { name: string })[];
+  scripts: (Timing & { entity: string; script: string; kind: string })[];
+}
+
+const ms = (value: number) => value.toFixed(2);
+
+function TimingCells(props: { timing: Timing }) {
+  return (
+    <>
+      <td class="px-1 text-right">{props.timing.calls}</td>
+      <td class="px-1 text-right">{ms(props.timing.average_ms)}</td>
+      <td class="px-1 text-right">{ms(props.timing.max_ms)}</td>
+      <td class="px-1 text-right">{ms(props.timing.self_ms)}</td>
+      <td class="px-1 text-right">{ms(props.timing.total_ms)}</td>
+    </>
+  );
+}
+
+function Header(props: { title: string }) {
+  return (
+    <tr class="text-tertiary bg-secondary">
+      <th class="px-1 text-left font-normal">{props.title}</th>
+      <th class="px-1 text-right font-normal">calls</th>
+      <th class="px-1 text-right font-normal">avg ms</th>
+      <th class="px-1 text-right font-normal">max ms</th>
+      <th class="px-1 text-right font-normal">self ms</th>
+      <th class="px-1 text-right font-normal">total ms</th>
+    </tr>
+  );
+}
+
+// only asks for reports while it's shown, since each one goes through the Lua thread
+export default function ProfilerPanel(props: { active: boolean }) {
+  const [report, setReport] = useState<ProfileReport>();
+
+  const refresh = async () =>
+    setReport(await invoke<ProfileReport>("profile_report"));
+
+  useEffect(() => {
+    if (!props.active) return;
+    refresh();
+    if (!report?.enabled) return;
+    const interval = setInterval(refresh, 500);
+    return () => clearInterval(interval);
+  }, [props.active, report?.enabled]);
+
+  const setProfiling = async (enabled: boolean) => {
+    await invoke("set_profiling", { enabled });
+    refresh();
+  };
+
+  const exportTrace = async () => {
+    const path = await save({
+      filters: [{ name: "trace", extensions: ["json"] }],
+    });
+    if (!path) return;
+    const [success, msg] = await invoke<[boolean, string]>(
+      "export_profile_trace",
+      { path },
+    );
+    if (!success) message(msg, { title: `Error`, kind: "error" });
+  };
+
+  return (
+    <>
+      <div class="flex flex-row gap-2 p-1 items-center text-sm bg-secondary border-b border-border">
+        <button
+          class="p-1 text-tertiary hover:text-text-color"
+          title={report?.enabled ? "Stop profiling" : "Start profiling"}
+          onClick={() => setProfiling(!report?.enabled)}
+        >
+          {report?.enabled ? <Square size={16} /> : <Circle size={16} />}
+        </button>
+        <span class="grow text-tertiary">
+          {report?.enabled
+            ? `Profiling for ${report.seconds.toFixed(1)}s`
+            : "Start profiling to see what's taking the time."}
+        </span>
+        <button
+          class="p-1 text-tertiary hover:text-text-color"
+          title="Reset"
+          onClick={async () => {
+            await invoke("reset_profile");
+            refresh();
+          }}
+        >
+          <RotateCcw size={16} />
+        </button>
+        <button
+          class="p-1 text-tertiary hover:text-text-color"
+          title="Export trace"
+          onClick={exportTrace}
+        >
+          <Download size={16} />
+        </button>
+      </div>
+      <div class="flex-1 overflow-y-auto font-mono text-sm select-text">
+        <table class="w-full">
+          <Header title="Tick" />
+          {report?.phases.map((phase) => (
+            <tr class="border-b border-border">
+              <td class="px-1">{phase.name}</td>
+              <TimingCells timing={phase} />
+            </tr>
+          ))}
+          <Header title="Scripts" />
+          {report?.scripts.map((script) => (
+            <tr class="border-b border-border">
+              <td class="px-1">
+                {script.entity}:{script.script}{" "}
+                <span class="text-tertiary">{script.kind}</span>
+              </td>
+              <TimingCells timing={script} />
+            </tr>
+          ))}
+        </table>
+      </div>
+    </>
+  );
+}
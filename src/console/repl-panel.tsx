@@ -0,0 +1,148 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { useEffect, useLayoutEffect, useRef, useState } from "preact/hooks";
+import { ChevronRight, Trash2 } from "preact-feather";
+
+interface Result {
+  code: string;
+  entity: string;
+  success: boolean;
+  output: string;
+}
+
+// runs Lua in the live scene, like a terminal: Enter runs, Shift+Enter adds a line, up & down go through history
+export default function ReplPanel() {
+  const [entityIds, setEntityIds] = useState<string[]>([]);
+  const [entity, setEntity] = useState("");
+  const [code, setCode] = useState("");
+  const [results, setResults] = useState<Result[]>([]);
+  const [history, setHistory] = useState<string[]>([]);
+  // how far back through the history the input is, where history.length is what's being typed
+  const [position, setPosition] = useState(0);
+  const list = useRef<HTMLDivElement>(null);
+
+  useEffect(() => {
+    let listeners: (() => void)[] = [];
+
+    (async () => {
+      const saved = await invoke<string[]>("get_eval_history");
+      setHistory(saved);
+      setPosition(saved.length);
+      listeners.push(
+        await listen<{ entities: { [id: string]: unknown } }>(
+          "scene_update",
+          (e) => {
+            const ids = Object.keys(e.payload.entities).sort();
+            // the scene updates every tick, but the entities in it rarely change
+            setEntityIds((current) =>
+              current.join("\n") == ids.join("\n") ? current : ids,
+            );
+          },
+        ),
+      );
+    })();
+
+    return () => listeners.forEach((unsubscribe) => unsubscribe());
+  }, []);
+
+  useLayoutEffect(() => {
+    if (list.current) list.current.scrollTop = list.current.scrollHeight;
+  }, [results]);
+
+  const run = async () => {
+    if (!code.trim()) return;
+    const [success, output] = await invoke<[boolean, string]>("eval", {
+      code,
+      entity: entity || null,
+    });
+    setResults((results) => [...results, { code, entity, success, output }]);
+    const updated =
+      history[history.length - 1] == code ? history : [...history, code];
+    setHistory(updated);
+    setPosition(updated.length);
+    setCode("");
+  };
+
+  const recall = (to: number) => {
+    if (to < 0 || to > history.length) return;
+    setPosition(to);
+    setCode(history[to] ?? "");
+  };
+
+  return (
+    <>
+      <div class="flex flex-row gap-2 p-1 items-center text-sm bg-secondary border-b border-border">
+        <label class="flex flex-row gap-1 items-center grow">
+          Run as
+          <select
+            value={entity}
+            onChange={(e) => setEntity(e.currentTarget.value)}
+          >
+            <option value="">no entity</option>
+            {entityIds.map((id) => (
+              <option key={id} value={id}>
+                {id}
+              </option>
+            ))}
+          </select>
+        </label>
+        <button
+          class="p-1 text-tertiary hover:text-text-color"
+          title="Clear"
+          onClick={() => setResults([])}
+        >
+          <Trash2 size={16} />
+        </button>
+      </div>
+      <div
+        ref={list}
+        class="flex-1 overflow-y-auto font-mono text-sm select-text"
+      >
+        {results.map((result) => (
+          <div class="flex flex-col p-1 border-b border-border">
+            <span class="text-tertiary whitespace-pre-wrap break-words">
+              {result.entity}&gt; {result.code}
+            </span>
+            {result.output && (
+              <pre
+                class={`whitespace-pre-wrap break-words ${result.success ? "" : "text-red-600/75"}`}
+              >
+                {result.output}
+              </pre>
+            )}
+          </div>
+        ))}
+      </div>
+      <div class="flex flex-row gap-1 p-1 items-start border-t border-border font-mono text-sm">
+        <span class="shrink-0 mt-0.5 text-tertiary">
+          <ChevronRight size={16} />
+        </span>
+        <textarea
+          class="grow bg-transparent resize-none outline-none"
+          rows={Math.min(code.split("\n").length, 8)}
+          placeholder={
+            entity ? "self.pos.x = 100" : "currentScene.entities.test1.pos"
+          }
+          value={code}
+          onInput={(e) => setCode(e.currentTarget.value)}
+          onKeyDown={(e) => {
+            const input = e.currentTarget;
+            if (e.key == "Enter" && !e.shiftKey) {
+              e.preventDefault();
+              run();
+            } else if (e.key == "ArrowUp" && input.selectionStart == 0) {
+              e.preventDefault();
+              recall(position - 1);
+            } else if (
+              e.key == "ArrowDown" &&
+              input.selectionEnd == input.value.length
+            ) {
+              e.preventDefault();
+              recall(position + 1);
+            }
+          }}
+        />
+      </div>
+    </>
+  );
+}
@@ -5,8 +5,9 @@ import { emitTo } from "@tauri-apps/api/event";
 import { getCurrentWindow } from "@tauri-apps/api/window";
 import Markdown from "marked-react";
 import { JSX } from "preact/jsx-runtime";
-import { message } from "@tauri-apps/plugin-dialog";
 import { Entity } from "./entity-type";
+import SpriteImage from "./sprite-image";
+import TilemapCanvas from "./tilemap-canvas";
 
 interface EntityProps {
   entity: any;
@@ -85,21 +86,38 @@ export default function EntityComponent(props: EntityProps) {
               dangerouslySetInnerHTML={{ __html: props.entity.content }}
             />
           );
+        } else if (value == "sprite") {
+          content = (
+            <SpriteImage
+              image={props.entity.image}
+              sheet={props.entity.sheet}
+              frame={props.entity.frame}
+              flip_x={props.entity.flip_x}
+              flip_y={props.entity.flip_y}
+            />
+          );
+        } else if (value == "tilemap") {
+          content = (
+            <TilemapCanvas
+              tileset={props.entity.tileset}
+              sheet={props.entity.sheet}
+              tile_size={props.entity.tile_size}
+              columns={props.entity.columns}
+              rows={props.entity.rows}
+              tiles={props.entity.tiles}
+            />
+          );
         }
     }
   });
 
+  // failures are reported through the script error console
   async function runScript(script: string, params: any) {
-    const [success, msg] = await invoke<[boolean, string]>("run_script", {
+    invoke("run_script", {
       id: props.entity.id,
       function: script,
       params: params,
     });
-    if (!success)
-      message(msg, {
-        title: `Error executing script "${script}" on entity "${props.entity.id}"`,
-        kind: "error",
-      });
   }
 
   async function openInspector() {
@@ -16,6 +16,30 @@ interface SvgEntity {
   size: { width: number; height: number };
 }
 
+interface SpriteEntity {
+  type: "sprite";
+  image: string;
+  size: { width: number; height: number };
+  sheet?: { columns?: number; rows?: number };
+  frame?: number;
+  animations?: {
+    [key: string]: { frames: number[]; fps?: number; loop?: boolean };
+  };
+  animation?: string | { name: string; step?: number; time?: number };
+  flip_x?: boolean;
+  flip_y?: boolean;
+}
+
+interface TilemapEntity {
+  type: "tilemap";
+  tileset: string;
+  sheet?: { columns?: number; rows?: number };
+  tile_size: { width: number; height: number };
+  columns: number;
+  rows: number;
+  tiles?: number[] | string;
+}
+
 interface TextInputEntity {
   type: "text_input";
   content?: string;
@@ -36,4 +60,11 @@ interface BaseEntity {
 }
 
 export type Entity = BaseEntity &
-  (RectEntity | TextEntity | SvgEntity | TextInputEntity);
+  (
+    | RectEntity
+    | TextEntity
+    | SvgEntity
+    | SpriteEntity
+    | TilemapEntity
+    | TextInputEntity
+  );
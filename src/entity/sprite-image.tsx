@@ -0,0 +1,42 @@
+import { convertFileSrc, invoke } from "@tauri-apps/api/core";
+import { useEffect, useState } from "preact/hooks";
+
+interface SpriteImageProps {
+  image: string;
+  sheet?: { columns?: number; rows?: number };
+  frame?: number;
+  flip_x?: boolean;
+  flip_y?: boolean;
+}
+
+export default function SpriteImage(props: SpriteImageProps) {
+  const [src, setSrc] = useState<string | undefined>(undefined);
+
+  // images are found relative to the scene file, which only the runtime knows about
+  useEffect(() => {
+    invoke<string>("resolve_asset", { name: props.image })
+      .then((path) => setSrc(convertFileSrc(path)))
+      .catch(() => setSrc(undefined));
+  }, [props.image]);
+
+  const columns = Math.max(props.sheet?.columns || 1, 1);
+  const rows = Math.max(props.sheet?.rows || 1, 1);
+  // frames are numbered from 1, left to right & then top to bottom
+  const index = Math.max((props.frame || 1) - 1, 0);
+  const column = index % columns;
+  const row = Math.floor(index / columns) % rows;
+
+  return (
+    <div
+      class="w-full h-full"
+      style={{
+        backgroundImage: src ? `url("${src}")` : undefined,
+        backgroundSize: `${columns * 100}% ${rows * 100}%`,
+        backgroundPosition: `${columns > 1 ? (column / (columns - 1)) * 100 : 0}% ${rows > 1 ? (row / (rows - 1)) * 100 : 0}%`,
+        backgroundRepeat: "no-repeat",
+        imageRendering: "pixelated",
+        transform: `scale(${props.flip_x ? -1 : 1}, ${props.flip_y ? -1 : 1})`,
+      }}
+    />
+  );
+}
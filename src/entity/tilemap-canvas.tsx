@@ -0,0 +1,74 @@
+import { convertFileSrc, invoke } from "@tauri-apps/api/core";
+import { useEffect, useRef, useState } from "preact/hooks";
+
+interface TilemapCanvasProps {
+  tileset: string;
+  sheet?: { columns?: number; rows?: number };
+  tile_size: { width: number; height: number };
+  columns: number;
+  rows: number;
+  tiles?: number[];
+}
+
+export default function TilemapCanvas(props: TilemapCanvasProps) {
+  const canvas = useRef<HTMLCanvasElement>(null);
+  const [tileset, setTileset] = useState<HTMLImageElement | undefined>(
+    undefined,
+  );
+
+  // tilesets are found relative to the scene file, which only the runtime knows about
+  useEffect(() => {
+    invoke<string>("resolve_asset", { name: props.tileset })
+      .then((path) => {
+        const image = new Image();
+        image.onload = () => setTileset(image);
+        image.src = convertFileSrc(path);
+      })
+      .catch(() => setTileset(undefined));
+  }, [props.tileset]);
+
+  const width = props.columns * props.tile_size.width;
+  const height = props.rows * props.tile_size.height;
+
+  // drawn onto one canvas, rather than an element per tile, so big levels stay quick to update
+  useEffect(() => {
+    const context = canvas.current?.getContext("2d");
+    if (!context) return;
+    context.clearRect(0, 0, width, height);
+    if (!tileset || !Array.isArray(props.tiles)) return;
+    context.imageSmoothingEnabled = false;
+
+    const sheetColumns = Math.max(props.sheet?.columns || 1, 1);
+    const sheetRows = Math.max(props.sheet?.rows || 1, 1);
+    const sourceWidth = tileset.naturalWidth / sheetColumns;
+    const sourceHeight = tileset.naturalHeight / sheetRows;
+    props.tiles.forEach((tile, i) => {
+      // 0 is an empty tile, and the rest are frames of the tileset, numbered like a sprite's
+      if (!tile || i >= props.columns * props.rows) return;
+      context.drawImage(
+        tileset,
+        ((tile - 1) % sheetColumns) * sourceWidth,
+        Math.floor((tile - 1) / sheetColumns) * sourceHeight,
+        sourceWidth,
+        sourceHeight,
+        (i % props.columns) * props.tile_size.width,
+        Math.floor(i / props.columns) * props.tile_size.height,
+        props.tile_size.width,
+        props.tile_size.height,
+      );
+    });
+  }, [tileset, props.tiles, props.sheet, props.columns, props.tile_size]);
+
+  return (
+    <canvas
+      ref={canvas}
+      width={width}
+      height={height}
+      style={{
+        width: `calc(${width}px * var(--scene-scale))`,
+        height: `calc(${height}px * var(--scene-scale))`,
+        imageRendering: "pixelated",
+      }}
+    />
+  );
+}
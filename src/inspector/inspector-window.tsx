@@ -11,7 +11,7 @@ import Scripts from "./scripts-component/scripts-component";
 import { lazy, Suspense } from "preact/compat";
 import { invoke } from "@tauri-apps/api/core";
 import { confirm, message } from "@tauri-apps/plugin-dialog";
-import CodeEditor from "../components/code-editor";
+import CodeEditor, { Diagnostic } from "../components/code-editor";
 import { getCurrentWebviewWindow } from "@tauri-apps/api/webviewWindow";
 
 export default function InspectorWindow() {
@@ -27,6 +27,9 @@ export default function InspectorWindow() {
     new Map(),
   );
   const [saved, setSaved] = useState(false);
+  const [diagnostics, setDiagnostics] = useState<Map<string, Diagnostic[]>>(
+    new Map(),
+  );
 
   useEffect(() => {
     let listeners: (() => void)[] = [];
@@ -85,7 +88,7 @@ export default function InspectorWindow() {
       {
         originalId: entity.id,
         inspector: inspectorContents,
-        scripts: scriptsContents,
+        scripts: Object.fromEntries(scriptsContents),
       },
     );
 
@@ -126,6 +129,26 @@ export default function InspectorWindow() {
     if (entity) getCurrentWindow().setTitle(entity.id + (saved ? "" : " *"));
   }, [saved]);
 
+  // compile scripts as they're edited, so errors show before saving
+  useEffect(() => {
+    if (!entity) return;
+    const timeout = setTimeout(async () => {
+      const found = await invoke<(Diagnostic & { script: string })[]>(
+        "check_scripts",
+        { id: entity.id, scripts: Object.fromEntries(scriptsContents) },
+      );
+      const byScript = new Map<string, Diagnostic[]>();
+      found.forEach((diagnostic) =>
+        byScript.set(diagnostic.script, [
+          ...(byScript.get(diagnostic.script) || []),
+          diagnostic,
+        ]),
+      );
+      setDiagnostics(byScript);
+    }, 300);
+    return () => clearTimeout(timeout);
+  }, [entity, scriptsContents]);
+
   useEffect(() => {
     if (!(entity && inspectorContents != "")) return;
     let listeners: (() => void)[] = [];
@@ -175,6 +198,7 @@ export default function InspectorWindow() {
           }}
           editorHeights={editorHeights}
           setEditorHeights={setEditorHeights}
+          diagnostics={diagnostics}
           theme={theme}
         />
       ),
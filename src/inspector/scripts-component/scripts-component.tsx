@@ -4,8 +4,11 @@ const Accordion = lazy(() => import("../../components/accordion"));
 import { lazy, Suspense } from "preact/compat";
 import { Menu } from "@tauri-apps/api/menu";
 import { confirm } from "@tauri-apps/plugin-dialog";
-import CodeEditor from "../../components/code-editor";
+import CodeEditor, { Diagnostic } from "../../components/code-editor";
 import AddScriptForm from "./add-script-form";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { useEffect, useState } from "preact/hooks";
 
 export default function Scripts(props: {
   entity: Entity;
@@ -16,7 +19,60 @@ export default function Scripts(props: {
   onContentsChange: (scripts: Map<string, string>) => void;
   editorHeights: Map<string, number>;
   setEditorHeights: (heights: Map<string, number>) => void;
+  diagnostics: Map<string, Diagnostic[]>;
 }) {
+  // lines by script, for this entity
+  const [breakpoints, setBreakpoints] = useState<Map<string, number[]>>(
+    new Map(),
+  );
+  const [paused, setPaused] = useState<{ script: string; line: number }>();
+
+  useEffect(() => {
+    let listeners: (() => void)[] = [];
+    const showPause = (
+      pause: { entity: string; script: string; line: number } | null,
+    ) =>
+      setPaused(pause && pause.entity == props.entity.id ? pause : undefined);
+
+    (async () => {
+      const all = await invoke<{
+        [id: string]: { [script: string]: number[] };
+      }>("get_breakpoints");
+      setBreakpoints(new Map(Object.entries(all[props.entity.id] || {})));
+      showPause(await invoke("get_paused"));
+      listeners.push(
+        await listen<{ entity: string; script: string; line: number }>(
+          "debugger_paused",
+          (e) => showPause(e.payload),
+        ),
+      );
+      listeners.push(
+        await listen("debugger_resumed", () => setPaused(undefined)),
+      );
+    })();
+
+    return () => listeners.forEach((unsubscribe) => unsubscribe());
+  }, [props.entity.id]);
+
+  function toggleBreakpoint(script: string, line: number) {
+    const lines = breakpoints.get(script) || [];
+    const toggled = lines.includes(line)
+      ? lines.filter((l) => l != line)
+      : [...lines, line];
+    setBreakpoints(new Map(breakpoints).set(script, toggled));
+    invoke("set_breakpoints", {
+      entity: props.entity.id,
+      script: script,
+      lines: toggled,
+    });
+  }
+
+  function scriptLabel(script: string) {
+    const errors = props.diagnostics.get(script)?.length || 0;
+    if (errors == 0) return script;
+    return `${script} (${errors} error${errors > 1 ? "s" : ""})`;
+  }
+
   async function handleContextMenu(
     script: string,
     contents: Map<string, string>,
@@ -60,7 +116,7 @@ export default function Scripts(props: {
       <div class="flex flex-col font-mono overflow-y-auto overflow-x-hidden">
         {Array.from(props.contents).map(([key, value], index) => (
           <Accordion
-            label={key}
+            label={scriptLabel(key)}
             open={props.openScripts.has(key)}
             onToggle={(open) => {
               const clone = new Set(props.openScripts);
@@ -86,6 +142,10 @@ export default function Scripts(props: {
                   props.onContentsChange(newContents);
                 }}
                 theme={props.theme}
+                diagnostics={props.diagnostics.get(key)}
+                breakpoints={breakpoints.get(key)}
+                onToggleBreakpoint={(line) => toggleBreakpoint(key, line)}
+                pausedLine={paused?.script == key ? paused.line : undefined}
               />
             </div>
             {index < props.contents.size - 1 && (
@@ -8,42 +8,89 @@ import { WebviewWindow } from "@tauri-apps/api/webviewWindow";
 import { useEffect, useState } from "preact/hooks";
 import { Entity } from "./entity/entity-type";
 import { getCurrentWindow } from "@tauri-apps/api/window";
+import openConsole from "./console/open-console";
+import TimelineBar from "./timeline/timeline-bar";
 
-const SCENE_BASE_SIZE = {
-  width: 1280,
-  height: 720,
+// what the scene looks through, sent with every update
+interface View {
+  camera: { x: number; y: number; zoom: number; rotation: number };
+  canvas: { width: number; height: number };
+}
+
+const DEFAULT_VIEW: View = {
+  camera: { x: 640, y: 360, zoom: 1, rotation: 0 },
+  canvas: { width: 1280, height: 720 },
 };
 
+// where a point on the canvas is in the world, undoing the camera's transform
+const toWorld = ({ camera, canvas }: View, x: number, y: number) => {
+  const ang = camera.rotation * (Math.PI / 180);
+  const [dx, dy] = [
+    (x - canvas.width / 2) / camera.zoom,
+    (y - canvas.height / 2) / camera.zoom,
+  ];
+  return {
+    x: camera.x + dx * Math.cos(ang) - dy * Math.sin(ang),
+    y: camera.y + dx * Math.sin(ang) + dy * Math.cos(ang),
+  };
+};
+
+// puts the camera's point in the middle of the canvas
+const worldTransform = ({ camera, canvas }: View) =>
+  [
+    `translate(calc(${canvas.width / 2}px * var(--scene-scale)), calc(${canvas.height / 2}px * var(--scene-scale)))`,
+    `rotate(${-camera.rotation}deg)`,
+    `scale(${camera.zoom})`,
+    `translate(calc(${-camera.x}px * var(--scene-scale)), calc(${-camera.y}px * var(--scene-scale)))`,
+  ].join(" ");
+
 export default function Scene() {
   const [entities, setEntities] = useState<Map<string, Entity>>(new Map());
+  const [view, setView] = useState<View>(DEFAULT_VIEW);
   const [transformScale, setTransformScale] = useState<number>(1);
-  const [lastTime, setLastTime] = useState(performance.now());
-  const [animationFrameId, setAnimationFrameId] = useState<
-    number | undefined
-  >();
   const [selectedId, setSelectedId] = useState<string | undefined>();
   const [selectedInitialPosition, setSelectedInitialPosition] = useState({
     x: 0,
     y: 0,
   });
   const [selectedInitialRotation, setSelectedInitialRotation] = useState(0);
+  const [showTimeline, setShowTimeline] = useState(false);
   const selectedEntity = selectedId ? entities.get(selectedId) : null;
 
   useEffect(() => {
     let listeners: (() => void)[] = [];
+    // plain variables rather than state, which the tick closure would only ever see the first value of
+    let lastTime = performance.now();
+    let animationFrameId: number | undefined;
+    let canvas = DEFAULT_VIEW.canvas;
+    // the Lua thread can't tick while a script is paused in the debugger
+    let debuggerPaused = false;
 
     (async () =>
       listeners.push(
-        await listen<{ [id: string]: Partial<Entity> }>("scene_update", (e) => {
-          setEntities(
-            new Map(
-              Object.entries(e.payload).map(([id, ent]) => [
-                id,
-                { ...ent, id: id } as Entity,
-              ]),
-            ),
-          );
-        }),
+        await listen<View & { entities: { [id: string]: Partial<Entity> } }>(
+          "scene_update",
+          async (e) => {
+            const { entities, ...view } = e.payload;
+            setEntities(
+              new Map(
+                Object.entries(entities).map(([id, ent]) => [
+                  id,
+                  { ...ent, id: id } as Entity,
+                ]),
+              ),
+            );
+            setView(view);
+            // a new canvas size reshapes the window to match
+            if (
+              view.canvas.width != canvas.width ||
+              view.canvas.height != canvas.height
+            ) {
+              canvas = view.canvas;
+              emit("tauri://resize", await WebviewWindow.getCurrent().size());
+            }
+          },
+        ),
       ))();
 
     (async () =>
@@ -66,13 +113,13 @@ export default function Scene() {
           const windowHeight = e.payload.height; // full window dimensions
           const titleBarHeight = windowHeight / scaleFactor - contentHeight; // calculate title bar height dynamically
 
-          const newScale = e.payload.width / SCENE_BASE_SIZE.width;
+          const newScale = e.payload.width / canvas.width;
           setTransformScale(scaleFactor / newScale);
 
           invoke("resize_window", {
-            width: Math.round(SCENE_BASE_SIZE.width * newScale),
+            width: Math.round(canvas.width * newScale),
             height: Math.round(
-              SCENE_BASE_SIZE.height * newScale + titleBarHeight * scaleFactor,
+              canvas.height * newScale + titleBarHeight * scaleFactor,
             ),
           });
           document.documentElement.style.setProperty(
@@ -87,6 +134,48 @@ export default function Scene() {
       );
     })();
 
+    // keyboard input goes to scripts, unless it's typing into a text box
+    const sendKey = (type: string) => (e: KeyboardEvent) => {
+      if (e.repeat || (e.target as Element).closest?.("input, textarea"))
+        return;
+      invoke("input_event", { event: { type: type, key: e.key } });
+    };
+    const onKeyDown = sendKey("key_down");
+    const onKeyUp = sendKey("key_up");
+    const onBlur = () => invoke("input_event", { event: { type: "blur" } });
+    window.addEventListener("keydown", onKeyDown);
+    window.addEventListener("keyup", onKeyUp);
+    window.addEventListener("blur", onBlur);
+    listeners.push(() => {
+      window.removeEventListener("keydown", onKeyDown);
+      window.removeEventListener("keyup", onKeyUp);
+      window.removeEventListener("blur", onBlur);
+    });
+
+    (async () => {
+      listeners.push(await listen("open_console", () => openConsole(true)));
+      listeners.push(
+        await listen("toggle_timeline", () =>
+          setShowTimeline((show) => !show),
+        ),
+      );
+      // surface script errors without stealing focus from the scene
+      listeners.push(await listen("script_error", () => openConsole(false)));
+      listeners.push(
+        await listen("debugger_paused", () => {
+          debuggerPaused = true;
+          openConsole(true);
+        }),
+      );
+      listeners.push(
+        await listen("debugger_resumed", () => {
+          debuggerPaused = false;
+          // so the next tick doesn't make up for the time spent paused
+          lastTime = performance.now();
+        }),
+      );
+    })();
+
     (async () => {
       listeners.push(
         await listen<string>("file_operation", async (e) => {
@@ -117,6 +206,35 @@ export default function Scene() {
                 filters: [{ name: "scene", extensions: ["txt"] }],
               }),
             });
+          } else if (e.payload == "start_recording") {
+            const [success, msg] = await invoke<[boolean, string]>(
+              "start_recording",
+            );
+            if (!success) message(msg, { title: `Error`, kind: "error" });
+          } else if (e.payload == "stop_recording") {
+            const path = await save({
+              filters: [{ name: "replay", extensions: ["json"] }],
+            });
+            if (!path) return;
+            const [success, msg] = await invoke<[boolean, string]>(
+              "stop_recording",
+              { path: path },
+            );
+            if (!success) message(msg, { title: `Error`, kind: "error" });
+          } else if (e.payload == "replay") {
+            const path = await open({
+              multiple: false,
+              directory: false,
+              filters: [{ name: "replay", extensions: ["json"] }],
+            });
+            if (!path) return;
+            const [success, msg] = await invoke<[boolean, string]>("replay", {
+              path: path,
+            });
+            message(msg, {
+              title: success ? "Replay finished" : "Replay didn't match",
+              kind: success ? "info" : "warning",
+            });
           } else console.warn("Unhandled file operation", e.payload);
         }),
       );
@@ -125,11 +243,11 @@ export default function Scene() {
     const tick = () => {
       const now = performance.now();
       const dt = (now - lastTime) / 1000;
-      setLastTime(now);
-      invoke("tick", { dt });
-      setAnimationFrameId(requestAnimationFrame(tick));
+      lastTime = now;
+      if (!debuggerPaused) invoke("tick", { dt });
+      animationFrameId = requestAnimationFrame(tick);
     };
-    setAnimationFrameId(requestAnimationFrame(tick));
+    animationFrameId = requestAnimationFrame(tick);
 
     return () => {
       listeners.forEach((unsubscribe) => unsubscribe());
@@ -187,6 +305,19 @@ export default function Scene() {
     });
   };
 
+  // captured before entities see the event, so scripts get it even when an entity stops it propagating.
+  // positions are sent on the canvas, and the camera turns them into world positions for scripts
+  const sendPointer = (type: string, e: MouseEvent) =>
+    invoke("input_event", {
+      event: {
+        type: type,
+        button: e.button,
+        x: e.clientX * transformScale,
+        y: e.clientY * transformScale,
+        entity: (e.target as Element).closest(".entity")?.id ?? null,
+      },
+    });
+
   const addNewEntity = async (entity: Entity) => {
     // ensure unique id
     var unique_index: number = 0;
@@ -213,6 +344,19 @@ export default function Scene() {
       onClick={(e) => {
         if (e.target === e.currentTarget) setSelectedId(undefined);
       }}
+      onMouseMoveCapture={(e) => sendPointer("mouse_move", e)}
+      onMouseDownCapture={(e) => sendPointer("mouse_down", e)}
+      onMouseUpCapture={(e) => sendPointer("mouse_up", e)}
+      onWheelCapture={(e) =>
+        invoke("input_event", {
+          event: {
+            type: "wheel",
+            dx: e.deltaX * transformScale,
+            dy: e.deltaY * transformScale,
+          },
+        })
+      }
+      onMouseLeave={(e) => sendPointer("mouse_move", e)}
       onContextMenu={async (e) => {
         if (e.target !== e.currentTarget) return;
         e.preventDefault();
@@ -234,10 +378,11 @@ export default function Scene() {
                         id: "text_entity",
                         type: "text",
                         content: "text",
-                        pos: {
-                          x: e.x * transformScale,
-                          y: e.y * transformScale,
-                        },
+                        pos: toWorld(
+                          view,
+                          e.x * transformScale,
+                          e.y * transformScale,
+                        ),
                         scripts: {},
                       });
                     },
@@ -249,16 +394,56 @@ export default function Scene() {
                       addNewEntity({
                         id: "rect_entity",
                         type: "rect",
-                        pos: {
-                          x: e.x * transformScale,
-                          y: e.y * transformScale,
-                        },
+                        pos: toWorld(
+                          view,
+                          e.x * transformScale,
+                          e.y * transformScale,
+                        ),
                         size: { width: 100, height: 100 },
                         color: "#ff0000",
                         scripts: {},
                       });
                     },
                   },
+                  {
+                    id: "add_sprite_entity",
+                    text: "sprite",
+                    action: async () => {
+                      addNewEntity({
+                        id: "sprite_entity",
+                        type: "sprite",
+                        image: "image.png",
+                        pos: toWorld(
+                          view,
+                          e.x * transformScale,
+                          e.y * transformScale,
+                        ),
+                        size: { width: 64, height: 64 },
+                        scripts: {},
+                      });
+                    },
+                  },
+                  {
+                    id: "add_tilemap_entity",
+                    text: "tilemap",
+                    action: async () => {
+                      addNewEntity({
+                        id: "tilemap_entity",
+                        type: "tilemap",
+                        tileset: "tiles.png",
+                        pos: toWorld(
+                          view,
+                          e.x * transformScale,
+                          e.y * transformScale,
+                        ),
+                        tile_size: { width: 32, height: 32 },
+                        columns: 10,
+                        rows: 5,
+                        tiles: "0*50",
+                        scripts: {},
+                      });
+                    },
+                  },
                   {
                     id: "add_text_input_entity",
                     text: "text_input",
@@ -266,10 +451,11 @@ export default function Scene() {
                       addNewEntity({
                         id: "text_input_entity",
                         type: "text_input",
-                        pos: {
-                          x: e.x * transformScale,
-                          y: e.y * transformScale,
-                        },
+                        pos: toWorld(
+                          view,
+                          e.x * transformScale,
+                          e.y * transformScale,
+                        ),
                         size: { width: 120, height: 40 },
                         color: "#aaaaaa",
                         scripts: {},
@@ -283,14 +469,19 @@ export default function Scene() {
         ).popup();
       }}
     >
-      {Array.from(entities).map(([id, entity]) => (
-        <EntityComponent
-          key={id}
-          entity={entity}
-          onSelect={() => handleEntitySelect(id)}
-          isSelected={id === selectedId}
-        />
-      ))}
+      <div
+        class="absolute top-0 left-0 origin-top-left"
+        style={{ transform: worldTransform(view) }}
+      >
+        {Array.from(entities).map(([id, entity]) => (
+          <EntityComponent
+            key={id}
+            entity={entity}
+            onSelect={() => handleEntitySelect(id)}
+            isSelected={id === selectedId}
+          />
+        ))}
+      </div>
       {selectedEntity && (
         <Moveable
           target={`#${selectedId}`}
@@ -300,6 +491,9 @@ export default function Scene() {
           onRotate={handleRotate}
         />
       )}
+      {showTimeline && (
+        <TimelineBar entityIds={Array.from(entities.keys())} />
+      )}
     </div>
   );
 }
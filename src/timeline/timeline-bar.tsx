@@ -0,0 +1,118 @@
+import { invoke } from "@tauri-apps/api/core";
+import { message } from "@tauri-apps/plugin-dialog";
+import { useEffect, useState } from "preact/hooks";
+import { Pause, Play, RotateCcw } from "preact-feather";
+
+interface Timeline {
+  first?: number;
+  last?: number;
+  viewing?: number;
+  time?: number;
+  // ticks between snapshots
+  every?: number;
+}
+
+// scrubs back through the last few seconds of the scene, which pauses it until it's resumed
+export default function TimelineBar(props: { entityIds: string[] }) {
+  const [timeline, setTimeline] = useState<Timeline>({});
+  const [entityId, setEntityId] = useState<string>("");
+  const [entity, setEntity] = useState<string>("");
+
+  const refresh = async () =>
+    setTimeline(await invoke<Timeline>("get_timeline"));
+
+  // the history keeps growing while the scene runs
+  useEffect(() => {
+    refresh();
+    if (timeline.viewing != undefined) return;
+    const interval = setInterval(refresh, 250);
+    return () => clearInterval(interval);
+  }, [timeline.viewing]);
+
+  useEffect(() => {
+    if (timeline.viewing == undefined || !entityId) {
+      setEntity("");
+      return;
+    }
+    invoke<[boolean, string]>("get_snapshot_entity", {
+      tick: timeline.viewing,
+      id: entityId,
+    }).then(([_, msg]) => setEntity(msg));
+  }, [timeline.viewing, entityId]);
+
+  const report = async (command: string, args: object) => {
+    const [success, msg] = await invoke<[boolean, string]>(command, args);
+    if (!success) message(msg, { title: `Error`, kind: "error" });
+    refresh();
+  };
+
+  const paused = timeline.viewing != undefined;
+
+  return (
+    <div class="absolute bottom-0 left-0 right-0 flex flex-col gap-1 p-1 text-sm bg-secondary border-t border-border">
+      <div class="flex flex-row gap-2 items-center">
+        <button
+          title={paused ? "Back to live" : "Pause"}
+          onClick={() =>
+            paused
+              ? report("resume_from_snapshot", { tick: null })
+              : timeline.last != undefined &&
+                report("view_snapshot", { tick: timeline.last })
+          }
+        >
+          {paused ? <Play size={16} /> : <Pause size={16} />}
+        </button>
+        <input
+          type="range"
+          class="grow"
+          disabled={timeline.first == undefined}
+          min={timeline.first ?? 0}
+          max={timeline.last ?? 0}
+          step={timeline.every ?? 1}
+          value={timeline.viewing ?? timeline.last ?? 0}
+          onInput={(e) =>
+            report("view_snapshot", {
+              tick: Number((e.target as HTMLInputElement).value),
+            })
+          }
+        />
+        <span class="shrink-0 font-mono text-tertiary">
+          {paused
+            ? `tick ${timeline.viewing} (${timeline.time?.toFixed(2)}s)`
+            : "live"}
+        </span>
+        <button
+          title="Resume here"
+          disabled={!paused}
+          onClick={() =>
+            report("resume_from_snapshot", { tick: timeline.viewing })
+          }
+        >
+          <RotateCcw size={16} />
+        </button>
+      </div>
+      {paused && (
+        <div class="flex flex-col gap-1">
+          <select
+            value={entityId}
+            onChange={(e) =>
+              setEntityId((e.target as HTMLSelectElement).value)
+            }
+          >
+            <option value="">Inspect an entity…</option>
+            {props.entityIds.map((id) => (
+              <option key={id} value={id}>
+                {id}
+              </option>
+            ))}
+          </select>
+          {entity && (
+            <pre class="max-h-40 overflow-auto font-mono whitespace-pre-wrap select-text">
+              {entity}
+            </pre>
+          )}
+        </div>
+      )}
+    </div>
+  );
+}
@@ -23,6 +23,20 @@ declare module "react-ace" {
       exec: ((editor: any) => void) | string;
     }[];
     onCopy?: (text: string) => void;
+    annotations?: {
+      row: number;
+      column: number;
+      text: string;
+      type: "error" | "warning" | "info";
+    }[];
+    markers?: {
+      startRow: number;
+      startCol: number;
+      endRow: number;
+      endCol: number;
+      className: string;
+      type: "text" | "fullLine" | "screenLine";
+    }[];
   }
 
   const ReactAce: ComponentType<IAceEditorProps>;
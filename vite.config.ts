@@ -13,6 +13,7 @@ export default defineConfig(async () => ({
       input: {
         main: "index.html",
         inspector: "src/inspector/inspector.html",
+        console: "src/console/console.html",
       },
     },
   },